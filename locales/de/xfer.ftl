@@ -0,0 +1,187 @@
+## Labels
+
+error = Fehler
+hint = Tipp
+to = nach
+sending = Sende
+getting = Hole
+syncing = Synchronisiere
+
+## Transfers
+
+transferred = { $bytes } in { $elapsed } übertragen ({ $rate }/s)
+unknown-server = Unbekannter Server-Alias '{ $alias }'. Bitte zuerst in der Konfiguration anlegen.
+no-remote-match = Keine entfernten Dateien passen auf '{ $location }'
+batch-failed = { $failed } von { $total } Übertragungen fehlgeschlagen: { $errors }
+retrying = { $reason }; neuer Versuch in { $delay } s ({ $attempt } von { $attempts })
+remote-to-remote = Direkte Übertragungen zwischen zwei Servern werden noch nicht unterstützt
+
+## Destination locks
+
+lock-busy = In { $path } schreibt gerade { $holder }. Mit --wait erneut ausführen, um zu warten, oder 'xfer unlock', falls diese Übertragung nicht mehr läuft.
+lock-waiting = Warte auf { $path } (belegt von { $holder })
+lock-stale = Entferne die Sperre auf { $path }: { $holder } läuft nicht mehr
+
+## Server groups
+
+broadcast-failed = { $failed } von { $total } Hosts in @{ $group } fehlgeschlagen
+
+## Tool failures
+
+tool-failed = { $program } fehlgeschlagen (Exit-Code { $code }): { $reason }
+tool-failed-bare = { $program } fehlgeschlagen mit Exit-Code { $code }
+failure-host-key = der Host-Schlüssel des Servers konnte nicht überprüft werden
+hint-host-key = einmal mit ssh verbinden, um den Schlüssel zu bestätigen, oder den veralteten Eintrag aus ~/.ssh/known_hosts entfernen
+failure-auth = der Server hat die Anmeldung abgelehnt
+hint-auth = key_path und auth des Servers prüfen ('xfer server list') oder mit 'ssh -v' sehen, welche Schlüssel angeboten werden
+failure-dns = der Hostname konnte nicht aufgelöst werden
+hint-dns = den Host in der Server-Konfiguration sowie DNS oder VPN prüfen
+failure-dropped = die Verbindung ist während der Übertragung abgebrochen
+hint-dropped = das Netzwerk prüfen oder mit --retries N öfter neu versuchen
+failure-unreachable = der Server ist nicht erreichbar
+hint-unreachable = prüfen, ob der Host läuft, der Port stimmt und ob ein proxy_jump nötig ist
+failure-disk-full = das Ziellaufwerk ist voll
+hint-disk-full = am Ziel Platz schaffen (siehe 'df -h') oder einen anderen Pfad wählen
+failure-no-rsync = rsync ist auf dem Server nicht installiert
+hint-no-rsync = rsync auf dem Server installieren; einzelne Dateien ohne --resume werden per scp gesendet
+failure-not-found = ein Pfad existiert nicht
+hint-not-found = den Quellpfad prüfen und ob das Zielverzeichnis existiert ('xfer list alias:/pfad')
+failure-permission = Zugriff auf eine Datei oder ein Verzeichnis verweigert
+hint-permission = Besitzer und Rechte des Ziels prüfen ('xfer stat alias:/pfad')
+
+## Transfer checks
+
+no-files-matched = Keine Dateien passen auf { $sources }
+glob-windows = Entfernte Platzhalter werden auf Windows-Servern nicht unterstützt
+verify-s3 = --verify wird für s3:-Ziele nicht unterstützt
+archive-s3 = --archive wird für s3:-Ziele nicht unterstützt
+unpack-needs-ssh = --unpack braucht ein Archiv auf einem ssh-Server
+encrypt-needs-ssh = Verschlüsselung braucht auf der anderen Seite einen ssh-Server
+verify-kept-archive = --verify kann eine als Archivdatei abgelegte Übertragung nicht prüfen
+verify-windows = Die Prüfsummenkontrolle braucht eine POSIX-Shell auf dem Server
+verify-failed = Prüfsummenkontrolle fehlgeschlagen: { $mismatched } abweichend, { $missing } fehlend
+local-missing = Lokaler Pfad '{ $path }' existiert nicht
+no-space = Nicht genug Platz auf { $place }: { $needed } nötig, { $free } frei
+no-quota = Nicht genug Kontingent auf dem Server ({ $dir }): { $needed } nötig, { $left } von { $limit } übrig
+server-place = dem Server ({ $dir })
+
+## Deletions
+
+will-delete = Lösche
+will-delete-paths = { $count } Pfad(e) in { $dest }, die es in der Quelle nicht gibt:
+and-more = ... und { $count } weitere
+delete-them = Löschen?
+delete-declined = Nichts übertragen; mit --force wird ohne Rückfrage gelöscht
+
+## Locations
+
+invalid-location = Ungültiges Ortsformat. Bitte 'alias:/pfad' verwenden
+no-alias = '{ $location }' hat keinen Server-Alias vor dem ':' (alias:/pfad verwenden)
+no-bucket = '{ $location }' hat keinen Bucket (s3:bucket/schlüssel verwenden)
+unknown-home = Das Home-Verzeichnis von { $user }@{ $host } ließ sich nicht ermitteln
+unsafe-name = Unsicherer Dateiname vom Server abgelehnt: '{ $name }'
+verify-needs-remote = Das zweite Argument muss ein entfernter Ort sein (alias:/pfad)
+unlock-needs-remote = Sperren gibt es nur an entfernten Orten (alias:/pfad)
+unlocked = Sperre auf { $path } entfernt
+not-locked = { $path } ist nicht gesperrt
+
+## Adding servers
+
+no-servers = Keine Server konfiguriert. Jetzt einen anlegen.
+no-terminal = Kein Terminal für Rückfragen; den Server als Optionen angeben (ALIAS --host ...) oder --from-file verwenden
+no-command = Kein Befehl angegeben. --help zeigt die Verwendung.
+server-adding = Neuen Server anlegen
+prompt-alias = Server-Alias (z. B. 'gcp', 'aws-ec2'):
+prompt-host = Host-Adresse (z. B. 'example.com', '10.0.0.1'):
+prompt-user = Benutzername:
+prompt-auth = Anmeldung (key/agent/password/interactive, leer für die ssh-Vorgaben):
+prompt-key-path = Pfad zum SSH-Schlüssel (optional, leer für keinen):
+prompt-cert-path = Pfad zum SSH-Zertifikat (optional, für von einer CA signierte Schlüssel):
+prompt-port = SSH-Port (optional, Vorgabe 22):
+prompt-default-path = Entfernter Standardpfad (optional):
+prompt-jump = Jump-Host (optional, Alias oder user@host:port):
+prompt-set-default = Als Standardserver festlegen?
+unknown-auth = Unbekannte Anmeldemethode '{ $method }'
+server-added = Server erfolgreich angelegt!
+server-exists = Server '{ $alias }' existiert bereits (--force ersetzt ihn)
+no-user = Kein --user angegeben und $USER ist nicht gesetzt
+invalid-port = Ungültiger Port '{ $port }'
+added-server = Server hinzugefügt:
+added = Hinzugefügt
+skipped = Übersprungen
+server-skipped = { $alias } (schon eingerichtet; --force ersetzt ihn)
+imported = Importiert:
+servers-imported = { $added } Server hinzugefügt, { $replaced } ersetzt
+no-server-tables = Keine [servers.<alias>]-Tabellen in { $file }
+unknown-default-server = default_server '{ $alias }' ist nicht definiert
+
+## Project routes and hooks
+
+no-project = Keine { $file } in diesem Verzeichnis oder darüber
+no-routes = Keine Routen in { $file }
+routes-in = Routen in
+unknown-route = Keine Route '{ $name }' in { $file } (Routen: { $routes })
+none = keine
+route-not-run = { $error }; Route '{ $name }' nicht ausgeführt
+running = Starte
+dry-run = Probelauf
+nothing-to-delete = In { $dest } würde nichts gelöscht
+before = Vorher:
+on-failure = Bei Fehler:
+warning = Warnung:
+hook-not-run = (im Probelauf nicht ausgeführt)
+hook-not-started = '{ $command }' konnte nicht gestartet werden: { $error }
+hook-failed = '{ $command }' fehlgeschlagen ({ $status })
+
+## Mirrors
+
+mirror-root = In das Wurzelverzeichnis des Servers kann nicht gespiegelt werden
+mirror-windows = Spiegeln braucht eine POSIX-Shell auf dem Server
+heartbeat = Heartbeat:
+heartbeat-published = { $files } Datei(en), { $bytes } nach { $dest } gespiegelt um { $time }
+mirroring = Spiegele
+mirroring-every = { $src } -> { $dest } (mindestens alle { $interval } s)
+watch-error = Fehler beim Beobachten
+watcher-stopped = Die Dateiüberwachung wurde unerwartet beendet
+mirror-running = Spiegel läuft
+mirror-not-running = Spiegel läuft nicht
+sync = Abgleich:
+last-synced = zuletzt { $when }; { $running }
+never-synced = noch nie abgeglichen; { $running }
+lag = Rückstand:
+lag-current = aktuell
+lag-behind = { $files } Datei(en) ({ $bytes }) und { $deletions } Löschung(en) im Rückstand
+standby = Standby:
+standby-matches = stimmt mit dem Heartbeat überein
+standby-drifted = { $count } Datei(en) passen nicht zum Heartbeat: { $files }
+problem = Problem:
+no-mirror = Von hier wurde kein Spiegel nach { $dest } gestartet (siehe `xfer mirror start`)
+no-mirrors = Noch keine Spiegel gestartet.
+
+## Queue
+
+invalid-time = '{ $value }' ist keine Zeitangabe (HH:MM, "JJJJ-MM-TT HH:MM" oder z. B. +2h)
+queued = Eingereiht:
+queued-job = Job { $id }: { $transfer }
+queued-job-at = Job { $id } für { $time }: { $transfer }
+daemon-not-running-hint = Der Daemon läuft nicht; starte ihn mit `xfer daemon --detach`.
+queue-empty = Die Warteschlange ist leer
+daemon = Daemon:
+daemon-status = läuft (PID { $pid }, seit { $since }), { $concurrency } Job(s) gleichzeitig, bis zu { $attempts } Versuch(e) je Job
+daemon-status-stopped = läuft nicht (starten mit `xfer daemon --detach`)
+log = Log:
+jobs = Jobs:
+jobs-summary = { $queued } eingereiht, { $running } laufen, { $failed } fehlgeschlagen, { $done } fertig
+jobs-summary-next = { $queued } eingereiht (nächster um { $next }), { $running } laufen, { $failed } fehlgeschlagen, { $done } fertig
+no-job = Kein Job { $id } in der Warteschlange
+job-finished = Job { $id } ist bereits { $state }
+cancelled = Abgebrochen:
+job = Job { $id }
+not-all-cancelled = Einige Jobs wurden nicht abgebrochen
+daemon-already-running = Der Daemon läuft bereits (PID { $pid })
+started = Gestartet:
+daemon-started = Daemon (PID { $pid }), Log in { $log }
+daemon-not-running = Der Daemon läuft nicht
+stopping = Beende
+daemon-stopping = Daemon (PID { $pid }); er endet, sobald seine laufenden Jobs fertig sind
+daemon-running = Warteschlangen-Daemon, { $concurrency } Job(s) gleichzeitig, Log in { $log }
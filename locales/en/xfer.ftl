@@ -0,0 +1,190 @@
+# User-facing messages. Translations live next to this file as
+# locales/<lang>/xfer.ftl; anything they leave out falls back to English.
+
+## Labels
+
+error = Error
+hint = hint
+to = to
+sending = Sending
+getting = Getting
+syncing = Syncing
+
+## Transfers
+
+transferred = Transferred { $bytes } in { $elapsed } ({ $rate }/s)
+unknown-server = Unknown server alias '{ $alias }'. Add it to your config first.
+no-remote-match = No remote files matched '{ $location }'
+batch-failed = { $failed } of { $total } transfers failed: { $errors }
+remote-to-remote = Direct remote-to-remote transfers not supported yet
+retrying = { $reason }; retrying in { $delay }s (attempt { $attempt } of { $attempts })
+
+## Destination locks
+
+lock-busy = { $path } is being written by { $holder }. Re-run with --wait to queue behind it, or 'xfer unlock' if that transfer is gone.
+lock-waiting = Waiting for { $path } (held by { $holder })
+lock-stale = Breaking the lock on { $path }: { $holder } is gone
+
+## Server groups
+
+broadcast-failed = { $failed } of { $total } hosts in @{ $group } failed
+
+## Tool failures
+
+tool-failed = { $program } failed (exit code { $code }): { $reason }
+tool-failed-bare = { $program } failed with exit code { $code }
+failure-host-key = the server's host key could not be verified
+hint-host-key = connect once with plain ssh to accept the key, or remove the stale entry from ~/.ssh/known_hosts
+failure-auth = the server refused authentication
+hint-auth = check the server's key_path and auth settings ('xfer server list'), or run 'ssh -v' to see which keys are offered
+failure-dns = the host name could not be resolved
+hint-dns = check the host in the server config, and your DNS or VPN
+failure-dropped = the connection dropped mid-transfer
+hint-dropped = check the network, or retry more often with --retries N
+failure-unreachable = the server could not be reached
+hint-unreachable = check that the host is up and the port is right, and whether it needs a proxy_jump
+failure-disk-full = the destination disk is full
+hint-disk-full = free some space on the destination (see 'df -h') or pick another path
+failure-no-rsync = rsync is not installed on the server
+hint-no-rsync = install rsync on the server; single files without --resume are sent with scp instead
+failure-not-found = a path does not exist
+hint-not-found = check the source path and that the destination directory exists ('xfer list alias:/path')
+failure-permission = permission denied on a file or directory
+hint-permission = check ownership and permissions of the destination ('xfer stat alias:/path')
+
+## Transfer checks
+
+no-files-matched = No files matched { $sources }
+glob-windows = Remote globs are not supported on Windows servers
+verify-s3 = --verify is not supported for s3: locations
+archive-s3 = --archive is not supported for s3: locations
+unpack-needs-ssh = --unpack needs an archive on an ssh server
+encrypt-needs-ssh = Encryption needs an ssh server at the other end
+verify-kept-archive = --verify can't check a transfer kept as an archive file
+verify-windows = Checksum verification needs a POSIX shell on the server
+verify-failed = Checksum verification failed: { $mismatched } mismatched, { $missing } missing
+local-missing = Local path '{ $path }' does not exist
+no-space = Not enough space on { $place }: { $needed } needed, { $free } free
+no-quota = Not enough quota left on the server ({ $dir }): { $needed } needed, { $left } left of { $limit }
+server-place = the server ({ $dir })
+
+## Deletions
+
+will-delete = Will delete
+will-delete-paths = { $count } path(s) in { $dest } that the source doesn't have:
+and-more = ... and { $count } more
+delete-them = Delete them?
+delete-declined = Nothing was transferred; pass --force to delete without asking
+
+## Locations
+
+invalid-location = Invalid location format. Use 'alias:/path'
+no-alias = '{ $location }' has no server alias before the ':' (use alias:/path)
+no-bucket = '{ $location }' has no bucket (use s3:bucket/key)
+unknown-home = Could not determine the home directory of { $user }@{ $host }
+unsafe-name = Refusing unsafe file name from server: '{ $name }'
+verify-needs-remote = Second argument must be a remote location (alias:/path)
+unlock-needs-remote = Locks only exist on remote locations (alias:/path)
+unlocked = Removed lock on { $path }
+not-locked = { $path } is not locked
+
+## Adding servers
+
+no-servers = No server configurations found. Let's add one now.
+no-terminal = No terminal to prompt on; give the server as flags (ALIAS --host ...) or use --from-file
+no-command = No command specified. Use --help for usage information.
+server-adding = Adding a new server configuration
+prompt-alias = Server alias (e.g., 'gcp', 'aws-ec2'):
+prompt-host = Host address (e.g., 'example.com', '10.0.0.1'):
+prompt-user = Username:
+prompt-auth = Authentication (key/agent/password/interactive, leave blank for ssh defaults):
+prompt-key-path = SSH key path (optional, leave blank for none):
+prompt-cert-path = SSH certificate path (optional, for keys signed by a CA):
+prompt-port = SSH port (optional, default is 22):
+prompt-default-path = Default remote path (optional):
+prompt-jump = Jump host (optional, alias or user@host:port):
+prompt-set-default = Set as default server?
+unknown-auth = Unknown authentication method '{ $method }'
+server-added = Server configuration added successfully!
+server-exists = Server '{ $alias }' already exists (use --force to replace it)
+no-user = No --user given and $USER is not set
+invalid-port = Invalid port '{ $port }'
+added-server = Added server
+added = Added
+skipped = Skipped
+server-skipped = { $alias } (already configured; --force replaces it)
+imported = Imported:
+servers-imported = { $added } server(s) added, { $replaced } replaced
+no-server-tables = No [servers.<alias>] tables in { $file }
+unknown-default-server = default_server '{ $alias }' is not defined
+
+## Project routes and hooks
+
+no-project = No { $file } in this directory or any parent
+no-routes = No routes in { $file }
+routes-in = Routes in
+unknown-route = No route '{ $name }' in { $file } (routes: { $routes })
+none = none
+route-not-run = { $error }; route '{ $name }' not run
+running = Running
+dry-run = Dry run
+nothing-to-delete = Nothing in { $dest } would be deleted
+before = Before:
+on-failure = On failure:
+warning = Warning:
+hook-not-run = (not run in a dry run)
+hook-not-started = Failed to run '{ $command }': { $error }
+hook-failed = '{ $command }' failed ({ $status })
+
+## Mirrors
+
+mirror-root = Can't mirror to the server's root directory
+mirror-windows = Mirroring needs a POSIX shell on the server
+heartbeat = Heartbeat:
+heartbeat-published = { $files } file(s), { $bytes } mirrored to { $dest } at { $time }
+mirroring = Mirroring
+mirroring-every = { $src } -> { $dest } (every { $interval }s at least)
+watch-error = Watch error
+watcher-stopped = Filesystem watcher stopped unexpectedly
+mirror-running = mirror running
+mirror-not-running = mirror not running
+sync = Sync:
+last-synced = last synced { $when }; { $running }
+never-synced = never synced; { $running }
+lag = Lag:
+lag-current = current
+lag-behind = { $files } file(s) ({ $bytes }) and { $deletions } deletion(s) behind
+standby = Standby:
+standby-matches = matches the heartbeat
+standby-drifted = { $count } file(s) don't match the heartbeat: { $files }
+problem = Problem:
+no-mirror = No mirror to { $dest } was started here (use `xfer mirror start`)
+no-mirrors = No mirrors started yet.
+
+## Queue
+
+invalid-time = '{ $value }' is not a time (use HH:MM, "YYYY-MM-DD HH:MM" or e.g. +2h)
+queued = Queued
+queued-job = job { $id }: { $transfer }
+queued-job-at = job { $id } for { $time }: { $transfer }
+daemon-not-running-hint = The daemon isn't running; start it with `xfer daemon --detach`.
+queue-empty = The queue is empty
+daemon = Daemon:
+daemon-status = running (pid { $pid }, since { $since }), { $concurrency } job(s) at a time, up to { $attempts } attempt(s) each
+daemon-status-stopped = not running (start it with `xfer daemon --detach`)
+log = Log:
+jobs = Jobs:
+jobs-summary = { $queued } queued, { $running } running, { $failed } failed, { $done } done
+jobs-summary-next = { $queued } queued (next at { $next }), { $running } running, { $failed } failed, { $done } done
+no-job = No job { $id } in the queue
+job-finished = Job { $id } has already { $state }
+cancelled = Cancelled
+job = job { $id }
+not-all-cancelled = Some jobs were not cancelled
+daemon-already-running = The daemon is already running (pid { $pid })
+started = Started
+daemon-started = daemon (pid { $pid }), logging to { $log }
+daemon-not-running = The daemon isn't running
+stopping = Stopping
+daemon-stopping = daemon (pid { $pid }); it exits once its running jobs finish
+daemon-running = queue daemon, { $concurrency } job(s) at a time, logging to { $log }
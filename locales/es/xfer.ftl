@@ -0,0 +1,187 @@
+## Labels
+
+error = Error
+hint = sugerencia
+to = a
+sending = Enviando
+getting = Descargando
+syncing = Sincronizando
+
+## Transfers
+
+transferred = Transferido { $bytes } en { $elapsed } ({ $rate }/s)
+unknown-server = Alias de servidor desconocido '{ $alias }'. Añádelo primero a la configuración.
+no-remote-match = Ningún archivo remoto coincide con '{ $location }'
+batch-failed = { $failed } de { $total } transferencias fallaron: { $errors }
+retrying = { $reason }; reintentando en { $delay } s (intento { $attempt } de { $attempts })
+remote-to-remote = Las transferencias directas entre servidores aún no están soportadas
+
+## Destination locks
+
+lock-busy = { $holder } está escribiendo en { $path }. Vuelve a ejecutar con --wait para esperar, o usa 'xfer unlock' si esa transferencia ya no existe.
+lock-waiting = Esperando a { $path } (ocupado por { $holder })
+lock-stale = Se quita el bloqueo de { $path }: { $holder } ya no existe
+
+## Server groups
+
+broadcast-failed = { $failed } de { $total } hosts en @{ $group } fallaron
+
+## Tool failures
+
+tool-failed = { $program } falló (código de salida { $code }): { $reason }
+tool-failed-bare = { $program } falló con código de salida { $code }
+failure-host-key = no se pudo verificar la clave del servidor
+hint-host-key = conéctate una vez con ssh para aceptar la clave, o elimina la entrada antigua de ~/.ssh/known_hosts
+failure-auth = el servidor rechazó la autenticación
+hint-auth = revisa key_path y auth del servidor ('xfer server list'), o ejecuta 'ssh -v' para ver qué claves se ofrecen
+failure-dns = no se pudo resolver el nombre del host
+hint-dns = revisa el host en la configuración del servidor y tu DNS o VPN
+failure-dropped = la conexión se cortó durante la transferencia
+hint-dropped = comprueba la red o reintenta más veces con --retries N
+failure-unreachable = no se pudo contactar con el servidor
+hint-unreachable = comprueba que el host esté activo, que el puerto sea correcto y si necesita un proxy_jump
+failure-disk-full = el disco de destino está lleno
+hint-disk-full = libera espacio en el destino (ver 'df -h') o elige otra ruta
+failure-no-rsync = rsync no está instalado en el servidor
+hint-no-rsync = instala rsync en el servidor; los archivos sueltos sin --resume se envían con scp
+failure-not-found = una ruta no existe
+hint-not-found = revisa la ruta de origen y que el directorio de destino exista ('xfer list alias:/ruta')
+failure-permission = permiso denegado en un archivo o directorio
+hint-permission = revisa el propietario y los permisos del destino ('xfer stat alias:/ruta')
+
+## Transfer checks
+
+no-files-matched = Ningún archivo coincide con { $sources }
+glob-windows = Los comodines remotos no se admiten en servidores Windows
+verify-s3 = --verify no se admite con ubicaciones s3:
+archive-s3 = --archive no se admite con ubicaciones s3:
+unpack-needs-ssh = --unpack necesita un archivo en un servidor ssh
+encrypt-needs-ssh = El cifrado necesita un servidor ssh en el otro extremo
+verify-kept-archive = --verify no puede comprobar una transferencia guardada como archivo
+verify-windows = La verificación de sumas necesita un shell POSIX en el servidor
+verify-failed = La verificación de sumas falló: { $mismatched } distintas, { $missing } ausentes
+local-missing = La ruta local '{ $path }' no existe
+no-space = No hay espacio suficiente en { $place }: se necesitan { $needed }, quedan { $free } libres
+no-quota = No queda cuota suficiente en el servidor ({ $dir }): se necesitan { $needed }, quedan { $left } de { $limit }
+server-place = el servidor ({ $dir })
+
+## Deletions
+
+will-delete = Se borrarán
+will-delete-paths = { $count } ruta(s) en { $dest } que el origen no tiene:
+and-more = ... y { $count } más
+delete-them = ¿Borrarlas?
+delete-declined = No se transfirió nada; usa --force para borrar sin preguntar
+
+## Locations
+
+invalid-location = Formato de ubicación no válido. Usa 'alias:/ruta'
+no-alias = '{ $location }' no tiene alias de servidor antes de ':' (usa alias:/ruta)
+no-bucket = '{ $location }' no tiene bucket (usa s3:bucket/clave)
+unknown-home = No se pudo averiguar el directorio personal de { $user }@{ $host }
+unsafe-name = Se rechaza un nombre de archivo inseguro del servidor: '{ $name }'
+verify-needs-remote = El segundo argumento debe ser una ubicación remota (alias:/ruta)
+unlock-needs-remote = Los bloqueos solo existen en ubicaciones remotas (alias:/ruta)
+unlocked = Bloqueo de { $path } eliminado
+not-locked = { $path } no está bloqueado
+
+## Adding servers
+
+no-servers = No hay servidores configurados. Vamos a añadir uno.
+no-terminal = No hay terminal para preguntar; indica el servidor con opciones (ALIAS --host ...) o usa --from-file
+no-command = No se indicó ningún comando. Usa --help para ver el uso.
+server-adding = Añadiendo un servidor nuevo
+prompt-alias = Alias del servidor (p. ej., 'gcp', 'aws-ec2'):
+prompt-host = Dirección del host (p. ej., 'example.com', '10.0.0.1'):
+prompt-user = Usuario:
+prompt-auth = Autenticación (key/agent/password/interactive, en blanco para lo que use ssh):
+prompt-key-path = Ruta de la clave SSH (opcional, en blanco para ninguna):
+prompt-cert-path = Ruta del certificado SSH (opcional, para claves firmadas por una CA):
+prompt-port = Puerto SSH (opcional, 22 por defecto):
+prompt-default-path = Ruta remota por defecto (opcional):
+prompt-jump = Host de salto (opcional, alias o user@host:puerto):
+prompt-set-default = ¿Usarlo como servidor por defecto?
+unknown-auth = Método de autenticación desconocido '{ $method }'
+server-added = ¡Servidor añadido correctamente!
+server-exists = El servidor '{ $alias }' ya existe (usa --force para reemplazarlo)
+no-user = No se indicó --user y $USER no está definido
+invalid-port = Puerto no válido '{ $port }'
+added-server = Servidor añadido:
+added = Añadido
+skipped = Omitido
+server-skipped = { $alias } (ya configurado; --force lo reemplaza)
+imported = Importado:
+servers-imported = { $added } servidor(es) añadido(s), { $replaced } reemplazado(s)
+no-server-tables = No hay tablas [servers.<alias>] en { $file }
+unknown-default-server = default_server '{ $alias }' no está definido
+
+## Project routes and hooks
+
+no-project = No hay { $file } en este directorio ni en los superiores
+no-routes = No hay rutas en { $file }
+routes-in = Rutas en
+unknown-route = No existe la ruta '{ $name }' en { $file } (rutas: { $routes })
+none = ninguna
+route-not-run = { $error }; la ruta '{ $name }' no se ejecutó
+running = Ejecutando
+dry-run = Simulación
+nothing-to-delete = No se borraría nada en { $dest }
+before = Antes:
+on-failure = Si falla:
+warning = Aviso:
+hook-not-run = (no se ejecuta en una simulación)
+hook-not-started = No se pudo ejecutar '{ $command }': { $error }
+hook-failed = '{ $command }' falló ({ $status })
+
+## Mirrors
+
+mirror-root = No se puede replicar en el directorio raíz del servidor
+mirror-windows = La réplica necesita un shell POSIX en el servidor
+heartbeat = Latido:
+heartbeat-published = { $files } archivo(s), { $bytes } replicados en { $dest } a las { $time }
+mirroring = Replicando
+mirroring-every = { $src } -> { $dest } (al menos cada { $interval } s)
+watch-error = Error de vigilancia
+watcher-stopped = La vigilancia de archivos se detuvo inesperadamente
+mirror-running = réplica en marcha
+mirror-not-running = réplica detenida
+sync = Sincr.:
+last-synced = última sincronización { $when }; { $running }
+never-synced = nunca sincronizado; { $running }
+lag = Retraso:
+lag-current = al día
+lag-behind = { $files } archivo(s) ({ $bytes }) y { $deletions } borrado(s) pendientes
+standby = Réplica:
+standby-matches = coincide con el latido
+standby-drifted = { $count } archivo(s) no coinciden con el latido: { $files }
+problem = Problema:
+no-mirror = No se inició aquí ninguna réplica hacia { $dest } (usa `xfer mirror start`)
+no-mirrors = Todavía no se ha iniciado ninguna réplica.
+
+## Queue
+
+invalid-time = '{ $value }' no es una hora (usa HH:MM, "AAAA-MM-DD HH:MM" o p. ej. +2h)
+queued = En cola:
+queued-job = trabajo { $id }: { $transfer }
+queued-job-at = trabajo { $id } para las { $time }: { $transfer }
+daemon-not-running-hint = El daemon no está en marcha; inícialo con `xfer daemon --detach`.
+queue-empty = La cola está vacía
+daemon = Daemon:
+daemon-status = en marcha (pid { $pid }, desde { $since }), { $concurrency } trabajo(s) a la vez, hasta { $attempts } intento(s) cada uno
+daemon-status-stopped = detenido (inícialo con `xfer daemon --detach`)
+log = Registro:
+jobs = Trabajos:
+jobs-summary = { $queued } en cola, { $running } en marcha, { $failed } fallidos, { $done } hechos
+jobs-summary-next = { $queued } en cola (el siguiente a las { $next }), { $running } en marcha, { $failed } fallidos, { $done } hechos
+no-job = No hay ningún trabajo { $id } en la cola
+job-finished = El trabajo { $id } ya está { $state }
+cancelled = Cancelado:
+job = trabajo { $id }
+not-all-cancelled = Algunos trabajos no se cancelaron
+daemon-already-running = El daemon ya está en marcha (pid { $pid })
+started = Iniciado:
+daemon-started = daemon (pid { $pid }), registro en { $log }
+daemon-not-running = El daemon no está en marcha
+stopping = Deteniendo
+daemon-stopping = daemon (pid { $pid }); termina cuando acaben sus trabajos en marcha
+daemon-running = daemon de la cola, { $concurrency } trabajo(s) a la vez, registro en { $log }
@@ -0,0 +1,326 @@
+use crate::archive::{self, Sink};
+use crate::listings;
+use crate::location::Location;
+use crate::platform::Platform;
+use crate::preserve::Preserve;
+use crate::unpack::TarCheck;
+use crate::{
+    crypt, local_size, lock, sessions, shell_quote, verify, Config, ServerConfig, TransferEngine,
+    TransferOptions,
+};
+use colored::*;
+use indicatif::HumanBytes;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How much of a run to watch before judging it.
+const WATCH_FILES: usize = 3;
+const WATCH_TIME: Duration = Duration::from_secs(2);
+/// Below this share of the benchmarked rate the engine is the bottleneck.
+const SLOW_SHARE: f64 = 0.25;
+/// The benchmark moves up to this much, for at most `PROBE_TIME`.
+const PROBE_BYTES: u64 = 8 << 20;
+const PROBE_TIME: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Upload,
+    Download,
+}
+
+/// Watches a run of many sources sent one by one (each scp or rsync paying
+/// its own connection) and tells when it is going far slower than the link
+/// can, so the rest can go as one tar stream instead. See `--adaptive`.
+pub struct Monitor<'a> {
+    direction: Direction,
+    alias: String,
+    server: &'a ServerConfig,
+    dest: String,
+    config: &'a Config,
+    opts: &'a TransferOptions,
+    files: usize,
+    bytes: u64,
+    elapsed: Duration,
+    /// The benchmarked rate, measured the first time it is needed.
+    link: Option<u64>,
+}
+
+impl<'a> Monitor<'a> {
+    /// `None` when the run can't be batched: s3 or Windows on the other end,
+    /// a bandwidth limit (slow on purpose), or options that need a
+    /// file-by-file engine (--delete, --resume, --verify, encryption).
+    pub fn new(
+        expanded: &[String],
+        dest: &str,
+        config: &'a Config,
+        opts: &'a TransferOptions,
+    ) -> Result<Option<Self>, String> {
+        if !opts.adaptive || opts.delete || opts.resume || opts.unpack {
+            return Ok(None);
+        }
+        let dest_location = Location::parse(dest, config)?;
+        let (direction, remote) = match dest_location.remote() {
+            Some((alias, server)) => (Direction::Upload, Some((alias.to_string(), server))),
+            None if dest_location.is_local() => {
+                let first = Location::parse(&expanded[0], config)?;
+                (
+                    Direction::Download,
+                    first
+                        .remote()
+                        .map(|(alias, server)| (alias.to_string(), server)),
+                )
+            }
+            None => (Direction::Download, None),
+        };
+        let (alias, server) = match remote {
+            Some(remote) => remote,
+            None => return Ok(None),
+        };
+        if Platform::of(server)?.is_windows()
+            || opts.verify
+            || server.verify.unwrap_or(false)
+            || (direction == Direction::Upload && crypt::wanted_for_upload(server, opts))
+        {
+            return Ok(None);
+        }
+        let src_location = Location::parse(&expanded[0], config)?;
+        let mut limited = TransferOptions {
+            bwlimit: TransferEngine::bandwidth_limit(opts, &src_location, &dest_location)?,
+            ..opts.clone()
+        };
+        crate::throttle::adjust(&mut limited, config.throttle_for(Some(server)))?;
+        if limited.bwlimit.is_some() {
+            return Ok(None);
+        }
+
+        Ok(Some(Monitor {
+            direction,
+            alias,
+            server,
+            dest: dest_location.path(),
+            config,
+            opts,
+            files: 0,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+            link: None,
+        }))
+    }
+
+    /// Counts a source that was sent to `target` in `elapsed`.
+    pub fn sent(&mut self, src: &str, target: &str, elapsed: Duration) {
+        let size = match self.direction {
+            Direction::Upload => local_size(Path::new(src)),
+            Direction::Download => local_size(&verify::local_target(target, src)),
+        };
+        self.files += 1;
+        self.bytes += size;
+        self.elapsed += elapsed;
+    }
+
+    /// Once enough of the run has been watched, whether it is going at a
+    /// small share of what the link managed in a quick benchmark. The same
+    /// sample is never judged twice.
+    pub fn too_slow(&mut self) -> Option<(u64, u64)> {
+        if self.files < WATCH_FILES || self.elapsed < WATCH_TIME {
+            return None;
+        }
+        let rate = (self.bytes as f64 / self.elapsed.as_secs_f64()) as u64;
+        self.files = 0;
+        self.bytes = 0;
+        self.elapsed = Duration::ZERO;
+        let link = match self.link {
+            Some(link) => link,
+            None => *self.link.insert(self.benchmark().ok()?),
+        };
+        ((rate as f64) < link as f64 * SLOW_SHARE).then_some((rate, link))
+    }
+
+    /// The link's rate in bytes per second: random data pushed into (or
+    /// pulled out of) one ssh session, connection setup included, since
+    /// that is what a single stream would pay too.
+    fn benchmark(&self) -> Result<u64, String> {
+        let started = Instant::now();
+        let moved = match self.direction {
+            Direction::Upload => {
+                let mut ssh = archive::ssh_command(self.server, "cat > /dev/null".to_string())?;
+                let mut child = ssh
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .map_err(|e| format!("Failed to run ssh: {}", e))?;
+                let mut stdin = child.stdin.take().unwrap();
+                let mut block = vec![0u8; 64 << 10];
+                let mut state = 0x2545_f491_4f6c_dd1d_u64;
+                let mut moved = 0;
+                while moved < PROBE_BYTES && started.elapsed() < PROBE_TIME {
+                    // xorshift, so nothing along the way can compress it.
+                    for chunk in block.chunks_mut(8) {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        chunk.copy_from_slice(&state.to_le_bytes());
+                    }
+                    if stdin.write_all(&block).is_err() {
+                        break;
+                    }
+                    moved += block.len() as u64;
+                }
+                drop(stdin);
+                let _ = child.wait();
+                moved
+            }
+            Direction::Download => {
+                let mut ssh = archive::ssh_command(
+                    self.server,
+                    format!("head -c {} /dev/urandom", PROBE_BYTES),
+                )?;
+                let mut child = ssh
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .map_err(|e| format!("Failed to run ssh: {}", e))?;
+                let mut stdout = child.stdout.take().unwrap();
+                let mut block = vec![0u8; 64 << 10];
+                let mut moved = 0;
+                while started.elapsed() < PROBE_TIME {
+                    match stdout.read(&mut block) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => moved += n as u64,
+                    }
+                }
+                let _ = child.kill();
+                let _ = child.wait();
+                moved
+            }
+        };
+        if moved == 0 {
+            return Err("The benchmark moved nothing".to_string());
+        }
+        Ok((moved as f64 / started.elapsed().as_secs_f64()) as u64)
+    }
+
+    /// Splits the rest of the run into what can go in one tar stream (local
+    /// files on an upload, files on the same server on a download) and the
+    /// rest, which carries on one by one.
+    pub fn batchable(&self, rest: Vec<String>) -> (Vec<String>, Vec<String>) {
+        rest.into_iter()
+            .partition(|src| match Location::parse(src, self.config) {
+                Ok(Location::Local(path)) => self.direction == Direction::Upload && path.exists(),
+                Ok(Location::Remote { alias, .. }) => {
+                    self.direction == Direction::Download
+                        && alias == self.alias
+                        && !crypt::wanted_for_download(src, self.opts)
+                }
+                _ => false,
+            })
+    }
+
+    /// Says why the run is switching engines.
+    pub fn announce(&self, rate: u64, link: u64, batch: usize) {
+        let verb = match self.direction {
+            Direction::Upload => "sending",
+            Direction::Download => "fetching",
+        };
+        say!(
+            "{} {}/s is under a quarter of the link's {}/s; {} the remaining {} sources as one tar stream",
+            "Switching:".yellow(),
+            HumanBytes(rate),
+            HumanBytes(link),
+            verb,
+            batch
+        );
+    }
+
+    /// Sends `batch` as one tar stream into the destination directory,
+    /// where each lands as it would have one by one.
+    pub fn send(&self, batch: &[String]) -> Result<(), String> {
+        let preserved = Preserve::resolve(self.opts, Some(self.server))?;
+        let mut members = Vec::new();
+        // Each -C is taken relative to the one before, so local ones are
+        // made absolute.
+        let cwd = std::env::current_dir().unwrap_or_default();
+        for src in batch {
+            let path = match Location::parse(src, self.config)? {
+                Location::Local(path) => cwd.join(path).to_string_lossy().into_owned(),
+                location => location.path(),
+            };
+            let (dir, name) = member(&path);
+            members.push("-C".to_string());
+            members.push(dir);
+            members.push(name);
+        }
+        let dest = shell_quote(&self.dest);
+        let _session = sessions::begin(&batch.join(", "), &self.dest, None);
+
+        match self.direction {
+            Direction::Upload => {
+                let _lock = lock::acquire(self.server, &self.dest, self.opts.wait)?;
+                let mut tar = Command::new("tar");
+                tar.args(preserved.tar_create_args())
+                    .args(["-cf", "-"])
+                    .args(&members);
+                let remote_command = format!(
+                    "mkdir -p {0} && tar {1} -C {0} -xf -",
+                    dest,
+                    preserved.tar_extract_args().join(" ")
+                );
+                let size = batch.iter().map(|src| local_size(Path::new(src))).sum();
+                let result = archive::pipe(
+                    vec![("tar", tar)],
+                    Sink::Commands(vec![(
+                        "ssh",
+                        archive::ssh_command(self.server, remote_command)?,
+                    )]),
+                    Some(size),
+                    None,
+                );
+                listings::forget(self.server, &self.dest);
+                result
+            }
+            Direction::Download => {
+                let remote_command = format!(
+                    "tar {} -cf - {}",
+                    preserved.tar_create_args().join(" "),
+                    members
+                        .iter()
+                        .map(|m| shell_quote(m))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+                std::fs::create_dir_all(&self.dest)
+                    .map_err(|e| format!("Failed to create {}: {}", self.dest, e))?;
+                let mut tar = Command::new("tar");
+                tar.args(preserved.tar_extract_args())
+                    .args(["-C", &self.dest, "-xf", "-"]);
+                archive::pipe(
+                    vec![("ssh", archive::ssh_command(self.server, remote_command)?)],
+                    Sink::Commands(vec![("tar", tar)]),
+                    None,
+                    Some(&mut TarCheck::default()),
+                )
+            }
+        }
+    }
+}
+
+/// The directory to run tar in and the name to give it. A directory with a
+/// trailing slash sends its contents, like rsync.
+fn member(path: &str) -> (String, String) {
+    if path.ends_with('/') && path.len() > 1 {
+        return (path.to_string(), ".".to_string());
+    }
+    let path = Path::new(path);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().into_owned(),
+        _ => ".".to_string(),
+    };
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| ".".to_string());
+    (dir, name)
+}
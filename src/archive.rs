@@ -0,0 +1,489 @@
+use crate::platform::Platform;
+use crate::unpack::{self, TarCheck};
+use crate::zip;
+use crate::{
+    auth, errors, local_size, output, progress, scratch, sessions, shell_quote, tools, verify,
+    Running, ServerConfig, TransferEngine, TransferOptions,
+};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Zstd,
+    Gzip,
+}
+
+impl Compression {
+    pub fn parse(name: &str) -> Result<Compression, String> {
+        match name {
+            "zstd" => Ok(Compression::Zstd),
+            "gzip" => Ok(Compression::Gzip),
+            "deflate" | "store" => Err(format!("{} is for --archive=zip", name)),
+            _ => Err(format!("Unknown compression '{}' (use zstd or gzip)", name)),
+        }
+    }
+
+    pub fn program(self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    pub fn max_level(self) -> u32 {
+        match self {
+            Compression::Zstd => 19,
+            Compression::Gzip => 9,
+        }
+    }
+
+    pub fn compress_args(self, level: Option<u32>) -> Vec<String> {
+        let mut args = vec!["-q".to_string(), "-c".to_string()];
+        if let Some(level) = level {
+            args.push(format!("-{}", level));
+        }
+        args
+    }
+
+    fn compress_command(self, level: Option<u32>) -> String {
+        format!("{} {}", self.program(), self.compress_args(level).join(" "))
+    }
+
+    fn decompress_command(self) -> String {
+        format!("{} -dc", self.program())
+    }
+}
+
+/// What goes over the wire: a tar stream, possibly compressed, and whether
+/// the destination keeps it as an archive file instead of unpacking it.
+struct Mode {
+    compression: Option<Compression>,
+    level: Option<u32>,
+    keep: bool,
+}
+
+/// A destination named like an archive keeps the stream as that file, and
+/// its extension decides the compression.
+fn mode(dest: &str, opts: &TransferOptions) -> Result<Mode, String> {
+    let chosen = opts
+        .compress
+        .as_deref()
+        .map(Compression::parse)
+        .transpose()?;
+    let named = if dest.ends_with(".tar.zst") || dest.ends_with(".tzst") {
+        Some(Some(Compression::Zstd))
+    } else if dest.ends_with(".tar.gz") || dest.ends_with(".tgz") {
+        Some(Some(Compression::Gzip))
+    } else if dest.ends_with(".tar") {
+        Some(None)
+    } else {
+        None
+    };
+
+    let (compression, keep) = match named {
+        Some(named) if chosen.is_some() && chosen != named => {
+            return Err(format!(
+                "--compress {} doesn't match the archive name '{}'",
+                opts.compress.as_deref().unwrap_or_default(),
+                dest
+            ))
+        }
+        Some(named) => (named, true),
+        None => (chosen, false),
+    };
+
+    if let (Some(compression), Some(level)) = (compression, opts.level) {
+        if !(1..=compression.max_level()).contains(&level) {
+            return Err(format!(
+                "{} levels go from 1 to {}",
+                compression.program(),
+                compression.max_level()
+            ));
+        }
+    }
+
+    Ok(Mode {
+        compression,
+        level: opts.level,
+        keep,
+    })
+}
+
+/// Whether this transfer leaves an archive file rather than a directory
+/// tree, which `--verify` can't compare.
+pub fn keeps_archive(dest: &str, opts: &TransferOptions) -> bool {
+    opts.archive && (is_zip(dest, opts) || mode(dest, opts).map(|m| m.keep).unwrap_or(false))
+}
+
+fn is_zip(dest: &str, opts: &TransferOptions) -> bool {
+    match opts.archive_format.as_deref() {
+        Some(format) => format == "zip",
+        None => dest.to_ascii_lowercase().ends_with(".zip"),
+    }
+}
+
+/// Where a zip of `local` goes: `remote` itself when it's named `.zip`,
+/// otherwise `<remote>/<directory name>.zip`.
+fn zip_target(local: &Path, remote: &str) -> String {
+    if remote.to_ascii_lowercase().ends_with(".zip") {
+        return remote.to_string();
+    }
+    let name = local
+        .canonicalize()
+        .ok()
+        .and_then(|path| path.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "archive".to_string());
+    format!("{}/{}.zip", remote.trim_end_matches('/'), name)
+}
+
+pub fn ssh_command(server: &ServerConfig, remote_command: String) -> Result<Command, String> {
+    let mut command = Command::new("ssh");
+    auth::prepare(&mut command, server)?;
+    command
+        .args(TransferEngine::ssh_args(server))
+        .arg(remote_command);
+    Ok(command)
+}
+
+/// Sends a local directory as one tar stream and unpacks it into `remote`,
+/// or stores it there as-is when `remote` is named like an archive.
+pub fn upload(
+    local: &Path,
+    server: &ServerConfig,
+    remote: &str,
+    opts: &TransferOptions,
+) -> Result<(), String> {
+    if is_zip(remote, opts) {
+        return upload_zip(local, server, &zip_target(local, remote), opts);
+    }
+    if Platform::of(server)?.is_windows() {
+        return Err("--archive needs a POSIX shell on the server".to_string());
+    }
+    let mode = mode(remote, opts)?;
+    let size = local_size(local);
+    // Compressed archives are kept smaller than this, so only check the rest.
+    if !mode.keep || mode.compression.is_none() {
+        scratch::ensure_remote_space(server, remote, size)?;
+    }
+
+    let mut tar = Command::new("tar");
+    tar.args(opts.preserved.tar_create_args())
+        .arg("-C")
+        .arg(local)
+        .args(["-cf", "-", "."]);
+    let mut into = Vec::new();
+    if let Some(compression) = mode.compression {
+        let mut compress = Command::new(compression.program());
+        compress.args(compression.compress_args(mode.level));
+        into.push((compression.program(), compress));
+    }
+
+    let quoted = shell_quote(remote);
+    let remote_command = if mode.keep {
+        let part = shell_quote(&format!("{}.xfer-part", remote));
+        format!("cat > {1} && mv {1} {0}", quoted, part)
+    } else {
+        let decompress = mode
+            .compression
+            .map(|c| format!("{} | ", c.decompress_command()))
+            .unwrap_or_default();
+        format!(
+            "mkdir -p {0} && {1}tar {2} -C {0} -xf -",
+            quoted,
+            decompress,
+            opts.preserved.tar_extract_args().join(" ")
+        )
+    };
+    into.push(("ssh", ssh_command(server, remote_command)?));
+
+    pipe(vec![("tar", tar)], Sink::Commands(into), Some(size), None)
+}
+
+/// Sends a local directory as a zip file named `target`. It streams
+/// straight into the file on POSIX servers; Windows ones get it built
+/// locally first and copied with scp.
+fn upload_zip(
+    local: &Path,
+    server: &ServerConfig,
+    target: &str,
+    opts: &TransferOptions,
+) -> Result<(), String> {
+    let method = zip::Method::parse(opts.compress.as_deref(), opts.level)?;
+    // Deflating goes through gzip; better to say so than fail mid-stream.
+    if method != zip::Method::Store && !tools::has("gzip") {
+        return Err(
+            "Deflating a zip archive needs gzip, which is not installed on this machine (or not in PATH); install it or pass --compress store"
+                .to_string(),
+        );
+    }
+    let size = local_size(local);
+    let bar = progress::counter(Some(size));
+    let report = |done| {
+        bar.set_position(done);
+        sessions::set_progress(done);
+    };
+    let failed = |e: io::Error| format!("Failed to write the zip archive: {}", e);
+
+    if Platform::of(server)?.is_windows() {
+        let scratch = scratch::path("archive.zip")?;
+        if method == zip::Method::Store {
+            scratch::ensure_local_space(&scratch.path, size)?;
+        }
+        let file = File::create(&scratch.path)
+            .map_err(|e| format!("Failed to create {}: {}", scratch.path.display(), e))?;
+        let written = zip::write(local, BufWriter::new(file), method, opts.preserved, report);
+        bar.finish_and_clear();
+        written.map_err(failed)?;
+        let remote = format!("{}@{}:{}", server.user, server.host, target);
+        return TransferEngine::run_scp(
+            &scratch.path.to_string_lossy(),
+            &remote,
+            server,
+            opts,
+            None,
+        );
+    }
+
+    let parent = match Path::new(target).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().into_owned(),
+        _ => ".".to_string(),
+    };
+    if method == zip::Method::Store {
+        scratch::ensure_remote_space(server, &parent, size)?;
+    }
+    let part = shell_quote(&format!("{}.xfer-part", target));
+    let remote_command = format!(
+        "mkdir -p {} && cat > {1} && mv {1} {2}",
+        shell_quote(&parent),
+        part,
+        shell_quote(target)
+    );
+    let mut sinks = chain(
+        vec![("ssh", ssh_command(server, remote_command)?)],
+        Stdio::piped(),
+        output::child_stdout(),
+    )?;
+    let written = match sinks.first_mut().and_then(|(_, r)| r.child.stdin.take()) {
+        Some(stdin) => zip::write(local, BufWriter::new(stdin), method, opts.preserved, report),
+        None => Ok(0),
+    };
+    bar.finish_and_clear();
+
+    for (program, running) in sinks {
+        let (status, stderr) = TransferEngine::wait(program, running)?;
+        if !status.success() {
+            return Err(errors::describe(program, status.code(), &stderr));
+        }
+    }
+    written.map(|_| ()).map_err(failed)
+}
+
+/// Fetches a remote directory as one tar stream and unpacks it into
+/// `local`, or saves it as-is when `local` is named like an archive.
+pub fn download(
+    server: &ServerConfig,
+    remote: &str,
+    local: &str,
+    opts: &TransferOptions,
+) -> Result<(), String> {
+    if is_zip(local, opts) {
+        return Err(
+            "Zip archives are made for sends; fetch a remote .zip with get --unpack".to_string(),
+        );
+    }
+    if Platform::of(server)?.is_windows() {
+        return Err("--archive needs a POSIX shell on the server".to_string());
+    }
+    let mode = mode(local, opts)?;
+    if !mode.keep || mode.compression.is_none() {
+        let du = format!("du -sk {} 2>/dev/null || true", shell_quote(remote));
+        let size = TransferEngine::remote_command(server, &du, None)
+            .ok()
+            .and_then(|out| out.split_whitespace().next()?.parse::<u64>().ok());
+        if let Some(kib) = size {
+            scratch::ensure_local_space(Path::new(local), kib * 1024)?;
+        }
+    }
+
+    let compress = mode
+        .compression
+        .map(|c| format!(" | {}", c.compress_command(mode.level)))
+        .unwrap_or_default();
+    let remote_command = format!(
+        "cd {} && tar {} -cf - .{}",
+        shell_quote(remote),
+        opts.preserved.tar_create_args().join(" "),
+        compress
+    );
+    let from = vec![("ssh", ssh_command(server, remote_command)?)];
+
+    if mode.keep {
+        return pipe(from, Sink::File(Path::new(local)), None, None);
+    }
+
+    // Like scp, an existing directory gets the remote one inside it.
+    let target = verify::local_target(local, remote);
+    let local = target.to_string_lossy();
+    let local = local.as_ref();
+    fs::create_dir_all(local).map_err(|e| format!("Failed to create {}: {}", local, e))?;
+    let mut into = Vec::new();
+    if let Some(compression) = mode.compression {
+        let mut decompress = Command::new(compression.program());
+        decompress.arg("-dc");
+        into.push((compression.program(), decompress));
+    }
+    let mut tar = Command::new("tar");
+    tar.args(opts.preserved.tar_extract_args())
+        .args(["-C", local, "-xf", "-"]);
+    into.push(("tar", tar));
+
+    pipe(from, Sink::Commands(into), None, None)
+}
+
+pub enum Sink<'a> {
+    Commands(Vec<(&'static str, Command)>),
+    /// Written to `<path>.xfer-part` and renamed once complete.
+    File(&'a Path),
+}
+
+/// Starts each command with its stdin fed from the previous one's stdout.
+fn chain(
+    commands: Vec<(&'static str, Command)>,
+    first_stdin: Stdio,
+    last_stdout: Stdio,
+) -> Result<Vec<(&'static str, Running)>, String> {
+    let mut running: Vec<(&'static str, Running)> = Vec::new();
+    let count = commands.len();
+    let mut stdin = Some(first_stdin);
+    let mut last_stdout = Some(last_stdout);
+
+    for (i, (program, mut command)) in commands.into_iter().enumerate() {
+        let input = match running.last_mut() {
+            Some((_, previous)) => previous
+                .child
+                .stdout
+                .take()
+                .map(Stdio::from)
+                .unwrap_or_else(Stdio::null),
+            None => stdin.take().unwrap_or_else(Stdio::null),
+        };
+        let output = if i + 1 == count {
+            last_stdout.take().unwrap_or_else(Stdio::piped)
+        } else {
+            Stdio::piped()
+        };
+        command.stdin(input).stdout(output);
+        running.push((program, TransferEngine::spawn_command(program, command)?));
+    }
+    Ok(running)
+}
+
+/// Runs `from` as one pipeline and copies its output into `sink`, counting
+/// the bytes for the progress bar on the way. With `check`, every piece of
+/// the stream passes it before going on, and the stream stops at the first
+/// it refuses.
+pub fn pipe(
+    from: Vec<(&'static str, Command)>,
+    sink: Sink,
+    total: Option<u64>,
+    check: Option<&mut TarCheck>,
+) -> Result<(), String> {
+    let mut sources = chain(from, Stdio::null(), Stdio::piped())?;
+    let mut sinks = Vec::new();
+    let mut part = None;
+
+    let writer: Box<dyn Write> = match sink {
+        Sink::Commands(commands) => {
+            sinks = chain(commands, Stdio::piped(), output::child_stdout())?;
+            match sinks.first_mut().and_then(|(_, r)| r.child.stdin.take()) {
+                Some(stdin) => Box::new(stdin),
+                None => Box::new(io::sink()),
+            }
+        }
+        Sink::File(path) => {
+            let tmp = path.with_file_name(format!(
+                "{}.xfer-part",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            let file = File::create(&tmp)
+                .map_err(|e| format!("Failed to create {}: {}", tmp.display(), e))?;
+            part = Some((tmp, path.to_path_buf()));
+            Box::new(file)
+        }
+    };
+    let reader = sources.last_mut().and_then(|(_, r)| r.child.stdout.take());
+
+    let copied = match reader {
+        Some(reader) => copy(reader, writer, total, check),
+        None => Ok(()),
+    };
+
+    // Wait in stream order. The first tool to exit with an error code has
+    // the real reason; any killed by a signal just had the pipe close under
+    // them.
+    let mut failures = Vec::new();
+    for (program, running) in sources.into_iter().chain(sinks) {
+        let (status, stderr) = TransferEngine::wait(program, running)?;
+        if !status.success() {
+            failures.push((program, status.code(), stderr));
+        }
+    }
+    // A refused entry matters more than the tools' complaints about the
+    // stream being cut short.
+    if let Err(e) = &copied {
+        if e.kind() == io::ErrorKind::InvalidData {
+            return Err(e.to_string());
+        }
+    }
+    let failure = failures
+        .iter()
+        .find(|(_, code, _)| code.is_some())
+        .or(failures.first());
+    if let Some((program, code, stderr)) = failure {
+        if let Some((tmp, _)) = &part {
+            let _ = fs::remove_file(tmp);
+        }
+        return Err(errors::describe(program, *code, stderr));
+    }
+    copied.map_err(|e| format!("Failed to copy the archive stream: {}", e))?;
+
+    if let Some((tmp, path)) = part {
+        fs::rename(&tmp, &path)
+            .map_err(|e| format!("Failed to move {} into place: {}", tmp.display(), e))?;
+    }
+    Ok(())
+}
+
+fn copy(
+    mut reader: impl Read,
+    mut writer: Box<dyn Write>,
+    total: Option<u64>,
+    mut check: Option<&mut TarCheck>,
+) -> io::Result<()> {
+    let bar = progress::counter(total);
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut done = 0;
+    let result = loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break writer.flush(),
+            Ok(n) => n,
+            Err(e) => break Err(e),
+        };
+        if let Some(check) = check.as_deref_mut() {
+            if let Err(reason) = check.feed(&buf[..n]) {
+                break Err(unpack::stopped(reason));
+            }
+        }
+        if let Err(e) = writer.write_all(&buf[..n]) {
+            break Err(e);
+        }
+        done += n as u64;
+        bar.set_position(done);
+        sessions::set_progress(done);
+    };
+    bar.finish_and_clear();
+    result
+}
@@ -0,0 +1,161 @@
+use crate::location::Location;
+use crate::platform::Platform;
+use crate::remote_fs::{self, RemoteStat};
+use crate::{output, Config};
+use colored::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Debug, Serialize)]
+struct Discrepancy<'a> {
+    path: &'a str,
+    field: &'static str,
+    local: &'a str,
+    remote: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct Audit<'a> {
+    compared: usize,
+    only_local: usize,
+    only_remote: usize,
+    discrepancies: Vec<Discrepancy<'a>>,
+}
+
+/// Stats a local tree with the same `find`/`stat` command used on servers,
+/// so both sides report modes and owners the same way.
+fn local_entries(dir: &str) -> Result<Vec<RemoteStat>, String> {
+    let platform = Platform::local();
+    if platform.is_windows() {
+        return Err("audit-perms needs a POSIX system locally".to_string());
+    }
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(remote_fs::find_command(platform, dir, remote_fs::ENTRIES))
+        .output()
+        .map_err(|e| format!("Failed to run find: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("Cannot list {}", dir));
+    }
+    Ok(remote_fs::parse_find(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+fn by_path(entries: Vec<RemoteStat>) -> BTreeMap<String, RemoteStat> {
+    entries
+        .into_iter()
+        .map(|stat| (stat.path.clone(), stat))
+        .collect()
+}
+
+/// Compares type, mode, owner and group of the entries both trees have,
+/// e.g. after migrating a web root whose content matched but whose modes
+/// didn't. Entries on only one side are counted, not compared.
+pub fn audit_perms(
+    local: &str,
+    remote: &str,
+    ignore_owner: bool,
+    config: &Config,
+) -> Result<(), String> {
+    if !Path::new(local).is_dir() {
+        return Err(format!("'{}' is not a directory", local));
+    }
+    let (server, remote_dir) = match Location::parse(remote, config)? {
+        Location::Remote { server, path, .. } => (server, path.to_string()),
+        _ => {
+            return Err(format!(
+                "'{}' is not a remote location (alias:/path)",
+                remote
+            ))
+        }
+    };
+
+    let local_entries = by_path(local_entries(local)?);
+    let remote_entries = by_path(remote_fs::entries(server, &remote_dir)?);
+
+    let mut compared = 0;
+    let mut discrepancies = Vec::new();
+    for (path, ours) in &local_entries {
+        let theirs = match remote_entries.get(path) {
+            Some(theirs) => theirs,
+            None => continue,
+        };
+        compared += 1;
+
+        let mut fields = vec![
+            ("type", &ours.kind, &theirs.kind),
+            ("mode", &ours.mode, &theirs.mode),
+        ];
+        if !ignore_owner {
+            fields.push(("owner", &ours.owner, &theirs.owner));
+            fields.push(("group", &ours.group, &theirs.group));
+        }
+        for (field, local, remote) in fields {
+            if local != remote {
+                discrepancies.push(Discrepancy {
+                    path,
+                    field,
+                    local,
+                    remote,
+                });
+            }
+        }
+    }
+    let only_local = local_entries
+        .keys()
+        .filter(|path| !remote_entries.contains_key(*path))
+        .count();
+    let only_remote = remote_entries
+        .keys()
+        .filter(|path| !local_entries.contains_key(*path))
+        .count();
+
+    let audit = Audit {
+        compared,
+        only_local,
+        only_remote,
+        discrepancies,
+    };
+    if output::is_json() {
+        output::emit(&audit);
+    } else {
+        print(&audit);
+    }
+
+    match audit.discrepancies.len() {
+        0 => Ok(()),
+        n => Err(format!("{} permission discrepancies", n)),
+    }
+}
+
+fn print(audit: &Audit) {
+    for d in &audit.discrepancies {
+        say!(
+            "  {:<5}  {}: {} locally, {} on the server",
+            d.field.yellow(),
+            d.path,
+            d.local.green(),
+            d.remote.red()
+        );
+    }
+    let summary = format!(
+        "Compared {} entries: {} discrepancies",
+        audit.compared,
+        audit.discrepancies.len()
+    );
+    if audit.discrepancies.is_empty() {
+        say!("{}", summary.green());
+    } else {
+        say!("{}", summary.yellow());
+    }
+    if audit.only_local + audit.only_remote > 0 {
+        say!(
+            "  {} only local, {} only on the server (not compared)",
+            audit.only_local,
+            audit.only_remote
+        );
+    }
+}
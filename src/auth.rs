@@ -0,0 +1,242 @@
+use crate::{
+    crypt, history, multiplex, prompt, queue, redact, tools, unix_now, Config, ServerConfig,
+};
+use colored::*;
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+
+/// How xfer authenticates to a server. Without an `auth` field, ssh's own
+/// defaults apply (plus `key_path` when set).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Auth {
+    Key,
+    Agent,
+    Password,
+    /// Keyboard-interactive prompts such as one-time codes, after the key
+    /// when the server wants both. Signed in once per run; see `multiplex`.
+    Interactive,
+}
+
+const SERVICE: &str = "xfer";
+
+/// Servers whose certificate this run has found valid.
+static CERTS_CHECKED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Set on ssh when xfer runs itself as `SSH_ASKPASS`; names the keychain
+/// entry to answer from.
+pub const ASKPASS_ENV: &str = "XFER_ASKPASS";
+
+fn account(server: &ServerConfig) -> String {
+    format!(
+        "{}@{}:{}",
+        server.user,
+        server.host,
+        server.port.unwrap_or(22)
+    )
+}
+
+impl Auth {
+    /// Extra ssh options that pin the authentication method.
+    pub fn ssh_options(self) -> &'static [&'static str] {
+        match self {
+            Auth::Key => &["-o", "IdentitiesOnly=yes"],
+            Auth::Agent => &["-o", "PreferredAuthentications=publickey"],
+            Auth::Password => &[
+                "-o",
+                "PreferredAuthentications=keyboard-interactive,password",
+                "-o",
+                "PubkeyAuthentication=no",
+            ],
+            Auth::Interactive => &["-o", "KbdInteractiveAuthentication=yes"],
+        }
+    }
+}
+
+/// Prepares an ssh, scp or rsync command for the server's auth method. For
+/// password auth the password comes from the OS keychain (asked for and
+/// stored on first use) and is handed to ssh by running xfer as its askpass
+/// program, so it never touches disk or the command line. xfer also stands
+/// in as askpass when prompts go somewhere other than the terminal, and
+/// passes ssh's own questions (key passphrases, 2FA codes) on to it.
+pub fn prepare(command: &mut Command, server: &ServerConfig) -> Result<(), String> {
+    if server.cert_path.is_some() && server.auth != Some(Auth::Password) {
+        let mut checked = CERTS_CHECKED.lock().unwrap_or_else(|e| e.into_inner());
+        if !checked.contains(&account(server)) {
+            check_cert(server)?;
+            checked.push(account(server));
+        }
+    }
+    let account = match server.auth {
+        Some(Auth::Agent) if env::var_os("SSH_AUTH_SOCK").is_none() => {
+            return Err(format!(
+                "{} uses agent auth but no ssh-agent is running (SSH_AUTH_SOCK is unset)",
+                server.host
+            ))
+        }
+        Some(Auth::Password) => stored_password(server)?,
+        Some(Auth::Interactive) => {
+            multiplex::open(server)?;
+            None
+        }
+        _ => None,
+    };
+    if account.is_none() && !prompt::takes_over_ssh() {
+        return Ok(());
+    }
+    use_askpass(command, account)
+}
+
+/// Has ssh run xfer as its askpass program, answering from the keychain
+/// `account` when there is one and from the prompt backend otherwise.
+pub fn use_askpass(command: &mut Command, account: Option<String>) -> Result<(), String> {
+    let exe = env::current_exe().map_err(|e| format!("Failed to locate the xfer binary: {}", e))?;
+    command
+        .env("SSH_ASKPASS", exe)
+        .env("SSH_ASKPASS_REQUIRE", "force")
+        .env(ASKPASS_ENV, account.unwrap_or_default());
+    prompt::pass_on(command);
+    Ok(())
+}
+
+/// When the OpenSSH certificate at `path` stops being valid, read from
+/// `ssh-keygen -L` (which prints local time); `None` when it never does.
+fn cert_expiry(path: &Path) -> Result<Option<u64>, String> {
+    let output = Command::new("ssh-keygen")
+        .arg("-L")
+        .arg("-f")
+        .arg(path)
+        .output()
+        .map_err(|e| format!("Failed to run ssh-keygen: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("{} is not an OpenSSH certificate", path.display()));
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let valid = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Valid:"))
+        .map(str::trim)
+        .unwrap_or("forever");
+    // "from A to B", "before B", "after A" or "forever".
+    let until = match valid.rsplit_once(" to ") {
+        Some((_, until)) => until,
+        None => match valid.strip_prefix("before ") {
+            Some(until) => until,
+            None => return Ok(None),
+        },
+    };
+    let unreadable = || format!("Can't read the validity of {}: {}", path.display(), valid);
+    let (date, clock) = until.split_once('T').ok_or_else(unreadable)?;
+    let clock: Vec<u64> = clock
+        .split(':')
+        .map(|part| part.parse().map_err(|_| unreadable()))
+        .collect::<Result<_, _>>()?;
+    let time = match clock[..] {
+        [hours, minutes, seconds] => hours * 3600 + minutes * 60 + seconds,
+        _ => return Err(unreadable()),
+    };
+    let local = (history::parse_date(date)? + time) as i64;
+    Ok(Some((local - queue::local_offset()).max(0) as u64))
+}
+
+/// Checks the server's `cert_path` is there and still valid, so an expired
+/// short-lived certificate is named as the problem rather than showing up
+/// as a refused login. Describes how long it lasts.
+pub fn check_cert(server: &ServerConfig) -> Result<String, String> {
+    let Some(cert) = &server.cert_path else {
+        return Ok("no certificate".to_string());
+    };
+    let path = crypt::expand_tilde(cert);
+    if !path.is_file() {
+        return Err(format!("The certificate {} does not exist", cert));
+    }
+    let expiry = match cert_expiry(&path) {
+        Ok(expiry) => expiry,
+        // Without ssh-keygen, ssh gets to judge it.
+        Err(_) if !tools::has("ssh-keygen") => return Ok(cert.clone()),
+        Err(e) => return Err(e),
+    };
+    let local = |secs: u64| {
+        let local = (secs as i64 + queue::local_offset()).max(0) as u64;
+        history::format_timestamp(local)[..16].to_string()
+    };
+    match expiry {
+        Some(expiry) if expiry <= unix_now() => Err(format!(
+            "The certificate {} expired at {}; get a new one from your CA",
+            cert,
+            local(expiry)
+        )),
+        Some(expiry) => Ok(format!("{} valid until {}", cert, local(expiry))),
+        None => Ok(format!("{} valid forever", cert)),
+    }
+}
+
+/// The keychain account holding the server's password, asking for the
+/// password and storing it first if there is none. `None` without a
+/// keychain, which leaves the asking to ssh.
+fn stored_password(server: &ServerConfig) -> Result<Option<String>, String> {
+    let entry = match Entry::new(SERVICE, &account(server)) {
+        Ok(entry) => entry,
+        Err(_) => return Ok(None),
+    };
+    match entry.get_password() {
+        Ok(password) => redact::register(&password),
+        Err(keyring::Error::NoEntry) => store_password(&entry, server)?,
+        Err(_) => return Ok(None),
+    }
+    Ok(Some(account(server)))
+}
+
+fn store_password(entry: &Entry, server: &ServerConfig) -> Result<(), String> {
+    let password = prompt::secret(&format!("Password for {}@{}: ", server.user, server.host))?;
+    entry
+        .set_password(&password)
+        .map_err(|e| format!("Failed to store the password in the keychain: {}", e))
+}
+
+/// Entry point when ssh runs xfer as its askpass program. Prints the stored
+/// password for the account named in `XFER_ASKPASS` (empty when there is
+/// none), or else what the prompt backend answers, and returns the exit
+/// code. Host key confirmations are refused rather than answered.
+pub fn askpass(account: &str, question: &str) -> i32 {
+    if question.contains("(yes/no") {
+        eprintln!(
+            "xfer: unknown host key; connect once with plain ssh to confirm it ({})",
+            account
+        );
+        return 1;
+    }
+
+    if !account.is_empty() {
+        match Entry::new(SERVICE, account).and_then(|entry| entry.get_password()) {
+            Ok(password) => {
+                println!("{}", password);
+                return 0;
+            }
+            Err(e) => eprintln!("xfer: no stored password for {}: {}", account, e),
+        }
+    }
+    match prompt::secret(question) {
+        Ok(answer) => {
+            println!("{}", answer);
+            0
+        }
+        Err(e) => {
+            eprintln!("xfer: {}", e);
+            1
+        }
+    }
+}
+
+/// Replaces the stored password for a server, e.g. after it was rotated.
+pub fn set_password(alias: &str, config: &Config) -> Result<(), String> {
+    let server = config.require_server(alias)?;
+    let entry = Entry::new(SERVICE, &account(server)).map_err(|e| e.to_string())?;
+    store_password(&entry, server)?;
+    say!("{} password for {}", "Stored".green(), alias);
+    Ok(())
+}
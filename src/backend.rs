@@ -0,0 +1,82 @@
+use crate::listings;
+use crate::location::Location;
+use crate::platform::Platform;
+use crate::s3::{self, S3Backend};
+use crate::{Config, ServerConfig, TransferEngine, TransferOptions};
+use std::path::Path;
+
+/// A kind of remote storage. The engine works out which side of a transfer
+/// is remote; the backend moves the bytes.
+pub trait Backend {
+    fn upload(&self, local: &str, remote: &str, opts: &TransferOptions) -> Result<(), String>;
+    fn download(&self, remote: &str, local: &str, opts: &TransferOptions) -> Result<(), String>;
+    fn list(&self, remote: &str) -> Result<(), String>;
+    /// What uploading with `--delete` would remove from `remote`, without
+    /// changing anything.
+    fn upload_deletions(&self, local: &str, remote: &str) -> Result<Vec<String>, String>;
+    /// What downloading with `--delete` would remove from `local`.
+    fn download_deletions(&self, remote: &str, local: &str) -> Result<Vec<String>, String>;
+}
+
+/// Servers reached over ssh, using rsync/scp for transfers.
+pub struct Ssh<'a> {
+    server: &'a ServerConfig,
+}
+
+impl Backend for Ssh<'_> {
+    fn upload(&self, local: &str, remote: &str, opts: &TransferOptions) -> Result<(), String> {
+        let result =
+            TransferEngine::transfer_to_remote(local.to_string(), self.server, remote, opts);
+        // Even a failed upload may have left something behind.
+        listings::forget(self.server, remote);
+        result
+    }
+
+    fn download(&self, remote: &str, local: &str, opts: &TransferOptions) -> Result<(), String> {
+        TransferEngine::transfer_from_remote(self.server, remote, local.to_string(), opts)
+    }
+
+    fn list(&self, remote: &str) -> Result<(), String> {
+        let mut args = TransferEngine::ssh_args(self.server);
+        args.push(Platform::of(self.server)?.list_command(remote));
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        TransferEngine::run_command("ssh", &args, Some(self.server))
+    }
+
+    fn upload_deletions(&self, local: &str, remote: &str) -> Result<Vec<String>, String> {
+        if !Path::new(local).is_dir() {
+            return Ok(Vec::new());
+        }
+        TransferEngine::rsync_deletions(
+            &format!("{}/", local),
+            &format!("{}@{}:{}", self.server.user, self.server.host, remote),
+            Some(self.server),
+        )
+    }
+
+    fn download_deletions(&self, remote: &str, local: &str) -> Result<Vec<String>, String> {
+        TransferEngine::rsync_deletions(
+            &format!("{}@{}:{}", self.server.user, self.server.host, remote),
+            local,
+            Some(self.server),
+        )
+    }
+}
+
+/// The backend for a location, or `None` for local paths and unknown
+/// schemes.
+pub fn for_location<'a>(
+    location: &Location<'a>,
+    config: &'a Config,
+) -> Option<Box<dyn Backend + 'a>> {
+    match location {
+        Location::Url {
+            scheme: s3::SCHEME,
+            bucket,
+            ..
+        } => Some(Box::new(S3Backend::new(bucket, config))),
+        Location::Remote { server, .. } => Some(Box::new(Ssh { server })),
+        Location::Local(_) | Location::Url { .. } => None,
+    }
+}
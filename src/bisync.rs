@@ -0,0 +1,421 @@
+use crate::location::Location;
+use crate::platform::Platform;
+use crate::preserve::Preserve;
+use crate::{
+    data_dir, deadline, lock, output, remote_fs, scratch, shell_quote, throttle, Config,
+    ServerConfig, TransferEngine, TransferOptions,
+};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Size and mtime of one side of a file; a change in either counts as an
+/// edit.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stamp {
+    pub size: u64,
+    pub mtime: u64,
+}
+
+/// How a file looked on both sides when the pair was last in sync.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Synced {
+    local: Stamp,
+    remote: Stamp,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    local: String,
+    remote: String,
+    files: BTreeMap<String, Synced>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Prefer {
+    Local,
+    Remote,
+}
+
+impl Prefer {
+    pub fn parse(side: &str) -> Result<Prefer, String> {
+        match side {
+            "local" => Ok(Prefer::Local),
+            "remote" => Ok(Prefer::Remote),
+            _ => Err(format!("Unknown side '{}' (use local or remote)", side)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    InSync,
+    Upload,
+    Download,
+    DeleteLocal,
+    DeleteRemote,
+    /// Gone from both sides; only the state entry is left.
+    Forget,
+    Conflict(&'static str),
+}
+
+/// Decides what to do with one path from how it looks on each side now and
+/// at the last sync. Without a last sync, the newer side wins.
+fn decide(local: Option<Stamp>, remote: Option<Stamp>, last: Option<&Synced>) -> Action {
+    let last = match last {
+        Some(last) => last,
+        None => {
+            return match (local, remote) {
+                (Some(_), None) => Action::Upload,
+                (None, Some(_)) => Action::Download,
+                (None, None) => Action::Forget,
+                (Some(l), Some(r)) if l == r => Action::InSync,
+                (Some(l), Some(r)) if l.mtime > r.mtime => Action::Upload,
+                (Some(l), Some(r)) if l.mtime < r.mtime => Action::Download,
+                (Some(_), Some(_)) => Action::Conflict("differs with the same mtime"),
+            }
+        }
+    };
+
+    let local_changed = local != Some(last.local);
+    let remote_changed = remote != Some(last.remote);
+    match (local_changed, remote_changed) {
+        (false, false) => Action::InSync,
+        (true, false) if local.is_some() => Action::Upload,
+        (true, false) => Action::DeleteRemote,
+        (false, true) if remote.is_some() => Action::Download,
+        (false, true) => Action::DeleteLocal,
+        (true, true) => match (local, remote) {
+            (None, None) => Action::Forget,
+            (Some(l), Some(r)) if l == r => Action::InSync,
+            (Some(_), Some(_)) => Action::Conflict("changed on both sides"),
+            (Some(_), None) => Action::Conflict("changed locally, deleted remotely"),
+            (None, Some(_)) => Action::Conflict("deleted locally, changed remotely"),
+        },
+    }
+}
+
+/// Settles a conflict in favour of one side, including its deletions.
+fn resolve(local: Option<Stamp>, remote: Option<Stamp>, prefer: Prefer) -> Action {
+    match prefer {
+        Prefer::Local if local.is_some() => Action::Upload,
+        Prefer::Local => Action::DeleteRemote,
+        Prefer::Remote if remote.is_some() => Action::Download,
+        Prefer::Remote => Action::DeleteLocal,
+    }
+}
+
+fn state_path(local: &Path, server: &ServerConfig, remote: &str) -> PathBuf {
+    let key = format!(
+        "{}|{}@{}:{}:{}",
+        local.display(),
+        server.user,
+        server.host,
+        server.port.unwrap_or(22),
+        remote
+    );
+    let digest = format!("{:x}", Sha256::digest(key.as_bytes()));
+    data_dir()
+        .join("sync-state")
+        .join(format!("{}.json", &digest[..16]))
+}
+
+fn load(path: &Path) -> State {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(path: &Path, state: &State) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let content = serde_json::to_string_pretty(state).map_err(|e| e.to_string())?;
+    fs::write(path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+pub fn local_files(root: &Path) -> Result<BTreeMap<String, Stamp>, String> {
+    fn walk(root: &Path, dir: &Path, files: &mut BTreeMap<String, Stamp>) -> Result<(), String> {
+        let entries =
+            fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let metadata = entry
+                .metadata()
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            if metadata.is_dir() {
+                walk(root, &path, files)?;
+            } else if metadata.is_file() {
+                let mtime = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_secs());
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                files.insert(
+                    relative.to_string_lossy().into_owned(),
+                    Stamp {
+                        size: metadata.len(),
+                        mtime,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = BTreeMap::new();
+    walk(root, root, &mut files)?;
+    Ok(files)
+}
+
+fn remote_files(server: &ServerConfig, dir: &str) -> Result<BTreeMap<String, Stamp>, String> {
+    Ok(remote_fs::tree(server, dir)?
+        .into_iter()
+        .map(|stat| {
+            (
+                stat.path,
+                Stamp {
+                    size: stat.size,
+                    mtime: stat.mtime,
+                },
+            )
+        })
+        .collect())
+}
+
+/// Copies the listed paths with rsync, which keeps mtimes so both sides
+/// stamp the same afterwards.
+fn transfer(
+    paths: &[&String],
+    src: &str,
+    dest: &str,
+    server: &ServerConfig,
+    opts: &TransferOptions,
+) -> Result<(), String> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let content: Vec<&str> = paths.iter().map(|p| p.as_str()).collect();
+    let list = scratch::file("bisync-files.txt", content.join("\n").as_bytes())?;
+    let opts = TransferOptions {
+        files_from: Some(list.path.to_string_lossy().into_owned()),
+        delete: false,
+        resume: false,
+        ..opts.clone()
+    };
+    TransferEngine::run_rsync(src, dest, server, &opts)
+}
+
+#[derive(Debug, Serialize)]
+struct Conflict {
+    path: String,
+    reason: &'static str,
+}
+
+/// What a two-way sync did, for `--json`.
+#[derive(Debug, Default, Serialize)]
+struct Summary {
+    uploaded: Vec<String>,
+    downloaded: Vec<String>,
+    deleted_local: Vec<String>,
+    deleted_remote: Vec<String>,
+    conflicts: Vec<Conflict>,
+}
+
+/// Two-way sync between a local directory and a remote one. Files changed
+/// on one side since the last sync are copied to the other and deletions
+/// are carried over; files changed on both sides are reported as conflicts
+/// and left alone unless `prefer` picks a side.
+pub fn sync(
+    local: &str,
+    remote: &str,
+    config: &Config,
+    opts: &TransferOptions,
+    prefer: Option<Prefer>,
+) -> Result<(), String> {
+    let local_dir = Path::new(local);
+    if !local_dir.is_dir() {
+        return Err(format!("'{}' is not a directory", local));
+    }
+    deadline::start(opts.max_duration.as_deref())?;
+    let remote_location = Location::parse(remote, config)?;
+    let (server, remote_dir) = match &remote_location {
+        Location::Remote { server, path, .. } => (*server, path.to_string()),
+        _ => {
+            return Err(
+                "--bidirectional needs a local directory and a remote one (alias:/path)"
+                    .to_string(),
+            )
+        }
+    };
+    if Platform::of(server)?.is_windows() {
+        return Err("--bidirectional needs a POSIX shell on the server".to_string());
+    }
+    let remote_dir = remote_dir.trim_end_matches('/').to_string();
+
+    let _lock = lock::acquire(server, &remote_dir, opts.wait)?;
+    let mut opts = TransferOptions {
+        bwlimit: TransferEngine::bandwidth_limit(
+            opts,
+            &Location::Local(local_dir.to_path_buf()),
+            &remote_location,
+        )?,
+        preserved: Preserve::resolve(opts, Some(server))?,
+        ..opts.clone()
+    };
+    throttle::adjust(&mut opts, config.throttle_for(Some(server)))?;
+    let opts = &opts;
+
+    let local_root = local_dir
+        .canonicalize()
+        .map_err(|e| format!("Failed to read {}: {}", local, e))?;
+    let path = state_path(&local_root, server, &remote_dir);
+    let mut state = load(&path);
+
+    TransferEngine::remote_command(
+        server,
+        &format!("mkdir -p {}", shell_quote(&remote_dir)),
+        None,
+    )?;
+    let local_now = local_files(&local_root)?;
+    let remote_now = remote_files(server, &remote_dir)?;
+
+    let mut summary = Summary::default();
+    let paths: BTreeSet<&String> = local_now
+        .keys()
+        .chain(remote_now.keys())
+        .chain(state.files.keys())
+        .collect();
+    for path in paths {
+        let l = local_now.get(path).copied();
+        let r = remote_now.get(path).copied();
+        let action = match (decide(l, r, state.files.get(path)), prefer) {
+            (Action::Conflict(_), Some(prefer)) => resolve(l, r, prefer),
+            (action, _) => action,
+        };
+        match action {
+            Action::InSync | Action::Forget => {}
+            Action::Upload => summary.uploaded.push(path.clone()),
+            Action::Download => summary.downloaded.push(path.clone()),
+            Action::DeleteLocal => summary.deleted_local.push(path.clone()),
+            Action::DeleteRemote => summary.deleted_remote.push(path.clone()),
+            Action::Conflict(reason) => summary.conflicts.push(Conflict {
+                path: path.clone(),
+                reason,
+            }),
+        }
+    }
+
+    let remote_location = format!("{}@{}:{}/", server.user, server.host, remote_dir);
+    let local_location = format!("{}/", local_root.display());
+    let uploads: Vec<&String> = summary.uploaded.iter().collect();
+    let downloads: Vec<&String> = summary.downloaded.iter().collect();
+    transfer(&uploads, &local_location, &remote_location, server, opts)?;
+    transfer(&downloads, &remote_location, &local_location, server, opts)?;
+
+    for path in &summary.deleted_local {
+        let file = local_root.join(path);
+        fs::remove_file(&file)
+            .map_err(|e| format!("Failed to delete {}: {}", file.display(), e))?;
+    }
+    if !summary.deleted_remote.is_empty() {
+        let quoted: Vec<String> = summary
+            .deleted_remote
+            .iter()
+            .map(|p| shell_quote(p))
+            .collect();
+        TransferEngine::remote_command(
+            server,
+            &format!(
+                "cd {} && rm -f -- {}",
+                shell_quote(&remote_dir),
+                quoted.join(" ")
+            ),
+            None,
+        )?;
+    }
+
+    // Record both sides as they are now. Conflicted paths keep their old
+    // entry so they stay conflicts until one side wins.
+    let local_now = local_files(&local_root)?;
+    let remote_now = remote_files(server, &remote_dir)?;
+    let conflicted: BTreeSet<&String> = summary.conflicts.iter().map(|c| &c.path).collect();
+    let mut files = BTreeMap::new();
+    for (path, local) in &local_now {
+        if conflicted.contains(path) {
+            continue;
+        }
+        if let Some(remote) = remote_now.get(path) {
+            files.insert(
+                path.clone(),
+                Synced {
+                    local: *local,
+                    remote: *remote,
+                },
+            );
+        }
+    }
+    for path in conflicted {
+        if let Some(last) = state.files.get(path) {
+            files.insert(path.clone(), *last);
+        }
+    }
+    state = State {
+        local: local_root.display().to_string(),
+        remote: remote.to_string(),
+        files,
+    };
+    save(&path, &state)?;
+
+    print_summary(&summary);
+    if summary.conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} conflict(s) left alone; rerun with --prefer local or --prefer remote to settle them",
+            summary.conflicts.len()
+        ))
+    }
+}
+
+fn print_summary(summary: &Summary) {
+    if output::is_json() {
+        output::emit(summary);
+        return;
+    }
+    for path in &summary.uploaded {
+        say!("  {} {}", "↑".green(), path);
+    }
+    for path in &summary.downloaded {
+        say!("  {} {}", "↓".green(), path);
+    }
+    for path in &summary.deleted_local {
+        say!("  {} {} (deleted locally)", "✗".yellow(), path);
+    }
+    for path in &summary.deleted_remote {
+        say!("  {} {} (deleted remotely)", "✗".yellow(), path);
+    }
+    for conflict in &summary.conflicts {
+        say!(
+            "  {} {}: {}",
+            "!".red().bold(),
+            conflict.path,
+            conflict.reason
+        );
+    }
+    say!(
+        "{} {} up, {} down, {} deleted, {} conflict(s)",
+        "Synced:".green(),
+        summary.uploaded.len(),
+        summary.downloaded.len(),
+        summary.deleted_local.len() + summary.deleted_remote.len(),
+        summary.conflicts.len()
+    );
+}
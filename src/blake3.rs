@@ -0,0 +1,395 @@
+const OUT_LEN: usize = 32;
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+
+const CHUNK_START: u32 = 1 << 0;
+const CHUNK_END: u32 = 1 << 1;
+const PARENT: u32 = 1 << 2;
+const ROOT: u32 = 1 << 3;
+
+const IV: [u32; 8] = [
+    0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
+];
+
+const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+    state[d] = (state[d] ^ state[a]).rotate_right(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(12);
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+    state[d] = (state[d] ^ state[a]).rotate_right(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(7);
+}
+
+fn round(state: &mut [u32; 16], m: &[u32; 16]) {
+    // Columns, then diagonals.
+    g(state, 0, 4, 8, 12, m[0], m[1]);
+    g(state, 1, 5, 9, 13, m[2], m[3]);
+    g(state, 2, 6, 10, 14, m[4], m[5]);
+    g(state, 3, 7, 11, 15, m[6], m[7]);
+    g(state, 0, 5, 10, 15, m[8], m[9]);
+    g(state, 1, 6, 11, 12, m[10], m[11]);
+    g(state, 2, 7, 8, 13, m[12], m[13]);
+    g(state, 3, 4, 9, 14, m[14], m[15]);
+}
+
+fn compress(
+    cv: &[u32; 8],
+    block: &[u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+) -> [u32; 16] {
+    let mut state = [
+        cv[0],
+        cv[1],
+        cv[2],
+        cv[3],
+        cv[4],
+        cv[5],
+        cv[6],
+        cv[7],
+        IV[0],
+        IV[1],
+        IV[2],
+        IV[3],
+        counter as u32,
+        (counter >> 32) as u32,
+        block_len,
+        flags,
+    ];
+    let mut block = *block;
+    for i in 0..7 {
+        round(&mut state, &block);
+        if i < 6 {
+            block = MSG_PERMUTATION.map(|j| block[j]);
+        }
+    }
+    for i in 0..8 {
+        state[i] ^= state[i + 8];
+        state[i + 8] ^= cv[i];
+    }
+    state
+}
+
+fn words(bytes: &[u8; BLOCK_LEN]) -> [u32; 16] {
+    let mut words = [0; 16];
+    for (word, le) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes([le[0], le[1], le[2], le[3]]);
+    }
+    words
+}
+
+fn first_8(words: [u32; 16]) -> [u32; 8] {
+    let mut cv = [0; 8];
+    cv.copy_from_slice(&words[..8]);
+    cv
+}
+
+/// A node that hasn't been compressed yet, because whether it is the root
+/// is only known at the end.
+struct Output {
+    cv: [u32; 8],
+    block: [u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+}
+
+impl Output {
+    fn chaining_value(&self) -> [u32; 8] {
+        first_8(compress(
+            &self.cv,
+            &self.block,
+            self.counter,
+            self.block_len,
+            self.flags,
+        ))
+    }
+
+    fn root_bytes(&self) -> [u8; OUT_LEN] {
+        let words = compress(&self.cv, &self.block, 0, self.block_len, self.flags | ROOT);
+        let mut out = [0; OUT_LEN];
+        for (bytes, word) in out.chunks_exact_mut(4).zip(words) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+}
+
+fn parent(left: [u32; 8], right: [u32; 8]) -> Output {
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(&left);
+    block[8..].copy_from_slice(&right);
+    Output {
+        cv: IV,
+        block,
+        counter: 0,
+        block_len: BLOCK_LEN as u32,
+        flags: PARENT,
+    }
+}
+
+struct ChunkState {
+    cv: [u32; 8],
+    counter: u64,
+    block: [u8; BLOCK_LEN],
+    block_len: usize,
+    blocks_compressed: usize,
+}
+
+impl ChunkState {
+    fn new(counter: u64) -> Self {
+        ChunkState {
+            cv: IV,
+            counter,
+            block: [0; BLOCK_LEN],
+            block_len: 0,
+            blocks_compressed: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        BLOCK_LEN * self.blocks_compressed + self.block_len
+    }
+
+    fn start_flag(&self) -> u32 {
+        if self.blocks_compressed == 0 {
+            CHUNK_START
+        } else {
+            0
+        }
+    }
+
+    fn update(&mut self, mut input: &[u8]) {
+        while !input.is_empty() {
+            // The last block is held back: it gets CHUNK_END.
+            if self.block_len == BLOCK_LEN {
+                self.cv = first_8(compress(
+                    &self.cv,
+                    &words(&self.block),
+                    self.counter,
+                    BLOCK_LEN as u32,
+                    self.start_flag(),
+                ));
+                self.blocks_compressed += 1;
+                self.block = [0; BLOCK_LEN];
+                self.block_len = 0;
+            }
+            let take = (BLOCK_LEN - self.block_len).min(input.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&input[..take]);
+            self.block_len += take;
+            input = &input[take..];
+        }
+    }
+
+    fn output(&self) -> Output {
+        Output {
+            cv: self.cv,
+            block: words(&self.block),
+            counter: self.counter,
+            block_len: self.block_len as u32,
+            flags: self.start_flag() | CHUNK_END,
+        }
+    }
+}
+
+/// An incremental BLAKE3 hash (32-byte output, no key), after the
+/// reference implementation in the BLAKE3 paper. It gives the same digests
+/// as `b3sum`, which is what the servers run.
+pub struct Hasher {
+    chunk: ChunkState,
+    /// Chaining values of finished subtrees, one per set bit of the number
+    /// of chunks so far.
+    stack: Vec<[u32; 8]>,
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Hasher {
+            chunk: ChunkState::new(0),
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl Hasher {
+    pub fn update(&mut self, mut input: &[u8]) {
+        while !input.is_empty() {
+            // A full chunk is only finished once more input arrives, since
+            // the last one has to stay open for the root flag.
+            if self.chunk.len() == CHUNK_LEN {
+                let mut cv = self.chunk.output().chaining_value();
+                let mut total = self.chunk.counter + 1;
+                while total & 1 == 0 {
+                    cv = parent(self.stack.pop().unwrap(), cv).chaining_value();
+                    total >>= 1;
+                }
+                self.stack.push(cv);
+                self.chunk = ChunkState::new(self.chunk.counter + 1);
+            }
+            let take = (CHUNK_LEN - self.chunk.len()).min(input.len());
+            self.chunk.update(&input[..take]);
+            input = &input[take..];
+        }
+    }
+
+    /// The hash as lowercase hex, like `b3sum` prints it.
+    pub fn finalize_hex(&self) -> String {
+        let mut output = self.chunk.output();
+        for left in self.stack.iter().rev() {
+            output = parent(*left, output.chaining_value());
+        }
+        output
+            .root_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// From the official `test_vectors.json`: the input is the bytes
+    /// 0, 1, ..., 250, 0, 1, ... cut to the given length.
+    const VECTORS: [(usize, &str); 22] = [
+        (
+            0,
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+        ),
+        (
+            1,
+            "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213",
+        ),
+        (
+            1023,
+            "10108970eeda3eb932baac1428c7a2163b0e924c9a9e25b35bba72b28f70bd11",
+        ),
+        (
+            1024,
+            "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+        ),
+        (
+            1025,
+            "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+        ),
+        (
+            2048,
+            "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a",
+        ),
+        (
+            2049,
+            "5f4d72f40d7a5f82b15ca2b2e44b1de3c2ef86c426c95c1af0b6879522563030",
+        ),
+        (
+            3072,
+            "b98cb0ff3623be03326b373de6b9095218513e64f1ee2edd2525c7ad1e5cffd2",
+        ),
+        (
+            3073,
+            "7124b49501012f81cc7f11ca069ec9226cecb8a2c850cfe644e327d22d3e1cd3",
+        ),
+        (
+            4096,
+            "015094013f57a5277b59d8475c0501042c0b642e531b0a1c8f58d2163229e969",
+        ),
+        (
+            4097,
+            "9b4052b38f1c5fc8b1f9ff7ac7b27cd242487b3d890d15c96a1c25b8aa0fb995",
+        ),
+        (
+            5120,
+            "9cadc15fed8b5d854562b26a9536d9707cadeda9b143978f319ab34230535833",
+        ),
+        (
+            5121,
+            "628bd2cb2004694adaab7bbd778a25df25c47b9d4155a55f8fbd79f2fe154cff",
+        ),
+        (
+            6144,
+            "3e2e5b74e048f3add6d21faab3f83aa44d3b2278afb83b80b3c35164ebeca205",
+        ),
+        (
+            6145,
+            "f1323a8631446cc50536a9f705ee5cb619424d46887f3c376c695b70e0f0507f",
+        ),
+        (
+            7168,
+            "61da957ec2499a95d6b8023e2b0e604ec7f6b50e80a9678b89d2628e99ada77a",
+        ),
+        (
+            7169,
+            "a003fc7a51754a9b3c7fae0367ab3d782dccf28855a03d435f8cfe74605e7817",
+        ),
+        (
+            8192,
+            "aae792484c8efe4f19e2ca7d371d8c467ffb10748d8a5a1ae579948f718a2a63",
+        ),
+        (
+            8193,
+            "bab6c09cb8ce8cf459261398d2e7aef35700bf488116ceb94a36d0f5f1b7bc3b",
+        ),
+        (
+            16384,
+            "f875d6646de28985646f34ee13be9a576fd515f76b5b0a26bb324735041ddde4",
+        ),
+        (
+            31744,
+            "62b6960e1a44bcc1eb1a611a8d6235b6b4b78f32e7abc4fb4c6cdcce94895c47",
+        ),
+        (
+            102400,
+            "bc3e3d41a1146b069abffad3c0d44860cf664390afce4d9661f7902e7943e085",
+        ),
+    ];
+
+    fn input(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn matches_the_official_test_vectors() {
+        for (len, expected) in VECTORS {
+            let mut hasher = Hasher::default();
+            hasher.update(&input(len));
+            assert_eq!(hasher.finalize_hex(), expected, "{} bytes", len);
+        }
+    }
+
+    /// Pieces that straddle blocks and chunks hash the same as one update,
+    /// whatever the tree looks like when they arrive.
+    #[test]
+    fn gives_the_same_hash_however_the_input_is_split() {
+        for (len, expected) in VECTORS {
+            let data = input(len);
+            for piece in [1, 63, 64, 65, 1000, 1024, 4097] {
+                let mut hasher = Hasher::default();
+                for part in data.chunks(piece) {
+                    hasher.update(part);
+                }
+                assert_eq!(
+                    hasher.finalize_hex(),
+                    expected,
+                    "{} bytes in {}s",
+                    len,
+                    piece
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn finalizing_leaves_the_hasher_usable() {
+        let data = input(3073);
+        let mut hasher = Hasher::default();
+        hasher.update(&data[..2048]);
+        assert_eq!(hasher.finalize_hex(), VECTORS[5].1);
+        hasher.update(&data[2048..]);
+        assert_eq!(hasher.finalize_hex(), VECTORS[8].1);
+    }
+}
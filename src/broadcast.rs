@@ -0,0 +1,120 @@
+use crate::{progress, throttle, Config, TransferEngine, TransferOptions};
+use colored::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_PARALLEL: usize = 4;
+
+/// Splits `@group:/path` into the group name and path.
+pub fn parse_group(dest: &str) -> Option<(&str, &str)> {
+    dest.strip_prefix('@')?.split_once(':')
+}
+
+struct Outcome {
+    member: String,
+    elapsed: Duration,
+    result: Result<(), String>,
+}
+
+/// Sends the same sources to every server in a `[groups]` entry, a few at
+/// a time. One host failing doesn't stop the others; the summary at the
+/// end lists each host's result.
+pub fn send(
+    sources: &[String],
+    dest: &str,
+    config: &Config,
+    opts: &TransferOptions,
+) -> Result<(), String> {
+    let (group, path) =
+        parse_group(dest).ok_or_else(|| format!("Invalid group destination '{}'", dest))?;
+    let members = config
+        .groups
+        .get(group)
+        .ok_or_else(|| format!("Unknown server group '@{}'", group))?;
+    if members.is_empty() {
+        return Err(format!("Server group '@{}' has no members", group));
+    }
+    for member in members {
+        config.require_server(member)?;
+    }
+
+    let mut opts = opts.clone();
+    throttle::adjust(&mut opts, config.throttle.as_ref())?;
+    let opts = &opts;
+    let parallel = opts.max_parallel.unwrap_or(DEFAULT_PARALLEL).max(1);
+    say!(
+        "{} {} host(s) in @{} ({} at a time)",
+        "Broadcasting to".green(),
+        members.len(),
+        group,
+        parallel.min(members.len())
+    );
+
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..parallel.min(members.len()) {
+            scope.spawn(|| {
+                progress::hide_bars();
+                while let Some(member) = members.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    let member_dest = format!("{}:{}", member, path);
+                    // Each host gets its own ledger, since each has its own copy.
+                    let member_opts = TransferOptions {
+                        task: opts
+                            .task
+                            .as_ref()
+                            .map(|task| format!("{}@{}", task, member)),
+                        ..opts.clone()
+                    };
+
+                    let started = Instant::now();
+                    let result =
+                        TransferEngine::send_to(sources, &member_dest, config, &member_opts);
+                    outcomes.lock().unwrap().push(Outcome {
+                        member: member.clone(),
+                        elapsed: started.elapsed(),
+                        result,
+                    });
+                }
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by_key(|o| members.iter().position(|m| *m == o.member));
+
+    say!("{}", format!("Broadcast to @{}:", group).bold());
+    let width = members.iter().map(String::len).max().unwrap_or(0);
+    for outcome in &outcomes {
+        match &outcome.result {
+            Ok(()) => say!(
+                "  {:<width$}  {:<6}  {:.1}s",
+                outcome.member,
+                "ok".green(),
+                outcome.elapsed.as_secs_f64(),
+                width = width
+            ),
+            Err(e) => say!(
+                "  {:<width$}  {:<6}  {}",
+                outcome.member,
+                "FAILED".red(),
+                e,
+                width = width
+            ),
+        }
+    }
+
+    let failed = outcomes.iter().filter(|o| o.result.is_err()).count();
+    if failed > 0 {
+        Err(t!(
+            "broadcast-failed",
+            failed = failed,
+            total = outcomes.len(),
+            group = group
+        ))
+    } else {
+        Ok(())
+    }
+}
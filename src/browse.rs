@@ -0,0 +1,327 @@
+use crate::history::format_timestamp;
+use crate::listings;
+use crate::location::Location;
+use crate::remote_fs::RemoteStat;
+use crate::{progress, Config, ServerConfig, TransferEngine, TransferOptions};
+use colored::*;
+use console::{truncate_str, Key, Term};
+use indicatif::HumanBytes;
+use std::collections::BTreeSet;
+
+/// Lines around the listing: title, key help, blank and column header
+/// above; blank and status below.
+const CHROME_LINES: usize = 6;
+
+fn is_dir(entry: &RemoteStat) -> bool {
+    entry.kind == "directory"
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+fn parent(dir: &str) -> String {
+    match dir.trim_end_matches('/').rsplit_once('/') {
+        Some(("", _)) | None => "/".to_string(),
+        Some((parent, _)) => parent.to_string(),
+    }
+}
+
+struct Browser<'a> {
+    alias: &'a str,
+    server: &'a ServerConfig,
+    config: &'a Config,
+    dir: String,
+    entries: Vec<RemoteStat>,
+    cursor: usize,
+    offset: usize,
+    selected: BTreeSet<String>,
+    status: String,
+}
+
+impl Browser<'_> {
+    /// Reads the current directory, directories first. `refresh` asks the
+    /// server again instead of using a cached listing.
+    fn load(&mut self, refresh: bool) -> Result<(), String> {
+        let mut entries = listings::entries(self.server, &self.dir, self.config, refresh)?;
+        entries.sort_by(|a, b| is_dir(b).cmp(&is_dir(a)).then(a.path.cmp(&b.path)));
+        self.selected
+            .retain(|name| entries.iter().any(|e| &e.path == name));
+        self.entries = entries;
+        self.cursor = self.cursor.min(self.entries.len().saturating_sub(1));
+        Ok(())
+    }
+
+    /// Moves to `dir`, staying put if it can't be listed.
+    fn open(&mut self, dir: String) {
+        let previous = std::mem::replace(&mut self.dir, dir);
+        let cursor = self.cursor;
+        self.cursor = 0;
+        self.offset = 0;
+        match self.load(false) {
+            Ok(()) => {
+                self.selected.clear();
+                self.status.clear();
+            }
+            Err(e) => {
+                self.dir = previous;
+                self.cursor = cursor;
+                self.status = e;
+            }
+        }
+    }
+
+    fn row(&self, entry: &RemoteStat, at_cursor: bool, width: usize) -> String {
+        let size = if is_dir(entry) {
+            "-".to_string()
+        } else {
+            HumanBytes(entry.size).to_string()
+        };
+        let name = if is_dir(entry) {
+            format!("{}/", entry.path)
+        } else {
+            entry.path.clone()
+        };
+        let row = format!(
+            "{}{} {:>10}  {:<19}  {}",
+            if at_cursor { ">" } else { " " },
+            if self.selected.contains(&entry.path) {
+                "*"
+            } else {
+                " "
+            },
+            size,
+            format_timestamp(entry.mtime),
+            name
+        );
+        truncate_str(&row, width, "…").to_string()
+    }
+
+    fn render(&mut self, term: &Term) -> std::io::Result<()> {
+        let (height, width) = term.size();
+        let rows = (height as usize).saturating_sub(CHROME_LINES).max(1);
+        if self.cursor < self.offset {
+            self.offset = self.cursor;
+        } else if self.cursor >= self.offset + rows {
+            self.offset = self.cursor + 1 - rows;
+        }
+
+        let mut lines = vec![
+            format!(
+                "{} — {}:{} ({} selected)",
+                "xfer browse".green().bold(),
+                self.alias,
+                self.dir,
+                self.selected.len()
+            ),
+            "[↑/↓] move  [enter] open  [⌫] up  [space] select  [d] download  [u] upload  [r] refresh  [q] quit"
+                .dimmed()
+                .to_string(),
+            String::new(),
+            format!("   {:>10}  {:<19}  NAME", "SIZE", "MODIFIED (UTC)")
+                .bold()
+                .to_string(),
+        ];
+        if self.entries.is_empty() {
+            lines.push("  (empty directory)".dimmed().to_string());
+        }
+        for (i, entry) in self.entries.iter().enumerate().skip(self.offset).take(rows) {
+            let row = self.row(entry, i == self.cursor, width as usize);
+            lines.push(if i == self.cursor {
+                row.reversed().to_string()
+            } else {
+                row
+            });
+        }
+        lines.push(String::new());
+        lines.push(self.status.clone());
+
+        term.clear_screen()?;
+        term.write_line(&lines.join("\n"))
+    }
+
+    /// The selection, or the entry under the cursor when nothing is selected.
+    fn targets(&self) -> Vec<&RemoteStat> {
+        if self.selected.is_empty() {
+            self.entries.get(self.cursor).into_iter().collect()
+        } else {
+            self.entries
+                .iter()
+                .filter(|e| self.selected.contains(&e.path))
+                .collect()
+        }
+    }
+
+    /// Fetches the targets into the local working directory. Directories
+    /// come over as tar streams, since scp would need them one file at a
+    /// time.
+    fn download(&mut self, term: &Term) {
+        let targets = self.targets();
+        if targets.is_empty() {
+            return;
+        }
+        let (dirs, files): (Vec<&RemoteStat>, Vec<&RemoteStat>) =
+            targets.into_iter().partition(|e| is_dir(e));
+        let count = dirs.len() + files.len();
+        let location = |e: &RemoteStat| format!("{}:{}", self.alias, join(&self.dir, &e.path));
+
+        let _ = term.clear_screen();
+        let _ = term.show_cursor();
+        let mut result = Ok(());
+        if !files.is_empty() {
+            let sources: Vec<String> = files.iter().map(|e| location(e)).collect();
+            let sources: Vec<&str> = sources.iter().map(String::as_str).collect();
+            result =
+                TransferEngine::send_files(&sources, ".", self.config, &TransferOptions::default());
+        }
+        for dir in dirs {
+            if result.is_err() {
+                break;
+            }
+            let opts = TransferOptions {
+                archive: true,
+                ..TransferOptions::default()
+            };
+            result = TransferEngine::send_files(&[&location(dir)], ".", self.config, &opts);
+        }
+        let _ = term.hide_cursor();
+
+        self.status = match result {
+            Ok(()) => {
+                self.selected.clear();
+                format!("Downloaded {} item(s) to the current directory", count)
+            }
+            Err(e) => e.red().to_string(),
+        };
+    }
+
+    /// Asks for local paths and sends them into the directory on screen.
+    fn upload(&mut self, term: &Term) {
+        let _ = term.show_cursor();
+        let _ = term.write_str("Upload local paths (space-separated, empty to cancel): ");
+        let line = term.read_line().unwrap_or_default();
+        let paths: Vec<&str> = line.split_whitespace().collect();
+        if paths.is_empty() {
+            let _ = term.hide_cursor();
+            self.status.clear();
+            return;
+        }
+
+        let _ = term.clear_screen();
+        let dest = format!("{}:{}/", self.alias, self.dir.trim_end_matches('/'));
+        let result =
+            TransferEngine::send_files(&paths, &dest, self.config, &TransferOptions::default());
+        let _ = term.hide_cursor();
+
+        self.status = match result.and_then(|()| self.load(true)) {
+            Ok(()) => format!("Uploaded {} item(s) to {}", paths.len(), self.dir),
+            Err(e) => e.red().to_string(),
+        };
+    }
+
+    fn handle(&mut self, key: Key, term: &Term) {
+        let page = (term.size().0 as usize).saturating_sub(CHROME_LINES).max(1);
+        let last = self.entries.len().saturating_sub(1);
+        match key {
+            Key::ArrowUp | Key::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            Key::ArrowDown | Key::Char('j') => self.cursor = (self.cursor + 1).min(last),
+            Key::PageUp => self.cursor = self.cursor.saturating_sub(page),
+            Key::PageDown => self.cursor = (self.cursor + page).min(last),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = last,
+            Key::Enter | Key::ArrowRight | Key::Char('l') => {
+                if let Some(entry) = self.entries.get(self.cursor) {
+                    if entry.kind == "regular file" {
+                        self.status = "Press d to download this file".to_string();
+                    } else {
+                        // Symlinks may point at directories; listing tells.
+                        let dir = join(&self.dir, &entry.path);
+                        self.open(dir);
+                    }
+                }
+            }
+            Key::Backspace | Key::ArrowLeft | Key::Char('h') => self.open(parent(&self.dir)),
+            Key::Char(' ') => {
+                if let Some(entry) = self.entries.get(self.cursor) {
+                    if !self.selected.remove(&entry.path) {
+                        self.selected.insert(entry.path.clone());
+                    }
+                    self.cursor = (self.cursor + 1).min(last);
+                }
+            }
+            Key::Char('d') => self.download(term),
+            Key::Char('u') => self.upload(term),
+            Key::Char('r') => {
+                self.status = match self.load(true) {
+                    Ok(()) => String::new(),
+                    Err(e) => e.red().to_string(),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Prints the directory once, for `--plain-output` where a screen that
+/// redraws is no use.
+fn print_listing(browser: &Browser) {
+    say!("{}:{}", browser.alias, browser.dir);
+    for entry in &browser.entries {
+        say!("{}", browser.row(entry, false, usize::MAX));
+    }
+}
+
+/// Interactive browser for a remote directory: move around, select
+/// entries, and download or upload without leaving it.
+pub fn run(location: &str, refresh: bool, config: &Config) -> Result<(), String> {
+    let location = if location.contains(':') {
+        location.to_string()
+    } else {
+        format!("{}:", location)
+    };
+    let (alias, server, dir) = match Location::parse(&location, config)? {
+        Location::Remote {
+            alias,
+            server,
+            path,
+        } => (alias, server, path.to_string()),
+        _ => return Err(format!("'{}' is not a remote location", location)),
+    };
+
+    let mut browser = Browser {
+        alias: &alias,
+        server,
+        config,
+        dir: dir.trim_end_matches('/').to_string(),
+        entries: Vec::new(),
+        cursor: 0,
+        offset: 0,
+        selected: BTreeSet::new(),
+        status: String::new(),
+    };
+    if browser.dir.is_empty() {
+        browser.dir = "/".to_string();
+    }
+    browser.load(refresh)?;
+
+    let term = Term::stdout();
+    if progress::is_plain() {
+        print_listing(&browser);
+        return Ok(());
+    }
+    if !term.is_term() {
+        return Err("xfer browse needs an interactive terminal".to_string());
+    }
+
+    let _ = term.hide_cursor();
+    loop {
+        browser.render(&term).map_err(|e| e.to_string())?;
+        match term.read_key() {
+            Ok(Key::Char('q') | Key::Escape) | Err(_) => break,
+            Ok(key) => browser.handle(key, &term),
+        }
+    }
+    let _ = term.show_cursor();
+    let _ = term.clear_screen();
+    Ok(())
+}
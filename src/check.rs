@@ -0,0 +1,339 @@
+use crate::auth;
+use crate::location::Location;
+use crate::platform::Platform;
+use crate::{output, quota, shell_quote, Config, ServerConfig, TransferEngine};
+use colored::*;
+use indicatif::HumanBytes;
+use serde::Serialize;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// How long the TCP check waits for each address.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const STEPS: [&str; 6] = ["cert", "dns", "tcp", "ssh", "write", "rsync"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pass,
+    Fail,
+    /// Not run, because an earlier step failed or it doesn't apply.
+    Skip,
+}
+
+#[derive(Debug, Serialize)]
+struct Step {
+    name: &'static str,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    alias: &'a str,
+    steps: Vec<Step>,
+}
+
+impl Report<'_> {
+    fn passed(&self) -> bool {
+        self.steps.iter().all(|step| step.status != Status::Fail)
+    }
+}
+
+/// Where the TCP connection actually goes: the first jump host when there
+/// is one, otherwise the server itself.
+fn first_hop(server: &ServerConfig) -> (String, u16) {
+    let hop = match server
+        .jump_chain
+        .as_deref()
+        .and_then(|c| c.split(',').next())
+    {
+        Some(hop) => hop,
+        None => return (server.host.clone(), server.port.unwrap_or(22)),
+    };
+    let hop = hop.rsplit_once('@').map_or(hop, |(_, host)| host);
+    match hop.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => {
+            (host.to_string(), port.parse().unwrap())
+        }
+        _ => (hop.to_string(), 22),
+    }
+}
+
+fn resolve(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("{} does not resolve: {}", host, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} has no addresses", host));
+    }
+    Ok(addrs)
+}
+
+fn connect(addrs: &[SocketAddr]) -> Result<SocketAddr, String> {
+    let mut last = String::new();
+    for addr in addrs {
+        match TcpStream::connect_timeout(addr, CONNECT_TIMEOUT) {
+            Ok(_) => return Ok(*addr),
+            Err(e) => last = format!("{}: {}", addr, e),
+        }
+    }
+    Err(last)
+}
+
+/// Checks `dir` is writable by creating and removing a file in it.
+fn writable(server: &ServerConfig, dir: &str) -> Result<(), String> {
+    let command = format!(
+        "t={}/.xfer-check-$$ && : > \"$t\" && rm -f \"$t\"",
+        shell_quote(dir.trim_end_matches('/'))
+    );
+    TransferEngine::remote_command(server, &command, None)
+        .map(|_| ())
+        .map_err(|e| format!("{} is not writable: {}", dir, e))
+}
+
+/// The user's quota for `dir`, when the server keeps one: a failure once
+/// it is used up, since nothing more can be written there.
+fn room(server: &ServerConfig, dir: &str) -> Result<String, String> {
+    let quota = match quota::remote(server, dir)
+        .ok()
+        .and_then(|space| space.quota)
+    {
+        Some(quota) => quota,
+        None => return Ok(dir.to_string()),
+    };
+    if quota.remaining() == 0 {
+        return Err(format!(
+            "quota used up for {}: {} of {}",
+            dir,
+            HumanBytes(quota.used),
+            HumanBytes(quota.limit())
+        ));
+    }
+    Ok(format!(
+        "{} (quota: {} left of {})",
+        dir,
+        HumanBytes(quota.remaining()),
+        HumanBytes(quota.limit())
+    ))
+}
+
+fn rsync_version(server: &ServerConfig) -> Result<String, String> {
+    let output = TransferEngine::remote_command(
+        server,
+        "command -v rsync >/dev/null && rsync --version | head -n 1",
+        None,
+    )
+    .map_err(|_| "rsync is not installed on the server (scp is used instead)".to_string())?;
+    Ok(output.trim().to_string())
+}
+
+/// Records a step's outcome; a failure stops the checks after it.
+fn step<T>(
+    steps: &mut Vec<Step>,
+    name: &'static str,
+    result: Result<(T, String), String>,
+) -> Result<T, ()> {
+    let (status, detail, value) = match result {
+        Ok((value, detail)) => (Status::Pass, detail, Ok(value)),
+        Err(e) => (Status::Fail, e, Err(())),
+    };
+    steps.push(Step {
+        name,
+        status,
+        detail: Some(detail),
+    });
+    value
+}
+
+fn run_steps(
+    alias: &str,
+    server: &ServerConfig,
+    config: &Config,
+    steps: &mut Vec<Step>,
+) -> Result<(), ()> {
+    let (host, port) = first_hop(server);
+    let via = if server.jump_chain.is_some() {
+        format!(" (jump host {})", host)
+    } else {
+        String::new()
+    };
+
+    // Local, so an expired certificate shows up even off the network.
+    if server.cert_path.is_some() {
+        step(
+            steps,
+            "cert",
+            auth::check_cert(server).map(|detail| ((), detail)),
+        )?;
+    } else {
+        steps.push(Step {
+            name: "cert",
+            status: Status::Skip,
+            detail: None,
+        });
+    }
+    let addrs = step(
+        steps,
+        "dns",
+        resolve(&host, port).map(|addrs| {
+            let detail = format!("{}{}", addrs[0].ip(), via);
+            (addrs, detail)
+        }),
+    )?;
+    step(
+        steps,
+        "tcp",
+        connect(&addrs)
+            .map(|addr| ((), format!("{}{}", addr, via)))
+            .map_err(|e| format!("cannot connect to {}{}", e, via)),
+    )?;
+    step(
+        steps,
+        "ssh",
+        TransferEngine::remote_command(server, "echo ok", None)
+            .map(|_| ((), format!("{}@{}", server.user, server.host))),
+    )?;
+
+    if Platform::of(server).is_ok_and(Platform::is_windows) {
+        for name in ["write", "rsync"] {
+            steps.push(Step {
+                name,
+                status: Status::Skip,
+                detail: Some("needs a POSIX shell on the server".to_string()),
+            });
+        }
+        return Ok(());
+    }
+
+    let dir = Location::parse(&format!("{}:", alias), config).map(|location| location.path());
+    step(
+        steps,
+        "write",
+        dir.and_then(|dir| {
+            writable(server, &dir)?;
+            room(server, &dir).map(|detail| ((), detail))
+        }),
+    )?;
+    step(steps, "rsync", rsync_version(server).map(|v| ((), v)))?;
+    Ok(())
+}
+
+fn check<'a>(alias: &'a str, server: &ServerConfig, config: &Config) -> Report<'a> {
+    let mut steps = Vec::new();
+    let _ = run_steps(alias, server, config, &mut steps);
+    for name in STEPS {
+        if !steps.iter().any(|step| step.name == name) {
+            steps.push(Step {
+                name,
+                status: Status::Skip,
+                detail: None,
+            });
+        }
+    }
+    Report { alias, steps }
+}
+
+fn print(reports: &[Report]) {
+    let width = reports
+        .iter()
+        .map(|r| r.alias.len())
+        .max()
+        .unwrap_or(0)
+        .max("SERVER".len());
+    let header: Vec<String> = STEPS
+        .iter()
+        .map(|s| format!("{:<5}", s.to_uppercase()))
+        .collect();
+    say!(
+        "{}",
+        format!("{:<width$}  {}", "SERVER", header.join("  "), width = width).bold()
+    );
+    for report in reports {
+        let cells: Vec<String> = report
+            .steps
+            .iter()
+            .map(|step| match step.status {
+                Status::Pass => format!("{:<5}", "ok").green().to_string(),
+                Status::Fail => format!("{:<5}", "FAIL").red().bold().to_string(),
+                Status::Skip => format!("{:<5}", "-").dimmed().to_string(),
+            })
+            .collect();
+        say!(
+            "{:<width$}  {}",
+            report.alias,
+            cells.join("  "),
+            width = width
+        );
+    }
+
+    for report in reports {
+        for step in &report.steps {
+            match (step.status, &step.detail) {
+                (Status::Fail, Some(detail)) => {
+                    say!(
+                        "  {} {}: {}",
+                        report.alias.yellow(),
+                        step.name,
+                        detail.red()
+                    )
+                }
+                (Status::Skip, Some(detail)) => {
+                    say!(
+                        "  {} {}: {}",
+                        report.alias.yellow(),
+                        step.name,
+                        detail.dimmed()
+                    )
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Runs the diagnostics for the named servers, every server with `all`, or
+/// the default server: DNS, TCP, ssh login, write access to (and quota
+/// left in) the default remote path and rsync on the server, each one only
+/// once the one before it passed.
+pub fn run(aliases: &[&str], all: bool, config: &Config) -> Result<(), String> {
+    let mut aliases: Vec<&str> = if all {
+        config.servers.keys().map(String::as_str).collect()
+    } else if !aliases.is_empty() {
+        aliases.to_vec()
+    } else {
+        match &config.default_server {
+            Some(alias) => vec![alias.as_str()],
+            None => return Err("Name a server to check, or use --all".to_string()),
+        }
+    };
+    if all {
+        aliases.sort();
+    }
+
+    let mut reports = Vec::new();
+    for alias in aliases {
+        let server = config.require_server(alias)?;
+        reports.push(check(alias, server, config));
+    }
+
+    if output::is_json() {
+        output::emit(&reports);
+    } else {
+        print(&reports);
+    }
+
+    let failed = reports.iter().filter(|r| !r.passed()).count();
+    if failed > 0 {
+        Err(format!(
+            "{} of {} server(s) failed the check",
+            failed,
+            reports.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
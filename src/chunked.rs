@@ -0,0 +1,419 @@
+use crate::platform::Platform;
+use crate::sessions::{self, SessionHandle};
+use crate::{
+    auth, blake3, helper, progress, scratch, shell_quote, verify, ServerConfig, TransferEngine,
+    TransferOptions,
+};
+use colored::*;
+use indicatif::{HumanBytes, ProgressBar};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Files smaller than this go over a single stream even with `--parallel`;
+/// the extra ssh sessions would cost more than they save.
+pub const MIN_SIZE: u64 = 64 * 1024 * 1024;
+/// Smallest chunk worth its own ssh session.
+const MIN_CHUNK: u64 = 8 * 1024 * 1024;
+/// Aim for a few chunks per session so a slow one doesn't hold up the end.
+const CHUNKS_PER_SESSION: u64 = 4;
+const BLOCK: usize = 64 * 1024;
+
+/// Whether this upload should be split: `--parallel` above 1 and a regular
+/// file big enough to be worth it.
+pub fn applies(local: &Path, opts: &TransferOptions) -> bool {
+    opts.parallel.unwrap_or(1) > 1
+        && local
+            .metadata()
+            .map(|m| m.is_file() && m.len() >= MIN_SIZE)
+            .unwrap_or(false)
+}
+
+struct Chunk {
+    index: usize,
+    offset: u64,
+    len: u64,
+}
+
+fn plan(size: u64, sessions: usize) -> Vec<Chunk> {
+    let chunk_size = size
+        .div_ceil(sessions as u64 * CHUNKS_PER_SESSION)
+        .max(MIN_CHUNK);
+    (0..size.div_ceil(chunk_size))
+        .map(|index| {
+            let offset = index * chunk_size;
+            Chunk {
+                index: index as usize,
+                offset,
+                len: chunk_size.min(size - offset),
+            }
+        })
+        .collect()
+}
+
+/// Rounds of re-sending the chunks that didn't arrive intact, before the
+/// upload gives up.
+const RESEND_ROUNDS: usize = 3;
+
+/// How chunks are checked once they are on the server: BLAKE3 when the
+/// helper says it can hash them, which needs `b3sum` installed there,
+/// otherwise SHA-256, which any server has a tool for.
+#[derive(Clone, Copy)]
+enum Algorithm {
+    Blake3,
+    Sha256,
+}
+
+enum ChunkHasher {
+    Blake3(blake3::Hasher),
+    Sha256(Sha256),
+}
+
+impl ChunkHasher {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Blake3 => ChunkHasher::Blake3(blake3::Hasher::default()),
+            Algorithm::Sha256 => ChunkHasher::Sha256(Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            ChunkHasher::Blake3(hasher) => hasher.update(data),
+            ChunkHasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            ChunkHasher::Blake3(hasher) => hasher.finalize_hex(),
+            ChunkHasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+        }
+    }
+}
+
+/// Uploads a large file as chunks over several ssh sessions at once. Each
+/// chunk is hashed as it is sent and checked on the server, and only the
+/// ones that don't match are sent again; then the server stitches them
+/// together and the result's SHA-256 is checked against the local file.
+pub fn upload(
+    local: &Path,
+    server: &ServerConfig,
+    remote: &str,
+    opts: &TransferOptions,
+) -> Result<(), String> {
+    if Platform::of(server)?.is_windows() {
+        return Err("--parallel needs a POSIX shell on the server".to_string());
+    }
+
+    let size = local
+        .metadata()
+        .map_err(|e| format!("Failed to read {}: {}", local.display(), e))?
+        .len();
+    let parallel = opts.parallel.unwrap_or(1).max(1);
+    let chunks = plan(size, parallel);
+    let parallel = parallel.min(chunks.len());
+
+    // Work out the final path up front so the chunks can sit next to it.
+    let name = local
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let target = TransferEngine::remote_command(
+        server,
+        &format!(
+            "p={}; [ -d \"$p\" ] && p=\"${{p%/}}\"/{}; printf '%s' \"$p\"",
+            shell_quote(remote),
+            shell_quote(&name)
+        ),
+        None,
+    )?;
+    // The chunks and the assembled copy are both on disk until the end.
+    scratch::ensure_remote_space(server, &target, size * 2)?;
+    let staging = format!("{}.xfer-chunks", target);
+    TransferEngine::remote_command(
+        server,
+        &format!("rm -rf {0} && mkdir -p {0}", shell_quote(&staging)),
+        None,
+    )?;
+
+    say!(
+        "{} {} in {} chunks over {} sessions",
+        "Splitting".green(),
+        HumanBytes(size),
+        chunks.len(),
+        parallel
+    );
+
+    let algorithm = if helper::supports(server, "chunk-hash") {
+        Algorithm::Blake3
+    } else {
+        say!(
+            "{} the server has no xfer helper with b3sum; checking chunks with SHA-256",
+            "Fallback:".yellow()
+        );
+        Algorithm::Sha256
+    };
+    let upload = Upload {
+        local,
+        server,
+        staging,
+        parallel,
+        // Each session gets an equal share of any bandwidth limit.
+        rate: opts.bwlimit.map(|rate| (rate / parallel as u64).max(1)),
+        algorithm,
+        bar: progress::counter(Some(size)),
+        session: sessions::current(),
+    };
+    let mut todo: Vec<&Chunk> = chunks.iter().collect();
+    let (local_hash, mut bad) = thread::scope(|scope| {
+        let hasher = scope.spawn(|| verify::hash_file(local));
+        let bad = upload.send(&todo);
+        (hasher.join().unwrap(), bad)
+    });
+    for round in 1..=RESEND_ROUNDS {
+        if bad.is_empty() {
+            break;
+        }
+        todo.retain(|chunk| bad.contains_key(&chunk.index));
+        say!(
+            "{} {} of {} chunks (round {} of {}): {}",
+            "Resending".yellow(),
+            todo.len(),
+            chunks.len(),
+            round,
+            RESEND_ROUNDS,
+            describe(&bad)
+        );
+        // Everything else is on the server intact.
+        let resent: u64 = todo.iter().map(|chunk| chunk.len).sum();
+        upload.bar.set_position(size - resent);
+        bad = upload.send(&todo);
+    }
+    upload.bar.finish_and_clear();
+
+    let staging = &upload.staging;
+    if !bad.is_empty() {
+        let _ = TransferEngine::remote_command(
+            server,
+            &format!("rm -rf {}", shell_quote(staging)),
+            None,
+        );
+        return Err(format!(
+            "{} of {} chunks still failed after {} rounds of re-sending: {}",
+            bad.len(),
+            chunks.len(),
+            RESEND_ROUNDS,
+            describe(&bad)
+        ));
+    }
+
+    let assemble = if helper::supports(server, "assemble") {
+        format!(
+            "{} assemble {} {}/chunk.* && rmdir {}",
+            helper::REMOTE_PATH,
+            shell_quote(&target),
+            shell_quote(staging),
+            shell_quote(staging)
+        )
+    } else {
+        let part = shell_quote(&format!("{}.xfer-part", target));
+        format!(
+            "cat {1}/chunk.* > {2} && mv {2} {0} && rm -rf {1}",
+            shell_quote(&target),
+            shell_quote(staging),
+            part
+        )
+    };
+    TransferEngine::remote_command(server, &assemble, None)?;
+    if let Some(fixup) = opts.preserved.remote_fixup(local, &target)? {
+        TransferEngine::remote_command(server, &fixup, None)?;
+    }
+
+    let mut local_hashes = BTreeMap::new();
+    local_hashes.insert(String::new(), local_hash?);
+    let remote_hashes = verify::remote_hashes(server, &target, local)?;
+    verify::compare(&local_hashes, &remote_hashes)?;
+
+    say!(
+        "{} {} chunks into {} (SHA-256 matches)",
+        "Reassembled".green(),
+        chunks.len(),
+        target
+    );
+    Ok(())
+}
+
+/// "chunk 3: hash mismatch; chunk 7: ..." for the chunks in `bad`.
+fn describe(bad: &BTreeMap<usize, String>) -> String {
+    bad.iter()
+        .map(|(index, reason)| format!("chunk {}: {}", index, reason))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn chunk_name(chunk: &Chunk) -> String {
+    format!("chunk.{:05}", chunk.index)
+}
+
+/// What every chunk of one upload is sent with.
+struct Upload<'a> {
+    local: &'a Path,
+    server: &'a ServerConfig,
+    staging: String,
+    parallel: usize,
+    rate: Option<u64>,
+    algorithm: Algorithm,
+    bar: ProgressBar,
+    session: Option<SessionHandle>,
+}
+
+impl Upload<'_> {
+    /// Sends `chunks` over up to `parallel` sessions and checks them on the
+    /// server. Returns why each chunk that needs sending again does.
+    fn send(&self, chunks: &[&Chunk]) -> BTreeMap<usize, String> {
+        let next = AtomicUsize::new(0);
+        let hashes = Mutex::new(BTreeMap::new());
+        let bad = Mutex::new(BTreeMap::new());
+        thread::scope(|scope| {
+            for _ in 0..self.parallel.min(chunks.len()) {
+                scope.spawn(|| {
+                    while let Some(chunk) = chunks.get(next.fetch_add(1, Ordering::SeqCst)) {
+                        match self.send_chunk(chunk) {
+                            Ok(hash) => hashes.lock().unwrap().insert(chunk.index, hash),
+                            Err(e) => bad.lock().unwrap().insert(chunk.index, e),
+                        };
+                    }
+                });
+            }
+        });
+        let hashes = hashes.into_inner().unwrap();
+        let mut bad = bad.into_inner().unwrap();
+        if hashes.is_empty() {
+            return bad;
+        }
+
+        let sent: Vec<&Chunk> = chunks
+            .iter()
+            .copied()
+            .filter(|chunk| hashes.contains_key(&chunk.index))
+            .collect();
+        match self.remote_hashes(&sent) {
+            Ok(remote) => {
+                for (index, hash) in hashes {
+                    match remote.get(&index) {
+                        Some(remote) if *remote == hash => {}
+                        Some(_) => {
+                            bad.insert(index, "hash mismatch".to_string());
+                        }
+                        None => {
+                            bad.insert(index, "missing on the server".to_string());
+                        }
+                    }
+                }
+            }
+            // Without the server's hashes nothing sent can be trusted.
+            Err(e) => {
+                for index in hashes.into_keys() {
+                    bad.insert(index, format!("couldn't check it: {}", e));
+                }
+            }
+        }
+        bad
+    }
+
+    /// Streams one byte range of the file into `cat` on the server,
+    /// returning the hash of what was sent.
+    fn send_chunk(&self, chunk: &Chunk) -> Result<String, String> {
+        let local = self.local;
+        let mut file =
+            File::open(local).map_err(|e| format!("Failed to open {}: {}", local.display(), e))?;
+        file.seek(SeekFrom::Start(chunk.offset))
+            .map_err(|e| format!("Failed to read {}: {}", local.display(), e))?;
+        let mut reader = file.take(chunk.len);
+
+        let destination = format!("{}/{}", self.staging, chunk_name(chunk));
+        let mut command = Command::new("ssh");
+        auth::prepare(&mut command, self.server)?;
+        command
+            .args(TransferEngine::ssh_args(self.server))
+            .arg(format!("cat > {}", shell_quote(&destination)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null());
+        let mut running = TransferEngine::spawn_command("ssh", command)?;
+
+        let mut stdin = running.child.stdin.take();
+        let mut hasher = ChunkHasher::new(self.algorithm);
+        let started = Instant::now();
+        let mut total = 0u64;
+        let mut buf = vec![0u8; BLOCK];
+        let written: io::Result<()> = (|| {
+            let stdin = match stdin.as_mut() {
+                Some(stdin) => stdin,
+                None => return Ok(()),
+            };
+            loop {
+                let n = reader.read(&mut buf)?;
+                if n == 0 {
+                    return Ok(());
+                }
+                hasher.update(&buf[..n]);
+                stdin.write_all(&buf[..n])?;
+                total += n as u64;
+                self.bar.inc(n as u64);
+                if let Some(session) = &self.session {
+                    session.set_progress(self.bar.position());
+                }
+
+                if let Some(rate) = self.rate {
+                    let due = Duration::from_secs_f64(total as f64 / rate as f64);
+                    if let Some(wait) = due.checked_sub(started.elapsed()) {
+                        thread::sleep(wait);
+                    }
+                }
+            }
+        })();
+        // Closing stdin lets cat finish; a write error usually means ssh died,
+        // and finish() reports why.
+        drop(stdin);
+
+        TransferEngine::finish("ssh", running)?;
+        written.map_err(|e| format!("Failed to send {}: {}", local.display(), e))?;
+        Ok(hasher.finish())
+    }
+
+    /// The server's hash of each of `chunks`, by index.
+    fn remote_hashes(&self, chunks: &[&Chunk]) -> Result<BTreeMap<usize, String>, String> {
+        let names: Vec<String> = chunks.iter().map(|chunk| chunk_name(chunk)).collect();
+        let command = match self.algorithm {
+            Algorithm::Blake3 => format!(
+                "cd {} && {} chunk-hash {}",
+                shell_quote(&self.staging),
+                helper::REMOTE_PATH,
+                names.join(" ")
+            ),
+            Algorithm::Sha256 => format!(
+                "{}; cd {} && $H {}",
+                verify::REMOTE_SHA256,
+                shell_quote(&self.staging),
+                names.join(" ")
+            ),
+        };
+        let output = TransferEngine::remote_command(self.server, &command, None)?;
+        Ok(output
+            .lines()
+            .filter_map(|line| {
+                let (hash, name) = line.split_once("  ")?;
+                let index = name.strip_prefix("chunk.")?.parse().ok()?;
+                Some((index, hash.to_string()))
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,128 @@
+use crate::listings;
+use crate::location::Location;
+use crate::{app, s3, Config};
+use clap::Shell;
+
+/// Hooks the generated scripts up to `xfer __complete`, which knows the
+/// configured aliases and can list remote directories.
+const BASH: &str = r#"
+_xfer_dynamic() {
+    local line="${COMP_LINE:0:$COMP_POINT}"
+    local word="${line##*[[:space:]]}"
+    if [[ $COMP_CWORD -lt 2 || "$word" == -* ]]; then
+        _xfer "$@"
+        return
+    fi
+    local IFS=$'\n'
+    local items=($(xfer __complete "$word" 2>/dev/null))
+    if [[ "$word" != *:* ]]; then
+        _xfer "$@"
+    else
+        COMPREPLY=()
+    fi
+    if [[ ${#items[@]} -gt 0 ]]; then
+        # bash splits words at ':', so only the part after it is replaced.
+        if [[ "$word" == *:* && "$COMP_WORDBREAKS" == *:* ]]; then
+            local colon_prefix="${word%"${word##*:}"}"
+            items=("${items[@]#"$colon_prefix"}")
+        fi
+        COMPREPLY+=("${items[@]}")
+        compopt -o nospace 2>/dev/null
+    fi
+}
+complete -F _xfer_dynamic -o bashdefault -o default xfer
+"#;
+
+const ZSH: &str = r#"
+_xfer_dynamic() {
+    if (( CURRENT > 2 )) && [[ $PREFIX != -* ]]; then
+        local -a items
+        items=(${(f)"$(xfer __complete "$PREFIX" 2>/dev/null)"})
+        (( ${#items} )) && compadd -Q -S '' -- $items
+        [[ $PREFIX == *:* ]] && return
+    fi
+    _xfer "$@"
+}
+
+_xfer_dynamic "$@"
+"#;
+
+const FISH: &str = r#"
+complete -c xfer -n 'not __fish_use_subcommand' -a '(xfer __complete (commandline -ct))'
+"#;
+
+/// The completion script for `shell`: clap's static one, plus the dynamic
+/// part for shells that have one.
+pub fn script(shell: Shell) -> Vec<u8> {
+    let mut script = Vec::new();
+    app().gen_completions_to("xfer", shell, &mut script);
+    match shell {
+        Shell::Bash => script.extend_from_slice(BASH.as_bytes()),
+        Shell::Zsh => {
+            // The dynamic wrapper takes over the final call.
+            let text = String::from_utf8_lossy(&script).into_owned();
+            let text = text.trim_end().trim_end_matches("_xfer \"$@\"");
+            script = text.as_bytes().to_vec();
+            script.extend_from_slice(ZSH.as_bytes());
+        }
+        Shell::Fish => script.extend_from_slice(FISH.as_bytes()),
+        _ => {}
+    }
+    script
+}
+
+fn list(alias: &str, dir: &str, config: &Config) -> Option<Vec<String>> {
+    let location = Location::parse(&format!("{}:{}", alias, dir), config).ok()?;
+    let server = location.server()?;
+    let names = listings::entries(server, &location.path(), config, false)
+        .ok()?
+        .into_iter()
+        .map(|entry| match entry.kind.as_str() {
+            "directory" => format!("{}/", entry.path),
+            _ => entry.path,
+        })
+        .collect();
+    Some(names)
+}
+
+/// Candidates for the word being completed, one per line: `alias:` for the
+/// configured servers, buckets and `@group:`s, or the entries of the remote
+/// directory once the word has an alias in front. Errors just mean no
+/// candidates.
+pub fn complete(word: &str, config: &Config) {
+    let (alias, path) = match word.split_once(':') {
+        Some((alias, path)) if config.get_server(alias).is_some() => (alias, path),
+        Some(_) => return,
+        None => {
+            let mut names: Vec<String> = config
+                .servers
+                .keys()
+                .map(|alias| format!("{}:", alias))
+                .chain(config.groups.keys().map(|group| format!("@{}:", group)))
+                .collect();
+            if !config.buckets.is_empty() {
+                names.push(format!("{}:", s3::SCHEME));
+            }
+            names.sort();
+            for name in names.iter().filter(|name| name.starts_with(word)) {
+                println!("{}", name);
+            }
+            return;
+        }
+    };
+
+    // Complete the last path component against the directory before it.
+    let (dir, partial) = match path.rfind('/') {
+        Some(slash) => path.split_at(slash + 1),
+        None => ("", path),
+    };
+    let names = match list(alias, dir, config) {
+        Some(names) => names,
+        None => return,
+    };
+    for name in names.iter().filter(|name| name.starts_with(partial)) {
+        if partial.starts_with('.') || !name.starts_with('.') {
+            println!("{}:{}{}", alias, dir, name);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use crate::archive::{self, Sink};
+use crate::platform::Platform;
+use crate::unpack::TarCheck;
+use crate::{local_size, shell_quote, verify, ServerConfig, TransferOptions};
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// What encrypted files are named with, and how downloads are recognized
+/// as needing decryption. The age header inside them
+/// (`age-encryption.org/v1`) names the format and its recipients' stanzas.
+pub const SUFFIX: &str = ".age";
+
+/// Whether an upload to `server` is encrypted: `--encrypt` (or a
+/// recipient), or `encrypt = true` on the server.
+pub fn wanted_for_upload(server: &ServerConfig, opts: &TransferOptions) -> bool {
+    opts.encrypt || server.encrypt.unwrap_or(false)
+}
+
+/// Whether a download of `remote` is decrypted: `--decrypt` or
+/// `--no-decrypt`, otherwise whenever it is named `.age`.
+pub fn wanted_for_download(remote: &str, opts: &TransferOptions) -> bool {
+    opts.decrypt.unwrap_or_else(|| remote.ends_with(SUFFIX))
+}
+
+/// The name a download of `remote` is decrypted to: a file loses `.age`,
+/// a directory sent as `NAME.tar.age` unpacks to `NAME`.
+pub fn decrypted_name(remote: &str) -> &str {
+    remote
+        .strip_suffix(".tar.age")
+        .or_else(|| remote.strip_suffix(SUFFIX))
+        .unwrap_or(remote)
+}
+
+pub fn expand_tilde(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// `--recipient` wins over the server's `recipients`; each is a key, or a
+/// file of them. Without any, age asks for a passphrase.
+fn encrypt_args(server: &ServerConfig, opts: &TransferOptions) -> Result<Vec<String>, String> {
+    let recipients = if opts.recipients.is_empty() {
+        server.recipients.as_deref().unwrap_or_default()
+    } else {
+        &opts.recipients
+    };
+    if opts.passphrase || recipients.is_empty() {
+        if !io::stdin().is_terminal() {
+            return Err(
+                "Encrypting with a passphrase needs a terminal; give --recipient or set recipients for the server"
+                    .to_string(),
+            );
+        }
+        return Ok(vec!["-p".to_string()]);
+    }
+
+    let mut args = Vec::new();
+    for recipient in recipients {
+        let file = expand_tilde(recipient);
+        if file.is_file() {
+            args.push("-R".to_string());
+            args.push(file.to_string_lossy().into_owned());
+        } else {
+            args.push("-r".to_string());
+            args.push(recipient.clone());
+        }
+    }
+    Ok(args)
+}
+
+/// Encrypts `local` with age as it streams to the server, so only
+/// ciphertext leaves this machine. A file arrives as `NAME.age`, a
+/// directory as `NAME.tar.age`; a destination already ending in `.age` is
+/// used as it is.
+pub fn upload(
+    local: &Path,
+    server: &ServerConfig,
+    remote: &str,
+    opts: &TransferOptions,
+) -> Result<(), String> {
+    if Platform::of(server)?.is_windows() {
+        return Err("--encrypt needs a POSIX shell on the server".to_string());
+    }
+    let age_args = encrypt_args(server, opts)?;
+    let name = local
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .ok_or_else(|| format!("Can't encrypt {}: it has no file name", local.display()))?;
+
+    let mut from = Vec::new();
+    let mut age = Command::new("age");
+    age.args(&age_args);
+    let suffix = if local.is_dir() {
+        let parent = match local.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut tar = Command::new("tar");
+        tar.args(opts.preserved.tar_create_args())
+            .arg("-C")
+            .arg(parent)
+            .args(["-cf", "-"])
+            .arg(&name);
+        from.push(("tar", tar));
+        ".tar.age"
+    } else {
+        age.arg(local);
+        SUFFIX
+    };
+    from.push(("age", age));
+
+    let remote_command = format!(
+        "t={}; if [ -d \"$t\" ]; then t=\"$t\"/{}; fi; \
+         case \"$t\" in *{}) ;; *) t=\"$t\"{} ;; esac; \
+         cat > \"$t.xfer-part\" && mv \"$t.xfer-part\" \"$t\"",
+        shell_quote(remote),
+        shell_quote(&name),
+        SUFFIX,
+        suffix
+    );
+    let into = vec![("ssh", archive::ssh_command(server, remote_command)?)];
+    archive::pipe(from, Sink::Commands(into), Some(local_size(local)), None)
+}
+
+/// Fetches a remote age file and decrypts it on the way in with
+/// `--identity` or the server's `identities` (age asks for the passphrase
+/// of a passphrase-encrypted file). A `.tar.age` is unpacked into `local`;
+/// anything else is written with `.age` taken off its name.
+pub fn download(
+    server: &ServerConfig,
+    remote: &str,
+    local: &str,
+    opts: &TransferOptions,
+) -> Result<(), String> {
+    if Platform::of(server)?.is_windows() {
+        return Err("--decrypt needs a POSIX shell on the server".to_string());
+    }
+    let identities = if opts.identities.is_empty() {
+        server.identities.as_deref().unwrap_or_default()
+    } else {
+        &opts.identities
+    };
+    let mut age = Command::new("age");
+    age.arg("-d");
+    for identity in identities {
+        age.arg("-i").arg(expand_tilde(identity));
+    }
+    let from = vec![
+        (
+            "ssh",
+            archive::ssh_command(server, format!("cat {}", shell_quote(remote)))?,
+        ),
+        ("age", age),
+    ];
+
+    let plain = remote.strip_suffix(SUFFIX).unwrap_or(remote);
+    if plain.ends_with(".tar") {
+        if Path::new(local).is_file() {
+            return Err(format!(
+                "{} holds a directory, and {} is a file",
+                remote, local
+            ));
+        }
+        fs::create_dir_all(local).map_err(|e| format!("Failed to create {}: {}", local, e))?;
+        let mut tar = Command::new("tar");
+        tar.args(opts.preserved.tar_extract_args())
+            .args(["-C", local, "-xf", "-"]);
+        return archive::pipe(
+            from,
+            Sink::Commands(vec![("tar", tar)]),
+            None,
+            Some(&mut TarCheck::default()),
+        );
+    }
+
+    let target = verify::local_target(local, plain);
+    archive::pipe(from, Sink::File(&target), None, None)
+}
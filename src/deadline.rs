@@ -0,0 +1,120 @@
+use crate::parse_duration;
+use crate::sessions::{self, Session};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+/// The `max_duration` this run was started with, as given.
+static LIMIT: OnceLock<String> = OnceLock::new();
+static EXPIRED: AtomicBool = AtomicBool::new(false);
+
+/// Starts the clock for `max_duration`, once per run. When it runs out, the
+/// running rsync/scp children get SIGTERM the way `xfer top` cancels them,
+/// and nothing new is started, so the transfer stops with its partial
+/// state left for `xfer resume`.
+pub fn start(max_duration: Option<&str>) -> Result<(), String> {
+    let max_duration = match max_duration {
+        Some(max_duration) => max_duration,
+        None => return Ok(()),
+    };
+    let limit = parse_duration(max_duration)?;
+    if LIMIT.set(max_duration.to_string()).is_err() {
+        return Ok(());
+    }
+
+    thread::spawn(move || {
+        thread::sleep(limit);
+        EXPIRED.store(true, Ordering::SeqCst);
+        // Keep at it: a child may have been starting just as time ran out.
+        loop {
+            cancel(&sessions::list(), process::id());
+            thread::sleep(Duration::from_secs(1));
+        }
+    });
+    Ok(())
+}
+
+/// Sends SIGTERM to every child of `pid`'s sessions; parallel uploads run
+/// several at once.
+fn cancel(sessions: &[Session], pid: u32) {
+    for session in sessions.iter().filter(|session| session.pid == pid) {
+        // A paused child won't act on SIGTERM until resumed.
+        let _ = sessions::signal_all(&session.child_pids, "TERM");
+        let _ = sessions::signal_all(&session.child_pids, "CONT");
+    }
+}
+
+pub fn expired() -> bool {
+    EXPIRED.load(Ordering::SeqCst)
+}
+
+fn message() -> String {
+    format!(
+        "Cancelled after reaching max_duration ({}); run 'xfer resume' to continue",
+        LIMIT.get().map(String::as_str).unwrap_or_default()
+    )
+}
+
+/// Fails once time is up, so no new tool is started.
+pub fn check() -> Result<(), String> {
+    if expired() {
+        Err(message())
+    } else {
+        Ok(())
+    }
+}
+
+/// Replaces whatever error a cancelled tool reported with the reason it was
+/// cancelled.
+pub fn explain(error: String) -> String {
+    if expired() {
+        message()
+    } else {
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use std::time::Instant;
+
+    fn session(pid: u32, child_pids: Vec<u32>) -> Session {
+        Session {
+            id: format!("{}-test", pid),
+            pid,
+            child_pids,
+            kind: "foreground".to_string(),
+            source: "a".to_string(),
+            destination: "b".to_string(),
+            started: 0,
+            total_bytes: None,
+            bytes_done: None,
+        }
+    }
+
+    #[test]
+    fn cancel_stops_every_child_of_this_process_only() {
+        let mut ours: Vec<_> = (0..2)
+            .map(|_| Command::new("sleep").arg("30").spawn().unwrap())
+            .collect();
+        let mut theirs = Command::new("sleep").arg("30").spawn().unwrap();
+        let sessions = [
+            session(process::id(), ours.iter().map(|child| child.id()).collect()),
+            session(process::id() + 1, vec![theirs.id()]),
+        ];
+
+        let started = Instant::now();
+        cancel(&sessions, process::id());
+        for child in &mut ours {
+            assert!(!child.wait().unwrap().success());
+        }
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(theirs.try_wait().unwrap().is_none());
+        theirs.kill().unwrap();
+        theirs.wait().unwrap();
+    }
+}
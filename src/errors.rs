@@ -0,0 +1,171 @@
+use crate::redact;
+use std::cell::Cell;
+use std::io::{self, Read, Write};
+use std::process::ChildStderr;
+use std::thread::{self, JoinHandle};
+
+/// How much of a tool's stderr to keep for explaining a failure.
+const KEEP_BYTES: usize = 8 * 1024;
+
+/// Failure signatures seen in ssh, scp, rsync and aws output, checked in
+/// order (the first match wins). Each kind has a `failure-<kind>` summary
+/// and a `hint-<kind>` message.
+const KNOWN: &[(&[&str], &str)] = &[
+    (
+        &[
+            "Host key verification failed",
+            "REMOTE HOST IDENTIFICATION HAS CHANGED",
+        ],
+        "host-key",
+    ),
+    (
+        &[
+            "Permission denied (publickey",
+            "Permission denied, please try again",
+            "Too many authentication failures",
+            "Authentication failed",
+        ],
+        "auth",
+    ),
+    (
+        &["Could not resolve hostname", "Name or service not known"],
+        "dns",
+    ),
+    (
+        &[
+            "Timeout, server",
+            "client_loop: send disconnect",
+            "Broken pipe",
+            "Connection reset by",
+            "connection unexpectedly closed",
+        ],
+        "dropped",
+    ),
+    (
+        &[
+            "Connection refused",
+            "Connection timed out",
+            "Operation timed out",
+            "No route to host",
+            "Network is unreachable",
+            "Connection closed by",
+        ],
+        "unreachable",
+    ),
+    (
+        &["No space left on device", "Disk quota exceeded"],
+        "disk-full",
+    ),
+    (
+        &[
+            "rsync: command not found",
+            "rsync: not found",
+            "rsync error: remote command not found",
+        ],
+        "no-rsync",
+    ),
+    (&["No such file or directory"], "not-found"),
+    (
+        &[
+            "Permission denied",
+            "Operation not permitted",
+            "AccessDenied",
+        ],
+        "permission",
+    ),
+];
+
+/// Failure kinds where the network, not the request, was at fault, so the
+/// same transfer may well work a little later.
+const RETRYABLE: &[&str] = &["dropped", "unreachable", "dns"];
+
+/// rsync exit codes for socket, protocol stream and timeout errors.
+const RSYNC_RETRYABLE: &[i32] = &[10, 12, 30, 35];
+
+thread_local! {
+    /// Whether the last failure described on this thread is worth retrying.
+    static RETRYABLE_FAILURE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether the last failure was a network one rather than a permanent one
+/// such as a missing file or a refused login. Reading it resets it.
+pub fn retryable() -> bool {
+    RETRYABLE_FAILURE.with(|retryable| retryable.replace(false))
+}
+
+fn is_retryable(program: &str, code: Option<i32>, kind: Option<&str>) -> bool {
+    match (kind, code) {
+        (Some(kind), _) => RETRYABLE.contains(&kind),
+        // ssh and scp exit 255 when the connection itself failed.
+        (None, Some(255)) => matches!(program, "ssh" | "scp" | "rsync"),
+        (None, Some(code)) if program == "rsync" => RSYNC_RETRYABLE.contains(&code),
+        _ => false,
+    }
+}
+
+/// Copies a child's stderr through to ours a line at a time, with secrets
+/// masked, and keeps the last few KiB of it for `describe`. Progress lines
+/// end in `\r`, so they still show as they arrive.
+pub fn tee(mut stderr: ChildStderr) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut kept = Vec::new();
+        let mut pending = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            match stderr.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    pending.extend_from_slice(&buf[..n]);
+                    if let Some(end) = pending.iter().rposition(|&b| b == b'\n' || b == b'\r') {
+                        let lines: Vec<u8> = pending.drain(..=end).collect();
+                        echo(&lines);
+                    }
+                    kept.extend_from_slice(&buf[..n]);
+                    if kept.len() > KEEP_BYTES {
+                        kept.drain(..kept.len() - KEEP_BYTES);
+                    }
+                }
+            }
+        }
+        echo(&pending);
+        String::from_utf8_lossy(&kept).into_owned()
+    })
+}
+
+fn echo(bytes: &[u8]) {
+    if !bytes.is_empty() {
+        let text = redact::redact(&String::from_utf8_lossy(bytes));
+        let _ = io::stderr().write_all(text.as_bytes());
+    }
+}
+
+/// Turns a failed run into a message saying what went wrong and what to do
+/// about it, falling back to the tool's last line of stderr.
+pub fn describe(program: &str, code: Option<i32>, stderr: &str) -> String {
+    let stderr = &redact::redact(stderr);
+    let kind = KNOWN
+        .iter()
+        .find(|(patterns, _)| patterns.iter().any(|p| stderr.contains(p)))
+        .map(|(_, kind)| *kind);
+    RETRYABLE_FAILURE.with(|retryable| retryable.set(is_retryable(program, code, kind)));
+    let code = code.map_or("signal".to_string(), |c| c.to_string());
+
+    if let Some(kind) = kind {
+        return format!(
+            "{}\n  {}: {}",
+            t!(
+                "tool-failed",
+                program = program,
+                code = code,
+                reason = t!(&format!("failure-{}", kind))
+            ),
+            t!("hint"),
+            t!(&format!("hint-{}", kind))
+        );
+    }
+
+    match stderr.lines().map(str::trim).rfind(|line| !line.is_empty()) {
+        Some(line) => t!("tool-failed", program = program, code = code, reason = line),
+        None => t!("tool-failed-bare", program = program, code = code),
+    }
+}
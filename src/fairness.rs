@@ -0,0 +1,120 @@
+use crate::sessions;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+/// Percent of the time a throttled job keeps running, unless `share` says
+/// otherwise.
+const DEFAULT_SHARE: u32 = 25;
+/// One run-then-pause cycle of a throttled job. Short enough that its ssh
+/// connection doesn't notice, long enough not to spin.
+const CYCLE: Duration = Duration::from_secs(1);
+
+/// How queue jobs make way for transfers started from a terminal, which
+/// would otherwise have to share the link with them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fairness {
+    pub policy: Option<Policy>,
+    /// For `throttle`: the percent of the time a job keeps running.
+    pub share: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Policy {
+    /// Pause jobs for most of each second; the default.
+    Throttle,
+    /// Pause jobs until the foreground transfers are done.
+    Pause,
+    Off,
+}
+
+/// Watches for foreground transfers while this queue job runs, holding its
+/// rsync/scp child back with SIGSTOP/SIGCONT (the way `xfer top` pauses
+/// one) until they are done.
+pub fn start(fairness: Option<&Fairness>) {
+    let policy = fairness
+        .and_then(|fairness| fairness.policy)
+        .unwrap_or(Policy::Throttle);
+    let share = fairness
+        .and_then(|fairness| fairness.share)
+        .unwrap_or(DEFAULT_SHARE);
+    if policy == Policy::Off || (policy == Policy::Throttle && share >= 100) {
+        return;
+    }
+
+    thread::spawn(move || {
+        // The children this thread stopped; ones stopped by someone else
+        // are left as they are.
+        let mut stopped: Vec<u32> = Vec::new();
+        let mut yielding = false;
+        loop {
+            let sessions = sessions::list();
+            let foreground = sessions
+                .iter()
+                .filter(|session| session.kind == "foreground")
+                .count();
+            // Parallel uploads have several children, all held back alike.
+            let children: Vec<u32> = sessions
+                .iter()
+                .filter(|session| session.pid == process::id())
+                .flat_map(|session| session.child_pids.iter().copied())
+                .collect();
+            stopped.retain(|child| children.contains(child));
+
+            if (foreground > 0) != yielding {
+                yielding = foreground > 0;
+                announce(yielding, foreground, policy, share);
+            }
+            let children: Vec<u32> = children
+                .into_iter()
+                .filter(|&child| stopped.contains(&child) || !sessions::is_stopped(child))
+                .collect();
+            if !yielding || children.is_empty() {
+                let _ = sessions::signal_all(&stopped, "CONT");
+                stopped.clear();
+                thread::sleep(CYCLE);
+                continue;
+            }
+
+            let paused = match policy {
+                Policy::Throttle => {
+                    let _ = sessions::signal_all(&stopped, "CONT");
+                    stopped.clear();
+                    thread::sleep(CYCLE * share / 100);
+                    CYCLE * (100 - share) / 100
+                }
+                _ => CYCLE,
+            };
+            for child in children {
+                if !stopped.contains(&child) && sessions::signal(child, "STOP").is_ok() {
+                    stopped.push(child);
+                }
+            }
+            thread::sleep(paused);
+        }
+    });
+}
+
+/// Says in the job's log when it starts and stops making way.
+fn announce(yielding: bool, foreground: usize, policy: Policy, share: u32) {
+    if !yielding {
+        say!(
+            "{} foreground transfers are done; back to full speed",
+            "Fairness:".yellow()
+        );
+        return;
+    }
+    let held = match policy {
+        Policy::Pause => "paused".to_string(),
+        _ => format!("running {}% of the time", share),
+    };
+    say!(
+        "{} {} foreground transfer(s) running; this job is {} until they finish",
+        "Fairness:".yellow(),
+        foreground,
+        held
+    );
+}
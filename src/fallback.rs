@@ -0,0 +1,190 @@
+use crate::archive;
+use crate::platform::Platform;
+use crate::{local_size, progress, sessions, tools, ServerConfig, TransferEngine, TransferOptions};
+use colored::*;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ANNOUNCED: AtomicBool = AtomicBool::new(false);
+
+/// Says once per run that rsync is missing and what is used instead.
+fn announce(instead: &str) {
+    if !ANNOUNCED.swap(true, Ordering::SeqCst) {
+        say!(
+            "{} rsync is not installed here; using {}",
+            "Fallback:".yellow(),
+            instead
+        );
+    }
+}
+
+/// The error for options only rsync provides.
+pub fn needs_rsync(what: &str) -> String {
+    format!(
+        "{} needs rsync, which is not installed on this machine (or not in PATH)",
+        what
+    )
+}
+
+/// Copies a local directory like `rsync -r` would, in plain Rust: `src/`
+/// puts its contents into `dest`, `src` puts the directory itself inside
+/// it. `--delete` removes what the source doesn't have.
+pub fn copy_tree(src: &str, dest: &str, opts: &TransferOptions) -> Result<(), String> {
+    announce("a built-in copy");
+    let from = Path::new(src);
+    let to = target_dir(src, dest);
+
+    let bar = progress::counter(Some(local_size(from)));
+    let mut done = 0;
+    let mut advance = |bytes: u64| {
+        done += bytes;
+        bar.set_position(done);
+        sessions::set_progress(done);
+    };
+    let result = copy_dir(from, &to, opts, &mut advance);
+    bar.finish_and_clear();
+    result
+}
+
+fn target_dir(src: &str, dest: &str) -> PathBuf {
+    if src.ends_with('/') || src.ends_with(MAIN_SEPARATOR) {
+        return PathBuf::from(dest);
+    }
+    match Path::new(src)
+        .canonicalize()
+        .ok()
+        .and_then(|p| p.file_name().map(PathBuf::from))
+    {
+        Some(name) => Path::new(dest).join(name),
+        None => PathBuf::from(dest),
+    }
+}
+
+fn copy_dir(
+    from: &Path,
+    to: &Path,
+    opts: &TransferOptions,
+    advance: &mut dyn FnMut(u64),
+) -> Result<(), String> {
+    let read_failed = |e: std::io::Error| format!("Failed to read {}: {}", from.display(), e);
+    fs::create_dir_all(to).map_err(|e| format!("Failed to create {}: {}", to.display(), e))?;
+
+    let mut names = HashSet::new();
+    for entry in fs::read_dir(from).map_err(read_failed)? {
+        let entry = entry.map_err(read_failed)?;
+        let path = entry.path();
+        let target = to.join(entry.file_name());
+        names.insert(entry.file_name());
+
+        // Without `links`, a symlink stands for what it points to.
+        let is_dir = match entry.file_type().map_err(read_failed)? {
+            kind if kind.is_symlink() => !opts.preserved.links && path.is_dir(),
+            kind => kind.is_dir(),
+        };
+        if is_dir {
+            copy_dir(&path, &target, opts, advance)?;
+        } else {
+            opts.preserved.copy_local(&path, &target)?;
+            advance(fs::symlink_metadata(&path).map(|m| m.len()).unwrap_or(0));
+        }
+    }
+
+    if opts.delete {
+        for entry in fs::read_dir(to).map_err(read_failed)?.flatten() {
+            if names.contains(&entry.file_name()) {
+                continue;
+            }
+            let path = entry.path();
+            let removed = if path.is_dir() && !path.is_symlink() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+            removed.map_err(|e| format!("Failed to delete {}: {}", path.display(), e))?;
+        }
+    }
+    opts.preserved.copy_dir_attributes(from, to)
+}
+
+/// What `copy_tree` with `--delete` would remove, relative to `dest`.
+pub fn deletions(src: &str, dest: &str) -> Vec<String> {
+    fn walk(from: &Path, to: &Path, prefix: &str, found: &mut Vec<String>) {
+        let names: HashSet<_> = fs::read_dir(from)
+            .map(|entries| entries.flatten().map(|e| e.file_name()).collect())
+            .unwrap_or_default();
+        for entry in fs::read_dir(to).into_iter().flatten().flatten() {
+            let name = entry.file_name();
+            let relative = format!("{}{}", prefix, name.to_string_lossy());
+            let path = entry.path();
+            if !names.contains(&name) {
+                found.push(relative);
+            } else if path.is_dir() && !path.is_symlink() {
+                walk(&from.join(&name), &path, &format!("{}/", relative), found);
+            }
+        }
+    }
+    let mut found = Vec::new();
+    walk(Path::new(src), &target_dir(src, dest), "", &mut found);
+    found
+}
+
+/// Sends the contents of a local directory into `remote` without rsync: as
+/// a tar stream to POSIX servers, with `scp -r` otherwise.
+pub fn upload_dir(
+    local: &Path,
+    server: &ServerConfig,
+    remote: &str,
+    opts: &TransferOptions,
+) -> Result<(), String> {
+    if opts.delete {
+        return Err(needs_rsync("--delete"));
+    }
+    if tools::has("tar") && !Platform::of(server)?.is_windows() {
+        announce("a tar stream");
+        return archive::upload(
+            local,
+            server,
+            remote,
+            &TransferOptions {
+                archive: true,
+                archive_format: Some("tar".to_string()),
+                compress: None,
+                ..opts.clone()
+            },
+        );
+    }
+
+    // `dir/.` makes scp merge the contents into `remote` like rsync's
+    // `dir/`, rather than put the directory inside it.
+    announce("scp -r");
+    let mut args = TransferEngine::connection_args(server, "-P");
+    args.push("-r".to_string());
+    if opts.preserved.perms {
+        args.push("-p".to_string());
+    }
+    if progress::is_plain() {
+        args.push("-q".to_string());
+    }
+    if let Some(rate) = opts.bwlimit {
+        args.push("-l".to_string());
+        args.push((rate * 8 / 1000).max(1).to_string());
+    }
+    args.push(local.join(".").to_string_lossy().into_owned());
+    args.push(format!("{}@{}:{}", server.user, server.host, remote));
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let _spinner = progress::spinner(None, sessions::total_bytes());
+    TransferEngine::run_command("scp", &args, Some(server))
+}
+
+/// Whether a transfer rsync would make can go through scp instead, and
+/// the error when it can't. scp keeps less, so that is said once; an
+/// interrupted file is sent again from the start.
+pub fn scp_instead(opts: &TransferOptions) -> Result<(), String> {
+    if opts.delete {
+        return Err(needs_rsync("--delete"));
+    }
+    announce("scp, which keeps permissions and times but not owners or symlinks");
+    Ok(())
+}
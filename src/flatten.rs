@@ -0,0 +1,92 @@
+use crate::location::{self, Location};
+use crate::platform::Platform;
+use crate::{local_size, shell_quote, Config, TransferEngine, TransferOptions};
+use colored::*;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Picks a name in `dir` that neither exists yet nor was handed out earlier
+/// in this run: `result.csv`, then `result-1.csv`, `result-2.csv`, ...
+pub fn unique_target(dir: &Path, name: &str, taken: &mut HashSet<PathBuf>) -> PathBuf {
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (name, String::new()),
+    };
+
+    let mut candidate = dir.join(name);
+    let mut n = 1;
+    while taken.contains(&candidate) || candidate.exists() {
+        candidate = dir.join(format!("{}-{}{}", stem, n, ext));
+        n += 1;
+    }
+    taken.insert(candidate.clone());
+    candidate
+}
+
+/// Downloads every file under the remote sources (optionally only those
+/// whose name matches `opts.matching`) into `dest` without their directory
+/// structure. Returns the number of bytes collected.
+pub fn get(
+    sources: &[String],
+    dest: &str,
+    config: &Config,
+    opts: &TransferOptions,
+) -> Result<u64, String> {
+    let dest_dir = Path::new(dest);
+    if dest.contains(':') {
+        return Err("--flatten downloads into a local directory".to_string());
+    }
+    fs::create_dir_all(dest_dir)
+        .map_err(|e| format!("Failed to create {}: {}", dest_dir.display(), e))?;
+
+    let mut taken = HashSet::new();
+    let mut collected = 0;
+    let mut renamed = 0;
+    let mut bytes = 0;
+
+    for src in sources {
+        let (alias, server, path) = match Location::parse(src, config)? {
+            Location::Remote {
+                alias,
+                server,
+                path,
+            } => (alias, server, path.to_string()),
+            _ => return Err(format!("--flatten needs remote sources, got '{}'", src)),
+        };
+        if Platform::of(server)?.is_windows() {
+            return Err("--flatten is not supported on Windows servers".to_string());
+        }
+
+        let filter = match &opts.matching {
+            Some(pattern) => format!(" -name {}", shell_quote(pattern)),
+            None => String::new(),
+        };
+        let command = format!("find {} -type f{}", shell_quote(&path), filter);
+        let output = TransferEngine::remote_command(server, &command, None)?;
+
+        for remote_file in output.lines().filter(|line| !line.is_empty()) {
+            let name = remote_file.rsplit('/').next().unwrap_or(remote_file);
+            location::received_name(name)?;
+
+            let target = unique_target(dest_dir, name, &mut taken);
+            if target.file_name().map(|n| n != name).unwrap_or(false) {
+                renamed += 1;
+            }
+
+            let remote = format!("{}:{}", alias, remote_file);
+            TransferEngine::send_file(&remote, &target.to_string_lossy(), config, opts)?;
+            bytes += local_size(&target);
+            collected += 1;
+        }
+    }
+
+    say!(
+        "{} {} file(s) into {} ({} renamed to avoid collisions)",
+        "Collected".green(),
+        collected,
+        dest_dir.display(),
+        renamed
+    );
+    Ok(bytes)
+}
@@ -0,0 +1,101 @@
+use crate::{Config, ServerConfig, TransferEngine};
+use colored::*;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+const SCRIPT: &str = include_str!("helper.sh");
+const VERSION: &str = "4";
+pub const REMOTE_PATH: &str = "$HOME/.xfer/bin/xfer-helper";
+
+static CAPABILITIES: OnceLock<Mutex<HashMap<String, Vec<String>>>> = OnceLock::new();
+
+/// Returns the helper's version string if it is installed and runnable.
+pub fn detect(server: &ServerConfig) -> Option<String> {
+    let command = format!("test -x {0} && {0} version 2>/dev/null", REMOTE_PATH);
+    TransferEngine::remote_command(server, &command, None)
+        .ok()
+        .map(|out| out.trim().to_string())
+        .filter(|out| out.starts_with("xfer-helper "))
+}
+
+/// The helper's capabilities on this server, looked up once per run. Empty
+/// when the helper isn't installed, so callers fall back to plain ssh.
+pub fn capabilities(server: &ServerConfig) -> Vec<String> {
+    let key = format!(
+        "{}@{}:{}",
+        server.user,
+        server.host,
+        server.port.unwrap_or(22)
+    );
+    let cache = CAPABILITIES.get_or_init(|| Mutex::new(HashMap::new()));
+
+    if let Some(capabilities) = cache.lock().unwrap().get(&key) {
+        return capabilities.clone();
+    }
+
+    let command = format!(
+        "test -x {0} && {0} capabilities 2>/dev/null || true",
+        REMOTE_PATH
+    );
+    let capabilities: Vec<String> = TransferEngine::remote_command(server, &command, None)
+        .map(|out| out.split_whitespace().map(String::from).collect())
+        .unwrap_or_default();
+
+    cache.lock().unwrap().insert(key, capabilities.clone());
+    capabilities
+}
+
+pub fn supports(server: &ServerConfig, capability: &str) -> bool {
+    capabilities(server).iter().any(|c| c == capability)
+}
+
+pub fn install(alias: &str, config: &Config) -> Result<(), String> {
+    let server = config.require_server(alias)?;
+
+    say!("{} helper on {}", "Installing".green(), alias);
+    let command = format!(
+        "mkdir -p \"$HOME/.xfer/bin\" && cat > {0}.tmp && chmod +x {0}.tmp && mv {0}.tmp {0}",
+        REMOTE_PATH
+    );
+    TransferEngine::remote_command(server, &command, Some(SCRIPT.as_bytes()))?;
+
+    match detect(server) {
+        Some(version) => {
+            say!("{} {}", "Installed".green(), version);
+            Ok(())
+        }
+        None => Err("Helper was uploaded but could not be executed on the server".to_string()),
+    }
+}
+
+pub fn status(alias: &str, config: &Config) -> Result<(), String> {
+    let server = config.require_server(alias)?;
+
+    match detect(server) {
+        Some(version) => {
+            let current = version == format!("xfer-helper {}", VERSION);
+            say!("{}: {}", alias.yellow(), version.green());
+            if !current {
+                say!(
+                    "  {} run 'xfer helper install {}' to update",
+                    "Outdated:".yellow(),
+                    alias
+                );
+            }
+            say!("  capabilities: {}", capabilities(server).join(", "));
+        }
+        None => say!(
+            "{}: {} (xfer will fall back to plain ssh commands)",
+            alias.yellow(),
+            "not installed".dimmed()
+        ),
+    }
+    Ok(())
+}
+
+pub fn remove(alias: &str, config: &Config) -> Result<(), String> {
+    let server = config.require_server(alias)?;
+    TransferEngine::remote_command(server, &format!("rm -f {}", REMOTE_PATH), None)?;
+    say!("{} helper from {}", "Removed".green(), alias);
+    Ok(())
+}
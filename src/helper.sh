@@ -0,0 +1,70 @@
+#!/bin/sh
+# xfer-helper: optional server-side companion for xfer.
+#
+# Installed by `xfer helper install <alias>`. Everything it does has a
+# plain-ssh fallback in xfer, so it only ever makes things faster.
+set -e
+
+VERSION=4
+
+if command -v sha256sum >/dev/null 2>&1; then
+    SHA256="sha256sum"
+else
+    SHA256="shasum -a 256"
+fi
+
+case "$1" in
+    version)
+        echo "xfer-helper $VERSION"
+        ;;
+    capabilities)
+        echo hash-cached
+        echo assemble
+        if command -v b3sum >/dev/null 2>&1; then
+            echo chunk-hash
+        fi
+        ;;
+    hash-cached)
+        # hash-cached <dir>: reads previously seen "<size> <mtime> <sha256> <path>"
+        # lines on stdin, rehashes only files whose size or mtime changed, and
+        # prints the same format for every file under dir.
+        cd "$2" 2>/dev/null || exit 0
+        tmp=$(mktemp -d)
+        trap 'rm -rf "$tmp"' EXIT
+        cat > "$tmp/cache"
+        if find . -maxdepth 0 -printf '' >/dev/null 2>&1; then
+            find . -type f -printf '%s %T@ %p\n'
+        else
+            find . -type f -exec stat -f '%z %m %N' {} +
+        fi | awk '{ m = $2; sub(/\..*$/, "", m)
+                    print $1 " " m " " substr($0, length($1) + length($2) + 3) }' > "$tmp/stat"
+        awk 'FILENAME == ARGV[1] { seen[substr($0, length($1) + length($2) + length($3) + 4)] = $1 " " $2; next }
+             { p = substr($0, length($1) + length($2) + 3)
+               if (seen[p] != $1 " " $2) print p }' "$tmp/cache" "$tmp/stat" > "$tmp/todo"
+        # -r: with nothing changed there is nothing to hash.
+        tr '\n' '\0' < "$tmp/todo" | xargs -0 -r $SHA256 > "$tmp/new" 2>/dev/null || true
+        awk 'FILENAME == ARGV[1] { c[substr($0, length($1) + length($2) + length($3) + 4)] = $3; next }
+             FILENAME == ARGV[2] { n[substr($0, length($1) + 3)] = $1; next }
+             { p = substr($0, length($1) + length($2) + 3)
+               h = (p in n) ? n[p] : c[p]
+               if (h != "") print $1 " " $2 " " h " " p }' "$tmp/cache" "$tmp/new" "$tmp/stat"
+        ;;
+    chunk-hash)
+        # chunk-hash <file>...: "<blake3>  <file>" for each file, to check
+        # upload chunks before they are assembled.
+        shift
+        b3sum "$@"
+        ;;
+    assemble)
+        # assemble <dest> <chunk>...: concatenate chunks into dest atomically.
+        dest=$2
+        shift 2
+        cat "$@" > "$dest.xfer-part"
+        mv "$dest.xfer-part" "$dest"
+        rm -f "$@"
+        ;;
+    *)
+        echo "usage: xfer-helper version|capabilities|hash-cached <dir>|chunk-hash <file>...|assemble <dest> <chunk>..." >&2
+        exit 2
+        ;;
+esac
@@ -0,0 +1,363 @@
+use crate::parquet::{self, Column, Values};
+use crate::{data_dir, output, queue, redact, unix_now, Config, TransferEngine, TransferOptions};
+use colored::*;
+use console::Term;
+use indicatif::HumanBytes;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub id: u64,
+    pub timestamp: u64,
+    pub direction: String,
+    pub sources: Vec<String>,
+    pub destination: String,
+    pub bytes: u64,
+    pub duration_ms: u64,
+    pub status: String,
+    pub error: Option<String>,
+    pub cwd: PathBuf,
+    pub options: TransferOptions,
+}
+
+fn history_path() -> PathBuf {
+    data_dir().join("history.jsonl")
+}
+
+pub fn load() -> Vec<Record> {
+    fs::read_to_string(history_path())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+pub fn direction(sources: &[String], destination: &str) -> &'static str {
+    let remote_src = sources.iter().any(|s| s.contains(':'));
+    let remote_dest = destination.contains(':');
+    match (remote_src, remote_dest) {
+        (false, true) => "upload",
+        (true, false) => "download",
+        (false, false) => "local",
+        (true, true) => "remote",
+    }
+}
+
+/// Appends a finished transfer to the history. Like the session registry,
+/// failing to write history never fails the transfer itself.
+pub fn record(
+    sources: &[String],
+    destination: &str,
+    bytes: u64,
+    duration_ms: u64,
+    result: &Result<(), String>,
+    opts: &TransferOptions,
+) {
+    if fs::create_dir_all(data_dir()).is_err() {
+        return;
+    }
+    // Held from reading the last id to appending, so transfers finishing
+    // together don't both take it. Without the lock the record still goes
+    // in; a duplicate id beats a lost entry.
+    let _lock = queue::lock_data("history").ok();
+    let record = Record {
+        id: load().last().map(|r| r.id + 1).unwrap_or(1),
+        ..entry(sources, destination, bytes, duration_ms, result, opts)
+    };
+    if let (Ok(line), Ok(mut file)) = (
+        serde_json::to_string(&record),
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(history_path()),
+    ) {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+/// The record for a finished transfer, with credentials masked; `record`
+/// numbers it.
+pub fn entry(
+    sources: &[String],
+    destination: &str,
+    bytes: u64,
+    duration_ms: u64,
+    result: &Result<(), String>,
+    opts: &TransferOptions,
+) -> Record {
+    Record {
+        id: 0,
+        timestamp: unix_now(),
+        direction: direction(sources, destination).to_string(),
+        sources: sources.iter().map(|s| redact::redact(s)).collect(),
+        destination: redact::redact(destination),
+        bytes,
+        duration_ms,
+        status: if result.is_ok() { "ok" } else { "failed" }.to_string(),
+        error: result.as_ref().err().map(|e| redact::redact(e)),
+        cwd: env::current_dir().unwrap_or_default(),
+        // Identity files and recipients stay out of the history; a redo
+        // takes the server's `identities` and `recipients` again.
+        options: TransferOptions {
+            identities: Vec::new(),
+            recipients: Vec::new(),
+            ..opts.clone()
+        },
+    }
+}
+
+/// Formats a Unix timestamp as "YYYY-MM-DD HH:MM:SS" (UTC).
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil-from-days, after Howard Hinnant's date algorithms.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem / 60) % 60,
+        rem % 60
+    )
+}
+
+/// Parses "YYYY-MM-DD" as midnight UTC, in Unix seconds.
+pub fn parse_date(date: &str) -> Result<u64, String> {
+    let invalid = || format!("'{}' is not a date (use YYYY-MM-DD)", date);
+    let parts: Vec<i64> = date
+        .split('-')
+        .map(|part| part.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let (year, month, day) = match parts[..] {
+        [year, month @ 1..=12, day @ 1..=31] => (year, month, day),
+        _ => return Err(invalid()),
+    };
+
+    // Days-from-civil, the inverse of format_timestamp.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    u64::try_from(days * 86_400).map_err(|_| invalid())
+}
+
+fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+pub fn list(limit: usize) {
+    let records = load();
+    let skip = records.len().saturating_sub(limit);
+    if output::is_json() {
+        output::emit(&records[skip..]);
+        return;
+    }
+    if records.is_empty() {
+        say!("No transfers recorded yet.");
+        return;
+    }
+
+    say!(
+        "{}",
+        format!(
+            "{:>5}  {:<19}  {:<8}  {:<6}  {:>10}  {:<8}  TRANSFER",
+            "ID", "WHEN (UTC)", "DIR", "STATUS", "SIZE", "TIME"
+        )
+        .bold()
+    );
+
+    for record in &records[skip..] {
+        let status = if record.status == "ok" {
+            record.status.green()
+        } else {
+            record.status.red()
+        };
+        say!(
+            "{:>5}  {:<19}  {:<8}  {:<6}  {:>10}  {:<8}  {} -> {}",
+            record.id,
+            format_timestamp(record.timestamp),
+            record.direction,
+            status,
+            HumanBytes(record.bytes).to_string(),
+            format_duration(record.duration_ms),
+            record.sources.join(", "),
+            record.destination
+        );
+    }
+}
+
+pub fn redo(id: &str, config: &Config) -> Result<(), String> {
+    let records = load();
+    let record = if id == "last" {
+        records.last()
+    } else {
+        let id: u64 = id
+            .parse()
+            .map_err(|_| format!("'{}' is not a history id (or 'last')", id))?;
+        records.iter().find(|r| r.id == id)
+    }
+    .ok_or_else(|| format!("No transfer with id '{}' in history", id))?;
+
+    env::set_current_dir(&record.cwd)
+        .map_err(|e| format!("Failed to enter {}: {}", record.cwd.display(), e))?;
+
+    say!(
+        "{} #{}: {} {} {}",
+        "Repeating".green(),
+        record.id,
+        record.sources.join(", "),
+        "to".green(),
+        record.destination
+    );
+    let sources: Vec<&str> = record.sources.iter().map(String::as_str).collect();
+    TransferEngine::send_files(&sources, &record.destination, config, &record.options)
+}
+
+/// Quotes a CSV field when it needs it, per RFC 4180.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn to_csv(records: &[&Record]) -> Vec<u8> {
+    let mut csv = String::from(
+        "id,timestamp,direction,status,bytes,duration_ms,sources,destination,error,cwd\r\n",
+    );
+    for record in records {
+        let fields = [
+            record.id.to_string(),
+            format!("{}Z", format_timestamp(record.timestamp).replace(' ', "T")),
+            record.direction.clone(),
+            record.status.clone(),
+            record.bytes.to_string(),
+            record.duration_ms.to_string(),
+            record.sources.join(" "),
+            record.destination.clone(),
+            record.error.clone().unwrap_or_default(),
+            record.cwd.display().to_string(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv.into_bytes()
+}
+
+fn to_parquet(records: &[&Record]) -> Vec<u8> {
+    let ints = |f: fn(&Record) -> u64| Values::Int64(records.iter().map(|r| f(r) as i64).collect());
+    let strings = |f: fn(&Record) -> String| Values::Utf8(records.iter().map(|r| f(r)).collect());
+    let columns = [
+        Column {
+            name: "id",
+            values: ints(|r| r.id),
+        },
+        Column {
+            name: "timestamp",
+            values: Values::Timestamp(records.iter().map(|r| r.timestamp as i64 * 1000).collect()),
+        },
+        Column {
+            name: "direction",
+            values: strings(|r| r.direction.clone()),
+        },
+        Column {
+            name: "status",
+            values: strings(|r| r.status.clone()),
+        },
+        Column {
+            name: "bytes",
+            values: ints(|r| r.bytes),
+        },
+        Column {
+            name: "duration_ms",
+            values: ints(|r| r.duration_ms),
+        },
+        Column {
+            name: "sources",
+            values: strings(|r| r.sources.join(" ")),
+        },
+        Column {
+            name: "destination",
+            values: strings(|r| r.destination.clone()),
+        },
+        Column {
+            name: "error",
+            values: Values::OptionalUtf8(records.iter().map(|r| r.error.clone()).collect()),
+        },
+        Column {
+            name: "cwd",
+            values: strings(|r| r.cwd.display().to_string()),
+        },
+    ];
+    parquet::write(
+        &columns,
+        concat!("xfer version ", env!("CARGO_PKG_VERSION")),
+    )
+}
+
+/// Writes the history, optionally only the transfers since `since`
+/// (YYYY-MM-DD, UTC), as CSV or Parquet to `to` or stdout, for loading
+/// into a spreadsheet or warehouse. Sources are joined with spaces.
+pub fn export(format: &str, since: Option<&str>, to: Option<&str>) -> Result<(), String> {
+    let since = since.map(parse_date).transpose()?.unwrap_or(0);
+    let records = load();
+    let records: Vec<&Record> = records.iter().filter(|r| r.timestamp >= since).collect();
+    let data = match format {
+        "csv" => to_csv(&records),
+        "parquet" => to_parquet(&records),
+        _ => {
+            return Err(format!(
+                "Unknown export format '{}' (use csv or parquet)",
+                format
+            ))
+        }
+    };
+
+    match to {
+        Some(path) => {
+            fs::write(path, &data).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            say!(
+                "{} {} transfer(s) to {}",
+                "Exported".green(),
+                records.len(),
+                path
+            );
+        }
+        None => {
+            if format == "parquet" && Term::stdout().is_term() {
+                return Err("Refusing to write Parquet to a terminal; use --to".to_string());
+            }
+            io::stdout()
+                .write_all(&data)
+                .map_err(|e| format!("Failed to write export: {}", e))?;
+        }
+    }
+    Ok(())
+}
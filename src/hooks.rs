@@ -0,0 +1,157 @@
+use crate::deadline;
+use colored::*;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set in a hook's environment when it runs as part of a dry run, so a hook
+/// that opted in can look without touching anything.
+pub const DRY_RUN_VAR: &str = "XFER_DRY_RUN";
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Makes this run a dry run: hooks are shown instead of run, unless they
+/// were marked safe to preview.
+pub fn set_dry_run() {
+    DRY_RUN.store(true, Ordering::SeqCst);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::SeqCst)
+}
+
+/// Runs a user's shell command with `sh -c` in `dir`, with `vars` added to
+/// its environment, and fails if it does. In a dry run it is only shown,
+/// unless `previews` says it can run with `XFER_DRY_RUN=1` set.
+pub fn run(
+    label: &str,
+    command: &str,
+    dir: &Path,
+    vars: &[(&str, String)],
+    previews: bool,
+) -> Result<(), String> {
+    run_as(is_dry_run(), label, command, dir, vars, previews)
+}
+
+/// Runs an `on_failure` command after `task` (a route or queue job) failed
+/// with `error`, e.g. to send a mail or a chat message. It gets the task in
+/// `XFER_TASK`, the error in `XFER_ERROR` and, when the run was cancelled
+/// by `max_duration`, the command that picks it up again in `XFER_RESUME`.
+/// A notification that fails only gets a warning: the task's own error is
+/// what the caller reports.
+pub fn notify_failure(command: &str, dir: &Path, task: &str, error: &str) {
+    let vars = failure_vars(task, error, deadline::expired());
+    if let Err(e) = run(&t!("on-failure"), command, dir, &vars, false) {
+        say!("{} {}", t!("warning").yellow(), e);
+    }
+}
+
+fn failure_vars(task: &str, error: &str, expired: bool) -> Vec<(&'static str, String)> {
+    let mut vars = vec![
+        ("XFER_TASK", task.to_string()),
+        ("XFER_ERROR", error.to_string()),
+    ];
+    if expired {
+        vars.push(("XFER_RESUME", "xfer resume".to_string()));
+    }
+    vars
+}
+
+fn run_as(
+    dry_run: bool,
+    label: &str,
+    command: &str,
+    dir: &Path,
+    vars: &[(&str, String)],
+    previews: bool,
+) -> Result<(), String> {
+    if dry_run && !previews {
+        say!("{} {} {}", label.yellow(), command, t!("hook-not-run"));
+        return Ok(());
+    }
+    say!("{} {}", label.green(), command);
+    let mut sh = Command::new("sh");
+    sh.args(["-c", command])
+        .current_dir(dir)
+        .envs(vars.iter().map(|(name, value)| (name, value)));
+    if dry_run {
+        sh.env(DRY_RUN_VAR, "1");
+    }
+    let status = sh
+        .status()
+        .map_err(|e| t!("hook-not-started", command = command, error = e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(t!("hook-failed", command = command, status = status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("xfer-hooks-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    const SHOW_ENV: &str = "printf '%s|%s' \"$XFER_ROUTE\" \"${XFER_DRY_RUN:-unset}\" > ran";
+
+    #[test]
+    fn runs_in_the_directory_with_the_variables() {
+        let dir = scratch("run");
+        let vars = [("XFER_ROUTE", "deploy".to_string())];
+        run_as(false, "Before:", SHOW_ENV, &dir, &vars, false).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("ran")).unwrap(), "deploy|unset");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_failing_command_is_an_error() {
+        let dir = scratch("fail");
+        let error = run_as(false, "Before:", "exit 3", &dir, &[], false).unwrap_err();
+        assert!(error.contains("'exit 3' failed"), "{}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failure_notifications_get_the_task_error_and_resume_hint() {
+        let dir = scratch("notify");
+        let show =
+            "printf '%s|%s|%s' \"$XFER_TASK\" \"$XFER_ERROR\" \"${XFER_RESUME:-none}\" > ran";
+        let vars = failure_vars(
+            "nightly",
+            "Cancelled after reaching max_duration (2h)",
+            true,
+        );
+        run_as(false, "On failure:", show, &dir, &vars, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("ran")).unwrap(),
+            "nightly|Cancelled after reaching max_duration (2h)|xfer resume"
+        );
+        let vars = failure_vars("nightly", "Connection refused", false);
+        run_as(false, "On failure:", show, &dir, &vars, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("ran")).unwrap(),
+            "nightly|Connection refused|none"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dry_runs_skip_hooks_unless_they_preview() {
+        let dir = scratch("dry");
+        let vars = [("XFER_ROUTE", "deploy".to_string())];
+        run_as(true, "Before:", SHOW_ENV, &dir, &vars, false).unwrap();
+        assert!(!dir.join("ran").exists());
+        run_as(true, "Before:", SHOW_ENV, &dir, &vars, true).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("ran")).unwrap(), "deploy|1");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
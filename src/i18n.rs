@@ -0,0 +1,105 @@
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use std::env;
+use std::sync::OnceLock;
+use unic_langid::LanguageIdentifier;
+
+/// Bundled translations, keyed by language. English is the fallback for
+/// anything a translation lacks.
+const LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en/xfer.ftl")),
+    ("de", include_str!("../locales/de/xfer.ftl")),
+    ("es", include_str!("../locales/es/xfer.ftl")),
+];
+
+struct Localizer {
+    selected: Option<FluentBundle<FluentResource>>,
+    fallback: FluentBundle<FluentResource>,
+}
+
+static LOCALIZER: OnceLock<Localizer> = OnceLock::new();
+
+fn bundle(language: &str, source: &str) -> FluentBundle<FluentResource> {
+    let id: LanguageIdentifier = language.parse().unwrap_or_default();
+    let mut bundle = FluentBundle::new_concurrent(vec![id]);
+    // Unicode isolation marks around arguments only confuse terminals.
+    bundle.set_use_isolating(false);
+    let resource =
+        FluentResource::try_new(source.to_string()).unwrap_or_else(|(resource, _)| resource);
+    let _ = bundle.add_resource(resource);
+    bundle
+}
+
+/// "de_DE.UTF-8" -> "de", checking the usual locale variables in order.
+fn system_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .split(['_', '.', '-', '@'])
+                .next()
+                .unwrap_or_default()
+                .to_lowercase()
+        })
+}
+
+/// Picks the language from the config's `language`, falling back to the
+/// system locale. Call once at startup; messages looked up before that use
+/// the system locale.
+pub fn init(configured: Option<&str>) {
+    let _ = LOCALIZER.set(localizer(configured.map(str::to_lowercase)));
+}
+
+fn localizer(language: Option<String>) -> Localizer {
+    let language = language.or_else(system_language).unwrap_or_default();
+    let selected = LOCALES
+        .iter()
+        .find(|(lang, _)| *lang != "en" && *lang == language)
+        .map(|(lang, source)| bundle(lang, source));
+    Localizer {
+        selected,
+        fallback: bundle("en", LOCALES[0].1),
+    }
+}
+
+fn format(
+    bundle: &FluentBundle<FluentResource>,
+    id: &str,
+    args: Option<&FluentArgs>,
+) -> Option<String> {
+    let pattern = bundle.get_message(id)?.value()?;
+    let mut errors = Vec::new();
+    Some(
+        bundle
+            .format_pattern(pattern, args, &mut errors)
+            .into_owned(),
+    )
+}
+
+/// Looks up a message by id, in the selected language if it has it. An id
+/// missing everywhere comes back as itself, so a typo is visible rather
+/// than silent.
+pub fn message(id: &str, args: Option<&FluentArgs>) -> String {
+    let localizer = LOCALIZER.get_or_init(|| localizer(None));
+    localizer
+        .selected
+        .as_ref()
+        .and_then(|bundle| format(bundle, id, args))
+        .or_else(|| format(&localizer.fallback, id, args))
+        .unwrap_or_else(|| id.to_string())
+}
+
+/// `t!("id")` or `t!("id", name = value, ...)`: a localized message.
+#[macro_export]
+macro_rules! t {
+    ($id:expr) => {
+        $crate::i18n::message($id, None)
+    };
+    ($id:expr, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = fluent_bundle::FluentArgs::new();
+        $(args.set(stringify!($name), $value.to_string());)+
+        $crate::i18n::message($id, Some(&args))
+    }};
+}
@@ -0,0 +1,246 @@
+use crate::{completions, config_dir, confirm, data_dir, Config, ServerConfig};
+use clap::{ArgMatches, Shell};
+use colored::*;
+use dirs::home_dir;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Reads `Host` blocks from an ssh config. Wildcard patterns are skipped
+/// since they aren't servers, and `Match` blocks are ignored.
+fn parse_ssh_config(content: &str) -> Vec<(String, ServerConfig)> {
+    let mut hosts: Vec<(String, ServerConfig)> = Vec::new();
+    let mut current: Vec<usize> = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once(|c: char| c.is_whitespace() || c == '=') {
+            Some((key, value)) => (
+                key.to_lowercase(),
+                value
+                    .trim_start_matches(|c: char| c.is_whitespace() || c == '=')
+                    .trim(),
+            ),
+            None => continue,
+        };
+        let value = value.trim_matches('"');
+
+        match key.as_str() {
+            "host" => {
+                current.clear();
+                for alias in value.split_whitespace() {
+                    if alias.contains(['*', '?', '!']) {
+                        continue;
+                    }
+                    current.push(hosts.len());
+                    hosts.push((
+                        alias.to_string(),
+                        ServerConfig {
+                            host: alias.to_string(),
+                            user: String::new(),
+                            key_path: None,
+                            cert_path: None,
+                            auth: None,
+                            port: None,
+                            default_remote_path: None,
+                            verify: None,
+                            bandwidth_limit: None,
+                            proxy_jump: None,
+                            keepalive: None,
+                            reconnect: None,
+                            throttle: None,
+                            preserve: None,
+                            on_upload: None,
+                            encrypt: None,
+                            recipients: None,
+                            identities: None,
+                            os: None,
+                            jump_chain: None,
+                        },
+                    ));
+                }
+            }
+            "match" => current.clear(),
+            _ => {
+                for &i in &current {
+                    let server = &mut hosts[i].1;
+                    match key.as_str() {
+                        "hostname" => server.host = value.to_string(),
+                        "user" => server.user = value.to_string(),
+                        "port" => server.port = value.parse().ok(),
+                        // ssh uses the first IdentityFile it is given.
+                        "identityfile" if server.key_path.is_none() => {
+                            server.key_path = Some(expand_tilde(value))
+                        }
+                        "certificatefile" if server.cert_path.is_none() => {
+                            server.cert_path = Some(expand_tilde(value))
+                        }
+                        "proxyjump" if !value.eq_ignore_ascii_case("none") => {
+                            server.proxy_jump = Some(value.to_string())
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    let default_user = env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_default();
+    for (_, server) in &mut hosts {
+        if server.user.is_empty() {
+            server.user = default_user.clone();
+        }
+    }
+    hosts
+}
+
+fn expand_tilde(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => home_dir()
+            .unwrap_or_default()
+            .join(rest)
+            .to_string_lossy()
+            .into_owned(),
+        None => path.to_string(),
+    }
+}
+
+fn import_ssh_config(matches: &ArgMatches, config: &mut Config) -> Result<Vec<String>, String> {
+    let path = home_dir().unwrap_or_default().join(".ssh").join("config");
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let new: Vec<(String, ServerConfig)> = parse_ssh_config(&content)
+        .into_iter()
+        .filter(|(alias, _)| !config.servers.contains_key(alias))
+        .collect();
+    if new.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let import = matches.is_present("import-ssh")
+        || (!matches.is_present("no-import-ssh")
+            && confirm(&format!(
+                "Import {} host(s) from {}?",
+                new.len(),
+                path.display()
+            )));
+    if !import {
+        return Ok(Vec::new());
+    }
+
+    let mut imported: Vec<String> = new.iter().map(|(alias, _)| alias.clone()).collect();
+    imported.sort();
+    config.servers.extend(new);
+    config.save().map_err(|e| e.to_string())?;
+    Ok(imported)
+}
+
+fn detect_shell() -> Option<Shell> {
+    let shell = env::var("SHELL").ok()?;
+    Path::new(&shell).file_name()?.to_str()?.parse().ok()
+}
+
+/// Where each shell picks up completions without further setup, where
+/// there is such a place. The string is a step left for the user, if any.
+fn completion_path(shell: Shell) -> (PathBuf, Option<String>) {
+    let home = home_dir().unwrap_or_default();
+    match shell {
+        Shell::Bash => (
+            home.join(".local/share/bash-completion/completions/xfer"),
+            None,
+        ),
+        Shell::Zsh => (
+            home.join(".zfunc/_xfer"),
+            Some("add 'fpath+=~/.zfunc' before 'compinit' in ~/.zshrc".to_string()),
+        ),
+        Shell::Fish => (home.join(".config/fish/completions/xfer.fish"), None),
+        Shell::PowerShell => {
+            let path = data_dir().join("completions").join("xfer.ps1");
+            let step = format!("add '. {}' to your $PROFILE", path.display());
+            (path, Some(step))
+        }
+        Shell::Elvish => {
+            let path = data_dir().join("completions").join("xfer.elv");
+            let step = format!("add 'eval (slurp < {})' to your rc.elv", path.display());
+            (path, Some(step))
+        }
+    }
+}
+
+fn install_completions(shell: Shell) -> Result<(PathBuf, Option<String>), String> {
+    let (path, step) = completion_path(shell);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    fs::write(&path, completions::script(shell))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok((path, step))
+}
+
+/// First-run setup in one go. Safe to run again: existing config and
+/// servers are left alone.
+pub fn run(matches: &ArgMatches, config: &mut Config) -> Result<(), String> {
+    let config_path = config_dir().join("config.toml");
+    if config_path.exists() {
+        say!("{} {}", "Config:".green(), config_path.display());
+    } else {
+        config.save().map_err(|e| e.to_string())?;
+        say!("{} {}", "Created".green(), config_path.display());
+    }
+
+    let imported = import_ssh_config(matches, config)?;
+    if !imported.is_empty() {
+        say!(
+            "{} {} host(s) from ~/.ssh/config: {}",
+            "Imported".green(),
+            imported.len(),
+            imported.join(", ")
+        );
+    }
+
+    let mut steps = Vec::new();
+    if !matches.is_present("no-completions") {
+        let shell = match matches.value_of("shell") {
+            Some(shell) => Some(shell.parse::<Shell>()?),
+            None => detect_shell(),
+        };
+        match shell {
+            Some(shell) => {
+                let (path, step) = install_completions(shell)?;
+                say!(
+                    "{} {} completions to {}",
+                    "Installed".green(),
+                    shell.to_string().to_lowercase(),
+                    path.display()
+                );
+                steps.extend(step);
+            }
+            None => say!(
+                "{} couldn't tell which shell you use; run 'xfer init --shell <name>' for completions",
+                "Skipped:".yellow()
+            ),
+        }
+    }
+
+    if config.servers.is_empty() {
+        steps.push("add a server with 'xfer server add'".to_string());
+    } else {
+        steps.push("check your servers with 'xfer server list'".to_string());
+    }
+    steps.push("send something: 'xfer send ./file <alias>:/path/'".to_string());
+
+    say!("{}", "Next steps:".bold());
+    for (i, step) in steps.iter().enumerate() {
+        say!("  {}. {}", i + 1, step);
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use crate::location::Location;
+use crate::{data_dir, scratch, verify, Config, TransferEngine, TransferOptions};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Hashes of the files a named task has already delivered, so repeated runs
+/// skip unchanged content even when a fresh build touched every mtime.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Ledger {
+    destination: String,
+    files: BTreeMap<String, String>,
+}
+
+fn ledger_path(task: &str) -> PathBuf {
+    let name: String = task
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    data_dir().join("ledger").join(format!("{}.json", name))
+}
+
+fn load(task: &str) -> Ledger {
+    fs::read_to_string(ledger_path(task))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(task: &str, ledger: &Ledger) -> Result<(), String> {
+    let path = ledger_path(task);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let content = serde_json::to_string_pretty(ledger).map_err(|e| e.to_string())?;
+    fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn ledger_key(source: &str, relative: &str) -> String {
+    if relative.is_empty() {
+        source.to_string()
+    } else {
+        format!("{}/{}", source.trim_end_matches('/'), relative)
+    }
+}
+
+pub fn send(
+    task: &str,
+    sources: &[String],
+    dest: &str,
+    config: &Config,
+    opts: &TransferOptions,
+) -> Result<(), String> {
+    let dest_is_remote = Location::parse(dest, config)?.remote().is_some();
+    let mut ledger = load(task);
+    if ledger.destination != dest {
+        // A different destination has none of the recorded files.
+        ledger = Ledger {
+            destination: dest.to_string(),
+            files: BTreeMap::new(),
+        };
+    }
+
+    let mut skipped = 0;
+    let mut sent = 0;
+
+    for src in sources {
+        let dest = if sources.len() > 1 {
+            TransferEngine::batch_target(src, dest)
+        } else {
+            dest.to_string()
+        };
+        let dest = dest.as_str();
+
+        let local = Path::new(src);
+        if src.contains(':') || !dest_is_remote || !local.exists() {
+            TransferEngine::send_file(src, dest, config, opts)?;
+            continue;
+        }
+
+        let hashes = verify::local_hashes(local)?;
+        let changed: Vec<&String> = hashes
+            .iter()
+            .filter(|(rel, hash)| {
+                opts.full || ledger.files.get(&ledger_key(src, rel)) != Some(*hash)
+            })
+            .map(|(rel, _)| rel)
+            .collect();
+        skipped += hashes.len() - changed.len();
+
+        if changed.is_empty() {
+            continue;
+        }
+
+        if local.is_dir() {
+            // Broadcasts run several tasks at once, so the list is per task.
+            let stem = ledger_path(task)
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let content: Vec<&str> = changed.iter().map(|rel| rel.as_str()).collect();
+            let list = scratch::file(
+                &format!("files-{}.txt", stem),
+                content.join("\n").as_bytes(),
+            )?;
+
+            let opts = TransferOptions {
+                files_from: Some(list.path.to_string_lossy().into_owned()),
+                ..opts.clone()
+            };
+            TransferEngine::send_file(src, dest, config, &opts)?;
+        } else {
+            TransferEngine::send_file(src, dest, config, opts)?;
+        }
+
+        sent += changed.len();
+        for rel in changed {
+            ledger
+                .files
+                .insert(ledger_key(src, rel), hashes[rel].clone());
+        }
+        save(task, &ledger)?;
+    }
+
+    say!(
+        "{} task '{}': {} file(s) sent, {} unchanged skipped",
+        "Ledger".green(),
+        task,
+        sent,
+        skipped
+    );
+    Ok(())
+}
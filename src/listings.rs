@@ -0,0 +1,150 @@
+use crate::platform::Platform;
+use crate::remote_fs::{self, RemoteStat};
+use crate::{cache_dir, unix_now, Config, ServerConfig, TransferEngine};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+/// Seconds a remote listing is reused for unless `listing_ttl` says
+/// otherwise, so completions, the browser and repeated `xfer list` don't
+/// pay a round trip each time.
+const DEFAULT_TTL: u64 = 60;
+
+/// The two forms a listing is kept in: parsed entries for completions, the
+/// browser and `--json`, and the text `xfer list` prints.
+const ENTRIES: &str = "entries";
+const TEXT: &str = "text";
+
+#[derive(Serialize, Deserialize)]
+struct Cached<T> {
+    time: u64,
+    listing: T,
+}
+
+fn dir() -> PathBuf {
+    cache_dir().join("listings")
+}
+
+/// `/srv/` and `/srv` are the same directory.
+fn normalize(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" if path.starts_with('/') => "/",
+        trimmed => trimmed,
+    }
+}
+
+/// Keyed by where the server is rather than its alias, so aliases for the
+/// same host share listings and a transfer can invalidate them without
+/// knowing which alias it came through.
+fn cache_path(server: &ServerConfig, kind: &str, path: &str) -> PathBuf {
+    let key = format!(
+        "{}@{}:{}|{}|{}",
+        server.user,
+        server.host,
+        server.port.unwrap_or(22),
+        kind,
+        normalize(path)
+    );
+    let digest = format!("{:x}", Sha256::digest(key));
+    dir().join(&digest[..16])
+}
+
+/// The listing of `path` from the cache while it is younger than the TTL,
+/// otherwise from `fetch`, which is then kept. `refresh` skips the cache.
+/// Also returns the age of a cached listing.
+fn cached<T: Serialize + DeserializeOwned>(
+    server: &ServerConfig,
+    kind: &str,
+    path: &str,
+    config: &Config,
+    refresh: bool,
+    fetch: impl FnOnce() -> Result<T, String>,
+) -> Result<(T, Option<u64>), String> {
+    let ttl = config.listing_ttl.unwrap_or(DEFAULT_TTL);
+    if ttl == 0 {
+        return Ok((fetch()?, None));
+    }
+    let file = cache_path(server, kind, path);
+    if !refresh {
+        if let Some(cached) = fs::read_to_string(&file)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Cached<T>>(&content).ok())
+        {
+            let age = unix_now().saturating_sub(cached.time);
+            if age < ttl {
+                return Ok((cached.listing, Some(age)));
+            }
+        }
+    }
+
+    let cached = Cached {
+        time: unix_now(),
+        listing: fetch()?,
+    };
+    // A cache that can't be written just means asking the server next time.
+    if let Ok(content) = serde_json::to_string(&cached) {
+        if fs::create_dir_all(dir()).is_ok() {
+            let _ = fs::write(&file, content);
+        }
+    }
+    Ok((cached.listing, None))
+}
+
+/// The entries of a remote directory, like `remote_fs::list`.
+pub fn entries(
+    server: &ServerConfig,
+    path: &str,
+    config: &Config,
+    refresh: bool,
+) -> Result<Vec<RemoteStat>, String> {
+    cached(server, ENTRIES, path, config, refresh, || {
+        remote_fs::list(server, path)
+    })
+    .map(|(entries, _)| entries)
+}
+
+/// What the server's own listing command prints for `path`, and how many
+/// seconds old it is when it came from the cache.
+pub fn text(
+    server: &ServerConfig,
+    path: &str,
+    config: &Config,
+    refresh: bool,
+) -> Result<(String, Option<u64>), String> {
+    cached(server, TEXT, path, config, refresh, || {
+        let command = Platform::of(server)?.list_command(path);
+        TransferEngine::remote_command(server, &command, None)
+    })
+}
+
+/// Drops what is cached for `path` and the directory it is in, after
+/// something on the server was changed there.
+pub fn forget(server: &ServerConfig, path: &str) {
+    let path = normalize(path);
+    let parent = match path.rsplit_once('/') {
+        Some(("", _)) => "/",
+        Some((parent, _)) => parent,
+        None => ".",
+    };
+    for dir in [path, parent] {
+        for kind in [ENTRIES, TEXT] {
+            let _ = fs::remove_file(cache_path(server, kind, dir));
+        }
+    }
+}
+
+/// Empties the cache for every server, returning how many listings were in
+/// it.
+pub fn clear() -> Result<usize, String> {
+    let entries = match fs::read_dir(dir()) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(format!("Failed to read {}: {}", dir().display(), e)),
+    };
+    let count = entries.flatten().count();
+    fs::remove_dir_all(dir())
+        .map_err(|e| format!("Failed to remove {}: {}", dir().display(), e))?;
+    Ok(count)
+}
@@ -0,0 +1,381 @@
+use crate::platform::Platform;
+use crate::{cache_dir, s3, Config, ServerConfig, TransferEngine};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// One end of a transfer, as given on the command line.
+#[derive(Debug, Clone)]
+pub enum Location<'a> {
+    Local(PathBuf),
+    Remote {
+        alias: String,
+        server: &'a ServerConfig,
+        path: RemotePath,
+    },
+    /// Object storage, e.g. `s3:bucket/key`.
+    Url {
+        scheme: &'static str,
+        bucket: String,
+        key: String,
+    },
+}
+
+impl<'a> Location<'a> {
+    /// Parses `alias:/path`, `s3:bucket/key` or a local path. As with scp,
+    /// a `:` after a `/` belongs to a local file name; so does one after a
+    /// Windows drive letter that isn't also a server alias.
+    pub fn parse(raw: &str, config: &'a Config) -> Result<Location<'a>, String> {
+        let (alias, path) = match raw.split_once(':') {
+            Some((alias, path)) if !alias.contains(['/', '\\']) => (alias, path),
+            _ => return Ok(Location::Local(PathBuf::from(raw))),
+        };
+
+        if alias.is_empty() {
+            return Err(t!("no-alias", location = raw));
+        }
+        if alias == s3::SCHEME {
+            let (bucket, key) = s3::split(path);
+            if bucket.is_empty() {
+                return Err(t!("no-bucket", location = raw));
+            }
+            return Ok(Location::Url {
+                scheme: s3::SCHEME,
+                bucket,
+                key,
+            });
+        }
+
+        let server = match config.get_server(alias) {
+            Some(server) => server,
+            None if alias.len() == 1
+                && alias.chars().all(|c| c.is_ascii_alphabetic())
+                && path.starts_with(['/', '\\']) =>
+            {
+                return Ok(Location::Local(PathBuf::from(raw)))
+            }
+            None => return Err(t!("unknown-server", alias = alias)),
+        };
+        let path = RemotePath::resolve(path, server.default_remote_path.as_deref(), || {
+            remote_home(server)
+        })?;
+        Ok(Location::Remote {
+            alias: alias.to_string(),
+            server,
+            path,
+        })
+    }
+
+    /// The server alias with its config, for remote locations.
+    pub fn remote(&self) -> Option<(&str, &'a ServerConfig)> {
+        match self {
+            Location::Remote { alias, server, .. } => Some((alias, server)),
+            _ => None,
+        }
+    }
+
+    pub fn server(&self) -> Option<&'a ServerConfig> {
+        self.remote().map(|(_, server)| server)
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(self, Location::Local(_))
+    }
+
+    pub fn is_url(&self) -> bool {
+        matches!(self, Location::Url { .. })
+    }
+
+    /// The local path, remote path or object key, as the tools take it.
+    pub fn path(&self) -> String {
+        match self {
+            Location::Local(path) => path.to_string_lossy().into_owned(),
+            Location::Remote { path, .. } => path.to_string(),
+            Location::Url { key, .. } => key.clone(),
+        }
+    }
+}
+
+/// An absolute, normalized path on a remote server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemotePath(String);
+
+impl RemotePath {
+    /// Resolves a user-supplied remote path: `~` and `~/...` expand to the
+    /// remote home, relative paths are taken from `default_dir` (or the
+    /// home), and the result is normalized. `home` is only called when the
+    /// path actually needs it. A trailing slash is kept because rsync and
+    /// scp treat `dir/` differently from `dir`.
+    pub fn resolve<F>(raw: &str, default_dir: Option<&str>, home: F) -> Result<RemotePath, String>
+    where
+        F: FnOnce() -> Result<String, String>,
+    {
+        let raw = raw.replace('\\', "/");
+        let joined = if raw.starts_with('/') {
+            raw
+        } else if let Some(rest) = strip_tilde(&raw) {
+            // A bare `~` is the home itself, not its contents.
+            match rest {
+                "" => home()?,
+                rest => format!("{}/{}", home()?, rest),
+            }
+        } else {
+            match default_dir {
+                Some(dir) if dir.starts_with('/') => format!("{}/{}", dir, raw),
+                Some(dir) => format!("{}/{}/{}", home()?, strip_tilde(dir).unwrap_or(dir), raw),
+                None => format!("{}/{}", home()?, raw),
+            }
+        };
+        Ok(RemotePath(normalize(&joined)))
+    }
+}
+
+fn strip_tilde(path: &str) -> Option<&str> {
+    if path == "~" {
+        Some("")
+    } else {
+        path.strip_prefix("~/")
+    }
+}
+
+fn homes_path() -> PathBuf {
+    cache_dir().join("homes.json")
+}
+
+/// The remote user's `$HOME`, asked for once per server and then cached,
+/// since it can't be guessed (`/Users/...` on macOS, custom layouts).
+pub fn remote_home(server: &ServerConfig) -> Result<String, String> {
+    let key = format!(
+        "{}@{}:{}",
+        server.user,
+        server.host,
+        server.port.unwrap_or(22)
+    );
+    let mut homes: HashMap<String, String> = fs::read_to_string(homes_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+
+    if let Some(home) = homes.get(&key) {
+        return Ok(home.clone());
+    }
+
+    let platform = Platform::of(server)?;
+    let output = TransferEngine::remote_command(server, platform.home_command(), None)?;
+    let home = platform.home_path(&output);
+    if !home.starts_with('/') {
+        return Err(t!("unknown-home", user = server.user, host = server.host));
+    }
+
+    homes.insert(key, home.clone());
+    if fs::create_dir_all(cache_dir()).is_ok() {
+        if let Ok(content) = serde_json::to_string_pretty(&homes) {
+            let _ = fs::write(homes_path(), content);
+        }
+    }
+    Ok(home)
+}
+
+impl fmt::Display for RemotePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Collapses duplicate slashes and `.` segments and resolves `..`
+/// lexically, never climbing above the root.
+fn normalize(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let trailing = path.len() > 1 && path.ends_with('/');
+    let mut parts: Vec<&str> = Vec::new();
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if parts.last().is_some_and(|p| *p != "..") {
+                    parts.pop();
+                } else if !absolute {
+                    parts.push("..");
+                }
+            }
+            segment => parts.push(segment),
+        }
+    }
+
+    let mut normalized = parts.join("/");
+    if absolute {
+        normalized.insert(0, '/');
+    }
+    if trailing && !normalized.ends_with('/') {
+        normalized.push('/');
+    }
+    if normalized.is_empty() {
+        normalized.push('.');
+    }
+    normalized
+}
+
+/// Checks a relative file name that came from a server before it is used
+/// to build a local path, rejecting anything that could escape the
+/// destination (absolute paths, `..`, drive prefixes).
+pub fn received_name(name: &str) -> Result<&Path, String> {
+    let path = Path::new(name);
+    let safe = !name.is_empty()
+        && path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+
+    if safe {
+        Ok(path)
+    } else {
+        Err(t!("unsafe-name", name = name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+            [servers.prod]
+            host = "example.com"
+            user = "deploy"
+            default_remote_path = "/srv/app"
+
+            [servers.c]
+            host = "c.example.com"
+            user = "deploy"
+            default_remote_path = "/home/deploy"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn home() -> Result<String, String> {
+        Ok("/home/deploy".to_string())
+    }
+
+    fn resolve(raw: &str, default_dir: Option<&str>) -> String {
+        RemotePath::resolve(raw, default_dir, home)
+            .unwrap()
+            .to_string()
+    }
+
+    fn remote_path(location: &Location) -> String {
+        match location {
+            Location::Remote { path, .. } => path.to_string(),
+            other => panic!("expected a remote location, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn drive_letters_are_local_unless_they_are_an_alias() {
+        let config = config();
+        assert!(Location::parse("D:/backups/site", &config)
+            .unwrap()
+            .is_local());
+        assert!(Location::parse(r"D:\backups\site", &config)
+            .unwrap()
+            .is_local());
+        let location = Location::parse("c:/var/log", &config).unwrap();
+        assert_eq!(location.remote().unwrap().0, "c");
+        assert_eq!(remote_path(&location), "/var/log");
+    }
+
+    #[test]
+    fn unknown_aliases_are_an_error() {
+        let error = Location::parse("staging:/srv", &config()).unwrap_err();
+        assert!(error.contains("'staging'"), "{}", error);
+        // A single letter only passes for a drive with a path after it.
+        assert!(Location::parse("d:backups", &config()).is_err());
+    }
+
+    #[test]
+    fn an_empty_path_is_the_default_directory() {
+        let config = config();
+        let location = Location::parse("prod:", &config).unwrap();
+        assert_eq!(remote_path(&location), "/srv/app/");
+        assert!(Location::parse(":/srv", &config).is_err());
+    }
+
+    #[test]
+    fn expands_tilde_and_relative_paths() {
+        assert_eq!(resolve("~", None), "/home/deploy");
+        assert_eq!(resolve("~/logs/", None), "/home/deploy/logs/");
+        assert_eq!(resolve("logs", None), "/home/deploy/logs");
+        assert_eq!(resolve("logs", Some("/srv/app")), "/srv/app/logs");
+        assert_eq!(resolve("logs", Some("~/app")), "/home/deploy/app/logs");
+        assert_eq!(resolve("logs", Some("app")), "/home/deploy/app/logs");
+        // `~user` is a file name here, not another user's home.
+        assert_eq!(resolve("~root", Some("/srv")), "/srv/~root");
+    }
+
+    #[test]
+    fn only_asks_for_the_home_when_needed() {
+        let path = RemotePath::resolve("/etc/hosts", None, || panic!("asked for the home"));
+        assert_eq!(path.unwrap().to_string(), "/etc/hosts");
+        let path = RemotePath::resolve("x", Some("/srv"), || panic!("asked for the home"));
+        assert_eq!(path.unwrap().to_string(), "/srv/x");
+    }
+
+    #[test]
+    fn normalizes_without_climbing_past_the_root() {
+        assert_eq!(resolve("/../../etc/./passwd", None), "/etc/passwd");
+        assert_eq!(resolve("/srv/app/../../..", None), "/");
+        assert_eq!(resolve("../../../../tmp", None), "/tmp");
+        assert_eq!(resolve("//srv//app//", None), "/srv/app/");
+        assert_eq!(normalize("a/../../b"), "../b");
+        assert_eq!(normalize("a/.."), ".");
+    }
+
+    #[test]
+    fn parses_s3_locations() {
+        let config = config();
+        for raw in ["s3:assets/site/index.html", "s3://assets/site/index.html"] {
+            match Location::parse(raw, &config).unwrap() {
+                Location::Url {
+                    scheme,
+                    bucket,
+                    key,
+                } => {
+                    assert_eq!(scheme, "s3");
+                    assert_eq!(bucket, "assets");
+                    assert_eq!(key, "site/index.html");
+                }
+                other => panic!("{} parsed as {:?}", raw, other),
+            }
+        }
+        assert!(Location::parse("s3:", &config).is_err());
+        assert!(Location::parse("s3://", &config).is_err());
+    }
+
+    #[test]
+    fn a_colon_after_a_slash_is_part_of_a_local_name() {
+        let config = config();
+        for raw in [
+            "./report:2026.txt",
+            "logs/12:00.log",
+            "/tmp/a:b",
+            r"dir\x:y",
+        ] {
+            match Location::parse(raw, &config).unwrap() {
+                Location::Local(path) => assert_eq!(path, PathBuf::from(raw)),
+                other => panic!("{} parsed as {:?}", raw, other),
+            }
+        }
+        // As with scp, a bare `name:` names a server.
+        assert!(Location::parse("report:2026.txt", &config).is_err());
+    }
+
+    #[test]
+    fn rejects_unsafe_names_from_servers() {
+        assert!(received_name("logs/app.log").is_ok());
+        for name in ["", "/etc/passwd", "../x", "a/../../x"] {
+            assert!(received_name(name).is_err(), "{}", name);
+        }
+    }
+}
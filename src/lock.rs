@@ -0,0 +1,241 @@
+use crate::{sessions, shell_quote, unix_now, ServerConfig, TransferEngine};
+use colored::*;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// World-writable (sticky) directory on the server that holds one lock
+/// directory per destination. `mkdir` is atomic, which is all a lock needs.
+const LOCK_DIR: &str = "/tmp/xfer-locks";
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// A lock older than this was left by a run that died, even when its owner
+/// is on another machine and can't be checked from here.
+const STALE_LOCK: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Destinations this process holds, as (user@host, path). A send of many
+/// files locks their destination once, and the files in it go without.
+static HELD: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+pub struct DestinationLock {
+    server: ServerConfig,
+    /// None for a destination inside one this process already holds.
+    lock_path: Option<String>,
+    held: (String, String),
+}
+
+impl Drop for DestinationLock {
+    fn drop(&mut self) {
+        let lock_path = match &self.lock_path {
+            Some(lock_path) => lock_path,
+            None => return,
+        };
+        if let Ok(mut held) = HELD.lock() {
+            held.retain(|held| *held != self.held);
+        }
+        let command = format!("rm -rf {}", shell_quote(lock_path));
+        let _ = TransferEngine::remote_command(&self.server, &command, None);
+    }
+}
+
+fn lock_path(dest_path: &str) -> String {
+    let normalized = dest_path.trim_end_matches('/');
+    let digest = format!("{:x}", Sha256::digest(normalized.as_bytes()));
+    format!("{}/{}", LOCK_DIR, &digest[..16])
+}
+
+fn hostname() -> String {
+    Command::new("hostname")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_default()
+}
+
+fn owner() -> String {
+    let user = env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    format!(
+        "{}@{} {} {}",
+        user,
+        hostname(),
+        std::process::id(),
+        unix_now()
+    )
+}
+
+/// Whether the xfer in "user@host pid started" is gone: a process on this
+/// machine that has exited, or any owner older than STALE_LOCK.
+fn is_stale(owner: &str, host: &str, now: u64) -> bool {
+    let fields: Vec<&str> = owner.split_whitespace().collect();
+    let (who, pid, started) = match fields.as_slice() {
+        [who, pid, started] => (who, pid, started),
+        _ => return false,
+    };
+    let (pid, started) = match (pid.parse::<u32>(), started.parse::<u64>()) {
+        (Ok(pid), Ok(started)) => (pid, started),
+        _ => return false,
+    };
+    if now.saturating_sub(started) > STALE_LOCK.as_secs() {
+        return true;
+    }
+    who.rsplit('@').next() == Some(host) && !sessions::is_running(pid)
+}
+
+/// Whether `dest_path` is, or is inside, a destination this process holds.
+fn holds(server: &str, dest_path: &str) -> bool {
+    HELD.lock().is_ok_and(|held| {
+        held.iter().any(|(held_server, held_path)| {
+            held_server == server
+                && (dest_path == held_path || dest_path.starts_with(&format!("{}/", held_path)))
+        })
+    })
+}
+
+/// Turns "user@host pid started" into something a human can act on.
+fn describe_owner(owner: &str) -> String {
+    let fields: Vec<&str> = owner.split_whitespace().collect();
+    match fields.as_slice() {
+        [who, pid, started] => {
+            let age = unix_now().saturating_sub(started.parse().unwrap_or(0));
+            format!("{} (pid {}) for {}m{}s", who, pid, age / 60, age % 60)
+        }
+        _ => "another xfer instance".to_string(),
+    }
+}
+
+/// Takes the lock for `dest_path` on the server. If someone else holds it,
+/// either waits for it or fails straight away with who holds it.
+pub fn acquire(
+    server: &ServerConfig,
+    dest_path: &str,
+    wait: bool,
+) -> Result<DestinationLock, String> {
+    let held = (
+        format!("{}@{}", server.user, server.host),
+        dest_path.trim_end_matches('/').to_string(),
+    );
+    if holds(&held.0, &held.1) {
+        return Ok(DestinationLock {
+            server: server.clone(),
+            lock_path: None,
+            held,
+        });
+    }
+    let lock_path = lock_path(dest_path);
+    let command = format!(
+        "mkdir -p {dir} 2>/dev/null && chmod 1777 {dir} 2>/dev/null; \
+         if mkdir {lock} 2>/dev/null; then echo {owner} > {lock}/owner; echo acquired; \
+         else cat {lock}/owner 2>/dev/null || echo unknown; fi",
+        dir = LOCK_DIR,
+        lock = shell_quote(&lock_path),
+        owner = shell_quote(&owner()),
+    );
+
+    let host = hostname();
+    let mut announced = false;
+    loop {
+        let output = TransferEngine::remote_command(server, &command, None)?;
+        let output = output.trim();
+        if output == "acquired" {
+            if let Ok(mut all) = HELD.lock() {
+                all.push(held.clone());
+            }
+            return Ok(DestinationLock {
+                server: server.clone(),
+                lock_path: Some(lock_path),
+                held,
+            });
+        }
+
+        let holder = describe_owner(output);
+        if is_stale(output, &host, unix_now()) {
+            say!(
+                "{}",
+                t!("lock-stale", path = dest_path, holder = holder).yellow()
+            );
+            // Only if it is still the same owner's, so two runs breaking it
+            // at once don't remove the lock one of them then took.
+            let command = format!(
+                "[ \"$(cat {lock}/owner 2>/dev/null)\" = {owner} ] && rm -rf {lock}; true",
+                lock = shell_quote(&lock_path),
+                owner = shell_quote(output),
+            );
+            TransferEngine::remote_command(server, &command, None)?;
+            continue;
+        }
+        if !wait {
+            return Err(t!("lock-busy", path = dest_path, holder = holder));
+        }
+
+        if !announced {
+            say!(
+                "{}",
+                t!("lock-waiting", path = dest_path, holder = holder).yellow()
+            );
+            announced = true;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+pub fn unlock(server: &ServerConfig, dest_path: &str) -> Result<(), String> {
+    let lock_path = lock_path(dest_path);
+    let command = format!(
+        "if [ -d {0} ]; then rm -rf {0} && echo removed; fi",
+        shell_quote(&lock_path)
+    );
+    let output = TransferEngine::remote_command(server, &command, None)?;
+
+    if output.trim() == "removed" {
+        say!("{}", t!("unlocked", path = dest_path).green());
+    } else {
+        say!("{}", t!("not-locked", path = dest_path));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn locks_of_exited_processes_here_are_stale() {
+        let mine = format!("me@box {} {}", std::process::id(), NOW - 60);
+        assert!(!is_stale(&mine, "box", NOW));
+        let mut exited = Command::new("true").spawn().unwrap();
+        let pid = exited.id();
+        exited.wait().unwrap();
+        assert!(is_stale(
+            &format!("me@box {} {}", pid, NOW - 60),
+            "box",
+            NOW
+        ));
+        // Another machine's pid means nothing here.
+        assert!(!is_stale(
+            &format!("me@other {} {}", pid, NOW - 60),
+            "box",
+            NOW
+        ));
+    }
+
+    #[test]
+    fn old_locks_are_stale_wherever_they_came_from() {
+        let old = NOW - STALE_LOCK.as_secs() - 1;
+        assert!(is_stale(&format!("me@other 1 {}", old), "box", NOW));
+        assert!(!is_stale("unknown", "box", NOW));
+    }
+
+    #[test]
+    fn destinations_inside_a_held_one_are_held() {
+        HELD.lock()
+            .unwrap()
+            .push(("deploy@held.test".to_string(), "/srv/site".to_string()));
+        assert!(holds("deploy@held.test", "/srv/site"));
+        assert!(holds("deploy@held.test", "/srv/site/index.html"));
+        assert!(!holds("deploy@held.test", "/srv/site2"));
+        assert!(!holds("deploy@other.test", "/srv/site/index.html"));
+    }
+}
@@ -1,57 +1,290 @@
-use clap::{App, Arg, SubCommand};
+use auth::Auth;
+use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
 use colored::*;
 use dirs::home_dir;
+use location::Location;
+use platform::Platform;
+use preserve::Preserve;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
-use std::io::{self, Write};
-use std::path::Path;
-use std::process::{Command, Stdio};
-
-#[derive(Debug, Serialize, Deserialize)]
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[macro_use]
+mod i18n;
+#[macro_use]
+mod output;
+
+mod adaptive;
+mod archive;
+mod audit;
+mod auth;
+mod backend;
+mod bisync;
+mod blake3;
+mod broadcast;
+mod browse;
+mod check;
+mod chunked;
+mod completions;
+mod crypt;
+mod deadline;
+mod errors;
+mod fairness;
+mod fallback;
+mod flatten;
+mod helper;
+mod history;
+mod hooks;
+mod init;
+mod ledger;
+mod listings;
+mod location;
+mod lock;
+mod migrate;
+mod mirror;
+mod multiplex;
+mod naming;
+mod parquet;
+mod platform;
+mod preserve;
+mod progress;
+mod project;
+mod prompt;
+mod queue;
+mod quota;
+mod recording;
+mod redact;
+mod remote_fs;
+mod resume;
+mod s3;
+mod scratch;
+mod sessions;
+mod snapshot;
+mod templates;
+mod throttle;
+mod tools;
+mod top;
+mod unpack;
+mod verify;
+mod watch;
+mod zip;
+
+/// Seconds between ssh keepalives unless a server sets `keepalive`.
+const DEFAULT_KEEPALIVE: u64 = 15;
+/// Unanswered keepalives before ssh gives up on the connection.
+const KEEPALIVE_COUNT: u32 = 4;
+/// Retries for a transfer that failed on the network, unless `--retries`,
+/// the server's `reconnect` or the config's `retries` say otherwise.
+const DEFAULT_RETRIES: u32 = 3;
+/// First retry delay, doubled for each retry after it up to MAX_BACKOFF.
+const BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ServerConfig {
     host: String,
     user: String,
     key_path: Option<String>,
+    /// An OpenSSH certificate for the key, e.g. one a CA signed for a day.
+    cert_path: Option<String>,
+    auth: Option<Auth>,
     port: Option<u16>,
     default_remote_path: Option<String>,
+    verify: Option<bool>,
+    bandwidth_limit: Option<String>,
+    proxy_jump: Option<String>,
+    /// Seconds between ssh keepalives; 0 turns them off.
+    keepalive: Option<u64>,
+    /// How many times a transfer that fails on the network reconnects and
+    /// resumes before giving up.
+    reconnect: Option<u32>,
+    /// Detected on first connect; see `platform`.
+    os: Option<Platform>,
+    /// Attributes transfers with this server keep, e.g. "perms,times";
+    /// see `preserve`.
+    preserve: Option<String>,
+    /// How names are rewritten on upload, e.g. "sanitize-windows,lowercase";
+    /// see `naming`.
+    on_upload: Option<String>,
+    /// Always encrypt uploads to this server; see `crypt`.
+    encrypt: Option<bool>,
+    /// age or SSH public keys (or files of them) uploads are encrypted to.
+    recipients: Option<Vec<String>>,
+    /// age or SSH identity files downloads are decrypted with.
+    identities: Option<Vec<String>>,
+    /// Replaces the config's `[throttle]` for transfers with this server.
+    /// Tables come after plain values for the TOML serializer.
+    throttle: Option<throttle::Throttle>,
+    /// `proxy_jump` with configured aliases expanded to `user@host:port`
+    /// hops, filled in by `Config::load`.
+    #[serde(skip)]
+    jump_chain: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Config {
-    servers: HashMap<String, ServerConfig>,
+    // Plain values must come before tables for the TOML serializer.
     default_server: Option<String>,
+    /// Language for messages, e.g. "de". Defaults to the system locale.
+    language: Option<String>,
+    /// Always behave as if `--plain-output` was given.
+    plain_output: Option<bool>,
+    /// Where scratch files go. Defaults to `xfer` under the system temp dir.
+    temp_dir: Option<String>,
+    /// Default for `--retries`.
+    retries: Option<u32>,
+    /// Seconds remote listings are cached for; 0 turns the cache off.
+    listing_ttl: Option<u64>,
+    /// Where passwords, passphrases and 2FA codes are asked for.
+    prompt: Option<prompt::Prompt>,
+    /// The askpass helper or pinentry program for `prompt`.
+    prompt_program: Option<String>,
+    /// Always behave as if `--record` was given.
+    record: Option<bool>,
+    /// Where `--record` writes transcripts. Defaults to `recordings` under
+    /// the data dir.
+    record_dir: Option<String>,
+    /// A shell command run when a route or queue job fails; see
+    /// `hooks::notify_failure`.
+    on_failure: Option<String>,
+    servers: HashMap<String, ServerConfig>,
+    #[serde(default)]
+    buckets: HashMap<String, s3::BucketConfig>,
+    #[serde(default)]
+    groups: HashMap<String, Vec<String>>,
+    /// Holding back on battery, metered networks or high load.
+    throttle: Option<throttle::Throttle>,
+    /// How queue jobs make way for foreground transfers.
+    fairness: Option<fairness::Fairness>,
+    /// The `.xfer.toml` whose servers and routes were merged in, if any.
+    #[serde(skip)]
+    project: Option<project::Project>,
 }
 
 impl Config {
     fn load() -> Result<Self, Box<dyn std::error::Error>> {
-        let config_path = home_dir()
-            .unwrap_or_default()
-            .join(".config")
-            .join("xfer")
-            .join("config.toml");
-
-        if !config_path.exists() {
-            return Ok(Config {
+        let config_path = config_dir().join("config.toml");
+
+        let mut config = if config_path.exists() {
+            let content = fs::read_to_string(config_path)?;
+            toml::from_str(&content)?
+        } else {
+            Config {
                 servers: HashMap::new(),
                 default_server: None,
-            });
-        }
+                language: None,
+                plain_output: None,
+                temp_dir: None,
+                retries: None,
+                listing_ttl: None,
+                prompt: None,
+                prompt_program: None,
+                record: None,
+                record_dir: None,
+                on_failure: None,
+                buckets: HashMap::new(),
+                groups: HashMap::new(),
+                throttle: None,
+                fairness: None,
+                project: None,
+            }
+        };
+        project::load(&mut config)?;
 
-        let content = fs::read_to_string(config_path)?;
-        let config: Config = toml::from_str(&content)?;
+        config.register_secrets();
+        let aliases: Vec<String> = config.servers.keys().cloned().collect();
+        for alias in aliases {
+            let chain = config.jump_chain(&alias, &mut vec![alias.clone()])?;
+            config.servers.get_mut(&alias).unwrap().jump_chain = chain;
+        }
         Ok(config)
     }
 
+    /// Key paths and bucket credentials from the config, so error messages,
+    /// history and `--show-cmd` never print them.
+    fn register_secrets(&self) {
+        for server in self.servers.values() {
+            for key in [&server.key_path, &server.cert_path].into_iter().flatten() {
+                redact::register(key);
+                if let Some(rest) = key.strip_prefix("~/") {
+                    redact::register(&home_dir().unwrap_or_default().join(rest).to_string_lossy());
+                }
+            }
+        }
+        for bucket in self.buckets.values() {
+            for secret in [&bucket.access_key_id, &bucket.secret_access_key]
+                .into_iter()
+                .flatten()
+            {
+                redact::register(secret);
+            }
+        }
+    }
+
+    /// Expands a server's `proxy_jump` into the comma-separated hop list
+    /// `ssh -J` expects. Hops naming another alias pull in that server's own
+    /// jump hosts first, so chains can be built from aliases.
+    fn jump_chain(&self, alias: &str, seen: &mut Vec<String>) -> Result<Option<String>, String> {
+        let proxy_jump = match self.servers.get(alias).and_then(|s| s.proxy_jump.as_ref()) {
+            Some(proxy_jump) => proxy_jump,
+            None => return Ok(None),
+        };
+
+        let mut hops = Vec::new();
+        for hop in proxy_jump.split(',').map(str::trim) {
+            let jump = match self.servers.get(hop) {
+                Some(jump) => jump,
+                None => {
+                    hops.push(hop.to_string());
+                    continue;
+                }
+            };
+
+            if seen.iter().any(|s| s == hop) {
+                return Err(format!("proxy_jump loop: {} -> {}", seen.join(" -> "), hop));
+            }
+            seen.push(hop.to_string());
+            if let Some(inner) = self.jump_chain(hop, seen)? {
+                hops.push(inner);
+            }
+            seen.pop();
+
+            hops.push(match jump.port {
+                Some(port) => format!("{}@{}:{}", jump.user, jump.host, port),
+                None => format!("{}@{}", jump.user, jump.host),
+            });
+        }
+        Ok(Some(hops.join(",")))
+    }
+
     fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let config_dir = home_dir().unwrap_or_default().join(".config").join("xfer");
+        let config_dir = config_dir();
 
         if !config_dir.exists() {
             fs::create_dir_all(&config_dir)?;
         }
 
         let config_path = config_dir.join("config.toml");
-        let content = toml::to_string_pretty(self)?;
+        let content = match &self.project {
+            // Servers from a .xfer.toml stay in the project.
+            Some(project) if !project.shadowed.is_empty() => {
+                let mut value = toml::Value::try_from(self)?;
+                if let Some(servers) = value.get_mut("servers").and_then(|s| s.as_table_mut()) {
+                    for (alias, global) in &project.shadowed {
+                        servers.remove(alias);
+                        if let Some(global) = global {
+                            servers.insert(alias.clone(), toml::Value::try_from(global)?);
+                        }
+                    }
+                }
+                toml::to_string_pretty(&value)?
+            }
+            _ => toml::to_string_pretty(self)?,
+        };
         fs::write(config_path, content)?;
         Ok(())
     }
@@ -59,320 +292,1223 @@ impl Config {
     fn get_server(&self, alias: &str) -> Option<&ServerConfig> {
         self.servers.get(alias)
     }
+
+    /// The throttle rules for a transfer with `server`: its own, or else the
+    /// config's.
+    fn throttle_for<'a>(
+        &'a self,
+        server: Option<&'a ServerConfig>,
+    ) -> Option<&'a throttle::Throttle> {
+        server
+            .and_then(|server| server.throttle.as_ref())
+            .or(self.throttle.as_ref())
+    }
+
+    fn require_server(&self, alias: &str) -> Result<&ServerConfig, String> {
+        self.get_server(alias)
+            .ok_or_else(|| t!("unknown-server", alias = alias))
+    }
+}
+
+fn config_dir() -> PathBuf {
+    home_dir().unwrap_or_default().join(".config").join("xfer")
+}
+
+fn data_dir() -> PathBuf {
+    home_dir()
+        .unwrap_or_default()
+        .join(".local")
+        .join("share")
+        .join("xfer")
+}
+
+fn cache_dir() -> PathBuf {
+    home_dir().unwrap_or_default().join(".cache").join("xfer")
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn local_size(path: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+
+    if metadata.is_dir() {
+        fs::read_dir(path)
+            .map(|entries| entries.flatten().map(|e| local_size(&e.path())).sum())
+            .unwrap_or(0)
+    } else {
+        metadata.len()
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct TransferOptions {
+    delete: bool,
+    verify: bool,
+    resume: bool,
+    task: Option<String>,
+    full: bool,
+    wait: bool,
+    limit: Option<String>,
+    flatten: bool,
+    matching: Option<String>,
+    max_parallel: Option<usize>,
+    parallel: Option<usize>,
+    archive: bool,
+    /// `--archive=zip` or `--archive=tar`; a bare `--archive` is tar.
+    archive_format: Option<String>,
+    /// Unpack a fetched .tar.gz/.zip into the destination as it streams.
+    unpack: bool,
+    /// Switch a slow run of many sources to one tar stream; see `adaptive`.
+    adaptive: bool,
+    /// Encrypt uploads with age, to `recipients` or with a passphrase.
+    encrypt: bool,
+    recipients: Vec<String>,
+    passphrase: bool,
+    /// `--decrypt`/`--no-decrypt`; by default `.age` downloads are decrypted.
+    decrypt: Option<bool>,
+    identities: Vec<String>,
+    compress: Option<String>,
+    level: Option<u32>,
+    retries: Option<u32>,
+    no_throttle: bool,
+    /// E.g. "2h"; see `deadline`.
+    max_duration: Option<String>,
+    /// `--preserve` and `--no-preserve` lists.
+    preserve: Option<String>,
+    no_preserve: Option<String>,
+    /// Show what `delete` would remove and ask first; `sync` sets this
+    /// unless `--force` is given.
+    #[serde(skip)]
+    confirm_delete: bool,
+    #[serde(skip)]
+    files_from: Option<String>,
+    /// Effective rate in bytes per second, resolved from `limit` and the
+    /// server's `bandwidth_limit` for the transfer at hand.
+    #[serde(skip)]
+    bwlimit: Option<u64>,
+    /// What to keep, resolved from `preserve`, `no_preserve` and the
+    /// server's `preserve`.
+    #[serde(skip)]
+    preserved: Preserve,
+    /// The queue job this transfer runs for, when the daemon started it.
+    #[serde(skip)]
+    job: Option<u64>,
+}
+
+fn transfer_options(matches: &ArgMatches) -> TransferOptions {
+    TransferOptions {
+        delete: matches.is_present("delete"),
+        verify: matches.is_present("verify"),
+        resume: matches.is_present("resume"),
+        task: matches.value_of("task").map(String::from),
+        full: matches.is_present("full"),
+        wait: matches.is_present("wait"),
+        limit: matches.value_of("limit").map(String::from),
+        flatten: matches.is_present("flatten"),
+        matching: matches.value_of("match").map(String::from),
+        max_parallel: matches
+            .value_of("max-parallel")
+            .and_then(|n| n.parse().ok()),
+        parallel: matches.value_of("parallel").and_then(|n| n.parse().ok()),
+        archive: matches.is_present("archive"),
+        archive_format: matches.value_of("archive").map(String::from),
+        unpack: matches.is_present("unpack"),
+        adaptive: matches.is_present("adaptive"),
+        encrypt: matches.is_present("encrypt")
+            || matches.is_present("recipient")
+            || matches.is_present("passphrase"),
+        recipients: matches
+            .values_of("recipient")
+            .map(|v| v.map(String::from).collect())
+            .unwrap_or_default(),
+        passphrase: matches.is_present("passphrase"),
+        decrypt: if matches.is_present("decrypt") {
+            Some(true)
+        } else if matches.is_present("no-decrypt") {
+            Some(false)
+        } else {
+            None
+        },
+        identities: matches
+            .values_of("identity")
+            .map(|v| v.map(String::from).collect())
+            .unwrap_or_default(),
+        compress: matches.value_of("compress").map(String::from),
+        level: matches.value_of("level").and_then(|n| n.parse().ok()),
+        retries: matches.value_of("retries").and_then(|n| n.parse().ok()),
+        no_throttle: matches.is_present("no-throttle"),
+        max_duration: matches.value_of("max-duration").map(String::from),
+        // A bare --preserve or --no-preserve means every attribute.
+        preserve: matches
+            .is_present("preserve")
+            .then(|| matches.value_of("preserve").unwrap_or_default().to_string()),
+        no_preserve: matches.is_present("no-preserve").then(|| {
+            matches
+                .value_of("no-preserve")
+                .unwrap_or_default()
+                .to_string()
+        }),
+        confirm_delete: false,
+        files_from: None,
+        bwlimit: None,
+        preserved: Preserve::default(),
+        job: None,
+    }
+}
+
+/// Parses a rate such as "500K" or "5M" into bytes per second. Suffixes are
+/// binary, as in rsync's `--bwlimit`.
+fn parse_rate(rate: &str) -> Result<u64, String> {
+    let rate = rate.trim();
+    let (number, multiplier) = match rate.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&rate[..rate.len() - 1], 1u64 << 10),
+        Some('M') => (&rate[..rate.len() - 1], 1u64 << 20),
+        Some('G') => (&rate[..rate.len() - 1], 1u64 << 30),
+        _ => (rate, 1),
+    };
+
+    match number.parse::<f64>() {
+        Ok(n) if n > 0.0 => Ok((n * multiplier as f64) as u64),
+        _ => Err(format!(
+            "Invalid rate '{}': expected bytes per second with an optional K, M or G suffix (e.g. 5M)",
+            rate
+        )),
+    }
+}
+
+/// Parses a duration such as "90s", "30m", "2h" or "1d"; a bare number is
+/// seconds.
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let (number, unit) = match duration.chars().last() {
+        Some('s') => (&duration[..duration.len() - 1], 1),
+        Some('m') => (&duration[..duration.len() - 1], 60),
+        Some('h') => (&duration[..duration.len() - 1], 60 * 60),
+        Some('d') => (&duration[..duration.len() - 1], 24 * 60 * 60),
+        _ => (duration, 1),
+    };
+    match number.trim().parse::<u64>() {
+        Ok(n) if n > 0 => Ok(Duration::from_secs(n * unit)),
+        _ => Err(format!(
+            "Invalid duration '{}' (use e.g. 90s, 30m, 2h or 1d)",
+            duration
+        )),
+    }
+}
+
+/// Lists what a `--delete` transfer would remove from `dest`.
+fn show_deletions(dest: &str, paths: &[String]) {
+    const SHOWN: usize = 20;
+    say!(
+        "{} {}",
+        t!("will-delete").red().bold(),
+        t!("will-delete-paths", count = paths.len(), dest = dest)
+    );
+    for path in paths.iter().take(SHOWN) {
+        say!("  {} {}", "-".red(), path);
+    }
+    if paths.len() > SHOWN {
+        say!("  {}", t!("and-more", count = paths.len() - SHOWN));
+    }
+}
+
+/// Shows what a `--delete` transfer is about to remove from `dest` and asks
+/// before going ahead.
+fn confirm_deletions(dest: &str, paths: &[String]) -> Result<(), String> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+    show_deletions(dest, paths);
+    if confirm(&t!("delete-them")) {
+        Ok(())
+    } else {
+        Err(t!("delete-declined"))
+    }
+}
+
+/// Asks a yes/no question on the terminal. Without one, the answer is no.
+fn confirm(question: &str) -> bool {
+    if !console::user_attended() {
+        return false;
+    }
+    print!("{} (y/n): ", question);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y")
+}
+
+/// Quotes a string for use as a single argument in a remote shell command.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Quotes a remote glob for the shell, leaving only `*`, `?` and simple
+/// bracket expressions like `[0-9]` for it to expand. Everything else,
+/// spaces and `$(...)` included, is taken literally.
+fn glob_quote(pattern: &str) -> String {
+    let mut quoted = String::new();
+    let mut literal = String::new();
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        let special = match c {
+            '*' | '?' => Some(c.len_utf8()),
+            '[' => rest.find(']').filter(|&end| {
+                end > 1
+                    && rest[1..end]
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "!^-_.".contains(c))
+            }),
+            _ => None,
+        };
+        match special {
+            Some(len) => {
+                let len = if c == '[' { len + 1 } else { len };
+                if !literal.is_empty() {
+                    quoted.push_str(&shell_quote(&literal));
+                    literal.clear();
+                }
+                quoted.push_str(&rest[..len]);
+                rest = &rest[len..];
+            }
+            None => {
+                literal.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if !literal.is_empty() {
+        quoted.push_str(&shell_quote(&literal));
+    }
+    quoted
 }
 
 struct TransferEngine;
 
+/// A started tool. Its stderr is passed through live and also kept, so a
+/// failure can be explained.
+struct Running {
+    child: Child,
+    stderr: Option<std::thread::JoinHandle<String>>,
+    record: Option<recording::Entry>,
+}
+
 impl TransferEngine {
-    fn parse_location(
-        location_str: &str,
+    fn send_files(
+        sources: &[&str],
+        dest: &str,
         config: &Config,
-    ) -> Result<(String, String, String), String> {
-        if !location_str.contains(':') {
-            return Ok((
-                "local".to_string(),
-                "".to_string(),
-                location_str.to_string(),
-            ));
+        opts: &TransferOptions,
+    ) -> Result<(), String> {
+        let mut expanded = Vec::new();
+        for src in sources {
+            expanded.extend(Self::expand_remote_glob(src, config)?);
+        }
+
+        if expanded.is_empty() {
+            return Err(t!("no-files-matched", sources = sources.join(", ")));
+        }
+
+        // The queue retries its own jobs; `xfer resume` is for the terminal.
+        if opts.job.is_none() {
+            resume::record(sources, dest, opts);
+        }
+        let started = Instant::now();
+        let mut flattened_bytes = None;
+        let result = if opts.flatten {
+            flatten::get(&expanded, dest, config, opts).map(|bytes| flattened_bytes = Some(bytes))
+        } else if broadcast::parse_group(dest).is_some() {
+            broadcast::send(&expanded, dest, config, opts)
+        } else {
+            Self::send_to(&expanded, dest, config, opts)
+        };
+        if result.is_ok() && opts.job.is_none() {
+            resume::clear();
+        }
+
+        let bytes = flattened_bytes.unwrap_or_else(|| {
+            if opts.unpack {
+                local_size(Path::new(dest))
+            } else {
+                Self::transferred_bytes(&expanded, dest)
+            }
+        });
+        let elapsed = started.elapsed();
+        if result.is_ok() {
+            progress::summary(bytes, elapsed);
+        }
+
+        let sources: Vec<String> = sources.iter().map(|s| s.to_string()).collect();
+        history::record(
+            &sources,
+            dest,
+            bytes,
+            elapsed.as_millis() as u64,
+            &result,
+            opts,
+        );
+        Self::report(&sources, dest, bytes, elapsed, &result);
+        result
+    }
+
+    /// `send_file` for a single source, recorded in the history the way
+    /// `send_files` records its runs so `xfer redo` can repeat it.
+    fn send_recorded(
+        src: &str,
+        dest: &str,
+        config: &Config,
+        opts: &TransferOptions,
+    ) -> Result<(), String> {
+        let started = Instant::now();
+        let result = Self::send_file(src, dest, config, opts);
+        let sources = [src.to_string()];
+        let bytes = Self::transferred_bytes(&sources, dest);
+        let elapsed = started.elapsed();
+        history::record(
+            &sources,
+            dest,
+            bytes,
+            elapsed.as_millis() as u64,
+            &result,
+            opts,
+        );
+        Self::report(&sources, dest, bytes, elapsed, &result);
+        result
+    }
+
+    /// Bytes moved, measured on the local side once the transfer is done.
+    fn transferred_bytes(sources: &[String], dest: &str) -> u64 {
+        sources
+            .iter()
+            .map(|src| {
+                if src.contains(':') {
+                    let target = verify::local_target(dest, src);
+                    let decrypted = verify::local_target(dest, crypt::decrypted_name(src));
+                    local_size(if target.exists() { &target } else { &decrypted })
+                } else {
+                    local_size(Path::new(src))
+                }
+            })
+            .sum()
+    }
+
+    /// The transfer summary for `--json`.
+    fn report(
+        sources: &[String],
+        dest: &str,
+        bytes: u64,
+        elapsed: Duration,
+        result: &Result<(), String>,
+    ) {
+        if output::is_json() {
+            output::emit(&output::TransferReport {
+                status: if result.is_ok() { "ok" } else { "failed" },
+                sources,
+                destination: dest,
+                bytes,
+                duration_ms: elapsed.as_millis() as u64,
+                error: result.as_ref().err().map(String::as_str),
+            });
+        }
+    }
+
+    fn send_to(
+        expanded: &[String],
+        dest: &str,
+        config: &Config,
+        opts: &TransferOptions,
+    ) -> Result<(), String> {
+        match &opts.task {
+            Some(task) => ledger::send(task, expanded, dest, config, opts),
+            None => Self::send_expanded(expanded, dest, config, opts),
         }
+    }
 
-        let parts: Vec<&str> = location_str.splitn(2, ':').collect();
-        if parts.len() != 2 {
-            return Err("Invalid location format. Use 'alias:/path/to/file'".to_string());
+    fn send_expanded(
+        expanded: &[String],
+        dest: &str,
+        config: &Config,
+        opts: &TransferOptions,
+    ) -> Result<(), String> {
+        if expanded.len() == 1 {
+            return Self::send_file(&expanded[0], dest, config, opts);
         }
+        // Once for the lot; each file's send finds it already held.
+        let _lock = Self::lock_destination(&Location::parse(dest, config)?, opts)?;
+
+        let mut monitor = adaptive::Monitor::new(expanded, dest, config, opts)?;
+        let mut failures = Vec::new();
+        let mut queue: VecDeque<String> = expanded.iter().cloned().collect();
+        while let Some(src) = queue.pop_front() {
+            let target = Self::batch_target(&src, dest);
+            let started = Instant::now();
+            match Self::send_file(&src, &target, config, opts) {
+                Ok(()) => {
+                    if let Some(monitor) = &mut monitor {
+                        monitor.sent(&src, &target, started.elapsed());
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}: {}: {}", t!("error").red().bold(), src, e);
+                    failures.push(src);
+                }
+            }
 
-        let alias = parts[0];
-        let path = parts[1];
+            let slow = match &mut monitor {
+                Some(monitor) if !queue.is_empty() => monitor.too_slow(),
+                _ => None,
+            };
+            if let Some((rate, link)) = slow {
+                // A run switches once; the rest is never judged again.
+                let monitor = monitor.take().expect("only a monitor finds a run slow");
+                let (batch, rest) = monitor.batchable(queue.drain(..).collect());
+                queue.extend(rest);
+                if batch.is_empty() {
+                    continue;
+                }
+                monitor.announce(rate, link, batch.len());
+                if let Err(e) = monitor.send(&batch) {
+                    eprintln!("{}: {}", t!("error").red().bold(), e);
+                    failures.extend(batch);
+                }
+            }
+        }
 
-        let server = config.get_server(alias).ok_or_else(|| {
-            format!(
-                "Unknown server alias '{}'. Add it to your config first.",
-                alias
-            )
-        })?;
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(t!(
+                "batch-failed",
+                failed = failures.len(),
+                total = expanded.len(),
+                errors = failures.join(", ")
+            ))
+        }
+    }
 
-        let remote_path = if path.starts_with('/') {
-            path.to_string()
-        } else if let Some(default_path) = &server.default_remote_path {
-            format!("{}/{}", default_path, path)
+    /// With several sources the destination is a directory, so a directory
+    /// source without a trailing slash lands inside it under its own name,
+    /// like rsync does.
+    fn batch_target(src: &str, dest: &str) -> String {
+        if !src.contains(':') && !src.ends_with('/') && Path::new(src).is_dir() {
+            let name = Path::new(src)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            format!("{}/{}", dest.trim_end_matches('/'), name)
         } else {
-            format!("/home/{}/{}", server.user, path)
+            dest.to_string()
+        }
+    }
+
+    /// Expands a glob in a remote location (`alias:/var/log/*.gz`) into one
+    /// location per match. Local paths are returned untouched since the shell
+    /// has already expanded them.
+    fn expand_remote_glob(location: &str, config: &Config) -> Result<Vec<String>, String> {
+        let (alias, server, path) = match Location::parse(location, config)? {
+            Location::Remote {
+                alias,
+                server,
+                path,
+            } if path.to_string().contains(['*', '?', '[']) => (alias, server, path.to_string()),
+            _ => return Ok(vec![location.to_string()]),
         };
+        if Platform::of(server)?.is_windows() {
+            return Err(t!("glob-windows"));
+        }
+        let output =
+            Self::remote_command(server, &format!("ls -1d -- {}", glob_quote(&path)), None)
+                .map_err(|_| t!("no-remote-match", location = location))?;
+
+        Ok(output
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| format!("{}:{}", alias, line))
+            .collect())
+    }
 
-        Ok((alias.to_string(), server.host.clone(), remote_path))
-    }
-
-    fn send_file(src: &str, dest: &str, config: &Config) -> Result<(), String> {
-        let (src_alias, src_host, src_path) = Self::parse_location(src, config)?;
-        let (dest_alias, dest_host, dest_path) = Self::parse_location(dest, config)?;
-
-        if src_alias == "local" && dest_alias != "local" {
-            let server = config.get_server(&dest_alias).unwrap();
-            Self::transfer_to_remote(
-                src_path,
-                &dest_host,
-                &server.user,
-                &dest_path,
-                server.key_path.as_deref(),
-                server.port,
-            )
-        } else if src_alias != "local" && dest_alias == "local" {
-            let server = config.get_server(&src_alias).unwrap();
-            Self::transfer_from_remote(
-                &src_host,
-                &server.user,
-                &src_path,
-                dest_path,
-                server.key_path.as_deref(),
-                server.port,
-            )
-        } else if src_alias == "local" && dest_alias == "local" {
-            Self::transfer_local_to_local(src_path, dest_path)
-        } else {
+    /// Locks a remote destination against other xfer runs. Locks live in
+    /// /tmp and need a POSIX shell, so Windows servers go without.
+    fn lock_destination(
+        dest: &Location,
+        opts: &TransferOptions,
+    ) -> Result<Option<lock::DestinationLock>, String> {
+        match dest.server() {
+            Some(server) if !Platform::of(server)?.is_windows() => {
+                Ok(Some(lock::acquire(server, &dest.path(), opts.wait)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn send_file(
+        src: &str,
+        dest: &str,
+        config: &Config,
+        opts: &TransferOptions,
+    ) -> Result<(), String> {
+        deadline::start(opts.max_duration.as_deref())?;
+        let src_location = Location::parse(src, config)?;
+        let dest_location = Location::parse(dest, config)?;
+        let src_path = src_location.path();
+        let dest_path = dest_location.path();
+
+        let total_bytes = match &src_location {
+            Location::Local(path) => Some(local_size(path)),
+            _ => None,
+        };
+        let _lock = Self::lock_destination(&dest_location, opts)?;
+        let _session = sessions::begin(src, dest, total_bytes);
+        // Names the destination won't take are rewritten in a linked copy
+        // of the source, which is sent (and verified) in its place.
+        let view = match (&src_location, naming::policy(&dest_location, config)) {
+            (Location::Local(_), Some(list)) => naming::view(Path::new(&src_path), list, dest)?,
+            _ => None,
+        };
+        let src_path = match &view {
+            Some(view) => view.path.to_string_lossy().into_owned(),
+            None => src_path,
+        };
+        let server = dest_location.server().or_else(|| src_location.server());
+        let mut opts = TransferOptions {
+            bwlimit: Self::bandwidth_limit(opts, &src_location, &dest_location)?,
+            preserved: Preserve::resolve(opts, server)?,
+            ..opts.clone()
+        };
+        throttle::adjust(&mut opts, config.throttle_for(server))?;
+        let opts = &opts;
+
+        let object_storage = src_location.is_url() || dest_location.is_url();
+        if opts.verify && object_storage {
+            return Err(t!("verify-s3"));
+        }
+        if opts.archive && object_storage {
+            return Err(t!("archive-s3"));
+        }
+        if opts.unpack && (object_storage || src_location.server().is_none()) {
+            return Err(t!("unpack-needs-ssh"));
+        }
+        if (opts.encrypt && dest_location.server().is_none())
+            || (opts.decrypt == Some(true) && src_location.server().is_none())
+        {
+            return Err(t!("encrypt-needs-ssh"));
+        }
+        if opts.verify && archive::keeps_archive(&dest_path, opts) {
+            return Err(t!("verify-kept-archive"));
+        }
+
+        let src_backend = backend::for_location(&src_location, config);
+        let dest_backend = backend::for_location(&dest_location, config);
+        if opts.delete && opts.confirm_delete {
+            let deletions =
+                Self::deletions(&src_location, &dest_location, &src_path, &dest_path, config)?;
+            confirm_deletions(dest, &deletions)?;
+        }
+        let transfer = |opts: &TransferOptions| match (&src_backend, &dest_backend) {
+            (None, Some(dest_backend)) => dest_backend.upload(&src_path, &dest_path, opts),
+            (Some(src_backend), None) => src_backend.download(&src_path, &dest_path, opts),
+            (None, None) => {
+                Self::transfer_local_to_local(src_path.clone(), dest_path.clone(), opts)
+            }
             // TODO: Remote to remote transfer
-            Err("Direct remote-to-remote transfers not supported yet".to_string())
+            (Some(_), Some(_)) => Err(t!("remote-to-remote")),
+        };
+
+        // Network failures are retried with growing delays, resuming with
+        // rsync so the part already sent isn't sent twice. Permanent ones
+        // (missing files, refused logins) fail straight away.
+        let attempts = opts
+            .retries
+            .or_else(|| {
+                src_location
+                    .server()
+                    .or(dest_location.server())
+                    .and_then(|s| s.reconnect)
+            })
+            .or(config.retries)
+            .unwrap_or(DEFAULT_RETRIES);
+        errors::retryable();
+        let mut result = transfer(opts);
+        let mut attempt = 0;
+        while attempt < attempts && errors::retryable() && !deadline::expired() {
+            let error = match &result {
+                Ok(()) => break,
+                Err(e) => e.lines().next().unwrap_or_default().to_string(),
+            };
+            attempt += 1;
+            let delay = BACKOFF
+                .saturating_mul(1 << (attempt - 1).min(16))
+                .min(MAX_BACKOFF);
+            eprintln!(
+                "{} {}",
+                "⟳".yellow(),
+                t!(
+                    "retrying",
+                    reason = error,
+                    delay = delay.as_secs(),
+                    attempt = attempt,
+                    attempts = attempts
+                )
+            );
+            std::thread::sleep(delay);
+            result = transfer(&TransferOptions {
+                resume: true,
+                ..opts.clone()
+            });
+        }
+        result.map_err(deadline::explain)?;
+
+        // An unpacked archive has nothing on the server to compare with, and
+        // an encrypted file never matches its plaintext.
+        let should_verify =
+            |server: &ServerConfig| !opts.unpack && (opts.verify || server.verify.unwrap_or(false));
+
+        match (src_location.server(), dest_location.server()) {
+            (None, Some(server))
+                if should_verify(server) && !crypt::wanted_for_upload(server, opts) =>
+            {
+                verify::verify(Path::new(&src_path), server, &dest_path)?;
+            }
+            (Some(server), None)
+                if should_verify(server) && !crypt::wanted_for_download(&src_path, opts) =>
+            {
+                let local = verify::local_target(&dest_path, &src_path);
+                verify::verify(&local, server, &src_path)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// `--limit` wins over the configured `bandwidth_limit` of whichever
+    /// server is on the other end.
+    fn bandwidth_limit(
+        opts: &TransferOptions,
+        src: &Location,
+        dest: &Location,
+    ) -> Result<Option<u64>, String> {
+        if let Some(limit) = &opts.limit {
+            return parse_rate(limit).map(Some);
+        }
+
+        match dest.remote().or_else(|| src.remote()) {
+            Some((alias, server)) => match server.bandwidth_limit.as_deref() {
+                Some(limit) => parse_rate(limit)
+                    .map(Some)
+                    .map_err(|e| format!("bandwidth_limit for '{}': {}", alias, e)),
+                None => Ok(None),
+            },
+            None => Ok(None),
         }
     }
 
     fn transfer_to_remote(
         local_path: String,
-        host: &str,
-        user: &str,
+        server: &ServerConfig,
         remote_path: &str,
-        key_path: Option<&str>,
-        port: Option<u16>,
+        opts: &TransferOptions,
     ) -> Result<(), String> {
         let path = Path::new(&local_path);
-
-        if path.is_dir() {
-            Self::run_rsync(
-                &format!("{}/", local_path),
-                &format!("{}@{}:{}", user, host, remote_path),
-                key_path,
-                port,
-            )
+        let remote = format!("{}@{}:{}", server.user, server.host, remote_path);
+
+        if crypt::wanted_for_upload(server, opts) {
+            crypt::upload(path, server, remote_path, opts)
+        } else if opts.archive && path.is_dir() {
+            archive::upload(path, server, remote_path, opts)
+        } else if path.is_dir() && !tools::has("rsync") {
+            fallback::upload_dir(path, server, remote_path, opts)
+        } else if path.is_dir() {
+            Self::run_rsync(&format!("{}/", local_path), &remote, server, opts)
+        } else if chunked::applies(path, opts) {
+            chunked::upload(path, server, remote_path, opts)
+        } else if (opts.resume || !opts.preserved.scp_can(Some(path))) && tools::has("rsync") {
+            Self::run_rsync(&local_path, &remote, server, opts)
+        } else if opts.resume || !opts.preserved.scp_can(Some(path)) {
+            fallback::scp_instead(opts)?;
+            Self::run_scp(&local_path, &remote, server, opts, None)
         } else {
-            Self::run_scp(
-                &local_path,
-                &format!("{}@{}:{}", user, host, remote_path),
-                key_path,
-                port,
-            )
+            Self::run_scp(&local_path, &remote, server, opts, None)
         }
     }
 
     fn transfer_from_remote(
-        host: &str,
-        user: &str,
+        server: &ServerConfig,
         remote_path: &str,
         local_path: String,
-        key_path: Option<&str>,
-        port: Option<u16>,
+        opts: &TransferOptions,
     ) -> Result<(), String> {
+        let remote = format!("{}@{}:{}", server.user, server.host, remote_path);
+
+        if opts.unpack {
+            return unpack::unpack(server, remote_path, &local_path, opts);
+        }
+        if crypt::wanted_for_download(remote_path, opts) {
+            return crypt::download(server, remote_path, &local_path, opts);
+        }
+        if opts.archive {
+            return archive::download(server, remote_path, &local_path, opts);
+        }
+        // scp can't remove anything, so --delete goes through rsync.
+        if opts.resume || opts.delete || !opts.preserved.scp_can(None) {
+            if tools::has("rsync") {
+                return Self::run_rsync(&remote, &local_path, server, opts);
+            }
+            fallback::scp_instead(opts)?;
+        }
+
         Self::run_scp(
-            &format!("{}@{}:{}", user, host, remote_path),
+            &remote,
             &local_path,
-            key_path,
-            port,
+            server,
+            opts,
+            Some(verify::local_target(&local_path, remote_path)),
         )
     }
 
-    fn transfer_local_to_local(src: String, dest: String) -> Result<(), String> {
+    fn transfer_local_to_local(
+        src: String,
+        dest: String,
+        opts: &TransferOptions,
+    ) -> Result<(), String> {
         let path = Path::new(&src);
 
-        if path.is_dir() {
-            let output = Command::new("rsync")
-                .args(&["-av", "--progress", &src, &dest])
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit())
-                .output()
-                .map_err(|e| format!("Failed to execute rsync: {}", e))?;
-
-            if !output.status.success() {
-                return Err(format!(
-                    "rsync failed with exit code: {:?}",
-                    output.status.code()
-                ));
+        if path.is_dir() && !tools::has("rsync") {
+            fallback::copy_tree(&src, &dest, opts)
+        } else if path.is_dir() {
+            let flags = opts.preserved.rsync_flags();
+            let mut args = vec![flags.as_str()];
+            if opts.delete {
+                args.push("--delete");
             }
-        } else {
-            let output = Command::new("cp")
-                .args(&[&src, &dest])
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit())
-                .output()
-                .map_err(|e| format!("Failed to execute cp: {}", e))?;
-
-            if !output.status.success() {
-                return Err(format!(
-                    "cp failed with exit code: {:?}",
-                    output.status.code()
-                ));
+            let bwlimit_storage;
+            if let Some(rate) = opts.bwlimit {
+                bwlimit_storage = format!("--bwlimit={}", (rate / 1024).max(1));
+                args.push(&bwlimit_storage);
             }
+            args.extend([src.as_str(), dest.as_str()]);
+            Self::run_rsync_command(args, None)
+        } else {
+            opts.preserved.copy_local(path, Path::new(&dest))
         }
-
-        Ok(())
     }
 
     fn run_rsync(
         src: &str,
         dest: &str,
-        key_path: Option<&str>,
-        port: Option<u16>,
+        server: &ServerConfig,
+        opts: &TransferOptions,
     ) -> Result<(), String> {
-        let mut args = vec!["-avz", "--progress"];
+        let flags = format!("{}z", opts.preserved.rsync_flags());
+        let mut args = vec![flags.as_str()];
+        if opts.delete {
+            args.push("--delete");
+        }
+        if opts.resume {
+            args.extend(["--partial", "--append-verify"]);
+        } else if opts.max_duration.is_some() {
+            // Keep what was sent if time runs out, for `xfer resume`.
+            args.push("--partial");
+        }
+        let files_from_storage;
+        if let Some(list) = &opts.files_from {
+            files_from_storage = format!("--files-from={}", list);
+            args.push(&files_from_storage);
+        }
+        // rsync takes KiB/s.
+        let bwlimit_storage;
+        if let Some(rate) = opts.bwlimit {
+            bwlimit_storage = format!("--bwlimit={}", (rate / 1024).max(1));
+            args.push(&bwlimit_storage);
+        }
         let ssh_cmd_storage;
-
-        if let Some(key) = key_path {
-            args.push("-e");
-            if let Some(p) = port {
-                ssh_cmd_storage = format!("ssh -i {} -p {}", key, p);
-            } else {
-                ssh_cmd_storage = format!("ssh -i {}", key);
-            }
-            args.push(&ssh_cmd_storage);
-        } else if let Some(p) = port {
+        let connection = Self::connection_args(server, "-p");
+        if !connection.is_empty() {
             args.push("-e");
-            ssh_cmd_storage = format!("ssh -p {}", p);
+            ssh_cmd_storage = format!("ssh {}", connection.join(" "));
             args.push(&ssh_cmd_storage);
         }
 
         args.push(src);
         args.push(dest);
 
-        let output = Command::new("rsync")
-            .args(&args)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
+        Self::run_rsync_command(args, Some(server))
+    }
+
+    /// What a `--delete` transfer between the two locations would remove,
+    /// found with a dry run of the backend that changes nothing.
+    fn deletions(
+        src: &Location,
+        dest: &Location,
+        src_path: &str,
+        dest_path: &str,
+        config: &Config,
+    ) -> Result<Vec<String>, String> {
+        match (
+            backend::for_location(src, config),
+            backend::for_location(dest, config),
+        ) {
+            (None, Some(dest_backend)) => dest_backend.upload_deletions(src_path, dest_path),
+            (Some(src_backend), None) => src_backend.download_deletions(src_path, dest_path),
+            (None, None) if Path::new(src_path).is_dir() => {
+                Self::rsync_deletions(src_path, dest_path, None)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// What `rsync --delete` from `src` to `dest` would remove, found with a
+    /// dry run that changes nothing.
+    fn rsync_deletions(
+        src: &str,
+        dest: &str,
+        server: Option<&ServerConfig>,
+    ) -> Result<Vec<String>, String> {
+        if !tools::has("rsync") {
+            return match server {
+                Some(_) => Err(fallback::needs_rsync("--delete")),
+                None => Ok(fallback::deletions(src, dest)),
+            };
+        }
+        let mut command = Command::new("rsync");
+        command.args(["-a", "--delete", "--dry-run", "--itemize-changes"]);
+        if let Some(server) = server {
+            auth::prepare(&mut command, server)?;
+            let connection = Self::connection_args(server, "-p");
+            if !connection.is_empty() {
+                command
+                    .arg("-e")
+                    .arg(format!("ssh {}", connection.join(" ")));
+            }
+        }
+        command.args([src, dest]);
+        output::show_command(&command);
+
+        let output = command
             .output()
             .map_err(|e| format!("Failed to execute rsync: {}", e))?;
-
         if !output.status.success() {
-            return Err(format!(
-                "rsync failed with exit code: {:?}",
-                output.status.code()
+            return Err(errors::describe(
+                "rsync",
+                output.status.code(),
+                &String::from_utf8_lossy(&output.stderr),
             ));
         }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.strip_prefix("*deleting"))
+            .map(|path| path.trim().to_string())
+            .collect())
+    }
 
-        Ok(())
+    /// Runs rsync with a progress bar when the local rsync can report
+    /// whole-transfer progress (3.1+), or with its own per-file output if not.
+    fn run_rsync_command(mut args: Vec<&str>, server: Option<&ServerConfig>) -> Result<(), String> {
+        if !progress::rsync_has_progress2() {
+            // --progress redraws its line with '\r'.
+            if !progress::is_plain() {
+                args.insert(1, "--progress");
+            }
+            return Self::run_command("rsync", &args, server);
+        }
+
+        args.insert(1, "--info=progress2");
+        let mut running = Self::spawn("rsync", &args, Stdio::piped(), server)?;
+        if let Some(stdout) = running.child.stdout.take() {
+            progress::track_rsync(stdout, sessions::total_bytes());
+        }
+        Self::finish("rsync", running)
     }
 
     fn run_scp(
         src: &str,
         dest: &str,
-        key_path: Option<&str>,
-        port: Option<u16>,
+        server: &ServerConfig,
+        opts: &TransferOptions,
+        watch: Option<PathBuf>,
     ) -> Result<(), String> {
-        let mut args = Vec::new();
-        let port_str_storage;
+        let mut args = Self::connection_args(server, "-P");
+        // Callers go through rsync when -p can't keep exactly what's asked.
+        if opts.preserved.perms {
+            args.push("-p".to_string());
+        }
 
-        if let Some(key) = key_path {
-            args.push("-i");
-            args.push(key);
+        // scp's own meter redraws in place when it has a terminal.
+        if progress::is_plain() {
+            args.push("-q".to_string());
         }
 
-        if let Some(p) = port {
-            args.push("-P");
-            port_str_storage = p.to_string();
-            args.push(&port_str_storage);
+        // scp takes Kbit/s.
+        if let Some(rate) = opts.bwlimit {
+            args.push("-l".to_string());
+            args.push((rate * 8 / 1000).max(1).to_string());
         }
 
-        args.push(src);
-        args.push(dest);
+        args.push(src.to_string());
+        args.push(dest.to_string());
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
 
-        let output = Command::new("scp")
-            .args(&args)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .output()
-            .map_err(|e| format!("Failed to execute scp: {}", e))?;
+        // scp only draws progress on a terminal it owns, so show a spinner
+        // instead, following the local file as it grows on downloads.
+        let _spinner = progress::spinner(watch, sessions::total_bytes());
+        Self::run_command("scp", &args, Some(server))
+    }
 
-        if !output.status.success() {
-            return Err(format!(
-                "scp failed with exit code: {:?}",
-                output.status.code()
-            ));
+    fn run_command(
+        program: &str,
+        args: &[&str],
+        server: Option<&ServerConfig>,
+    ) -> Result<(), String> {
+        let child = Self::spawn(program, args, output::child_stdout(), server)?;
+        Self::finish(program, child)
+    }
+
+    /// Starts `program`, set up to authenticate to `server` when it is one
+    /// of the ssh-based tools.
+    fn spawn(
+        program: &str,
+        args: &[&str],
+        stdout: Stdio,
+        server: Option<&ServerConfig>,
+    ) -> Result<Running, String> {
+        let mut command = Command::new(program);
+        if let Some(server) = server {
+            auth::prepare(&mut command, server)?;
         }
+        command.args(args).stdout(stdout);
+        Self::spawn_command(program, command)
+    }
 
-        Ok(())
+    /// Starts a fully prepared command and registers it with the session so
+    /// `xfer top` can pause or cancel it.
+    fn spawn_command(program: &str, mut command: Command) -> Result<Running, String> {
+        deadline::check()?;
+        tools::require(program)?;
+        output::show_command(&command);
+        let record = recording::start(&command);
+        let mut child = command
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to execute {}: {}", program, e))?;
+
+        sessions::add_child(child.id());
+        let stderr = child.stderr.take().map(errors::tee);
+        Ok(Running {
+            child,
+            stderr,
+            record,
+        })
     }
 
-    fn list_remote(alias: &str, path: &str, config: &Config) -> Result<(), String> {
-        let server = config.get_server(alias).ok_or_else(|| {
-            format!(
-                "Unknown server alias '{}'. Add it to your config first.",
-                alias
-            )
-        })?;
+    fn finish(program: &str, running: Running) -> Result<(), String> {
+        let (status, stderr) = Self::wait(program, running)?;
+        if !status.success() {
+            return Err(errors::describe(program, status.code(), &stderr));
+        }
 
-        let remote_path = if path.is_empty() {
-            server
-                .default_remote_path
-                .clone()
-                .unwrap_or_else(|| format!("/home/{}", server.user))
-        } else {
-            path.to_string()
-        };
+        Ok(())
+    }
 
-        let mut args = Vec::new();
-        let port_str_storage;
+    /// Waits for the process and returns its exit status along with the
+    /// stderr `errors::describe` needs.
+    fn wait(program: &str, mut running: Running) -> Result<(ExitStatus, String), String> {
+        let status = running.child.wait();
+        sessions::remove_child(running.child.id());
+        let stderr = running
+            .stderr
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default();
+        let status = status.map_err(|e| format!("Failed to execute {}: {}", program, e))?;
+        recording::finish(running.record, status.code(), None, &stderr);
+        Ok((status, stderr))
+    }
 
-        if let Some(key) = &server.key_path {
-            args.push("-i");
-            args.push(key);
+    fn list_remote(alias: &str, path: &str, refresh: bool, config: &Config) -> Result<(), String> {
+        let location = Location::parse(&format!("{}:{}", alias, path), config)?;
+        let path = location.path();
+        if output::is_json() {
+            let server = location
+                .server()
+                .ok_or_else(|| "--json listings are only available for servers".to_string())?;
+            output::emit(&listings::entries(server, &path, config, refresh)?);
+            return Ok(());
         }
-
-        if let Some(p) = server.port {
-            args.push("-p");
-            port_str_storage = p.to_string();
-            args.push(&port_str_storage);
+        if let Some(server) = location.server() {
+            let (text, age) = listings::text(server, &path, config, refresh)?;
+            print!("{}", text);
+            if let Some(age) = age {
+                say!(
+                    "{} listed {}s ago; pass --refresh to ask the server again",
+                    "Cached:".yellow(),
+                    age
+                );
+            }
+            return Ok(());
         }
+        match backend::for_location(&location, config) {
+            Some(backend) => backend.list(&path),
+            None => Err(t!("invalid-location")),
+        }
+    }
 
-        let host_str = format!("{}@{}", server.user, server.host);
-        let cmd_str = format!("ls -la {}", remote_path);
-        args.push(&host_str);
-        args.push(&cmd_str);
-
-        let output = Command::new("ssh")
-            .args(&args)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .output()
+    /// Connection options shared by ssh, scp and rsync's `-e`. scp spells
+    /// the port flag `-P`, so the caller passes it in.
+    fn connection_args(server: &ServerConfig, port_flag: &str) -> Vec<String> {
+        let mut args = Vec::new();
+
+        match (&server.key_path, server.auth) {
+            (_, Some(Auth::Agent | Auth::Password)) => {}
+            (Some(key), _) => {
+                args.push("-i".to_string());
+                args.push(key.clone());
+            }
+            (None, _) => {}
+        }
+        match (&server.cert_path, server.auth) {
+            (_, Some(Auth::Password)) | (None, _) => {}
+            (Some(cert), _) => {
+                args.push("-o".to_string());
+                args.push(format!(
+                    "CertificateFile={}",
+                    crypt::expand_tilde(cert).display()
+                ));
+            }
+        }
+        if let Some(auth) = server.auth {
+            args.extend(auth.ssh_options().iter().map(|o| o.to_string()));
+        }
+        args.extend(multiplex::args(server));
+
+        // A dead connection is noticed after KEEPALIVE_COUNT unanswered
+        // keepalives instead of whenever TCP gives up.
+        let keepalive = server.keepalive.unwrap_or(DEFAULT_KEEPALIVE);
+        if keepalive > 0 {
+            args.push("-o".to_string());
+            args.push(format!("ServerAliveInterval={}", keepalive));
+            args.push("-o".to_string());
+            args.push(format!("ServerAliveCountMax={}", KEEPALIVE_COUNT));
+        }
+
+        if let Some(p) = server.port {
+            args.push(port_flag.to_string());
+            args.push(p.to_string());
+        }
+
+        if let Some(chain) = &server.jump_chain {
+            args.push("-J".to_string());
+            args.push(chain.clone());
+        }
+
+        args
+    }
+
+    fn ssh_args(server: &ServerConfig) -> Vec<String> {
+        let mut args = Self::connection_args(server, "-p");
+        args.push(format!("{}@{}", server.user, server.host));
+        args
+    }
+
+    /// Runs `command` on the server and returns its stdout, optionally
+    /// feeding `input` to its stdin.
+    fn remote_command(
+        server: &ServerConfig,
+        command: &str,
+        input: Option<&[u8]>,
+    ) -> Result<String, String> {
+        let mut args = Self::ssh_args(server);
+        args.push(command.to_string());
+
+        let mut ssh = Command::new("ssh");
+        auth::prepare(&mut ssh, server)?;
+        ssh.args(&args);
+        output::show_command(&ssh);
+        let record = recording::start(&ssh);
+        let mut child = ssh
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to execute ssh: {}", e))?;
+
+        // Written from its own thread while the output is read, so a command
+        // that answers before it has read everything can't block on a full
+        // pipe.
+        let writer = match (input, child.stdin.take()) {
+            (Some(input), Some(mut stdin)) => {
+                let input = input.to_vec();
+                Some(std::thread::spawn(move || stdin.write_all(&input)))
+            }
+            _ => None,
+        };
+        let output = child
+            .wait_with_output()
             .map_err(|e| format!("Failed to execute ssh: {}", e))?;
+        let written = writer.map_or(Ok(()), |writer| writer.join().unwrap_or(Ok(())));
 
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        recording::finish(record, output.status.code(), Some(&stdout), &stderr);
         if !output.status.success() {
-            return Err(format!(
-                "ssh failed with exit code: {:?}",
-                output.status.code()
-            ));
+            // The whole of it only on failure; --json callers get the error.
+            if !output::is_json() {
+                eprint!("{}", redact::redact(&stderr));
+            }
+            return Err(errors::describe("ssh", output.status.code(), &stderr));
         }
+        written.map_err(|e| format!("Failed to write to ssh: {}", e))?;
 
-        Ok(())
+        Ok(stdout)
     }
 }
 
 fn add_server(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
-    println!("{}", "Adding a new server configuration".green().bold());
+    say!("{}", t!("server-adding").green().bold());
 
     let mut alias = String::new();
-    print!("Server alias (e.g., 'gcp', 'aws-ec2'): ");
+    print!("{} ", t!("prompt-alias"));
     io::stdout().flush()?;
     io::stdin().read_line(&mut alias)?;
     let alias = alias.trim().to_string();
 
     let mut host = String::new();
-    print!("Host address (e.g., 'example.com', '10.0.0.1'): ");
+    print!("{} ", t!("prompt-host"));
     io::stdout().flush()?;
     io::stdin().read_line(&mut host)?;
     let host = host.trim().to_string();
 
     let mut user = String::new();
-    print!("Username: ");
+    print!("{} ", t!("prompt-user"));
     io::stdout().flush()?;
     io::stdin().read_line(&mut user)?;
     let user = user.trim().to_string();
 
+    let mut auth = String::new();
+    print!("{} ", t!("prompt-auth"));
+    io::stdout().flush()?;
+    io::stdin().read_line(&mut auth)?;
+    let auth = match auth.trim() {
+        "" => None,
+        "key" => Some(Auth::Key),
+        "agent" => Some(Auth::Agent),
+        "password" => Some(Auth::Password),
+        "interactive" => Some(Auth::Interactive),
+        other => return Err(t!("unknown-auth", method = other).into()),
+    };
+
     let mut key_path = String::new();
-    print!("SSH key path (optional, leave blank for none): ");
+    print!("{} ", t!("prompt-key-path"));
     io::stdout().flush()?;
     io::stdin().read_line(&mut key_path)?;
     let key_path = key_path.trim().to_string();
@@ -382,8 +1518,19 @@ fn add_server(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
         Some(key_path)
     };
 
+    let mut cert_path = String::new();
+    print!("{} ", t!("prompt-cert-path"));
+    io::stdout().flush()?;
+    io::stdin().read_line(&mut cert_path)?;
+    let cert_path = cert_path.trim().to_string();
+    let cert_path = if cert_path.is_empty() {
+        None
+    } else {
+        Some(cert_path)
+    };
+
     let mut port_str = String::new();
-    print!("SSH port (optional, default is 22): ");
+    print!("{} ", t!("prompt-port"));
     io::stdout().flush()?;
     io::stdin().read_line(&mut port_str)?;
     let port_str = port_str.trim();
@@ -394,7 +1541,7 @@ fn add_server(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
     };
 
     let mut default_path = String::new();
-    print!("Default remote path (optional): ");
+    print!("{} ", t!("prompt-default-path"));
     io::stdout().flush()?;
     io::stdin().read_line(&mut default_path)?;
     let default_path = default_path.trim().to_string();
@@ -404,19 +1551,45 @@ fn add_server(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
         Some(default_path)
     };
 
+    let mut proxy_jump = String::new();
+    print!("{} ", t!("prompt-jump"));
+    io::stdout().flush()?;
+    io::stdin().read_line(&mut proxy_jump)?;
+    let proxy_jump = proxy_jump.trim().to_string();
+    let proxy_jump = if proxy_jump.is_empty() {
+        None
+    } else {
+        Some(proxy_jump)
+    };
+
     let server_config = ServerConfig {
         host,
         user,
         key_path,
+        cert_path,
+        auth,
         port,
         default_remote_path: default_path,
+        verify: None,
+        bandwidth_limit: None,
+        proxy_jump,
+        keepalive: None,
+        reconnect: None,
+        throttle: None,
+        preserve: None,
+        on_upload: None,
+        encrypt: None,
+        recipients: None,
+        identities: None,
+        os: None,
+        jump_chain: None,
     };
 
     config.servers.insert(alias.clone(), server_config);
 
     if config.default_server.is_none() {
         let mut set_default = String::new();
-        print!("Set as default server? (y/n): ");
+        print!("{} (y/n): ", t!("prompt-set-default"));
         io::stdout().flush()?;
         io::stdin().read_line(&mut set_default)?;
         if set_default.trim().to_lowercase() == "y" {
@@ -425,41 +1598,482 @@ fn add_server(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
     }
 
     config.save()?;
-    println!("{}", "Server configuration added successfully!".green());
+    say!("{}", t!("server-added").green());
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let matches = App::new("xfer")
+/// `server add` with the server given as flags, for scripts and
+/// provisioning tools that can't answer prompts.
+fn add_server_from_flags(matches: &ArgMatches, config: &mut Config) -> Result<(), String> {
+    let alias = matches.value_of("ALIAS").unwrap();
+    if config.servers.contains_key(alias) && !matches.is_present("force") {
+        return Err(t!("server-exists", alias = alias));
+    }
+    let user = match matches.value_of("user") {
+        Some(user) => user.to_string(),
+        None => std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .map_err(|_| t!("no-user"))?,
+    };
+    let port = matches
+        .value_of("port")
+        .map(|port| {
+            port.parse::<u16>()
+                .map_err(|_| t!("invalid-port", port = port))
+        })
+        .transpose()?;
+
+    let server = ServerConfig {
+        host: matches.value_of("host").unwrap().to_string(),
+        user,
+        key_path: matches.value_of("key").map(String::from),
+        cert_path: matches.value_of("cert").map(String::from),
+        auth: match matches.value_of("auth") {
+            Some("key") => Some(Auth::Key),
+            Some("agent") => Some(Auth::Agent),
+            Some("password") => Some(Auth::Password),
+            Some("interactive") => Some(Auth::Interactive),
+            _ => None,
+        },
+        port,
+        default_remote_path: matches.value_of("default-path").map(String::from),
+        verify: None,
+        bandwidth_limit: None,
+        proxy_jump: matches.value_of("jump").map(String::from),
+        keepalive: None,
+        reconnect: None,
+        throttle: None,
+        preserve: None,
+        on_upload: None,
+        encrypt: None,
+        recipients: None,
+        identities: None,
+        os: None,
+        jump_chain: None,
+    };
+    config.servers.insert(alias.to_string(), server);
+    if matches.is_present("default") {
+        config.default_server = Some(alias.to_string());
+    }
+    config.save().map_err(|e| e.to_string())?;
+    say!("{} {}", t!("added-server").green(), alias.yellow());
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ServersFile {
+    default_server: Option<String>,
+    #[serde(default)]
+    servers: HashMap<String, ServerConfig>,
+}
+
+/// `server add --from-file`: adds the `[servers.<alias>]` tables of a file
+/// laid out like the config, or of stdin for `-`. Aliases that already
+/// exist are kept unless `force`; the file's `default_server` applies when
+/// there is none yet, or with `force`.
+fn import_servers(path: &str, force: bool, config: &mut Config) -> Result<(), String> {
+    let content = if path == "-" {
+        let mut content = String::new();
+        io::stdin()
+            .read_to_string(&mut content)
+            .map_err(|e| format!("Failed to read stdin: {}", e))?;
+        content
+    } else {
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?
+    };
+    let name = if path == "-" { "stdin" } else { path };
+    let file: ServersFile = toml::from_str(&content).map_err(|e| format!("{}: {}", name, e))?;
+    if file.servers.is_empty() {
+        return Err(t!("no-server-tables", file = name));
+    }
+    if let Some(default) = &file.default_server {
+        if !file.servers.contains_key(default) && !config.servers.contains_key(default) {
+            return Err(t!("unknown-default-server", alias = default));
+        }
+    }
+
+    let mut aliases: Vec<String> = file.servers.keys().cloned().collect();
+    aliases.sort();
+    let mut servers = file.servers;
+    let (mut added, mut replaced) = (0, 0);
+    for alias in aliases {
+        let server = servers.remove(&alias).unwrap();
+        if config.servers.contains_key(&alias) {
+            if !force {
+                say!(
+                    "  {} {}",
+                    t!("skipped").yellow(),
+                    t!("server-skipped", alias = alias)
+                );
+                continue;
+            }
+            replaced += 1;
+        } else {
+            added += 1;
+        }
+        say!("  {} {}", t!("added").green(), alias.yellow());
+        config.servers.insert(alias, server);
+    }
+    if let Some(default) = file.default_server {
+        if force || config.default_server.is_none() {
+            config.default_server = Some(default);
+        }
+    }
+
+    config.save().map_err(|e| e.to_string())?;
+    say!(
+        "{} {}",
+        t!("imported").green().bold(),
+        t!("servers-imported", added = added, replaced = replaced)
+    );
+    Ok(())
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("xfer")
         .version("0.1.0")
         .author("Mutasim")
         .about("Simple file transfer tool")
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("Print results as JSON on stdout, with messages on stderr (same as --output json)"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .value_name("FORMAT")
+                .possible_values(&["human", "json"])
+                .global(true)
+                .help("Output format"),
+        )
+        .arg(
+            Arg::with_name("show-cmd")
+                .long("show-cmd")
+                .global(true)
+                .help("Print each ssh, scp, rsync or aws command before running it, with secrets masked"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .global(true)
+                .help("Record every remote command, with timestamps and output, in a transcript for this run"),
+        )
+        .arg(
+            Arg::with_name("plain-output")
+                .long("plain-output")
+                .global(true)
+                .help("No colors, spinners or redrawn lines; print a status line every few seconds instead (for screen readers and serial consoles)"),
+        )
         .subcommand(
             SubCommand::with_name("send")
-                .about("Send a file or directory")
+                .about("Send files or directories")
                 .arg(
-                    Arg::with_name("SOURCE")
+                    Arg::with_name("PATHS")
                         .required(true)
-                        .help("Source file or directory"),
+                        .multiple(true)
+                        .min_values(2)
+                        .value_name("PATH")
+                        .help("Source files or directories (remote sources may use globs) followed by the destination"),
                 )
                 .arg(
-                    Arg::with_name("DESTINATION")
-                        .required(true)
-                        .help("Destination path"),
+                    Arg::with_name("verify")
+                        .long("verify")
+                        .help("Compare SHA-256 checksums of source and destination afterwards"),
+                )
+                .arg(
+                    Arg::with_name("resume")
+                        .long("resume")
+                        .help("Keep partial files and continue interrupted transfers (uses rsync)"),
+                )
+                .arg(
+                    Arg::with_name("archive")
+                        .long("archive")
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .value_name("FORMAT")
+                        .possible_values(&["tar", "zip"])
+                        .conflicts_with("resume")
+                        .help("Stream directories as one tar archive instead of file by file; a destination ending in .tar, .tar.gz or .tar.zst keeps the archive as-is. --archive=zip (or a .zip destination) delivers a zip file"),
+                )
+                .arg(
+                    Arg::with_name("compress")
+                        .long("compress")
+                        .takes_value(true)
+                        .value_name("ALGO")
+                        .possible_values(&["zstd", "gzip", "deflate", "store"])
+                        .requires("archive")
+                        .help("Compress the archive stream: zstd or gzip for tar, deflate (the default) or store for zip"),
+                )
+                .arg(
+                    Arg::with_name("encrypt")
+                        .long("encrypt")
+                        .conflicts_with_all(&["archive", "resume", "verify", "parallel"])
+                        .help("Encrypt with age before anything leaves this machine; files arrive as NAME.age, directories as NAME.tar.age"),
+                )
+                .arg(
+                    Arg::with_name("recipient")
+                        .long("recipient")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("KEY")
+                        .conflicts_with_all(&["archive", "resume", "verify", "parallel"])
+                        .help("age or SSH public key, or a file of them, to encrypt to (implies --encrypt; default: the server's recipients)"),
+                )
+                .arg(
+                    Arg::with_name("passphrase")
+                        .long("passphrase")
+                        .conflicts_with_all(&["recipient", "archive", "resume", "verify", "parallel"])
+                        .help("Encrypt with a passphrase age asks for instead of keys (implies --encrypt)"),
+                )
+                .arg(
+                    Arg::with_name("level")
+                        .long("level")
+                        .takes_value(true)
+                        .value_name("N")
+                        .requires("archive")
+                        .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("Compression level (zstd 1-19, gzip and deflate 1-9)"),
+                )
+                .arg(
+                    Arg::with_name("max-duration")
+                        .long("max-duration")
+                        .takes_value(true)
+                        .value_name("DURATION")
+                        .validator(|v| parse_duration(&v).map(|_| ()))
+                        .help("Cancel the transfer after this long, e.g. 2h, keeping what was sent for resume"),
+                )
+                .arg(
+                    Arg::with_name("preserve")
+                        .long("preserve")
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .value_name("ATTRS")
+                        .validator(|v| Preserve::validate(&v))
+                        .help("Keep these attributes: perms, times, links, owner (default: all)"),
+                )
+                .arg(
+                    Arg::with_name("no-preserve")
+                        .long("no-preserve")
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .value_name("ATTRS")
+                        .validator(|v| Preserve::validate(&v))
+                        .help("Drop these attributes (default: all); perms, times and links are kept otherwise"),
+                )
+                .arg(
+                    Arg::with_name("retries")
+                        .long("retries")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("Retry up to N times after a network failure, resuming each time (default 3)"),
+                )
+                .arg(
+                    Arg::with_name("no-throttle")
+                        .long("no-throttle")
+                        .help("Ignore the [throttle] rules for battery, metered networks and load"),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .long("limit")
+                        .takes_value(true)
+                        .value_name("RATE")
+                        .validator(|v| parse_rate(&v).map(|_| ()))
+                        .help("Cap bandwidth in bytes per second, e.g. 500K or 5M"),
+                )
+                .arg(
+                    Arg::with_name("task")
+                        .long("task")
+                        .takes_value(true)
+                        .value_name("NAME")
+                        .help("Name this transfer and skip files it already delivered unchanged"),
+                )
+                .arg(
+                    Arg::with_name("full")
+                        .long("full")
+                        .requires("task")
+                        .help("Send everything even if the task ledger says it is unchanged"),
+                )
+                .arg(
+                    Arg::with_name("wait")
+                        .long("wait")
+                        .help("Wait if another xfer is writing to the same destination"),
+                )
+                .arg(
+                    Arg::with_name("max-parallel")
+                        .long("max-parallel")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| match v.parse::<usize>() {
+                            Ok(n) if n > 0 => Ok(()),
+                            _ => Err("must be a positive number".to_string()),
+                        })
+                        .help("With an @group destination, how many hosts to send to at once (default 4)"),
+                )
+                .arg(
+                    Arg::with_name("adaptive")
+                        .long("adaptive")
+                        .help("With many sources, benchmark the link if the run falls far behind it and send the rest as one tar stream"),
+                )
+                .arg(
+                    Arg::with_name("parallel")
+                        .long("parallel")
+                        .takes_value(true)
+                        .value_name("N")
+                        .conflicts_with("resume")
+                        .validator(|v| match v.parse::<usize>() {
+                            Ok(n) if n > 0 => Ok(()),
+                            _ => Err("must be a positive number".to_string()),
+                        })
+                        .help("Upload large files (64 MiB and up) as chunks over N ssh sessions at once"),
                 ),
         )
         .subcommand(
             SubCommand::with_name("get")
-                .about("Get a file or directory")
+                .about("Get files or directories")
                 .arg(
-                    Arg::with_name("SOURCE")
+                    Arg::with_name("PATHS")
                         .required(true)
-                        .help("Source file or directory"),
+                        .multiple(true)
+                        .min_values(2)
+                        .value_name("PATH")
+                        .help("Source files or directories (remote sources may use globs) followed by the destination"),
                 )
                 .arg(
-                    Arg::with_name("DESTINATION")
-                        .required(true)
-                        .help("Destination path"),
+                    Arg::with_name("verify")
+                        .long("verify")
+                        .help("Compare SHA-256 checksums of source and destination afterwards"),
+                )
+                .arg(
+                    Arg::with_name("resume")
+                        .long("resume")
+                        .help("Keep partial files and continue interrupted transfers (uses rsync)"),
+                )
+                .arg(
+                    Arg::with_name("archive")
+                        .long("archive")
+                        .conflicts_with("resume")
+                        .help("Stream directories as one tar archive instead of file by file; a destination ending in .tar, .tar.gz or .tar.zst keeps the archive as-is"),
+                )
+                .arg(
+                    Arg::with_name("unpack")
+                        .long("unpack")
+                        .conflicts_with_all(&["archive", "resume", "verify", "flatten"])
+                        .help("Extract a remote .tar, .tar.gz, .tar.zst or .zip into the destination directory as it streams, without storing the archive"),
+                )
+                .arg(
+                    Arg::with_name("adaptive")
+                        .long("adaptive")
+                        .conflicts_with("flatten")
+                        .help("With many sources, benchmark the link if the run falls far behind it and fetch the rest as one tar stream"),
+                )
+                .arg(
+                    Arg::with_name("decrypt")
+                        .long("decrypt")
+                        .conflicts_with_all(&["archive", "unpack", "resume", "verify", "flatten"])
+                        .help("Decrypt with age even if the name doesn't end in .age"),
+                )
+                .arg(
+                    Arg::with_name("no-decrypt")
+                        .long("no-decrypt")
+                        .conflicts_with("decrypt")
+                        .help("Keep .age files encrypted instead of decrypting them"),
+                )
+                .arg(
+                    Arg::with_name("identity")
+                        .long("identity")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("FILE")
+                        .help("age or SSH identity file to decrypt with (default: the server's identities)"),
+                )
+                .arg(
+                    Arg::with_name("compress")
+                        .long("compress")
+                        .takes_value(true)
+                        .value_name("ALGO")
+                        .possible_values(&["zstd", "gzip"])
+                        .requires("archive")
+                        .help("Compress the archive stream"),
+                )
+                .arg(
+                    Arg::with_name("level")
+                        .long("level")
+                        .takes_value(true)
+                        .value_name("N")
+                        .requires("archive")
+                        .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("Compression level (zstd 1-19, gzip 1-9)"),
+                )
+                .arg(
+                    Arg::with_name("max-duration")
+                        .long("max-duration")
+                        .takes_value(true)
+                        .value_name("DURATION")
+                        .validator(|v| parse_duration(&v).map(|_| ()))
+                        .help("Cancel the transfer after this long, e.g. 2h, keeping what was sent for resume"),
+                )
+                .arg(
+                    Arg::with_name("preserve")
+                        .long("preserve")
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .value_name("ATTRS")
+                        .validator(|v| Preserve::validate(&v))
+                        .help("Keep these attributes: perms, times, links, owner (default: all)"),
+                )
+                .arg(
+                    Arg::with_name("no-preserve")
+                        .long("no-preserve")
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .value_name("ATTRS")
+                        .validator(|v| Preserve::validate(&v))
+                        .help("Drop these attributes (default: all); perms, times and links are kept otherwise"),
+                )
+                .arg(
+                    Arg::with_name("retries")
+                        .long("retries")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("Retry up to N times after a network failure, resuming each time (default 3)"),
+                )
+                .arg(
+                    Arg::with_name("no-throttle")
+                        .long("no-throttle")
+                        .help("Ignore the [throttle] rules for battery, metered networks and load"),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .long("limit")
+                        .takes_value(true)
+                        .value_name("RATE")
+                        .validator(|v| parse_rate(&v).map(|_| ()))
+                        .help("Cap bandwidth in bytes per second, e.g. 500K or 5M"),
+                )
+                .arg(
+                    Arg::with_name("flatten")
+                        .long("flatten")
+                        .help("Collect every file under the remote paths into one local directory"),
+                )
+                .arg(
+                    Arg::with_name("match")
+                        .long("match")
+                        .takes_value(true)
+                        .value_name("PATTERN")
+                        .requires("flatten")
+                        .help("With --flatten, only collect files whose name matches PATTERN"),
                 ),
         )
         .subcommand(
@@ -474,98 +2088,1318 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     Arg::with_name("DESTINATION")
                         .required(true)
                         .help("Destination directory"),
+                )
+                .arg(
+                    Arg::with_name("verify")
+                        .long("verify")
+                        .help("Compare SHA-256 checksums of source and destination afterwards"),
+                )
+                .arg(
+                    Arg::with_name("delete")
+                        .long("delete")
+                        .conflicts_with("bidirectional")
+                        .help("Remove files from the destination that the source doesn't have"),
+                )
+                .arg(
+                    Arg::with_name("mirror")
+                        .long("mirror")
+                        .conflicts_with("bidirectional")
+                        .help("Make the destination an exact copy: --delete plus --verify"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Delete without showing the list and asking first"),
+                )
+                .arg(
+                    Arg::with_name("wait")
+                        .long("wait")
+                        .help("Wait if another xfer is writing to the same destination"),
+                )
+                .arg(
+                    Arg::with_name("max-duration")
+                        .long("max-duration")
+                        .takes_value(true)
+                        .value_name("DURATION")
+                        .validator(|v| parse_duration(&v).map(|_| ()))
+                        .help("Cancel the transfer after this long, e.g. 2h, keeping what was sent for resume"),
+                )
+                .arg(
+                    Arg::with_name("preserve")
+                        .long("preserve")
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .value_name("ATTRS")
+                        .validator(|v| Preserve::validate(&v))
+                        .help("Keep these attributes: perms, times, links, owner (default: all)"),
+                )
+                .arg(
+                    Arg::with_name("no-preserve")
+                        .long("no-preserve")
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .value_name("ATTRS")
+                        .validator(|v| Preserve::validate(&v))
+                        .help("Drop these attributes (default: all); perms, times and links are kept otherwise"),
+                )
+                .arg(
+                    Arg::with_name("retries")
+                        .long("retries")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("Retry up to N times after a network failure, resuming each time (default 3)"),
+                )
+                .arg(
+                    Arg::with_name("no-throttle")
+                        .long("no-throttle")
+                        .help("Ignore the [throttle] rules for battery, metered networks and load"),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .long("limit")
+                        .takes_value(true)
+                        .value_name("RATE")
+                        .validator(|v| parse_rate(&v).map(|_| ()))
+                        .help("Cap bandwidth in bytes per second, e.g. 500K or 5M"),
+                )
+                .arg(
+                    Arg::with_name("bidirectional")
+                        .long("bidirectional")
+                        .conflicts_with("verify")
+                        .help("Sync both ways: copy files changed on either side since the last sync and report conflicts"),
+                )
+                .arg(
+                    Arg::with_name("prefer")
+                        .long("prefer")
+                        .takes_value(true)
+                        .value_name("SIDE")
+                        .possible_values(&["local", "remote"])
+                        .requires("bidirectional")
+                        .help("With --bidirectional, settle conflicts in favour of this side"),
                 ),
         )
         .subcommand(
-            SubCommand::with_name("list")
-                .about("List files on remote server")
+            SubCommand::with_name("watch")
+                .about("Watch a local directory and push changes to a remote")
+                .arg(
+                    Arg::with_name("SOURCE")
+                        .required(true)
+                        .help("Local directory to watch"),
+                )
+                .arg(
+                    Arg::with_name("DESTINATION")
+                        .required(true)
+                        .help("Remote directory (alias:/path)"),
+                )
+                .arg(
+                    Arg::with_name("delete")
+                        .long("delete")
+                        .help("Remove remote files that were deleted locally"),
+                )
+                .arg(
+                    Arg::with_name("debounce")
+                        .long("debounce")
+                        .takes_value(true)
+                        .default_value("500")
+                        .help("Milliseconds to wait for changes to settle before syncing"),
+                )
+                .arg(
+                    Arg::with_name("wait")
+                        .long("wait")
+                        .help("Wait if another xfer is writing to the same destination"),
+                )
+                .arg(
+                    Arg::with_name("retries")
+                        .long("retries")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("Retry up to N times after a network failure, resuming each time (default 3)"),
+                )
+                .arg(
+                    Arg::with_name("no-throttle")
+                        .long("no-throttle")
+                        .help("Ignore the [throttle] rules for battery, metered networks and load"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("mirror")
+                .about("Keep a warm standby copy of a directory and check how current it is")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("start")
+                        .about("Mirror a local directory to a remote one, publishing a heartbeat after each sync")
+                        .arg(
+                            Arg::with_name("SOURCE")
+                                .required(true)
+                                .help("Local directory to mirror"),
+                        )
+                        .arg(
+                            Arg::with_name("DESTINATION")
+                                .required(true)
+                                .help("Standby directory (alias:/path)"),
+                        )
+                        .arg(
+                            Arg::with_name("interval")
+                                .long("interval")
+                                .takes_value(true)
+                                .value_name("DURATION")
+                                .default_value("5m")
+                                .validator(|v| parse_duration(&v).map(|_| ()))
+                                .help("Sync and publish a heartbeat at least this often, changes or not"),
+                        )
+                        .arg(
+                            Arg::with_name("once")
+                                .long("once")
+                                .help("Sync and publish a heartbeat once, e.g. from cron; --interval is how often that runs"),
+                        )
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .help("Delete what the source doesn't have without asking, even before the first heartbeat"),
+                        )
+                        .arg(
+                            Arg::with_name("wait")
+                                .long("wait")
+                                .help("Wait if another xfer is writing to the same destination"),
+                        )
+                        .arg(
+                            Arg::with_name("retries")
+                                .long("retries")
+                                .takes_value(true)
+                                .value_name("N")
+                                .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                                .help("Retry up to N times after a network failure, resuming each time (default 3)"),
+                        )
+                        .arg(
+                            Arg::with_name("limit")
+                                .long("limit")
+                                .takes_value(true)
+                                .value_name("RATE")
+                                .validator(|v| parse_rate(&v).map(|_| ()))
+                                .help("Cap bandwidth in bytes per second, e.g. 500K or 5M"),
+                        )
+                        .arg(
+                            Arg::with_name("no-throttle")
+                                .long("no-throttle")
+                                .help("Ignore the [throttle] rules for battery, metered networks and load"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Show how far each standby trails its source, from its heartbeat")
+                        .arg(
+                            Arg::with_name("DESTINATION")
+                                .help("Only this standby (default: every mirror started here)"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Compare checksums of a local copy and a remote copy")
+                .arg(
+                    Arg::with_name("LOCAL")
+                        .required(true)
+                        .help("Local file or directory"),
+                )
+                .arg(
+                    Arg::with_name("REMOTE")
+                        .required(true)
+                        .help("Remote file or directory (alias:/path)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Save a remote directory as a local .tar, .tar.gz or .tar.zst with a manifest, writing nothing on the server")
+                .arg(
+                    Arg::with_name("SOURCE")
+                        .required(true)
+                        .help("Remote directory (alias:/path)"),
+                )
+                .arg(
+                    Arg::with_name("ARCHIVE")
+                        .required(true)
+                        .help("Local archive to create; {date}, {time} and {alias} are filled in, e.g. ./etc-{date}.tar.zst"),
+                )
+                .arg(
+                    Arg::with_name("level")
+                        .long("level")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("Compression level (zstd 1-19, gzip 1-9)"),
+                )
+                .arg(
+                    Arg::with_name("follow-links")
+                        .long("follow-links")
+                        .help("Store what symlinks point to instead of the links"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("List past transfers")
+                .arg(
+                    Arg::with_name("limit")
+                        .short("n")
+                        .long("limit")
+                        .takes_value(true)
+                        .default_value("20")
+                        .help("Number of most recent transfers to show"),
+                )
+                .subcommand(
+                    SubCommand::with_name("export")
+                        .about("Export the history for a spreadsheet or data warehouse")
+                        .arg(
+                            Arg::with_name("format")
+                                .long("format")
+                                .takes_value(true)
+                                .possible_values(&["csv", "parquet"])
+                                .default_value("csv")
+                                .help("Output format"),
+                        )
+                        .arg(
+                            Arg::with_name("since")
+                                .long("since")
+                                .takes_value(true)
+                                .value_name("DATE")
+                                .help("Only transfers on or after this date (YYYY-MM-DD, UTC)"),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .long("to")
+                                .takes_value(true)
+                                .value_name("FILE")
+                                .help("Write to FILE instead of stdout"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("redo")
+                .about("Repeat a transfer from history")
+                .arg(
+                    Arg::with_name("ID")
+                        .required(true)
+                        .help("History id, or 'last'"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("unlock")
+                .about("Remove a stale destination lock left by an interrupted transfer")
                 .arg(
                     Arg::with_name("LOCATION")
                         .required(true)
-                        .help("Location to list (alias:/path)"),
+                        .help("Locked destination (alias:/path)"),
                 ),
         )
         .subcommand(
-            SubCommand::with_name("server")
-                .about("Manage server configurations")
-                .subcommand(SubCommand::with_name("add").about("Add a new server configuration"))
-                .subcommand(SubCommand::with_name("list").about("List all server configurations")),
+            SubCommand::with_name("resume").about("Continue the last interrupted send or get"),
         )
-        .get_matches();
-
-    let mut config = Config::load()?;
-
-    if config.servers.is_empty() {
-        println!(
-            "{}",
-            "No server configurations found. Let's add one now.".yellow()
-        );
-        add_server(&mut config)?;
-    }
-
-    match matches.subcommand() {
-        ("send", Some(sub_m)) => {
-            let src = sub_m.value_of("SOURCE").unwrap();
-            let dest = sub_m.value_of("DESTINATION").unwrap();
+        .subcommand(
+            SubCommand::with_name("queue")
+                .about("Queue transfers for the background daemon")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Queue a send or get")
+                        .arg(
+                            Arg::with_name("PATHS")
+                                .required(true)
+                                .multiple(true)
+                                .min_values(2)
+                                .help("Sources followed by the destination"),
+                        )
+                        .arg(
+                            Arg::with_name("at")
+                                .long("at")
+                                .takes_value(true)
+                                .value_name("WHEN")
+                                .help("Don't start before this local time: HH:MM, \"YYYY-MM-DD HH:MM\" or +DURATION"),
+                        )
+                        .arg(
+                            Arg::with_name("verify")
+                                .long("verify")
+                                .help("Compare SHA-256 checksums of source and destination afterwards"),
+                        )
+                        .arg(
+                            Arg::with_name("archive")
+                                .long("archive")
+                                .help("Stream directories as one tar archive instead of file by file"),
+                        )
+                        .arg(
+                            Arg::with_name("limit")
+                                .long("limit")
+                                .takes_value(true)
+                                .value_name("RATE")
+                                .validator(|v| parse_rate(&v).map(|_| ()))
+                                .help("Cap bandwidth in bytes per second, e.g. 500K or 5M"),
+                        ),
+                )
+                .subcommand(SubCommand::with_name("list").about("List queued, running and recent jobs"))
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Show whether the daemon runs and how the jobs are doing"),
+                )
+                .subcommand(
+                    SubCommand::with_name("cancel")
+                        .about("Cancel queued jobs, stopping any that are running")
+                        .arg(
+                            Arg::with_name("ID")
+                                .required(true)
+                                .multiple(true)
+                                .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("run-job")
+                        .setting(AppSettings::Hidden)
+                        .about("Run one queued job (started by the daemon)")
+                        .arg(Arg::with_name("ID").required(true)),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("Run queued transfers in the background")
+                .arg(
+                    Arg::with_name("concurrency")
+                        .long("concurrency")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("2")
+                        .validator(|v| match v.parse::<usize>() {
+                            Ok(n) if n > 0 => Ok(()),
+                            _ => Err("must be a positive number".to_string()),
+                        })
+                        .help("Jobs to run at once"),
+                )
+                .arg(
+                    Arg::with_name("attempts")
+                        .long("attempts")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("3")
+                        .validator(|v| match v.parse::<u32>() {
+                            Ok(n) if n > 0 => Ok(()),
+                            _ => Err("must be a positive number".to_string()),
+                        })
+                        .help("Tries per job before it counts as failed; retries wait 1m, 2m, 4m, ..."),
+                )
+                .arg(
+                    Arg::with_name("log")
+                        .long("log")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Log file (default: ~/.local/share/xfer/queue.log)"),
+                )
+                .arg(
+                    Arg::with_name("detach")
+                        .long("detach")
+                        .conflicts_with("stop")
+                        .help("Start in the background and return, so the terminal can be closed"),
+                )
+                .arg(
+                    Arg::with_name("stop")
+                        .long("stop")
+                        .help("Stop the running daemon once its running jobs finish"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stat")
+                .about("Show size, modification time, permissions and owner of remote files")
+                .arg(
+                    Arg::with_name("LOCATION")
+                        .required(true)
+                        .multiple(true)
+                        .help("Remote files (alias:/path)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("df")
+                .about("Show free disk space and the quota left on servers")
+                .arg(
+                    Arg::with_name("LOCATION")
+                        .required(true)
+                        .multiple(true)
+                        .help("Servers (alias for the default remote path, or alias:/path)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("touch")
+                .about("Update the modification time of remote files")
+                .arg(
+                    Arg::with_name("LOCATION")
+                        .required(true)
+                        .multiple(true)
+                        .help("Remote files (alias:/path)"),
+                )
+                .arg(
+                    Arg::with_name("no-create")
+                        .short("c")
+                        .long("no-create")
+                        .help("Don't create files that don't exist"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("mkdir")
+                .about("Create remote directories")
+                .arg(
+                    Arg::with_name("LOCATION")
+                        .required(true)
+                        .multiple(true)
+                        .help("Remote directories (alias:/path)"),
+                )
+                .arg(
+                    Arg::with_name("parents")
+                        .short("p")
+                        .long("parents")
+                        .help("Create missing parent directories too, and don't fail if it exists"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rm")
+                .about("Delete remote files or directories")
+                .arg(
+                    Arg::with_name("LOCATION")
+                        .required(true)
+                        .multiple(true)
+                        .help("Remote paths (alias:/path)"),
+                )
+                .arg(
+                    Arg::with_name("recursive")
+                        .short("r")
+                        .long("recursive")
+                        .help("Delete directories and everything in them"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Don't ask for confirmation"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("mv")
+                .about("Move or rename a remote path")
+                .arg(
+                    Arg::with_name("FROM")
+                        .required(true)
+                        .help("Remote path to move (alias:/path)"),
+                )
+                .arg(
+                    Arg::with_name("TO")
+                        .required(true)
+                        .help("New remote path on the same server (alias:/path)"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Overwrite an existing file without asking"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Run a named route from the project's .xfer.toml")
+                .arg(
+                    Arg::with_name("ROUTE")
+                        .help("Route to run; without one, the routes are listed"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("For routes with delete, delete without showing the list and asking first"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Show the route's before hook and transfer without running either"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("template")
+                .about("Set up a common job as a project route from a built-in template")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list").about("List the templates and what they ask for"),
+                )
+                .subcommand(
+                    SubCommand::with_name("apply")
+                        .about("Fill in a template and add its route to .xfer.toml")
+                        .arg(
+                            Arg::with_name("TEMPLATE")
+                                .required(true)
+                                .help("Template to apply (see `xfer template list`)"),
+                        )
+                        .arg(
+                            Arg::with_name("name")
+                                .long("name")
+                                .takes_value(true)
+                                .value_name("ROUTE")
+                                .help("Name of the route (default: the template's)"),
+                        )
+                        .arg(
+                            Arg::with_name("set")
+                                .long("set")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .value_name("KEY=VALUE")
+                                .help("Answer a question up front instead of being asked"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("audit-perms")
+                .about("Compare modes and ownership between a local tree and a remote one")
+                .arg(
+                    Arg::with_name("LOCAL")
+                        .required(true)
+                        .help("Local directory"),
+                )
+                .arg(
+                    Arg::with_name("REMOTE")
+                        .required(true)
+                        .help("Remote directory (alias:/path)"),
+                )
+                .arg(
+                    Arg::with_name("ignore-owner")
+                        .long("ignore-owner")
+                        .help("Compare types and modes only, not owners and groups"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate-scripts")
+                .about("Propose servers and routes for the scp and rsync calls in shell scripts")
+                .arg(
+                    Arg::with_name("PATH")
+                        .required(true)
+                        .multiple(true)
+                        .help("Scripts, alias files or directories to scan"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Check that servers resolve, accept ssh logins and are ready for transfers")
+                .arg(
+                    Arg::with_name("ALIAS")
+                        .multiple(true)
+                        .help("Servers to check (default: the default server)"),
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .conflicts_with("ALIAS")
+                        .help("Check every configured server"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("top").about("Show running transfers"))
+        .subcommand(
+            SubCommand::with_name("browse")
+                .about("Browse a remote directory and download or upload from it")
+                .arg(
+                    Arg::with_name("LOCATION")
+                        .required(true)
+                        .help("Server alias, optionally with a path (alias:/path)"),
+                )
+                .arg(
+                    Arg::with_name("refresh")
+                        .long("refresh")
+                        .help("List the starting directory again instead of using the cache"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("init")
+                .about("Set up xfer on this machine: config, ssh hosts and shell completions")
+                .arg(
+                    Arg::with_name("import-ssh")
+                        .long("import-ssh")
+                        .help("Import hosts from ~/.ssh/config without asking"),
+                )
+                .arg(
+                    Arg::with_name("no-import-ssh")
+                        .long("no-import-ssh")
+                        .conflicts_with("import-ssh")
+                        .help("Don't import hosts from ~/.ssh/config"),
+                )
+                .arg(
+                    Arg::with_name("shell")
+                        .long("shell")
+                        .takes_value(true)
+                        .possible_values(&Shell::variants())
+                        .help("Shell to install completions for (default: from $SHELL)"),
+                )
+                .arg(
+                    Arg::with_name("no-completions")
+                        .long("no-completions")
+                        .conflicts_with("shell")
+                        .help("Don't install shell completions"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print the shell completion script, with server aliases and remote paths")
+                .arg(
+                    Arg::with_name("SHELL")
+                        .required(true)
+                        .possible_values(&Shell::variants())
+                        .help("Shell to generate completions for"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("helper")
+                .about("Manage the optional server-side helper")
+                .subcommand(
+                    SubCommand::with_name("install")
+                        .about("Upload the helper to a server")
+                        .arg(Arg::with_name("ALIAS").required(true).help("Server alias")),
+                )
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Check whether the helper is installed on a server")
+                        .arg(Arg::with_name("ALIAS").required(true).help("Server alias")),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Remove the helper from a server")
+                        .arg(Arg::with_name("ALIAS").required(true).help("Server alias")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("List files on remote server")
+                .arg(
+                    Arg::with_name("LOCATION")
+                        .required(true)
+                        .help("Location to list (alias:/path)"),
+                )
+                .arg(
+                    Arg::with_name("refresh")
+                        .long("refresh")
+                        .help("Ask the server again instead of using a cached listing"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cache")
+                .about("Manage cached remote listings")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("clear")
+                        .about("Forget every cached listing, so the next ones ask the servers"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("server")
+                .about("Manage server configurations")
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add a new server configuration (prompts unless given flags or --from-file)")
+                        .arg(
+                            Arg::with_name("ALIAS")
+                                .requires("host")
+                                .help("Server alias"),
+                        )
+                        .arg(
+                            Arg::with_name("host")
+                                .long("host")
+                                .takes_value(true)
+                                .requires("ALIAS")
+                                .help("Host address"),
+                        )
+                        .arg(
+                            Arg::with_name("user")
+                                .long("user")
+                                .takes_value(true)
+                                .requires("host")
+                                .help("Username (default: $USER)"),
+                        )
+                        .arg(
+                            Arg::with_name("key")
+                                .long("key")
+                                .takes_value(true)
+                                .value_name("PATH")
+                                .requires("host")
+                                .help("SSH key path"),
+                        )
+                        .arg(
+                            Arg::with_name("cert")
+                                .long("cert")
+                                .takes_value(true)
+                                .value_name("PATH")
+                                .requires("host")
+                                .help("OpenSSH certificate for the key (e.g. id_ed25519-cert.pub)"),
+                        )
+                        .arg(
+                            Arg::with_name("port")
+                                .long("port")
+                                .takes_value(true)
+                                .requires("host")
+                                .help("SSH port (default: 22)"),
+                        )
+                        .arg(
+                            Arg::with_name("auth")
+                                .long("auth")
+                                .takes_value(true)
+                                .possible_values(&["key", "agent", "password", "interactive"])
+                                .requires("host")
+                                .help("Authentication method (default: ssh defaults)"),
+                        )
+                        .arg(
+                            Arg::with_name("default-path")
+                                .long("default-path")
+                                .takes_value(true)
+                                .value_name("PATH")
+                                .requires("host")
+                                .help("Default remote path"),
+                        )
+                        .arg(
+                            Arg::with_name("jump")
+                                .long("jump")
+                                .takes_value(true)
+                                .requires("host")
+                                .help("Jump host (alias or user@host:port)"),
+                        )
+                        .arg(
+                            Arg::with_name("default")
+                                .long("default")
+                                .requires("host")
+                                .help("Make it the default server"),
+                        )
+                        .arg(
+                            Arg::with_name("from-file")
+                                .long("from-file")
+                                .takes_value(true)
+                                .value_name("FILE")
+                                .conflicts_with("ALIAS")
+                                .help("Add the [servers.<alias>] tables of a TOML file ('-' for stdin)"),
+                        )
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .help("Replace servers that already exist"),
+                        ),
+                )
+                .subcommand(SubCommand::with_name("list").about("List all server configurations"))
+                .subcommand(
+                    SubCommand::with_name("password")
+                        .about("Store or replace a server's password in the OS keychain")
+                        .arg(Arg::with_name("ALIAS").required(true).help("Server alias")),
+                ),
+        )
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if let Ok(account) = std::env::var(auth::ASKPASS_ENV) {
+        let prompt = std::env::args().nth(1).unwrap_or_default();
+        std::process::exit(auth::askpass(&account, &prompt));
+    }
+
+    // Kept out of `app()`: clap's bash generator can't handle the name, and
+    // the completion scripts don't need to offer it.
+    let matches = app()
+        .subcommand(
+            SubCommand::with_name("__complete")
+                .setting(AppSettings::Hidden)
+                .about("Print completion candidates for a word (used by the completion scripts)")
+                .arg(Arg::with_name("WORD").default_value("")),
+        )
+        .get_matches();
+
+    let mut config = Config::load()?;
+    i18n::init(config.language.as_deref());
+    tools::detect();
+    prompt::init(config.prompt, config.prompt_program.as_deref());
+    if matches.is_present("json") || matches.value_of("output") == Some("json") {
+        output::set_json();
+    }
+    scratch::init(config.temp_dir.as_deref());
+    if matches.is_present("show-cmd") {
+        output::set_show_commands();
+    }
+    recording::init(
+        matches.is_present("record") || config.record.unwrap_or(false),
+        config.record_dir.as_deref(),
+    );
+    if matches.is_present("plain-output") || config.plain_output.unwrap_or(false) {
+        progress::set_plain();
+    }
+
+    if config.servers.is_empty()
+        && config.buckets.is_empty()
+        && io::stdin().is_terminal()
+        && !matches!(
+            matches.subcommand_name(),
+            Some("init" | "completions" | "__complete" | "migrate-scripts" | "server")
+        )
+    {
+        say!("{}", t!("no-servers").yellow());
+        add_server(&mut config)?;
+    }
 
-            println!("{} {} {} {}", "Sending".green(), src, "to".green(), dest);
-            if let Err(e) = TransferEngine::send_file(src, dest, &config) {
-                eprintln!("{}: {}", "Error".red().bold(), e);
+    match matches.subcommand() {
+        ("send", Some(sub_m)) => {
+            let mut sources: Vec<&str> = sub_m.values_of("PATHS").unwrap().collect();
+            let dest = sources.pop().unwrap();
+
+            say!(
+                "{} {} {} {}",
+                t!("sending").green(),
+                sources.join(", "),
+                t!("to").green(),
+                dest
+            );
+            if let Err(e) =
+                TransferEngine::send_files(&sources, dest, &config, &transfer_options(sub_m))
+            {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
                 std::process::exit(1);
             }
         }
         ("get", Some(sub_m)) => {
+            let mut sources: Vec<&str> = sub_m.values_of("PATHS").unwrap().collect();
+            let dest = sources.pop().unwrap();
+
+            say!(
+                "{} {} {} {}",
+                t!("getting").green(),
+                sources.join(", "),
+                t!("to").green(),
+                dest
+            );
+            if let Err(e) =
+                TransferEngine::send_files(&sources, dest, &config, &transfer_options(sub_m))
+            {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("sync", Some(sub_m)) => {
             let src = sub_m.value_of("SOURCE").unwrap();
             let dest = sub_m.value_of("DESTINATION").unwrap();
 
-            println!("{} {} {} {}", "Getting".green(), src, "to".green(), dest);
-            if let Err(e) = TransferEngine::send_file(src, dest, &config) {
-                eprintln!("{}: {}", "Error".red().bold(), e);
+            if sub_m.is_present("bidirectional") {
+                say!(
+                    "{} {} {} {}",
+                    t!("syncing").green(),
+                    src,
+                    "<->".green(),
+                    dest
+                );
+                let prefer = sub_m
+                    .value_of("prefer")
+                    .map(|side| bisync::Prefer::parse(side).unwrap());
+                if let Err(e) = bisync::sync(src, dest, &config, &transfer_options(sub_m), prefer) {
+                    eprintln!("{}: {}", t!("error").red().bold(), e);
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+
+            say!(
+                "{} {} {} {}",
+                t!("syncing").green(),
+                src,
+                t!("to").green(),
+                dest
+            );
+            let mirror = sub_m.is_present("mirror");
+            let opts = TransferOptions {
+                delete: sub_m.is_present("delete") || mirror,
+                verify: sub_m.is_present("verify") || mirror,
+                confirm_delete: !sub_m.is_present("force"),
+                ..transfer_options(sub_m)
+            };
+            if let Err(e) = TransferEngine::send_recorded(src, dest, &config, &opts) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
                 std::process::exit(1);
             }
         }
-        ("sync", Some(sub_m)) => {
+        ("watch", Some(sub_m)) => {
             let src = sub_m.value_of("SOURCE").unwrap();
             let dest = sub_m.value_of("DESTINATION").unwrap();
-
-            println!("{} {} {} {}", "Syncing".green(), src, "to".green(), dest);
-            if let Err(e) = TransferEngine::send_file(src, dest, &config) {
-                eprintln!("{}: {}", "Error".red().bold(), e);
+            let debounce = match sub_m.value_of("debounce").unwrap().parse::<u64>() {
+                Ok(ms) => Duration::from_millis(ms),
+                Err(_) => {
+                    eprintln!(
+                        "{}: --debounce must be a number of milliseconds",
+                        t!("error").red().bold()
+                    );
+                    std::process::exit(1);
+                }
+            };
+            if let Err(e) = watch::watch(src, dest, &config, &transfer_options(sub_m), debounce) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
                 std::process::exit(1);
             }
         }
+        ("mirror", Some(sub_m)) => {
+            let result = match sub_m.subcommand() {
+                ("start", Some(start_m)) => mirror::start(
+                    start_m.value_of("SOURCE").unwrap(),
+                    start_m.value_of("DESTINATION").unwrap(),
+                    &config,
+                    &transfer_options(start_m),
+                    parse_duration(start_m.value_of("interval").unwrap())?,
+                    start_m.is_present("once"),
+                    start_m.is_present("force"),
+                ),
+                ("status", Some(status_m)) => {
+                    mirror::status(status_m.value_of("DESTINATION"), &config)
+                }
+                _ => unreachable!(),
+            };
+            if let Err(e) = result {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("verify", Some(sub_m)) => {
+            let local = sub_m.value_of("LOCAL").unwrap();
+            let remote = sub_m.value_of("REMOTE").unwrap();
+
+            say!(
+                "{} {} {} {}",
+                "Verifying".green(),
+                local,
+                "against".green(),
+                remote
+            );
+            let result = Location::parse(remote, &config).and_then(|location| match location {
+                Location::Remote { server, path, .. } => {
+                    verify::verify(Path::new(local), server, &path.to_string())
+                }
+                _ => Err(t!("verify-needs-remote")),
+            });
+            if let Err(e) = result {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("snapshot", Some(sub_m)) => {
+            if let Err(e) = snapshot::snapshot(
+                sub_m.value_of("SOURCE").unwrap(),
+                sub_m.value_of("ARCHIVE").unwrap(),
+                &TransferOptions {
+                    no_preserve: sub_m
+                        .is_present("follow-links")
+                        .then(|| "links".to_string()),
+                    ..transfer_options(sub_m)
+                },
+                &config,
+            ) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("history", Some(sub_m)) => {
+            if let Some(export_m) = sub_m.subcommand_matches("export") {
+                if let Err(e) = history::export(
+                    export_m.value_of("format").unwrap(),
+                    export_m.value_of("since"),
+                    export_m.value_of("to"),
+                ) {
+                    eprintln!("{}: {}", t!("error").red().bold(), e);
+                    std::process::exit(1);
+                }
+            } else {
+                let limit = sub_m.value_of("limit").unwrap().parse().unwrap_or(20);
+                history::list(limit);
+            }
+        }
+        ("redo", Some(sub_m)) => {
+            if let Err(e) = history::redo(sub_m.value_of("ID").unwrap(), &config) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("unlock", Some(sub_m)) => {
+            let location = sub_m.value_of("LOCATION").unwrap();
+            let result = Location::parse(location, &config).and_then(|location| match location {
+                Location::Remote { server, path, .. } => lock::unlock(server, &path.to_string()),
+                _ => Err(t!("unlock-needs-remote")),
+            });
+            if let Err(e) = result {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("stat", Some(sub_m)) => {
+            let locations: Vec<&str> = sub_m.values_of("LOCATION").unwrap().collect();
+            if let Err(e) = remote_fs::show(&locations, &config) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("df", Some(sub_m)) => {
+            let locations: Vec<&str> = sub_m.values_of("LOCATION").unwrap().collect();
+            if let Err(e) = quota::show(&locations, &config) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("touch", Some(sub_m)) => {
+            let locations: Vec<&str> = sub_m.values_of("LOCATION").unwrap().collect();
+            if let Err(e) = remote_fs::touch(&locations, sub_m.is_present("no-create"), &config) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("run", Some(sub_m)) => {
+            let result = match sub_m.value_of("ROUTE") {
+                Some(route) => project::run(
+                    route,
+                    sub_m.is_present("force"),
+                    sub_m.is_present("dry-run"),
+                    &config,
+                ),
+                None => project::list_routes(&config),
+            };
+            if let Err(e) = result {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("template", Some(sub_m)) => {
+            let result = match sub_m.subcommand() {
+                ("list", _) => {
+                    templates::list();
+                    Ok(())
+                }
+                ("apply", Some(apply_m)) => templates::apply(
+                    apply_m.value_of("TEMPLATE").unwrap(),
+                    apply_m.value_of("name"),
+                    &apply_m
+                        .values_of("set")
+                        .map(|v| v.collect::<Vec<_>>())
+                        .unwrap_or_default(),
+                    &config,
+                ),
+                _ => unreachable!(),
+            };
+            if let Err(e) = result {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("mkdir", Some(sub_m)) => {
+            let locations: Vec<&str> = sub_m.values_of("LOCATION").unwrap().collect();
+            if let Err(e) = remote_fs::mkdir(&locations, sub_m.is_present("parents"), &config) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("rm", Some(sub_m)) => {
+            let locations: Vec<&str> = sub_m.values_of("LOCATION").unwrap().collect();
+            if let Err(e) = remote_fs::rm(
+                &locations,
+                sub_m.is_present("recursive"),
+                sub_m.is_present("yes"),
+                &config,
+            ) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("mv", Some(sub_m)) => {
+            let from = sub_m.value_of("FROM").unwrap();
+            let to = sub_m.value_of("TO").unwrap();
+            if let Err(e) = remote_fs::mv(from, to, sub_m.is_present("yes"), &config) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("queue", Some(sub_m)) => {
+            let result = match sub_m.subcommand() {
+                ("add", Some(add_m)) => {
+                    let mut sources: Vec<&str> = add_m.values_of("PATHS").unwrap().collect();
+                    let dest = sources.pop().unwrap();
+                    queue::add(
+                        &sources,
+                        dest,
+                        add_m.value_of("at"),
+                        &transfer_options(add_m),
+                        &config,
+                    )
+                }
+                ("list", _) => {
+                    queue::list();
+                    Ok(())
+                }
+                ("status", _) => {
+                    queue::status();
+                    Ok(())
+                }
+                ("cancel", Some(cancel_m)) => {
+                    let ids: Vec<u64> = cancel_m
+                        .values_of("ID")
+                        .unwrap()
+                        .filter_map(|id| id.parse().ok())
+                        .collect();
+                    queue::cancel(&ids)
+                }
+                ("run-job", Some(job_m)) => match job_m.value_of("ID").unwrap().parse() {
+                    Ok(id) => queue::run_job(id, &config),
+                    Err(_) => Err("Invalid job id".to_string()),
+                },
+                _ => unreachable!(),
+            };
+            if let Err(e) = result {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("daemon", Some(sub_m)) => {
+            let concurrency = sub_m.value_of("concurrency").unwrap().parse().unwrap_or(2);
+            let attempts = sub_m.value_of("attempts").unwrap().parse().unwrap_or(3);
+            let log = sub_m.value_of("log");
+            let result = if sub_m.is_present("stop") {
+                queue::stop()
+            } else if sub_m.is_present("detach") {
+                queue::detach(concurrency, attempts, log)
+            } else {
+                queue::daemon(concurrency, attempts, log)
+            };
+            if let Err(e) = result {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("resume", _) => {
+            if let Err(e) = resume::resume_last(&config) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("init", Some(sub_m)) => {
+            if let Err(e) = init::run(sub_m, &mut config) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("browse", Some(sub_m)) => {
+            if let Err(e) = browse::run(
+                sub_m.value_of("LOCATION").unwrap(),
+                sub_m.is_present("refresh"),
+                &config,
+            ) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("audit-perms", Some(sub_m)) => {
+            if let Err(e) = audit::audit_perms(
+                sub_m.value_of("LOCAL").unwrap(),
+                sub_m.value_of("REMOTE").unwrap(),
+                sub_m.is_present("ignore-owner"),
+                &config,
+            ) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("migrate-scripts", Some(sub_m)) => {
+            let paths: Vec<&str> = sub_m.values_of("PATH").unwrap().collect();
+            if let Err(e) = migrate::migrate_scripts(&paths, &config) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("check", Some(sub_m)) => {
+            let aliases: Vec<&str> = sub_m.values_of("ALIAS").into_iter().flatten().collect();
+            if let Err(e) = check::run(&aliases, sub_m.is_present("all"), &config) {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("completions", Some(sub_m)) => {
+            let shell = sub_m.value_of("SHELL").unwrap().parse::<Shell>()?;
+            io::stdout().write_all(&completions::script(shell))?;
+        }
+        ("__complete", Some(sub_m)) => {
+            completions::complete(sub_m.value_of("WORD").unwrap(), &config);
+        }
+        ("top", _) => {
+            if let Err(e) = top::run() {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("helper", Some(sub_m)) => {
+            let result = match sub_m.subcommand() {
+                ("install", Some(m)) => helper::install(m.value_of("ALIAS").unwrap(), &config),
+                ("status", Some(m)) => helper::status(m.value_of("ALIAS").unwrap(), &config),
+                ("remove", Some(m)) => helper::remove(m.value_of("ALIAS").unwrap(), &config),
+                _ => Err("Use 'xfer helper install|status|remove <alias>'".to_string()),
+            };
+            if let Err(e) = result {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+        ("cache", Some(sub_m)) => match sub_m.subcommand() {
+            ("clear", _) => match listings::clear() {
+                Ok(count) => say!("{} {} cached listing(s)", "Cleared".green(), count),
+                Err(e) => {
+                    eprintln!("{}: {}", t!("error").red().bold(), e);
+                    std::process::exit(1);
+                }
+            },
+            _ => unreachable!(),
+        },
         ("list", Some(sub_m)) => {
             let location = sub_m.value_of("LOCATION").unwrap();
             let parts: Vec<&str> = location.splitn(2, ':').collect();
 
             if parts.len() != 2 {
-                eprintln!(
-                    "{}: Invalid location format. Use 'alias:/path'",
-                    "Error".red().bold()
-                );
+                eprintln!("{}: {}", t!("error").red().bold(), t!("invalid-location"));
                 std::process::exit(1);
             }
 
             let alias = parts[0];
             let path = parts[1];
 
-            println!("{} {} {}", "Listing".green(), path, "on".green());
-            if let Err(e) = TransferEngine::list_remote(alias, path, &config) {
-                eprintln!("{}: {}", "Error".red().bold(), e);
+            say!("{} {} {}", "Listing".green(), path, "on".green());
+            if let Err(e) =
+                TransferEngine::list_remote(alias, path, sub_m.is_present("refresh"), &config)
+            {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
                 std::process::exit(1);
             }
         }
         ("server", Some(sub_m)) => match sub_m.subcommand() {
-            ("add", _) => {
-                if let Err(e) = add_server(&mut config) {
-                    eprintln!("{}: {}", "Error".red().bold(), e);
+            ("add", Some(add_m)) => {
+                let result = if let Some(path) = add_m.value_of("from-file") {
+                    import_servers(path, add_m.is_present("force"), &mut config)
+                } else if add_m.is_present("host") {
+                    add_server_from_flags(add_m, &mut config)
+                } else if io::stdin().is_terminal() {
+                    add_server(&mut config).map_err(|e| e.to_string())
+                } else {
+                    Err(t!("no-terminal"))
+                };
+                if let Err(e) = result {
+                    eprintln!("{}: {}", t!("error").red().bold(), e);
                     std::process::exit(1);
                 }
             }
+            ("list", _) if output::is_json() => {
+                let mut servers: Vec<_> = config
+                    .servers
+                    .iter()
+                    .map(|(alias, server)| output::ServerEntry {
+                        alias,
+                        default: config.default_server.as_ref() == Some(alias),
+                        server,
+                    })
+                    .collect();
+                servers.sort_by_key(|entry| entry.alias);
+                output::emit(&servers);
+            }
             ("list", _) => {
-                println!("{}", "Configured Servers:".green().bold());
+                say!("{}", "Configured Servers:".green().bold());
                 for (alias, server) in &config.servers {
-                    println!(
+                    say!(
                         "  {} - {}@{}",
                         alias.yellow(),
                         server.user.cyan(),
@@ -573,17 +3407,75 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     );
                     if let Some(default) = &config.default_server {
                         if default == alias {
-                            println!("    {}", "DEFAULT".green());
+                            say!("    {}", "DEFAULT".green());
                         }
                     }
+                    if let Some(project) = &config.project {
+                        if project.defines(alias) {
+                            say!("    {} {}", "PROJECT".cyan(), project.path.display());
+                        }
+                    }
+                }
+            }
+            ("password", Some(pass_m)) => {
+                if let Err(e) = auth::set_password(pass_m.value_of("ALIAS").unwrap(), &config) {
+                    eprintln!("{}: {}", t!("error").red().bold(), e);
+                    std::process::exit(1);
                 }
             }
             _ => unreachable!(),
         },
         _ => {
-            println!("No command specified. Use --help for usage information.");
+            say!("{}", t!("no-command"));
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn glob_quote_leaves_only_the_glob_to_the_shell() {
+        assert_eq!(glob_quote("/var/log/*.gz"), "'/var/log/'*'.gz'");
+        assert_eq!(glob_quote("/a b/file?.[0-9]"), "'/a b/file'?'.'[0-9]");
+        assert_eq!(glob_quote("/x/$(touch y)*"), "'/x/$(touch y)'*");
+        assert_eq!(glob_quote("/x/[;rm]*"), "'/x/[;rm]'*");
+        assert_eq!(glob_quote("/x/it's*"), "'/x/it'\\''s'*");
+    }
+
+    #[test]
+    fn glob_quote_expands_in_a_real_shell() {
+        let dir = env::temp_dir().join(format!("xfer-main-{}-glob", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("a b")).unwrap();
+        for name in ["one.gz", "two.gz", "three.txt"] {
+            fs::write(dir.join("a b").join(name), "").unwrap();
+        }
+        let pattern = format!("{}/a b/*.gz;touch hacked", dir.display());
+        let output = Command::new("sh")
+            .current_dir(&dir)
+            .args(["-c", &format!("ls -1d -- {}", glob_quote(&pattern))])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(!dir.join("hacked").exists());
+
+        let pattern = format!("{}/a b/*.gz", dir.display());
+        let output = Command::new("sh")
+            .args(["-c", &format!("ls -1d -- {}", glob_quote(&pattern))])
+            .output()
+            .unwrap();
+        let listed = String::from_utf8_lossy(&output.stdout);
+        let names: Vec<&str> = listed
+            .lines()
+            .map(|l| l.rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(names, ["one.gz", "two.gz"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,832 @@
+use crate::{output, s3, Config};
+use colored::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::net::IpAddr;
+use std::ops::Not;
+use std::path::{Path, PathBuf};
+
+/// Files bigger than this aren't scripts.
+const MAX_SIZE: u64 = 1 << 20;
+
+/// Words that may come before the tool in a command.
+const WRAPPERS: [&str; 13] = [
+    "sudo", "exec", "command", "time", "nohup", "env", "then", "else", "do", "{", "(", "!", "nice",
+];
+
+/// rsync options that take a value, so it isn't mistaken for a path.
+const RSYNC_VALUES: [&str; 40] = [
+    "bwlimit",
+    "rsh",
+    "exclude",
+    "include",
+    "exclude-from",
+    "include-from",
+    "filter",
+    "files-from",
+    "chmod",
+    "chown",
+    "usermap",
+    "groupmap",
+    "port",
+    "timeout",
+    "contimeout",
+    "partial-dir",
+    "backup-dir",
+    "suffix",
+    "log-file",
+    "log-file-format",
+    "out-format",
+    "compare-dest",
+    "copy-dest",
+    "link-dest",
+    "temp-dir",
+    "max-size",
+    "min-size",
+    "max-delete",
+    "block-size",
+    "rsync-path",
+    "password-file",
+    "modify-window",
+    "iconv",
+    "compress-level",
+    "compress-choice",
+    "checksum-choice",
+    "skip-compress",
+    "info",
+    "debug",
+    "remote-option",
+];
+
+/// rsync options that don't change what ends up where, so dropping them
+/// needs no mention.
+const RSYNC_HARMLESS: [&str; 24] = [
+    "archive",
+    "recursive",
+    "verbose",
+    "quiet",
+    "compress",
+    "progress",
+    "partial",
+    "human-readable",
+    "stats",
+    "links",
+    "perms",
+    "times",
+    "group",
+    "owner",
+    "devices",
+    "specials",
+    "itemize-changes",
+    "hard-links",
+    "acls",
+    "xattrs",
+    "sparse",
+    "whole-file",
+    "timeout",
+    "contimeout",
+];
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    /// `;`, `|` or `&`: the end of a command.
+    Separator,
+}
+
+/// Splits a line the way the shell would, near enough: quotes and
+/// backslashes are honoured and a `#` starts a comment.
+fn tokenize(line: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+            }
+            '#' if !in_word => break,
+            '\'' => {
+                in_word = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' if matches!(chars.peek(), Some('"' | '\\' | '$' | '`')) => {
+                            word.extend(chars.next())
+                        }
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.extend(chars.next());
+            }
+            ';' | '|' | '&' => {
+                if in_word {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+                if tokens.last() != Some(&Token::Separator) {
+                    tokens.push(Token::Separator);
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        tokens.push(Token::Word(word));
+    }
+    tokens
+}
+
+fn words(line: &str) -> Vec<String> {
+    tokenize(line)
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Word(word) => Some(word),
+            Token::Separator => None,
+        })
+        .collect()
+}
+
+/// An scp or rsync call found in a script.
+struct Call {
+    line: usize,
+    text: String,
+    /// The shell alias it defines, if it is one.
+    name: Option<String>,
+    tool: &'static str,
+    args: Vec<String>,
+}
+
+fn find_calls(line: usize, text: &str, name: Option<&str>, calls: &mut Vec<Call>) {
+    let tokens = tokenize(text);
+    for command in tokens.split(|token| *token == Token::Separator) {
+        let command: Vec<&str> = command
+            .iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word.as_str()),
+                Token::Separator => None,
+            })
+            .collect();
+
+        if command.first() == Some(&"alias") {
+            for definition in &command[1..] {
+                if let Some((alias, value)) = definition.split_once('=') {
+                    find_calls(line, value, Some(alias), calls);
+                }
+            }
+            continue;
+        }
+
+        for (i, word) in command.iter().enumerate() {
+            let tool = match word.rsplit('/').next() {
+                Some("scp") => "scp",
+                Some("rsync") => "rsync",
+                _ => {
+                    let wrapper = WRAPPERS.contains(word)
+                        || word.starts_with('-')
+                        || (word.contains('=') && !word.starts_with('='));
+                    if wrapper {
+                        continue;
+                    }
+                    break;
+                }
+            };
+            calls.push(Call {
+                line,
+                text: text.trim().to_string(),
+                name: name.map(String::from),
+                tool,
+                args: command[i + 1..].iter().map(|s| s.to_string()).collect(),
+            });
+            break;
+        }
+    }
+}
+
+/// Lines of `content` with backslash continuations joined, numbered from 1.
+fn logical_lines(content: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+    for (i, line) in content.lines().enumerate() {
+        let (start, mut text, line) = match pending.take() {
+            Some((start, text)) => (start, text, line.trim_start()),
+            None => (i + 1, String::new(), line),
+        };
+        match line.strip_suffix('\\') {
+            Some(rest) => {
+                text.push_str(rest.trim_end());
+                text.push(' ');
+                pending = Some((start, text));
+            }
+            None => {
+                text.push_str(line);
+                lines.push((start, text));
+            }
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+#[derive(Debug, Default)]
+struct Ssh {
+    user: Option<String>,
+    port: Option<u16>,
+    key: Option<String>,
+    jump: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Parsed {
+    ssh: Ssh,
+    paths: Vec<String>,
+    delete: bool,
+    verify: bool,
+    limit: Option<String>,
+    notes: Vec<String>,
+}
+
+impl Parsed {
+    fn port(&mut self, value: &str) {
+        match value.parse() {
+            Ok(port) => self.ssh.port = Some(port),
+            Err(_) => self.notes.push(format!("port '{}' is not a number", value)),
+        }
+    }
+
+    /// An ssh `-o Key=value` option.
+    fn ssh_option(&mut self, option: &str) {
+        let (key, value) = match option.split_once(['=', ' ']) {
+            Some((key, value)) => (key.to_ascii_lowercase(), value.trim()),
+            None => (String::new(), ""),
+        };
+        match key.as_str() {
+            "port" => self.port(value),
+            "user" => self.ssh.user = Some(value.to_string()),
+            "identityfile" => self.ssh.key = Some(value.to_string()),
+            "proxyjump" => self.ssh.jump = Some(value.to_string()),
+            _ => self
+                .notes
+                .push(format!("ssh option '{}' is not carried over", option)),
+        }
+    }
+
+    /// The ssh command given to rsync's `-e`.
+    fn rsh(&mut self, command: &str) {
+        let args = words(command);
+        let mut args = args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => rest.split_at(1),
+                _ => {
+                    self.notes
+                        .push(format!("'{}' in the ssh command is not carried over", arg));
+                    continue;
+                }
+            };
+            if !"pilJoF".contains(flag) {
+                continue;
+            }
+            let value = match inline {
+                "" => args.next().cloned().unwrap_or_default(),
+                inline => inline.to_string(),
+            };
+            match flag {
+                "p" => self.port(&value),
+                "i" => self.ssh.key = Some(value),
+                "l" => self.ssh.user = Some(value),
+                "J" => self.ssh.jump = Some(value),
+                "o" => self.ssh_option(&value),
+                _ => self
+                    .notes
+                    .push(format!("ssh -{} is not carried over", flag)),
+            }
+        }
+    }
+}
+
+fn parse_scp(args: &[String]) -> Parsed {
+    let mut parsed = Parsed::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.paths.extend(args.by_ref().cloned());
+            break;
+        }
+        let flags = match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => flags,
+            _ => {
+                parsed.paths.push(arg.clone());
+                continue;
+            }
+        };
+        for (i, flag) in flags.char_indices() {
+            if !"PiJloFcSDX".contains(flag) {
+                if !"rpqCv46BTOsA3R".contains(flag) {
+                    parsed
+                        .notes
+                        .push(format!("scp -{} is not carried over", flag));
+                }
+                continue;
+            }
+            let value = match &flags[i + 1..] {
+                "" => args.next().cloned().unwrap_or_default(),
+                inline => inline.to_string(),
+            };
+            match flag {
+                'P' => parsed.port(&value),
+                'i' => parsed.ssh.key = Some(value),
+                'J' => parsed.ssh.jump = Some(value),
+                'o' => parsed.ssh_option(&value),
+                // Kbit/s.
+                'l' => match value.parse::<u64>() {
+                    Ok(kbits) => parsed.limit = Some((kbits * 125).to_string()),
+                    Err(_) => parsed
+                        .notes
+                        .push(format!("scp -l {} is not a number", value)),
+                },
+                _ => parsed
+                    .notes
+                    .push(format!("scp -{} {} is not carried over", flag, value)),
+            }
+            break;
+        }
+    }
+    parsed
+}
+
+/// rsync's `--bwlimit` is in KiB/s unless it has a suffix.
+fn rsync_limit(value: &str) -> String {
+    if value.chars().all(|c| c.is_ascii_digit()) {
+        format!("{}K", value)
+    } else {
+        value.to_ascii_uppercase()
+    }
+}
+
+fn parse_rsync(args: &[String]) -> Parsed {
+    let mut parsed = Parsed::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.paths.extend(args.by_ref().cloned());
+            break;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None if RSYNC_VALUES.contains(&long) => (long, args.next().cloned()),
+                None => (long, None),
+            };
+            let value = value.unwrap_or_default();
+            match name {
+                "delete" | "del" | "delete-before" | "delete-during" | "delete-delay"
+                | "delete-after" => parsed.delete = true,
+                "checksum" => parsed.verify = true,
+                "bwlimit" => parsed.limit = Some(rsync_limit(&value)),
+                "rsh" => parsed.rsh(&value),
+                name if RSYNC_HARMLESS.contains(&name) => {}
+                name if RSYNC_VALUES.contains(&name) => parsed
+                    .notes
+                    .push(format!("--{} {} is not carried over", name, value)),
+                name => parsed.notes.push(format!("--{} is not carried over", name)),
+            }
+            continue;
+        }
+
+        let flags = match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => flags,
+            _ => {
+                parsed.paths.push(arg.clone());
+                continue;
+            }
+        };
+        for (i, flag) in flags.char_indices() {
+            match flag {
+                'e' | 'f' | 'T' | 'B' => {
+                    let value = match &flags[i + 1..] {
+                        "" => args.next().cloned().unwrap_or_default(),
+                        inline => inline.to_string(),
+                    };
+                    if flag == 'e' {
+                        parsed.rsh(&value);
+                    } else {
+                        parsed
+                            .notes
+                            .push(format!("rsync -{} {} is not carried over", flag, value));
+                    }
+                    break;
+                }
+                'c' => parsed.verify = true,
+                c if "avzPhrlptgoDqiHAXSW".contains(c) => {}
+                c => parsed
+                    .notes
+                    .push(format!("rsync -{} is not carried over", c)),
+            }
+        }
+    }
+    parsed
+}
+
+/// `[user@]host:path`, split up; `None` for a local path.
+fn split_remote(path: &str) -> Option<(Option<&str>, &str, &str)> {
+    let colon = match path.find('[') {
+        // An IPv6 address: [::1]:path
+        Some(open) => path[open..].find("]:").map(|close| open + close + 1)?,
+        None => path.find(':')?,
+    };
+    let (spec, rest) = (&path[..colon], &path[colon + 1..]);
+    if spec.is_empty() || spec.contains('/') {
+        return None;
+    }
+    let (user, host) = match spec.rsplit_once('@') {
+        Some((user, host)) => (Some(user), host),
+        None => (None, spec),
+    };
+    Some((
+        user,
+        host.trim_start_matches('[').trim_end_matches(']'),
+        rest,
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ProposedServer {
+    host: String,
+    user: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proxy_jump: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProposedRoute {
+    src: String,
+    dest: String,
+    #[serde(skip_serializing_if = "Not::not")]
+    verify: bool,
+    #[serde(skip_serializing_if = "Not::not")]
+    delete: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Proposal {
+    servers: BTreeMap<String, ProposedServer>,
+    routes: BTreeMap<String, ProposedRoute>,
+}
+
+#[derive(Debug, Serialize)]
+struct Finding {
+    file: PathBuf,
+    line: usize,
+    command: String,
+    /// The equivalent xfer command.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    xfer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    route: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<String>,
+    /// Why nothing could be proposed for it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    findings: &'a [Finding],
+    #[serde(flatten)]
+    proposal: &'a Proposal,
+}
+
+/// `base`, or `base-2`, `base-3`... whichever is free.
+fn unique(base: &str, taken: impl Fn(&str) -> bool) -> String {
+    let base = if base.is_empty() { "server" } else { base };
+    if !taken(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|name| !taken(name))
+        .unwrap()
+}
+
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '-' | '_') => c,
+            _ => '-',
+        })
+        .collect::<String>()
+        .trim_matches('-')
+        .to_string()
+}
+
+struct Migration<'a> {
+    config: &'a Config,
+    default_user: String,
+    proposal: Proposal,
+}
+
+impl Migration<'_> {
+    /// The alias for a server: one already configured with the same host,
+    /// user and port, one proposed earlier, or a new one named after the
+    /// host.
+    fn alias(&mut self, server: ProposedServer) -> String {
+        let port = server.port.unwrap_or(22);
+        let mut existing: Vec<(&String, _)> = self.config.servers.iter().collect();
+        existing.sort_by_key(|(alias, _)| *alias);
+        if let Some((alias, _)) = existing.into_iter().find(|(_, s)| {
+            s.host == server.host && s.user == server.user && s.port.unwrap_or(22) == port
+        }) {
+            return alias.clone();
+        }
+        if let Some((alias, _)) = self.proposal.servers.iter().find(|(_, s)| **s == server) {
+            return alias.clone();
+        }
+
+        let base = if server.host.parse::<IpAddr>().is_ok() {
+            "server".to_string()
+        } else {
+            slug(server.host.split('.').next().unwrap_or_default())
+        };
+        let alias = unique(&base, |name| {
+            self.config.servers.contains_key(name)
+                || self.proposal.servers.contains_key(name)
+                || name == s3::SCHEME
+        });
+        self.proposal.servers.insert(alias.clone(), server);
+        alias
+    }
+
+    fn route_name(&self, base: &str) -> String {
+        let routes = self.config.project.as_ref().map(|p| &p.routes);
+        unique(&slug(base), |name| {
+            self.proposal.routes.contains_key(name)
+                || routes.is_some_and(|routes| routes.contains_key(name))
+        })
+    }
+
+    fn propose(&mut self, file: &Path, call: &Call) -> Finding {
+        let mut finding = Finding {
+            file: file.to_path_buf(),
+            line: call.line,
+            command: call.text.clone(),
+            xfer: None,
+            route: None,
+            notes: Vec::new(),
+            skipped: None,
+        };
+        let parsed = match call.tool {
+            "scp" => parse_scp(&call.args),
+            _ => parse_rsync(&call.args),
+        };
+        finding.notes = parsed.notes.clone();
+
+        let skip = if parsed.paths.len() < 2 {
+            Some("no source and destination found".to_string())
+        } else if parsed.paths.iter().any(|p| p.contains(['$', '`'])) {
+            Some("uses shell variables or substitutions".to_string())
+        } else if parsed
+            .paths
+            .iter()
+            .any(|p| p.starts_with("rsync://") || p.contains("::"))
+        {
+            Some("rsync daemon transfers are not supported".to_string())
+        } else if parsed.paths.iter().all(|p| split_remote(p).is_none()) {
+            Some("copies between local paths only".to_string())
+        } else {
+            None
+        };
+        if skip.is_some() {
+            finding.skipped = skip;
+            return finding;
+        }
+
+        let mut locations = Vec::new();
+        for path in &parsed.paths {
+            locations.push(match split_remote(path) {
+                Some((user, host, rest)) => {
+                    if user.is_none() && parsed.ssh.user.is_none() && self.default_user.is_empty() {
+                        finding
+                            .notes
+                            .push(format!("no user given for {}; fill it in", host));
+                    }
+                    let server = ProposedServer {
+                        host: host.to_string(),
+                        user: user
+                            .map(String::from)
+                            .or_else(|| parsed.ssh.user.clone())
+                            .unwrap_or_else(|| self.default_user.clone()),
+                        port: parsed.ssh.port.filter(|&port| port != 22),
+                        key_path: parsed.ssh.key.clone(),
+                        proxy_jump: parsed.ssh.jump.clone(),
+                    };
+                    format!("{}:{}", self.alias(server), rest)
+                }
+                None => path.clone(),
+            });
+        }
+        let dest = locations.pop().unwrap();
+
+        let mut command = vec!["xfer".to_string()];
+        if parsed.delete {
+            command.extend(["sync".to_string(), "--delete".to_string()]);
+            if locations.len() > 1 {
+                finding
+                    .notes
+                    .push("sync takes one source; run it once per source".to_string());
+            }
+        } else if dest.contains(':') {
+            command.push("send".to_string());
+        } else {
+            command.push("get".to_string());
+        }
+        if parsed.verify {
+            command.push("--verify".to_string());
+        }
+        if let Some(limit) = &parsed.limit {
+            command.extend(["--limit".to_string(), limit.clone()]);
+        }
+        command.extend(locations.iter().chain([&dest]).map(|arg| quote(arg)));
+        finding.xfer = Some(command.join(" "));
+
+        if let [src] = &locations[..] {
+            let base = call.name.clone().unwrap_or_else(|| {
+                file.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+            let name = self.route_name(&base);
+            self.proposal.routes.insert(
+                name.clone(),
+                ProposedRoute {
+                    src: src.clone(),
+                    dest,
+                    verify: parsed.verify,
+                    delete: parsed.delete,
+                    limit: parsed.limit,
+                },
+            );
+            finding.route = Some(name);
+        }
+        finding
+    }
+}
+
+/// Quotes `arg` for the shell when it needs it.
+fn quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:@%+=,~".contains(c))
+    {
+        arg.to_string()
+    } else {
+        crate::shell_quote(arg)
+    }
+}
+
+/// The files under `path`, in order; `.git` directories are skipped.
+fn collect(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name().is_some_and(|name| name != ".git"))
+        .collect();
+    entries.sort();
+    for entry in entries {
+        collect(&entry, files)?;
+    }
+    Ok(())
+}
+
+/// Reads a script; binaries and oversized files aren't.
+fn read_script(path: &Path) -> Result<Option<String>, String> {
+    let metadata =
+        fs::metadata(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if metadata.len() > MAX_SIZE {
+        return Ok(None);
+    }
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if bytes.contains(&0) {
+        return Ok(None);
+    }
+    Ok(String::from_utf8(bytes).ok())
+}
+
+fn print(findings: &[Finding], proposal: &Proposal) -> Result<(), String> {
+    for finding in findings {
+        say!(
+            "{}:{}: {}",
+            finding.file.display(),
+            finding.line,
+            finding.command.dimmed()
+        );
+        if let Some(reason) = &finding.skipped {
+            say!("  {} {}", "skipped:".yellow(), reason);
+            continue;
+        }
+        if let Some(xfer) = &finding.xfer {
+            say!("  {}", xfer.green());
+        }
+        if let Some(route) = &finding.route {
+            say!("  {} xfer run {}", "or:".dimmed(), route);
+        }
+        for note in &finding.notes {
+            say!("  {} {}", "note:".yellow(), note);
+        }
+    }
+
+    if proposal.servers.is_empty() && proposal.routes.is_empty() {
+        return Ok(());
+    }
+    let toml = toml::to_string(proposal)
+        .map_err(|e| format!("Failed to write TOML: {}", e))?
+        // The serializer runs the last server into the first route.
+        .replace("\n[routes.", "\n\n[routes.")
+        .replace("\n\n\n", "\n\n");
+    say!(
+        "\n{}",
+        "# Proposed entries: add them to .xfer.toml, or the servers to the global config.".bold()
+    );
+    say!("# Relative route paths are taken from the directory holding .xfer.toml.\n");
+    say!("{}", toml.trim_end());
+    Ok(())
+}
+
+/// Scans shell scripts and alias files under `paths` for scp and rsync
+/// calls, and proposes the servers and routes that replace them along with
+/// the equivalent xfer commands. Nothing is written.
+pub fn migrate_scripts(paths: &[&str], config: &Config) -> Result<(), String> {
+    let mut files = Vec::new();
+    for path in paths {
+        collect(Path::new(path), &mut files)?;
+    }
+
+    let mut migration = Migration {
+        config,
+        default_user: env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_default(),
+        proposal: Proposal::default(),
+    };
+    let mut findings = Vec::new();
+    for file in &files {
+        let content = match read_script(file)? {
+            Some(content) => content,
+            None => continue,
+        };
+        let mut calls = Vec::new();
+        for (line, text) in logical_lines(&content) {
+            find_calls(line, &text, None, &mut calls);
+        }
+        for call in &calls {
+            findings.push(migration.propose(file, call));
+        }
+    }
+
+    if output::is_json() {
+        output::emit(&Report {
+            findings: &findings,
+            proposal: &migration.proposal,
+        });
+        return Ok(());
+    }
+    if findings.is_empty() {
+        say!("No scp or rsync calls found in {} file(s).", files.len());
+        return Ok(());
+    }
+    print(&findings, &migration.proposal)
+}
@@ -0,0 +1,511 @@
+use crate::bisync::{local_files, Stamp};
+use crate::history::format_timestamp;
+use crate::location::Location;
+use crate::platform::Platform;
+use crate::queue::format_local;
+use crate::{
+    data_dir, output, remote_fs, sessions, shell_quote, unix_now, Config, ServerConfig,
+    TransferEngine, TransferOptions,
+};
+use colored::*;
+use indicatif::HumanBytes;
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How long changes have to settle before they are mirrored.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+/// A heartbeat this many intervals old means the mirror has stopped.
+const STALE_AFTER: u64 = 3;
+
+/// What a mirror publishes next to the standby after every sync: when it
+/// last caught up, and the files it had sent by then.
+#[derive(Debug, Serialize, Deserialize)]
+struct Heartbeat {
+    source: String,
+    host: String,
+    synced: u64,
+    /// Seconds between syncs, so a reader can tell a late heartbeat.
+    interval: u64,
+    files: usize,
+    bytes: u64,
+    /// SHA-256 of the manifest lines, to catch a heartbeat that was cut
+    /// short or edited.
+    digest: String,
+    manifest: BTreeMap<String, Stamp>,
+}
+
+/// A mirror started from this machine, as `xfer mirror status` finds it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Pair {
+    source: String,
+    pid: u32,
+    interval: u64,
+    started: u64,
+}
+
+fn pairs_path() -> PathBuf {
+    data_dir().join("mirrors.json")
+}
+
+fn load() -> BTreeMap<String, Pair> {
+    fs::read_to_string(pairs_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn remember(destination: &str, pair: Pair) -> Result<(), String> {
+    let mut pairs = load();
+    pairs.insert(destination.to_string(), pair);
+    fs::create_dir_all(data_dir()).map_err(|e| format!("Failed to create data dir: {}", e))?;
+    let content = serde_json::to_string_pretty(&pairs).map_err(|e| e.to_string())?;
+    fs::write(pairs_path(), content)
+        .map_err(|e| format!("Failed to write {}: {}", pairs_path().display(), e))
+}
+
+/// The heartbeat sits beside the standby rather than in it, so `--delete`
+/// leaves it alone and a served directory doesn't publish it.
+fn heartbeat_path(dir: &str) -> Result<String, String> {
+    let dir = dir.trim_end_matches('/');
+    if dir.is_empty() {
+        return Err(t!("mirror-root"));
+    }
+    Ok(format!("{}.xfer-heartbeat.json", dir))
+}
+
+fn digest(manifest: &BTreeMap<String, Stamp>) -> String {
+    let mut hasher = Sha256::new();
+    for (path, stamp) in manifest {
+        hasher.update(format!("{}\t{}\t{}\n", path, stamp.size, stamp.mtime).as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn remote_dir<'a>(dest: &str, config: &'a Config) -> Result<(&'a ServerConfig, String), String> {
+    let (server, dir) = match Location::parse(dest, config)? {
+        Location::Remote { server, path, .. } => (server, path.to_string()),
+        _ => return Err(format!("'{}' is not a remote location (alias:/path)", dest)),
+    };
+    if Platform::of(server)?.is_windows() {
+        return Err(t!("mirror-windows"));
+    }
+    Ok((server, dir))
+}
+
+fn hostname() -> String {
+    Command::new("hostname")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Mirrors `src` once, then publishes a heartbeat listing what was sent.
+/// The manifest is taken first, so a file changed mid-sync counts as
+/// behind until the next one.
+fn sync_once(
+    src: &Path,
+    dest: &str,
+    config: &Config,
+    opts: &TransferOptions,
+    interval: Duration,
+) -> Result<(), String> {
+    let (server, dir) = remote_dir(dest, config)?;
+    let manifest = local_files(src)?;
+    TransferEngine::send_recorded(&src.to_string_lossy(), dest, config, opts)?;
+
+    let heartbeat = Heartbeat {
+        source: src.display().to_string(),
+        host: hostname(),
+        synced: unix_now(),
+        interval: interval.as_secs(),
+        files: manifest.len(),
+        bytes: manifest.values().map(|stamp| stamp.size).sum(),
+        digest: digest(&manifest),
+        manifest,
+    };
+    let content = serde_json::to_vec(&heartbeat).map_err(|e| e.to_string())?;
+    let path = heartbeat_path(&dir)?;
+    // Written aside and moved into place, so status never reads half of it.
+    TransferEngine::remote_command(
+        server,
+        &format!(
+            "cat > {tmp} && mv -f {tmp} {path}",
+            tmp = shell_quote(&format!("{}.tmp", path)),
+            path = shell_quote(&path)
+        ),
+        Some(&content),
+    )?;
+    say!(
+        "{} {}",
+        t!("heartbeat").green(),
+        t!(
+            "heartbeat-published",
+            files = heartbeat.files,
+            bytes = HumanBytes(heartbeat.bytes),
+            dest = dest,
+            time = format_local(heartbeat.synced)
+        )
+    );
+    Ok(())
+}
+
+/// `xfer mirror start`: keeps `dest` an exact copy of `src`, syncing
+/// whenever `src` changes and at least every `interval`, and publishes a
+/// heartbeat after each sync. With `once`, syncs a single time, for running
+/// from cron or the queue.
+///
+/// A `dest` without a heartbeat has never been a standby, so what the
+/// first sync would delete there may not be spare: it is listed and asked
+/// about, and without a terminal nothing is sent, unless `force`.
+pub fn start(
+    src: &str,
+    dest: &str,
+    config: &Config,
+    opts: &TransferOptions,
+    interval: Duration,
+    once: bool,
+    force: bool,
+) -> Result<(), String> {
+    let root = Path::new(src)
+        .canonicalize()
+        .map_err(|e| format!("Failed to read {}: {}", src, e))?;
+    if !root.is_dir() {
+        return Err(format!("'{}' is not a directory", src));
+    }
+    let (server, dir) = remote_dir(dest, config)?;
+    let heartbeat = heartbeat_path(&dir)?;
+    let first = !force && !published(server, &heartbeat)?;
+    let mut opts = TransferOptions {
+        delete: true,
+        confirm_delete: first,
+        ..opts.clone()
+    };
+    remember(
+        dest,
+        Pair {
+            source: root.display().to_string(),
+            pid: std::process::id(),
+            interval: interval.as_secs(),
+            started: unix_now(),
+        },
+    )?;
+
+    if once {
+        return sync_once(&root, dest, config, &opts, interval);
+    }
+
+    let (tx, rx) = channel();
+    let mut watcher =
+        notify::recommended_watcher(tx).map_err(|e| format!("Failed to start watcher: {}", e))?;
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .map_err(|e| format!("Failed to watch '{}': {}", src, e))?;
+
+    say!(
+        "{} {}",
+        t!("mirroring").green(),
+        t!(
+            "mirroring-every",
+            src = root.display(),
+            dest = dest,
+            interval = interval.as_secs()
+        )
+    );
+    loop {
+        // A failed sync leaves the last heartbeat as it is, which is how
+        // `xfer mirror status` finds out the standby fell behind.
+        if let Err(e) = sync_once(&root, dest, config, &opts, interval) {
+            // Including declined deletions: there is no standby to keep.
+            if opts.confirm_delete {
+                return Err(e);
+            }
+            eprintln!("{}: {}", t!("error").red().bold(), e);
+        }
+        opts.confirm_delete = false;
+
+        let due = Instant::now() + interval;
+        let mut changed = false;
+        loop {
+            let now = Instant::now();
+            if now >= due {
+                break;
+            }
+            let timeout = if changed {
+                DEBOUNCE.min(due - now)
+            } else {
+                due - now
+            };
+            match rx.recv_timeout(timeout) {
+                Ok(Ok(event)) => {
+                    if !matches!(event.kind, EventKind::Access(_)) {
+                        changed = true;
+                    }
+                }
+                Ok(Err(e)) => eprintln!("{}: {}", t!("watch-error").yellow(), e),
+                Err(RecvTimeoutError::Timeout) if changed => break,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Err(t!("watcher-stopped")),
+            }
+        }
+    }
+}
+
+fn published(server: &ServerConfig, heartbeat: &str) -> Result<bool, String> {
+    let output = TransferEngine::remote_command(
+        server,
+        &format!("test -e {} && echo yes || true", shell_quote(heartbeat)),
+        None,
+    )?;
+    Ok(output.trim() == "yes")
+}
+
+/// How far the standby trails the source, going by the last heartbeat.
+#[derive(Debug, Default, Serialize)]
+struct Lag {
+    /// New or changed since the heartbeat.
+    files: usize,
+    bytes: u64,
+    /// Gone from the source but still listed in the heartbeat.
+    deletions: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    destination: String,
+    source: String,
+    running: bool,
+    synced: Option<u64>,
+    age: Option<u64>,
+    stale: bool,
+    lag: Option<Lag>,
+    /// Standby files that don't match the heartbeat: missing, a different
+    /// size, or never sent.
+    drifted: Vec<String>,
+    problem: Option<String>,
+}
+
+fn lag(source: &BTreeMap<String, Stamp>, heartbeat: &Heartbeat) -> Lag {
+    let mut lag = Lag::default();
+    for (path, stamp) in source {
+        if heartbeat.manifest.get(path) != Some(stamp) {
+            lag.files += 1;
+            lag.bytes += stamp.size;
+        }
+    }
+    lag.deletions = heartbeat
+        .manifest
+        .keys()
+        .filter(|path| !source.contains_key(*path))
+        .count();
+    lag
+}
+
+/// Compares the standby with the heartbeat. Sizes only: mtimes are only
+/// kept when `--preserve` keeps them.
+fn drift(standby: &BTreeMap<String, u64>, heartbeat: &Heartbeat) -> Vec<String> {
+    let mut drifted: Vec<String> = heartbeat
+        .manifest
+        .iter()
+        .filter(|(path, stamp)| standby.get(*path) != Some(&stamp.size))
+        .map(|(path, _)| path.clone())
+        .collect();
+    drifted.extend(
+        standby
+            .keys()
+            .filter(|path| !heartbeat.manifest.contains_key(*path))
+            .cloned(),
+    );
+    drifted.sort();
+    drifted
+}
+
+fn check(destination: &str, pair: &Pair, config: &Config) -> Report {
+    let mut report = Report {
+        destination: destination.to_string(),
+        source: pair.source.clone(),
+        running: sessions::is_running(pair.pid) && pair.pid != std::process::id(),
+        synced: None,
+        age: None,
+        stale: false,
+        lag: None,
+        drifted: Vec::new(),
+        problem: None,
+    };
+    let (server, dir) = match remote_dir(destination, config) {
+        Ok(found) => found,
+        Err(e) => {
+            report.problem = Some(e);
+            return report;
+        }
+    };
+    let heartbeat = heartbeat_path(&dir).and_then(|path| {
+        TransferEngine::remote_command(
+            server,
+            &format!("cat {} 2>/dev/null || true", shell_quote(&path)),
+            None,
+        )
+    });
+    let heartbeat = match heartbeat {
+        Ok(content) if content.trim().is_empty() => {
+            report.problem = Some("no heartbeat published yet".to_string());
+            return report;
+        }
+        Ok(content) => match serde_json::from_str::<Heartbeat>(&content) {
+            Ok(heartbeat) if heartbeat.digest == digest(&heartbeat.manifest) => heartbeat,
+            _ => {
+                report.problem = Some("the heartbeat is damaged".to_string());
+                return report;
+            }
+        },
+        Err(e) => {
+            report.problem = Some(e);
+            return report;
+        }
+    };
+
+    let age = unix_now().saturating_sub(heartbeat.synced);
+    report.synced = Some(heartbeat.synced);
+    report.age = Some(age);
+    report.stale = age > heartbeat.interval.max(1) * STALE_AFTER;
+    match local_files(Path::new(&pair.source)) {
+        Ok(source) => report.lag = Some(lag(&source, &heartbeat)),
+        Err(e) => report.problem = Some(e),
+    }
+    match remote_fs::tree(server, &dir) {
+        Ok(files) => {
+            let standby = files.into_iter().map(|f| (f.path, f.size)).collect();
+            report.drifted = drift(&standby, &heartbeat);
+        }
+        Err(e) => report.problem = Some(e),
+    }
+    report
+}
+
+fn ago(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86_399 => format!("{}h{}m ago", secs / 3600, secs / 60 % 60),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
+
+fn print(report: &Report) {
+    say!(
+        "{} {} {}",
+        report.destination.bold(),
+        "<-".dimmed(),
+        report.source
+    );
+    let running = if report.running {
+        t!("mirror-running").green()
+    } else {
+        t!("mirror-not-running").yellow()
+    };
+    match (report.synced, report.age) {
+        (Some(synced), Some(age)) => {
+            let when = format!("{} UTC ({})", format_timestamp(synced), ago(age));
+            let when = if report.stale {
+                when.red().bold()
+            } else {
+                when.normal()
+            };
+            say!(
+                "{:>8} {}",
+                t!("sync").bold(),
+                t!("last-synced", when = when, running = running)
+            );
+        }
+        _ => say!(
+            "{:>8} {}",
+            t!("sync").bold(),
+            t!("never-synced", running = running)
+        ),
+    }
+    if let Some(lag) = &report.lag {
+        if lag.files == 0 && lag.deletions == 0 {
+            say!("{:>8} {}", t!("lag").bold(), t!("lag-current").green());
+        } else {
+            say!(
+                "{:>8} {}",
+                t!("lag").bold(),
+                t!(
+                    "lag-behind",
+                    files = lag.files,
+                    bytes = HumanBytes(lag.bytes),
+                    deletions = lag.deletions
+                )
+                .yellow()
+            );
+        }
+    }
+    if report.synced.is_some() && report.problem.is_none() {
+        if report.drifted.is_empty() {
+            say!("{:>8} {}", t!("standby").bold(), t!("standby-matches"));
+        } else {
+            say!(
+                "{:>8} {}",
+                t!("standby").bold(),
+                t!(
+                    "standby-drifted",
+                    count = report.drifted.len(),
+                    files = report.drifted.join(", ")
+                )
+                .red()
+            );
+        }
+    }
+    if let Some(problem) = &report.problem {
+        say!("{:>8} {}", t!("problem").bold(), problem.red());
+    }
+}
+
+/// `xfer mirror status`: how current each mirror started here (or just the
+/// one to `destination`) is. Fails when a standby can't be trusted: no or a
+/// stale heartbeat, or files that don't match it.
+pub fn status(destination: Option<&str>, config: &Config) -> Result<(), String> {
+    let pairs = load();
+    let pairs: Vec<(&String, &Pair)> = match destination {
+        Some(destination) => match pairs.get_key_value(destination) {
+            Some(pair) => vec![pair],
+            None => return Err(t!("no-mirror", dest = destination)),
+        },
+        None => pairs.iter().collect(),
+    };
+
+    let reports: Vec<Report> = pairs
+        .into_iter()
+        .map(|(destination, pair)| check(destination, pair, config))
+        .collect();
+    if output::is_json() {
+        output::emit(&reports);
+    } else if reports.is_empty() {
+        say!("{}", t!("no-mirrors"));
+    } else {
+        for (i, report) in reports.iter().enumerate() {
+            if i > 0 {
+                say!();
+            }
+            print(report);
+        }
+    }
+
+    let failing = reports
+        .iter()
+        .filter(|r| r.stale || r.problem.is_some() || !r.drifted.is_empty())
+        .count();
+    if failing > 0 {
+        return Err(format!(
+            "{} standby(s) can't be trusted to be current",
+            failing
+        ));
+    }
+    Ok(())
+}
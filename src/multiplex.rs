@@ -0,0 +1,83 @@
+use crate::auth::{self, Auth};
+use crate::{errors, output, prompt, recording, scratch, ServerConfig, TransferEngine};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Seconds a shared connection outlives the last command using it, which
+/// bridges the gaps between the steps of a run without keeping a signed-in
+/// connection around much longer.
+const PERSIST_SECONDS: u32 = 10;
+
+/// Held while signing in, so parallel transfers wait for the one
+/// connection instead of each asking for a code.
+static SIGNING_IN: Mutex<()> = Mutex::new(());
+
+/// Keyboard-interactive servers ask for a one-time code on every new
+/// connection, so their commands share one: the first opens it and signs
+/// in, the rest go through it without asking again.
+fn shared(server: &ServerConfig) -> bool {
+    server.auth == Some(Auth::Interactive) && !cfg!(windows)
+}
+
+/// ssh options that make a command use the server's shared connection.
+/// The socket is named after this process, so another run never picks it
+/// up.
+pub fn args(server: &ServerConfig) -> Vec<String> {
+    if !shared(server) {
+        return Vec::new();
+    }
+    let socket = match scratch::socket("ssh-%C") {
+        Ok(socket) => socket,
+        Err(_) => return Vec::new(),
+    };
+    vec![
+        "-o".to_string(),
+        "ControlMaster=auto".to_string(),
+        "-o".to_string(),
+        format!("ControlPath={}", socket.display()),
+        "-o".to_string(),
+        format!("ControlPersist={}", PERSIST_SECONDS),
+    ]
+}
+
+/// Signs in to the server once, before anything captures ssh's output or
+/// draws over the terminal; ssh asks for its codes on the terminal (or
+/// through the prompt backend) and goes to the background once in. Later
+/// calls, from any thread, find it running and return straight away; one
+/// that finds it gone (say, in a long `watch`) signs in again.
+pub fn open(server: &ServerConfig) -> Result<(), String> {
+    if !shared(server) {
+        return Ok(());
+    }
+    let _guard = SIGNING_IN.lock().unwrap_or_else(|e| e.into_inner());
+    let running = Command::new("ssh")
+        .args(["-O", "check"])
+        .args(TransferEngine::ssh_args(server))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if running {
+        return Ok(());
+    }
+
+    let mut ssh = Command::new("ssh");
+    if prompt::takes_over_ssh() {
+        auth::use_askpass(&mut ssh, None)?;
+    }
+    // ssh takes the first value it is given for an option, so this wins
+    // over `ControlMaster=auto` from `args`.
+    ssh.args(["-o", "ControlMaster=yes", "-f", "-N"])
+        .args(TransferEngine::ssh_args(server))
+        .stdout(Stdio::null());
+    output::show_command(&ssh);
+    let record = recording::start(&ssh);
+    let status = ssh
+        .status()
+        .map_err(|e| format!("Failed to execute ssh: {}", e))?;
+    recording::finish(record, status.code(), None, "");
+    if !status.success() {
+        return Err(errors::describe("ssh", status.code(), ""));
+    }
+    Ok(())
+}
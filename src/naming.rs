@@ -0,0 +1,356 @@
+use crate::flatten::unique_target;
+use crate::location::Location;
+use crate::preserve::symlink;
+use crate::scratch::{self, Scratch};
+use crate::{data_dir, unix_now, Config};
+use colored::*;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+
+const NAMES: &str = "lowercase, sanitize-windows or ascii-only";
+
+/// Characters Windows (and so SMB shares) won't take in a name.
+const WINDOWS_RESERVED: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+/// Device names Windows won't take either, with or without an extension.
+const WINDOWS_DEVICES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Latin letters with their accents taken off, for `ascii-only`.
+const ACCENTED: [(&str, &str); 20] = [
+    ("ÀÁÂÃÄÅĀĂĄ", "A"),
+    ("àáâãäåāăą", "a"),
+    ("ÇĆČ", "C"),
+    ("çćč", "c"),
+    ("ÈÉÊËĒĖĘĚ", "E"),
+    ("èéêëēėęě", "e"),
+    ("ÌÍÎÏĪĮ", "I"),
+    ("ìíîïīįı", "i"),
+    ("ÑŃŇ", "N"),
+    ("ñńň", "n"),
+    ("ÒÓÔÕÖØŌŐ", "O"),
+    ("òóôõöøōő", "o"),
+    ("ÙÚÛÜŪŮŰ", "U"),
+    ("ùúûüūůű", "u"),
+    ("ÝŸŹŻŽ", "Y"),
+    ("ýÿźżž", "y"),
+    ("ŚŠ", "S"),
+    ("śš", "s"),
+    ("Ł", "L"),
+    ("ł", "l"),
+];
+
+/// A rewrite a server's `on_upload` asks for, for destinations that reject
+/// names a local filesystem allows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Policy {
+    Lowercase,
+    /// What Windows and SMB shares accept: no reserved characters, device
+    /// names or trailing dots and spaces.
+    SanitizeWindows,
+    AsciiOnly,
+}
+
+fn parse(list: &str) -> Result<Vec<Policy>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| match name {
+            "lowercase" => Ok(Policy::Lowercase),
+            "sanitize-windows" => Ok(Policy::SanitizeWindows),
+            "ascii-only" => Ok(Policy::AsciiOnly),
+            other => Err(format!("Unknown naming policy '{}' (use {})", other, NAMES)),
+        })
+        .collect()
+}
+
+fn ascii(c: char) -> String {
+    if c.is_ascii() {
+        return c.to_string();
+    }
+    match c {
+        'ß' => "ss".to_string(),
+        'Æ' => "AE".to_string(),
+        'æ' => "ae".to_string(),
+        'Œ' => "OE".to_string(),
+        'œ' => "oe".to_string(),
+        _ => ACCENTED
+            .iter()
+            .find(|(accented, _)| accented.contains(c))
+            .map_or("_", |(_, plain)| plain)
+            .to_string(),
+    }
+}
+
+fn sanitize_windows(name: &str) -> String {
+    let mut name: String = name
+        .chars()
+        .map(|c| {
+            if WINDOWS_RESERVED.contains(&c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    while name.ends_with(['.', ' ']) {
+        name.pop();
+    }
+    let stem = name.split('.').next().unwrap_or_default();
+    if WINDOWS_DEVICES
+        .iter()
+        .any(|device| device.eq_ignore_ascii_case(stem))
+    {
+        name.insert(stem.len(), '_');
+    }
+    if name.is_empty() {
+        name.push('_');
+    }
+    name
+}
+
+/// `name` with each policy applied in turn.
+fn rename(name: &str, policies: &[Policy]) -> String {
+    let mut name = name.to_string();
+    for policy in policies {
+        name = match policy {
+            Policy::Lowercase => name.to_lowercase(),
+            Policy::SanitizeWindows => sanitize_windows(&name),
+            Policy::AsciiOnly => name.chars().map(ascii).collect(),
+        };
+    }
+    name
+}
+
+/// The `on_upload` policies of the server or bucket a location is on.
+pub fn policy<'a>(location: &Location<'a>, config: &'a Config) -> Option<&'a str> {
+    match location {
+        Location::Remote { server, .. } => server.on_upload.as_deref(),
+        Location::Url { bucket, .. } => config.buckets.get(bucket)?.on_upload.as_deref(),
+        Location::Local(_) => None,
+    }
+}
+
+/// Whether anything under `path` (or `path` itself, for a file) would get a
+/// new name.
+fn needs_renaming(path: &Path, policies: &[Policy]) -> io::Result<bool> {
+    let differs = |name: &std::ffi::OsStr| {
+        let name = name.to_string_lossy();
+        rename(&name, policies) != name
+    };
+    if !path.is_dir() {
+        return Ok(path.file_name().is_some_and(differs));
+    }
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if differs(&entry.file_name())
+            || (entry.file_type()?.is_dir() && needs_renaming(&entry.path(), policies)?)
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[derive(Serialize)]
+struct Rename {
+    from: String,
+    to: String,
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Fills `view` with hard links to everything in `dir`, under the new
+/// names. Directories are made afresh and given their originals'
+/// permissions and times once filled.
+fn link_tree(
+    dir: &Path,
+    view: &Path,
+    (from, to): (&str, &str),
+    policies: &[Policy],
+    renames: &mut Vec<Rename>,
+) -> io::Result<()> {
+    let mut entries: Vec<(String, String, fs::DirEntry)> = fs::read_dir(dir)?
+        .map(|entry| {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            Ok((rename(&name, policies), name, entry))
+        })
+        .collect::<io::Result<_>>()?;
+    // Names that need no change keep them when a renamed one clashes, and
+    // the rest are sorted so clashes get the same suffixes every run.
+    entries.sort_by(|(new_a, a, _), (new_b, b, _)| (new_a != a, a).cmp(&(new_b != b, b)));
+    let mut taken = HashSet::new();
+    for (renamed, name, entry) in entries {
+        let target = unique_target(view, &renamed, &mut taken);
+        let new_name = target
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if new_name != name {
+            renames.push(Rename {
+                from: join(from, &name),
+                to: join(to, &new_name),
+            });
+        }
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            fs::create_dir(&target)?;
+            link_tree(
+                &entry.path(),
+                &target,
+                (&join(from, &name), &join(to, &new_name)),
+                policies,
+                renames,
+            )?;
+            copy_dir_attributes(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            symlink(
+                &link_target(&fs::read_link(entry.path())?, policies),
+                &target,
+            )?;
+        } else if file_type.is_file() {
+            fs::hard_link(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// A relative symlink still points at its target after the target was
+/// renamed, as long as the rename needed no suffix against a clash.
+fn link_target(link: &Path, policies: &[Policy]) -> PathBuf {
+    if link.is_absolute() {
+        return link.to_path_buf();
+    }
+    link.components()
+        .map(|component| match component {
+            Component::Normal(name) => PathBuf::from(rename(&name.to_string_lossy(), policies)),
+            other => PathBuf::from(other.as_os_str()),
+        })
+        .collect()
+}
+
+fn copy_dir_attributes(original: &Path, copy: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(original)?;
+    if let Ok(modified) = metadata.modified() {
+        File::open(copy)?.set_times(FileTimes::new().set_modified(modified))?;
+    }
+    fs::set_permissions(copy, metadata.permissions())
+}
+
+/// Builds the renamed copy of `local` in `root`.
+fn build(local: &Path, root: &Path, policies: &[Policy]) -> io::Result<(PathBuf, Vec<Rename>)> {
+    fs::create_dir_all(root)?;
+    let mut renames = Vec::new();
+    if !local.is_dir() {
+        let name = local
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let path = root.join(rename(&name, policies));
+        fs::hard_link(local, &path)?;
+        renames.push(Rename {
+            from: name,
+            to: path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        });
+        return Ok((path, renames));
+    }
+    link_tree(local, root, ("", ""), policies, &mut renames)?;
+    copy_dir_attributes(local, root)?;
+    Ok((root.to_path_buf(), renames))
+}
+
+/// The renamed copy an upload sends instead of its source. Its files are
+/// hard links, so it costs no space, and it is removed when dropped.
+pub struct View {
+    pub path: PathBuf,
+    _root: Scratch,
+}
+
+/// Notes what was renamed in `renames.jsonl` under the data dir, so
+/// whoever finds `report_2024_.pdf` on the share can tell it was
+/// `report_2024?.pdf`.
+fn record(source: &Path, destination: &str, renames: &[Rename]) -> PathBuf {
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        timestamp: u64,
+        source: String,
+        destination: &'a str,
+        renames: &'a [Rename],
+    }
+    let path = data_dir().join("renames.jsonl");
+    let entry = Entry {
+        timestamp: unix_now(),
+        source: source.display().to_string(),
+        destination,
+        renames,
+    };
+    // Like the history, a log that can't be written never fails a transfer.
+    if let (Ok(line), Ok(_)) = (
+        serde_json::to_string(&entry),
+        fs::create_dir_all(data_dir()),
+    ) {
+        if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&path) {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+    path
+}
+
+/// Applies the `on_upload` policies in `list` to the names under `local`
+/// for an upload to `destination`. Returns `None` when every name can go
+/// as it is; otherwise a renamed copy to send instead, after noting the
+/// renames.
+pub fn view(local: &Path, list: &str, destination: &str) -> Result<Option<View>, String> {
+    let policies = parse(list).map_err(|e| format!("on_upload in the config: {}", e))?;
+    let fail = |e: io::Error| format!("Failed to rename {} for upload: {}", local.display(), e);
+    if !needs_renaming(local, &policies).map_err(fail)? {
+        return Ok(None);
+    }
+
+    // Hard links can't cross filesystems, so when the scratch dir is on
+    // another one the copy goes next to the source instead.
+    let root = scratch::path("names")?;
+    let (root, (path, renames)) = match build(local, &root.path, &policies) {
+        Ok(built) => (root, built),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            drop(root);
+            let parent = local
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            let root = Scratch {
+                path: parent.join(format!(".xfer-names-{}", process::id())),
+            };
+            let built = build(local, &root.path, &policies).map_err(fail)?;
+            (root, built)
+        }
+        Err(e) => return Err(fail(e)),
+    };
+
+    let log = record(local, destination, &renames);
+    say!(
+        "{} {} name(s) for {} ({}); the mapping is in {}",
+        "Renamed:".yellow(),
+        renames.len(),
+        destination,
+        list,
+        log.display()
+    );
+    Ok(Some(View { path, _root: root }))
+}
@@ -0,0 +1,110 @@
+use crate::{redact, shell_quote, ServerConfig};
+use serde::Serialize;
+use std::io;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static SHOW_COMMANDS: AtomicBool = AtomicBool::new(false);
+
+/// With `--json`, stdout carries only JSON documents; everything meant for
+/// people moves to stderr.
+pub fn set_json() {
+    JSON.store(true, Ordering::SeqCst);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+/// Writes one JSON document to stdout.
+pub fn emit<T: Serialize + ?Sized>(value: &T) {
+    match serde_json::to_string(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("Failed to encode JSON output: {}", e),
+    }
+}
+
+pub fn set_show_commands() {
+    SHOW_COMMANDS.store(true, Ordering::SeqCst);
+}
+
+/// Prints a command line (with any environment it sets) to stderr for
+/// `--show-cmd`, after masking secrets.
+pub fn show_command(command: &Command) {
+    if SHOW_COMMANDS.load(Ordering::SeqCst) {
+        eprintln!("$ {}", command_line(command));
+    }
+}
+
+/// A command as a shell would take it, with secrets masked.
+pub fn command_line(command: &Command) -> String {
+    let quote = |word: &str| {
+        if !word.is_empty()
+            && word
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c))
+        {
+            word.to_string()
+        } else {
+            shell_quote(word)
+        }
+    };
+    let mut words = Vec::new();
+    for (name, value) in command.get_envs() {
+        let name = name.to_string_lossy();
+        if let Some(value) = value {
+            let value = if redact::is_secret_env(&name) {
+                "[REDACTED]".to_string()
+            } else {
+                quote(&value.to_string_lossy())
+            };
+            words.push(format!("{}={}", name, value));
+        }
+    }
+    words.push(quote(&command.get_program().to_string_lossy()));
+    words.extend(command.get_args().map(|arg| quote(&arg.to_string_lossy())));
+
+    redact::redact(&words.join(" "))
+}
+
+/// Where a tool's own output goes: our stdout, unless that is reserved for
+/// JSON.
+pub fn child_stdout() -> Stdio {
+    if is_json() {
+        io::stderr().into()
+    } else {
+        Stdio::inherit()
+    }
+}
+
+#[derive(Serialize)]
+pub struct TransferReport<'a> {
+    pub status: &'a str,
+    pub sources: &'a [String],
+    pub destination: &'a str,
+    pub bytes: u64,
+    pub duration_ms: u64,
+    pub error: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+pub struct ServerEntry<'a> {
+    pub alias: &'a str,
+    pub default: bool,
+    #[serde(flatten)]
+    pub server: &'a ServerConfig,
+}
+
+/// `println!` for messages meant for people, which go to stderr under
+/// `--json`.
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::output::is_json() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
@@ -0,0 +1,139 @@
+use crate::{Config, ServerConfig, TransferEngine};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// What kind of system a server runs, which decides the shell syntax of the
+/// commands we send it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Linux,
+    Macos,
+    Bsd,
+    /// Any other POSIX system (Solaris, Cygwin, ...).
+    Unix,
+    /// Win32-OpenSSH with its default `cmd` shell.
+    Windows,
+}
+
+static DETECTED: OnceLock<Mutex<HashMap<String, Platform>>> = OnceLock::new();
+
+impl Platform {
+    fn from_uname(output: &str) -> Platform {
+        let output = output.trim();
+        if output == "Linux" {
+            Platform::Linux
+        } else if output == "Darwin" {
+            Platform::Macos
+        } else if output.ends_with("BSD") || output == "DragonFly" {
+            Platform::Bsd
+        } else if output.contains("Windows") {
+            Platform::Windows
+        } else {
+            Platform::Unix
+        }
+    }
+
+    /// The server's platform: taken from the config when known, otherwise
+    /// detected on first connect and written back to the config.
+    pub fn of(server: &ServerConfig) -> Result<Platform, String> {
+        if let Some(platform) = server.os {
+            return Ok(platform);
+        }
+
+        let key = format!(
+            "{}@{}:{}",
+            server.user,
+            server.host,
+            server.port.unwrap_or(22)
+        );
+        let cache = DETECTED.get_or_init(|| Mutex::new(HashMap::new()));
+        if let Some(platform) = cache.lock().unwrap().get(&key) {
+            return Ok(*platform);
+        }
+
+        // `uname` doesn't exist in cmd.exe, where `ver` answers instead.
+        let output = TransferEngine::remote_command(server, "uname -s || ver", None)?;
+        let platform = Platform::from_uname(&output);
+        cache.lock().unwrap().insert(key, platform);
+        remember(server, platform);
+        Ok(platform)
+    }
+
+    /// The platform this binary was built for, for running the same
+    /// commands locally.
+    pub fn local() -> Platform {
+        if cfg!(target_os = "linux") {
+            Platform::Linux
+        } else if cfg!(target_os = "macos") {
+            Platform::Macos
+        } else if cfg!(any(
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd",
+            target_os = "dragonfly"
+        )) {
+            Platform::Bsd
+        } else if cfg!(windows) {
+            Platform::Windows
+        } else {
+            Platform::Unix
+        }
+    }
+
+    pub fn is_windows(self) -> bool {
+        self == Platform::Windows
+    }
+
+    /// A command that prints the login user's home directory.
+    pub fn home_command(self) -> &'static str {
+        match self {
+            Platform::Windows => "echo %USERPROFILE%",
+            _ => "printf '%s' \"$HOME\"",
+        }
+    }
+
+    /// Turns what `home_command` printed into a path scp and rsync accept.
+    /// Windows OpenSSH takes drive paths as `/C:/Users/...`.
+    pub fn home_path(self, output: &str) -> String {
+        let home = output.trim().trim_end_matches(['/', '\\']);
+        match self {
+            Platform::Windows => format!("/{}", home.replace('\\', "/")),
+            _ => home.to_string(),
+        }
+    }
+
+    /// Lists a directory. The path is left unquoted on POSIX servers so
+    /// `xfer list prod:/var/log/*.gz` still globs.
+    pub fn list_command(self, path: &str) -> String {
+        match self {
+            Platform::Windows => {
+                let native = path.trim_start_matches('/').replace('/', "\\");
+                format!("dir \"{}\"", native)
+            }
+            _ => format!("ls -la {}", path),
+        }
+    }
+}
+
+/// Writes a detected platform back to the server's entry in the config so
+/// later runs skip detection. Failing to save only costs a detection next
+/// time, so errors are ignored.
+fn remember(server: &ServerConfig, platform: Platform) {
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(_) => return,
+    };
+
+    let mut changed = false;
+    for entry in config.servers.values_mut() {
+        if entry.host == server.host && entry.user == server.user && entry.port == server.port {
+            entry.os = Some(platform);
+            changed = true;
+        }
+    }
+    if changed {
+        let _ = config.save();
+    }
+}
@@ -0,0 +1,291 @@
+use crate::history::format_timestamp;
+use crate::{shell_quote, ServerConfig, TransferOptions};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, FileTimes};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+const NAMES: &str = "perms, times, links, owner or all";
+
+/// Which file attributes a transfer keeps. Every way of moving files
+/// honours the same set, so a file sent with scp comes out like one sent
+/// with rsync.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Preserve {
+    /// Permission bits.
+    pub perms: bool,
+    /// Modification times.
+    pub times: bool,
+    /// Symlinks copied as symlinks rather than followed.
+    pub links: bool,
+    /// Owner and group; only root can set the owner on the receiving side.
+    pub owner: bool,
+}
+
+/// Permissions, times and links, which is what rsync keeps for a normal
+/// user.
+impl Default for Preserve {
+    fn default() -> Self {
+        Preserve {
+            perms: true,
+            times: true,
+            links: true,
+            owner: false,
+        }
+    }
+}
+
+impl Preserve {
+    fn none() -> Self {
+        Preserve {
+            perms: false,
+            times: false,
+            links: false,
+            owner: false,
+        }
+    }
+
+    /// Turns the attributes named in `list` (comma-separated, or "all") on
+    /// or off. An empty list means all of them.
+    fn set(&mut self, list: &str, on: bool) -> Result<(), String> {
+        for name in list.split(',').map(str::trim) {
+            match name {
+                "" | "all" => {
+                    *self = Preserve {
+                        perms: on,
+                        times: on,
+                        links: on,
+                        owner: on,
+                    }
+                }
+                "perms" => self.perms = on,
+                "times" => self.times = on,
+                "links" => self.links = on,
+                "owner" => self.owner = on,
+                "none" if on => *self = Preserve::none(),
+                other => return Err(format!("Unknown attribute '{}' (use {})", other, NAMES)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a `--preserve`/`--no-preserve` list.
+    pub fn validate(list: &str) -> Result<(), String> {
+        Preserve::default().set(list, true)
+    }
+
+    /// What a transfer with `server` keeps: the server's `preserve` list
+    /// (or the default), then `--preserve`, then `--no-preserve`.
+    pub fn resolve(opts: &TransferOptions, server: Option<&ServerConfig>) -> Result<Self, String> {
+        let mut preserve = Preserve::default();
+        if let Some(list) = server.and_then(|s| s.preserve.as_deref()) {
+            preserve = Preserve::none();
+            preserve
+                .set(list, true)
+                .map_err(|e| format!("preserve in the config: {}", e))?;
+        }
+        if let Some(list) = &opts.preserve {
+            preserve.set(list, true)?;
+        }
+        if let Some(list) = &opts.no_preserve {
+            preserve.set(list, false)?;
+        }
+        Ok(preserve)
+    }
+
+    /// rsync's flags for this set, recursion and compression included.
+    pub fn rsync_flags(self) -> String {
+        let mut flags = String::from("-r");
+        for (on, flag) in [
+            (self.perms, "p"),
+            (self.times, "t"),
+            (self.links, "l"),
+            (self.owner, "og"),
+        ] {
+            if on {
+                flags.push_str(flag);
+            }
+        }
+        flags.push('v');
+        flags
+    }
+
+    /// Whether scp can copy `local` (when it is the source) with exactly
+    /// this set: its `-p` keeps permissions and times together, never the
+    /// owner, and it follows symlinks.
+    pub fn scp_can(self, local: Option<&Path>) -> bool {
+        let symlink = local.is_some_and(|path| path.is_symlink());
+        self.perms == self.times && !self.owner && !(self.links && symlink)
+    }
+
+    /// tar flags for creating an archive.
+    pub fn tar_create_args(self) -> Vec<&'static str> {
+        if self.links {
+            Vec::new()
+        } else {
+            vec!["-h"]
+        }
+    }
+
+    /// tar flags for unpacking one. Links are stored as links either way.
+    pub fn tar_extract_args(self) -> Vec<&'static str> {
+        let mut args = Vec::new();
+        if self.perms {
+            args.push("-p");
+        }
+        if !self.times {
+            args.push("-m");
+        }
+        args.push(if self.owner {
+            "--same-owner"
+        } else {
+            "--no-same-owner"
+        });
+        args
+    }
+
+    /// A shell command that gives `remote` the attributes of `local`, for
+    /// uploads that write the file with plain shell commands.
+    pub fn remote_fixup(self, local: &Path, remote: &str) -> Result<Option<String>, String> {
+        let metadata = fs::metadata(local)
+            .map_err(|e| format!("Failed to read {}: {}", local.display(), e))?;
+        let mut commands = Vec::new();
+        if self.perms {
+            if let Some(mode) = mode(&metadata) {
+                commands.push(format!("chmod {:o} {}", mode, shell_quote(remote)));
+            }
+        }
+        if self.times {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok());
+            if let Some(modified) = modified {
+                // touch -t is the portable form; it takes local time, hence TZ.
+                let stamp: String = format_timestamp(modified.as_secs())
+                    .chars()
+                    .filter(char::is_ascii_digit)
+                    .collect();
+                commands.push(format!(
+                    "TZ=UTC0 touch -t {}.{} {}",
+                    &stamp[..12],
+                    &stamp[12..],
+                    shell_quote(remote)
+                ));
+            }
+        }
+        Ok(if commands.is_empty() {
+            None
+        } else {
+            Some(commands.join(" && "))
+        })
+    }
+
+    /// Copies a single local file, keeping this set of attributes.
+    pub fn copy_local(self, src: &Path, dest: &Path) -> Result<(), String> {
+        let target = match src.file_name() {
+            Some(name) if dest.is_dir() => dest.join(name),
+            _ => dest.to_path_buf(),
+        };
+        let fail = |e: std::io::Error| format!("Failed to copy to {}: {}", target.display(), e);
+
+        if self.links && cfg!(unix) && src.is_symlink() {
+            let link = fs::read_link(src).map_err(fail)?;
+            if target.is_symlink() || target.is_file() {
+                fs::remove_file(&target).map_err(fail)?;
+            }
+            return symlink(&link, &target).map_err(fail);
+        }
+
+        // fs::copy takes the permissions along; an existing file keeps its
+        // own unless they are preserved.
+        let existing = fs::metadata(&target).ok().map(|m| m.permissions());
+        fs::copy(src, &target).map_err(fail)?;
+        let metadata = fs::metadata(src).map_err(fail)?;
+        if self.owner {
+            chown(&metadata, &target).map_err(fail)?;
+        }
+        if self.perms {
+            fs::set_permissions(&target, metadata.permissions()).map_err(fail)?;
+        } else if let Some(existing) = existing {
+            fs::set_permissions(&target, existing).map_err(fail)?;
+        }
+        if self.times {
+            let mut times = FileTimes::new();
+            if let Ok(modified) = metadata.modified() {
+                times = times.set_modified(modified);
+            }
+            if let Ok(accessed) = metadata.accessed() {
+                times = times.set_accessed(accessed);
+            }
+            File::options()
+                .write(true)
+                .open(&target)
+                .and_then(|file| file.set_times(times))
+                .map_err(fail)?;
+        }
+        Ok(())
+    }
+
+    /// Gives the local directory `target` the attributes of `src`, once
+    /// everything inside it has been copied.
+    pub fn copy_dir_attributes(self, src: &Path, target: &Path) -> Result<(), String> {
+        let fail = |e: std::io::Error| format!("Failed to update {}: {}", target.display(), e);
+        let metadata = fs::metadata(src).map_err(fail)?;
+        if self.owner {
+            chown(&metadata, target).map_err(fail)?;
+        }
+        if self.perms {
+            fs::set_permissions(target, metadata.permissions()).map_err(fail)?;
+        }
+        // Windows can't open a directory as a file to set its times.
+        if self.times && cfg!(unix) {
+            if let Ok(modified) = metadata.modified() {
+                File::open(target)
+                    .and_then(|dir| dir.set_times(FileTimes::new().set_modified(modified)))
+                    .map_err(fail)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+pub fn symlink(link: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(link, target)
+}
+
+#[cfg(not(unix))]
+pub fn symlink(_link: &Path, _target: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Sets the owner and group, as far as this user may: like rsync, a normal
+/// user keeps what it can and isn't stopped by the rest.
+#[cfg(unix)]
+fn chown(metadata: &fs::Metadata, target: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    match std::os::unix::fs::chown(target, Some(metadata.uid()), Some(metadata.gid())) {
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            let _ = std::os::unix::fs::chown(target, None, Some(metadata.gid()));
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+#[cfg(not(unix))]
+fn chown(_metadata: &fs::Metadata, _target: &Path) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use crate::sessions;
+use colored::*;
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
+use std::cell::Cell;
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const BAR_TEMPLATE: &str =
+    "{spinner:.green} [{elapsed_precise}] [{bar:30.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} ETA {eta} {wide_msg}";
+const SPINNER_TEMPLATE: &str = "{spinner:.green} [{elapsed_precise}] {bytes} {wide_msg}";
+
+/// `--info=progress2` appeared in rsync 3.1; older builds (and openrsync on
+/// macOS) reject it.
+pub fn rsync_has_progress2() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        let output = match Command::new("rsync").arg("--version").output() {
+            Ok(output) => output,
+            Err(_) => return false,
+        };
+        let text = String::from_utf8_lossy(&output.stdout);
+        let version = text
+            .split_whitespace()
+            .skip_while(|word| *word != "version")
+            .nth(1)
+            .unwrap_or("");
+        let mut parts = version.split('.').map(|p| p.parse::<u32>().unwrap_or(0));
+        let (major, minor) = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+        (major, minor) >= (3, 1)
+    })
+}
+
+/// How often `--plain-output` prints a status line.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(5);
+
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Switches to output that reads well in a screen reader or on a serial
+/// console: no colors, spinners or redrawn lines, just a status line every
+/// few seconds.
+pub fn set_plain() {
+    PLAIN.store(true, Ordering::SeqCst);
+    colored::control::set_override(false);
+}
+
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::SeqCst)
+}
+
+thread_local! {
+    static HIDDEN: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Stops drawing progress bars for transfers started from this thread, for
+/// when several run side by side and their bars would overwrite each other.
+pub fn hide_bars() {
+    HIDDEN.with(|hidden| hidden.set(true));
+}
+
+fn bar(total: Option<u64>) -> ProgressBar {
+    if HIDDEN.with(Cell::get) {
+        return ProgressBar::hidden();
+    }
+    if is_plain() {
+        let bar = ProgressBar::hidden();
+        if let Some(total) = total {
+            bar.set_length(total);
+        }
+        report(bar.clone());
+        return bar;
+    }
+
+    match total {
+        Some(total) => {
+            let bar = ProgressBar::new(total);
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template(BAR_TEMPLATE)
+                    .progress_chars("=> "),
+            );
+            bar
+        }
+        None => {
+            let bar = ProgressBar::new_spinner();
+            bar.set_style(ProgressStyle::default_spinner().template(SPINNER_TEMPLATE));
+            bar
+        }
+    }
+}
+
+/// Prints the bar's progress as a plain line every few seconds until it is
+/// finished. A hidden bar's length stays at `!0` until the total is known.
+fn report(bar: ProgressBar) {
+    thread::spawn(move || {
+        let mut last = (Instant::now(), bar.position());
+        loop {
+            while last.0.elapsed() < PLAIN_INTERVAL {
+                if bar.is_finished() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(200));
+            }
+
+            let (now, done) = (Instant::now(), bar.position());
+            let rate = (done.saturating_sub(last.1) as f64
+                / now.duration_since(last.0).as_secs_f64()) as u64;
+            last = (now, done);
+
+            let total = bar.length();
+            if total != !0 && total > 0 {
+                eprintln!(
+                    "Progress: {}% ({} of {}), {}/s",
+                    done * 100 / total,
+                    HumanBytes(done),
+                    HumanBytes(total),
+                    HumanBytes(rate)
+                );
+            } else if done > 0 {
+                eprintln!(
+                    "Progress: {} copied, {}/s",
+                    HumanBytes(done),
+                    HumanBytes(rate)
+                );
+            } else {
+                // Nothing to measure, e.g. scp uploads.
+                eprintln!(
+                    "Progress: still copying, {} elapsed",
+                    HumanDuration(bar.elapsed())
+                );
+            }
+        }
+    });
+}
+
+/// A byte counter for transfers xfer moves itself rather than through a
+/// tool.
+pub fn counter(total: Option<u64>) -> ProgressBar {
+    let bar = bar(total);
+    bar.set_message("copying");
+    bar
+}
+
+/// Parses an rsync `--info=progress2` line such as
+/// `  1,234,567  45%   10.50MB/s    0:00:12 (xfr#3, to-chk=10/20)`
+/// into (bytes, percent).
+fn parse_progress2(line: &str) -> Option<(u64, u64)> {
+    let mut fields = line.split_whitespace();
+    let bytes = fields.next()?.replace(',', "").parse().ok()?;
+    let percent = fields.next()?.strip_suffix('%')?.parse().ok()?;
+    Some((bytes, percent))
+}
+
+fn is_rsync_chatter(line: &str) -> bool {
+    line.ends_with("file list")
+        || line.starts_with("sent ")
+        || line.starts_with("total size is")
+        || line.ends_with('/')
+}
+
+/// Feeds rsync's stdout into a progress bar until rsync closes it.
+pub fn track_rsync(stdout: impl Read, total: Option<u64>) {
+    let bar = bar(total);
+    let mut reader = BufReader::new(stdout);
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+
+    // progress2 redraws with '\r', file names end with '\n'.
+    while let Ok(1) = reader.read(&mut byte) {
+        if byte[0] != b'\r' && byte[0] != b'\n' {
+            line.push(byte[0]);
+            continue;
+        }
+
+        let text = String::from_utf8_lossy(&line).trim().to_string();
+        line.clear();
+        if text.is_empty() {
+            continue;
+        }
+
+        match parse_progress2(&text) {
+            Some((bytes, percent)) => {
+                if total.is_none() && percent > 0 {
+                    bar.set_length(bytes * 100 / percent);
+                    bar.set_style(
+                        ProgressStyle::default_bar()
+                            .template(BAR_TEMPLATE)
+                            .progress_chars("=> "),
+                    );
+                }
+                bar.set_position(bytes);
+                sessions::set_progress(bytes);
+            }
+            None if !is_rsync_chatter(&text) => bar.set_message(text),
+            None => {}
+        }
+    }
+
+    bar.finish_and_clear();
+}
+
+pub struct Spinner {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Shows activity while a tool that can't report progress runs. When `watch`
+/// is given, the bar follows that local file's size.
+pub fn spinner(watch: Option<PathBuf>, total: Option<u64>) -> Spinner {
+    let stop = Arc::new(AtomicBool::new(false));
+    let flag = stop.clone();
+    let session = sessions::current();
+
+    let bar = bar(total);
+
+    let handle = thread::spawn(move || {
+        bar.set_message("copying");
+        while !flag.load(Ordering::SeqCst) {
+            if let Some(size) = watch
+                .as_ref()
+                .and_then(|path| path.metadata().ok())
+                .map(|m| m.len())
+            {
+                bar.set_position(size);
+                if let Some(session) = &session {
+                    session.set_progress(size);
+                }
+            }
+            bar.tick();
+            thread::sleep(Duration::from_millis(200));
+        }
+        bar.finish_and_clear();
+    });
+
+    Spinner {
+        stop,
+        handle: Some(handle),
+    }
+}
+
+pub fn summary(bytes: u64, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64();
+    let rate = if secs > 0.0 {
+        (bytes as f64 / secs) as u64
+    } else {
+        bytes
+    };
+    say!(
+        "{}",
+        t!(
+            "transferred",
+            bytes = HumanBytes(bytes),
+            elapsed = HumanDuration(elapsed),
+            rate = HumanBytes(rate)
+        )
+        .green()
+    );
+}
@@ -0,0 +1,258 @@
+use crate::location::Location;
+use crate::{hooks, show_deletions, Config, ServerConfig, TransferEngine, TransferOptions};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::ops::Not;
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = ".xfer.toml";
+
+/// A named transfer a project carries with it, run with `xfer run <name>`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Route {
+    /// Relative local paths are taken from the directory holding the
+    /// `.xfer.toml`, not the one `xfer` runs in.
+    pub src: String,
+    pub dest: String,
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub verify: bool,
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub delete: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<String>,
+    /// E.g. "2h"; the run is cancelled after this long.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_duration: Option<String>,
+    /// A shell command run first in the project directory, e.g. a database
+    /// dump; the route stops if it fails.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+    /// `before` is safe to run in a dry run, where it gets `XFER_DRY_RUN=1`
+    /// and should only look; otherwise dry runs skip it.
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub dry_run_hooks: bool,
+    /// Run if the route fails, in place of the config's `on_failure`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectFile {
+    #[serde(default)]
+    servers: HashMap<String, ServerConfig>,
+    #[serde(default)]
+    routes: BTreeMap<String, Route>,
+}
+
+/// The `.xfer.toml` in effect. Its servers are merged into the config for
+/// this run only; `shadowed` keeps what they replaced so saving the config
+/// writes the global servers back untouched.
+#[derive(Debug)]
+pub struct Project {
+    pub path: PathBuf,
+    pub routes: BTreeMap<String, Route>,
+    pub shadowed: HashMap<String, Option<ServerConfig>>,
+}
+
+impl Project {
+    pub fn defines(&self, alias: &str) -> bool {
+        self.shadowed.contains_key(alias)
+    }
+
+    fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new("."))
+    }
+}
+
+/// Looks for a `.xfer.toml` in the current directory and its parents, the
+/// way git looks for `.git`.
+fn find() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Merges the nearest `.xfer.toml` into `config`, if there is one.
+pub fn load(config: &mut Config) -> Result<(), String> {
+    let path = match find() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let file: ProjectFile =
+        toml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut shadowed = HashMap::new();
+    for (alias, server) in file.servers {
+        let previous = config.servers.insert(alias.clone(), server);
+        shadowed.insert(alias, previous);
+    }
+    config.project = Some(Project {
+        path,
+        routes: file.routes,
+        shadowed,
+    });
+    Ok(())
+}
+
+fn require(config: &Config) -> Result<&Project, String> {
+    config
+        .project
+        .as_ref()
+        .ok_or_else(|| t!("no-project", file = FILE_NAME))
+}
+
+pub fn list_routes(config: &Config) -> Result<(), String> {
+    let project = require(config)?;
+    if project.routes.is_empty() {
+        say!("{}", t!("no-routes", file = project.path.display()));
+        return Ok(());
+    }
+    say!(
+        "{} {}",
+        t!("routes-in").green().bold(),
+        project.path.display()
+    );
+    for (name, route) in &project.routes {
+        say!(
+            "  {} - {} -> {}",
+            name.yellow(),
+            route.src,
+            route.dest.cyan()
+        );
+    }
+    Ok(())
+}
+
+/// Runs a route from the project file as a `send`. Routes with `delete`
+/// list what they would remove and ask first, unless `force`. A `dry_run`
+/// shows the transfer and what it would delete without making it, and
+/// skips the `before` hook unless the route allows it in dry runs.
+pub fn run(name: &str, force: bool, dry_run: bool, config: &Config) -> Result<(), String> {
+    let project = require(config)?;
+    let route = project.routes.get(name).ok_or_else(|| {
+        let names: Vec<&str> = project.routes.keys().map(String::as_str).collect();
+        t!(
+            "unknown-route",
+            name = name,
+            file = project.path.display(),
+            routes = if names.is_empty() {
+                t!("none")
+            } else {
+                names.join(", ")
+            }
+        )
+    })?;
+
+    let resolve = |location: &str| {
+        if location.contains(':') || Path::new(location).is_absolute() {
+            return location.to_string();
+        }
+        let mut joined = project.dir().join(location).to_string_lossy().into_owned();
+        // Keep a trailing slash: it decides what rsync copies.
+        if location.ends_with('/') && !joined.ends_with('/') {
+            joined.push('/');
+        }
+        joined
+    };
+    let src = resolve(&route.src);
+    let dest = resolve(&route.dest);
+
+    if dry_run {
+        hooks::set_dry_run();
+    }
+    if let Some(before) = &route.before {
+        let vars = [("XFER_ROUTE", name.to_string())];
+        hooks::run(
+            &t!("before"),
+            before,
+            project.dir(),
+            &vars,
+            route.dry_run_hooks,
+        )
+        .map_err(|e| t!("route-not-run", error = e, name = name))?;
+    }
+    if dry_run {
+        return preview(name, route, &src, &dest, config);
+    }
+
+    let opts = TransferOptions {
+        verify: route.verify,
+        delete: route.delete,
+        confirm_delete: !force,
+        limit: route.limit.clone(),
+        max_duration: route.max_duration.clone(),
+        ..TransferOptions::default()
+    };
+    say!(
+        "{} {}: {} {} {}",
+        t!("running").green(),
+        name.yellow(),
+        src,
+        t!("to").green(),
+        dest
+    );
+    let result = TransferEngine::send_files(&[&src], &dest, config, &opts);
+    if let Err(e) = &result {
+        if let Some(command) = route.on_failure.as_ref().or(config.on_failure.as_ref()) {
+            hooks::notify_failure(command, project.dir(), name, e);
+        }
+    }
+    result
+}
+
+/// `xfer run --dry-run`: the transfer a route would make, with what a
+/// `delete` route would remove from a dry run of rsync (or aws) that
+/// changes nothing.
+fn preview(
+    name: &str,
+    route: &Route,
+    src: &str,
+    dest: &str,
+    config: &Config,
+) -> Result<(), String> {
+    let mut options = Vec::new();
+    for (set, option) in [(route.delete, "delete"), (route.verify, "verify")] {
+        if set {
+            options.push(option.to_string());
+        }
+    }
+    options.extend(route.limit.iter().map(|limit| format!("limit {}", limit)));
+    options.extend(route.max_duration.iter().map(|max| format!("max {}", max)));
+    say!(
+        "{} {}: {} {} {}{}",
+        t!("dry-run").yellow(),
+        name.yellow(),
+        src,
+        t!("to").green(),
+        dest,
+        if options.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", options.join(", "))
+        }
+    );
+    if !route.delete {
+        return Ok(());
+    }
+    let src_location = Location::parse(src, config)?;
+    let dest_location = Location::parse(dest, config)?;
+    let deletions = TransferEngine::deletions(
+        &src_location,
+        &dest_location,
+        &src_location.path(),
+        &dest_location.path(),
+        config,
+    )?;
+    if deletions.is_empty() {
+        say!("{}", t!("nothing-to-delete", dest = dest));
+    } else {
+        show_deletions(dest, &deletions);
+    }
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use crate::tools;
+use console::Term;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+/// Where secrets (passwords, key passphrases, 2FA codes) are asked for.
+/// Without `prompt` in the config: the terminal when there is one,
+/// otherwise `SSH_ASKPASS` when it is set, otherwise pinentry when it is
+/// installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Prompt {
+    Tty,
+    /// An `SSH_ASKPASS`-style helper: run with the prompt as its argument,
+    /// it prints the answer.
+    Askpass,
+    /// A pinentry program, spoken to over the Assuan protocol.
+    Pinentry,
+}
+
+/// Carries the chosen backend to xfer when ssh runs it as its askpass
+/// program, e.g. `askpass:/usr/bin/ksshaskpass`.
+const PROMPT_ENV: &str = "XFER_PROMPT";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Backend {
+    Tty,
+    Askpass(String),
+    Pinentry(String),
+}
+
+impl Backend {
+    fn to_env(&self) -> String {
+        match self {
+            Backend::Tty => "tty".to_string(),
+            Backend::Askpass(program) => format!("askpass:{}", program),
+            Backend::Pinentry(program) => format!("pinentry:{}", program),
+        }
+    }
+
+    fn from_env(value: &str) -> Option<Backend> {
+        match value.split_once(':') {
+            Some(("askpass", program)) => Some(Backend::Askpass(program.to_string())),
+            Some(("pinentry", program)) => Some(Backend::Pinentry(program.to_string())),
+            _ if value == "tty" => Some(Backend::Tty),
+            _ => None,
+        }
+    }
+}
+
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+/// Chooses the backend from the config's `prompt` and `prompt_program`.
+pub fn init(prompt: Option<Prompt>, program: Option<&str>) {
+    let _ = BACKEND.set(resolve(prompt, program));
+}
+
+fn resolve(prompt: Option<Prompt>, program: Option<&str>) -> Backend {
+    // xfer itself may be the `SSH_ASKPASS` of a parent run.
+    let ssh_askpass = env::var("SSH_ASKPASS")
+        .ok()
+        .filter(|program| !program.is_empty() && env::var_os(PROMPT_ENV).is_none());
+    let askpass = || {
+        program
+            .map(str::to_string)
+            .or_else(|| ssh_askpass.clone())
+            .unwrap_or_else(|| "ssh-askpass".to_string())
+    };
+    let pinentry = || program.unwrap_or("pinentry").to_string();
+
+    match prompt {
+        Some(Prompt::Tty) => Backend::Tty,
+        Some(Prompt::Askpass) => Backend::Askpass(askpass()),
+        Some(Prompt::Pinentry) => Backend::Pinentry(pinentry()),
+        None if io::stdin().is_terminal() && io::stderr().is_terminal() => Backend::Tty,
+        None if ssh_askpass.is_some() => Backend::Askpass(askpass()),
+        None if tools::has("pinentry") => Backend::Pinentry(pinentry()),
+        None => Backend::Tty,
+    }
+}
+
+/// The chosen backend; when xfer runs as ssh's askpass program, the one
+/// its parent chose.
+fn backend() -> &'static Backend {
+    BACKEND.get_or_init(|| {
+        env::var(PROMPT_ENV)
+            .ok()
+            .and_then(|value| Backend::from_env(&value))
+            .unwrap_or_else(|| resolve(None, None))
+    })
+}
+
+/// Whether ssh's own prompts should come to xfer (as its askpass program)
+/// instead of going to the terminal.
+pub fn takes_over_ssh() -> bool {
+    *backend() != Backend::Tty
+}
+
+/// Hands the chosen backend on to a command that may run xfer as its
+/// askpass program.
+pub fn pass_on(command: &mut Command) {
+    command.env(PROMPT_ENV, backend().to_env());
+}
+
+/// Asks for a secret with `prompt`, e.g. "Password for deploy@web1: ".
+pub fn secret(prompt: &str) -> Result<String, String> {
+    match backend() {
+        Backend::Tty => tty(prompt),
+        Backend::Askpass(program) => askpass(program, prompt),
+        Backend::Pinentry(program) => pinentry(program, prompt),
+    }
+}
+
+fn tty(prompt: &str) -> Result<String, String> {
+    if !io::stdin().is_terminal() {
+        return Err(format!(
+            "No terminal to ask \"{}\" on; set prompt = \"askpass\" or \"pinentry\" in the config",
+            prompt.trim_end().trim_end_matches(':')
+        ));
+    }
+    let term = Term::stderr();
+    term.write_str(prompt).map_err(|e| e.to_string())?;
+    term.read_secure_line().map_err(|e| e.to_string())
+}
+
+fn askpass(program: &str, prompt: &str) -> Result<String, String> {
+    let output = Command::new(program)
+        .arg(prompt)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!("{} was cancelled", program));
+    }
+    let answer = String::from_utf8_lossy(&output.stdout);
+    Ok(answer.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Assuan escapes `%`, CR and LF in what it sends, both ways.
+fn escape(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn unescape(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text.get(i + 1..i + 3).filter(|_| bytes[i] == b'%');
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn pinentry(program: &str, prompt: &str) -> Result<String, String> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+    // Every request (and the greeting) is answered by `OK` or `ERR`, with
+    // data lines (`D ...`) before it.
+    let mut reply = || -> Result<Option<String>, String> {
+        let mut data = None;
+        loop {
+            let mut line = String::new();
+            if stdout.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+                return Err(format!("{} exited unexpectedly", program));
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line == "OK" || line.starts_with("OK ") {
+                return Ok(data);
+            } else if line.starts_with("ERR") {
+                return Err(format!("{} was cancelled", program));
+            } else if let Some(rest) = line.strip_prefix("D ") {
+                data = Some(unescape(rest));
+            }
+        }
+    };
+    let result = (|| {
+        reply()?;
+        let mut answer = None;
+        for request in [
+            "SETTITLE xfer".to_string(),
+            format!("SETDESC {}", escape(prompt.trim_end())),
+            "SETPROMPT >".to_string(),
+            "GETPIN".to_string(),
+        ] {
+            writeln!(stdin, "{}", request).map_err(|e| e.to_string())?;
+            answer = reply()?;
+        }
+        Ok(answer.unwrap_or_default())
+    })();
+    let _ = writeln!(stdin, "BYE");
+    drop(stdin);
+    let _ = child.wait();
+    result
+}
@@ -0,0 +1,800 @@
+use crate::history::{format_timestamp, parse_date};
+use crate::location::Location;
+use crate::{
+    data_dir, deadline, errors, fairness, hooks, output, parse_duration, redact, sessions,
+    unix_now, Config, TransferEngine, TransferOptions,
+};
+use colored::*;
+use indicatif::HumanBytes;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// A failed job waits this long before its second attempt, doubling after
+/// each further failure up to `MAX_RETRY_DELAY`.
+const RETRY_DELAY: u64 = 60;
+const MAX_RETRY_DELAY: u64 = 30 * 60;
+/// Finished jobs are dropped from the queue after a week.
+const KEEP_FINISHED: u64 = 7 * 24 * 60 * 60;
+/// A lock file older than this was left by a process that died holding it.
+const STALE_LOCK: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum State {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl State {
+    fn label(self) -> ColoredString {
+        match self {
+            State::Queued => "queued".yellow(),
+            State::Running => "running".cyan(),
+            State::Done => "done".green(),
+            State::Failed => "failed".red(),
+            State::Cancelled => "cancelled".dimmed(),
+        }
+    }
+
+    fn finished(self) -> bool {
+        matches!(self, State::Done | State::Failed | State::Cancelled)
+    }
+}
+
+/// A send or get waiting for the daemon. Like `resume`, it keeps the
+/// directory it was queued from so relative local paths still resolve.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Job {
+    id: u64,
+    sources: Vec<String>,
+    destination: String,
+    cwd: PathBuf,
+    options: TransferOptions,
+    added: u64,
+    /// Not started before this time.
+    at: Option<u64>,
+    state: State,
+    /// Failed attempts so far.
+    attempts: u32,
+    /// The `xfer queue run-job` process while the job runs.
+    pid: Option<u32>,
+    started: Option<u64>,
+    finished: Option<u64>,
+    error: Option<String>,
+    /// The last failure won't go away by trying again, e.g. a missing file.
+    #[serde(default)]
+    permanent: bool,
+}
+
+impl Job {
+    fn transfer(&self) -> String {
+        format!(
+            "{} -> {}",
+            redact::redact(&self.sources.join(", ")),
+            redact::redact(&self.destination)
+        )
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Queue {
+    next_id: u64,
+    jobs: Vec<Job>,
+}
+
+/// What the running daemon wrote about itself. Removing the file asks it
+/// to stop once its running jobs are done.
+#[derive(Debug, Serialize, Deserialize)]
+struct Daemon {
+    pid: u32,
+    started: u64,
+    concurrency: usize,
+    attempts: u32,
+    log: PathBuf,
+}
+
+fn queue_path() -> PathBuf {
+    data_dir().join("queue.json")
+}
+
+fn daemon_path() -> PathBuf {
+    data_dir().join("queue-daemon.json")
+}
+
+fn default_log() -> PathBuf {
+    data_dir().join("queue.log")
+}
+
+fn load() -> Queue {
+    fs::read_to_string(queue_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Held while the queue is read, changed and written back, so `queue add`
+/// and the daemon don't overwrite each other.
+/// A lock file in the data dir, removed when dropped.
+pub struct DataLock(PathBuf);
+
+impl Drop for DataLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Takes `<name>.lock` in the data dir, so xfer processes take turns at a
+/// file they all update, like the queue or the history.
+pub fn lock_data(name: &str) -> Result<DataLock, String> {
+    let path = data_dir().join(format!("{}.lock", name));
+    fs::create_dir_all(data_dir())
+        .map_err(|e| format!("Failed to create {}: {}", data_dir().display(), e))?;
+    for _ in 0..200 {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(DataLock(path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let stale = fs::metadata(&path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                    .is_some_and(|age| age > STALE_LOCK);
+                if stale {
+                    let _ = fs::remove_file(&path);
+                } else {
+                    thread::sleep(Duration::from_millis(50));
+                }
+            }
+            Err(e) => return Err(format!("Failed to lock the {}: {}", name, e)),
+        }
+    }
+    Err(format!(
+        "The {} is locked; if no xfer is running, remove {}",
+        name,
+        path.display()
+    ))
+}
+
+fn lock() -> Result<DataLock, String> {
+    lock_data("queue")
+}
+
+/// Applies `f` to the queue under the lock and saves the result.
+fn update<T>(f: impl FnOnce(&mut Queue) -> T) -> Result<T, String> {
+    let _lock = lock()?;
+    let mut queue = load();
+    let result = f(&mut queue);
+
+    let now = unix_now();
+    queue.jobs.retain(|job| {
+        !job.state.finished()
+            || job
+                .finished
+                .is_none_or(|finished| now.saturating_sub(finished) < KEEP_FINISHED)
+    });
+    let content = serde_json::to_string_pretty(&queue)
+        .map_err(|e| format!("Failed to encode the queue: {}", e))?;
+    let tmp = queue_path().with_extension("json.tmp");
+    fs::write(&tmp, content)
+        .and_then(|_| fs::rename(&tmp, queue_path()))
+        .map_err(|e| format!("Failed to save the queue: {}", e))?;
+    Ok(result)
+}
+
+/// Seconds east of UTC for local time, from `date` since std has no time
+/// zones. UTC when it can't tell.
+pub fn local_offset() -> i64 {
+    Command::new("date")
+        .arg("+%z")
+        .output()
+        .ok()
+        .and_then(|out| {
+            let text = String::from_utf8_lossy(&out.stdout).trim().to_string();
+            let sign = if text.starts_with('-') { -1 } else { 1 };
+            let digits = text.trim_start_matches(['+', '-']);
+            if digits.len() != 4 {
+                return None;
+            }
+            let hours: i64 = digits[..2].parse().ok()?;
+            let minutes: i64 = digits[2..].parse().ok()?;
+            Some(sign * (hours * 3600 + minutes * 60))
+        })
+        .unwrap_or(0)
+}
+
+/// "YYYY-MM-DD HH:MM" in local time.
+pub fn format_local(secs: u64) -> String {
+    let local = (secs as i64 + local_offset()).max(0) as u64;
+    format_timestamp(local)[..16].to_string()
+}
+
+fn parse_clock(clock: &str) -> Option<u64> {
+    let (hours, minutes) = clock.split_once(':')?;
+    match (hours.parse::<u64>().ok()?, minutes.parse::<u64>().ok()?) {
+        (hours @ 0..=23, minutes @ 0..=59) => Some(hours * 3600 + minutes * 60),
+        _ => None,
+    }
+}
+
+/// Parses `--at`: "HH:MM" (the next time the clock shows it), "YYYY-MM-DD
+/// HH:MM", both local time, or "+DURATION" from now.
+fn parse_at(at: &str) -> Result<u64, String> {
+    let at = at.trim();
+    let now = unix_now();
+    if let Some(delay) = at.strip_prefix('+') {
+        return Ok(now + parse_duration(delay)?.as_secs());
+    }
+
+    let invalid = || t!("invalid-time", value = at);
+    let offset = local_offset();
+    let (date, clock) = match at.split_once(' ') {
+        Some((date, clock)) => (Some(date), clock.trim()),
+        None => (None, at),
+    };
+    let time = parse_clock(clock).ok_or_else(invalid)? as i64;
+    let local = match date {
+        Some(date) => parse_date(date)? as i64 + time,
+        None => {
+            let local_now = now as i64 + offset;
+            let mut local = local_now - local_now.rem_euclid(86_400) + time;
+            if local <= local_now {
+                local += 86_400;
+            }
+            local
+        }
+    };
+    let at = local - offset;
+    if at < now as i64 {
+        return Err(format!(
+            "{} has already passed",
+            format_local(at.max(0) as u64)
+        ));
+    }
+    Ok(at as u64)
+}
+
+/// Queues a send or get of `sources` to `destination` for the daemon.
+pub fn add(
+    sources: &[&str],
+    destination: &str,
+    at: Option<&str>,
+    opts: &TransferOptions,
+    config: &Config,
+) -> Result<(), String> {
+    // Catch unknown aliases now rather than when the daemon gets to it.
+    for location in sources.iter().chain([&destination]) {
+        Location::parse(location, config)?;
+    }
+    let at = at.map(parse_at).transpose()?;
+    let cwd = env::current_dir().map_err(|e| format!("Failed to read the directory: {}", e))?;
+
+    let job = update(|queue| {
+        queue.next_id = queue.next_id.max(1);
+        let job = Job {
+            id: queue.next_id,
+            sources: sources.iter().map(|s| s.to_string()).collect(),
+            destination: destination.to_string(),
+            cwd,
+            options: opts.clone(),
+            added: unix_now(),
+            at,
+            state: State::Queued,
+            attempts: 0,
+            pid: None,
+            started: None,
+            finished: None,
+            error: None,
+            permanent: false,
+        };
+        queue.next_id += 1;
+        queue.jobs.push(job.clone());
+        job
+    })?;
+
+    if output::is_json() {
+        output::emit(&job);
+    }
+    let queued = match job.at {
+        Some(at) => t!(
+            "queued-job-at",
+            id = job.id,
+            time = format_local(at),
+            transfer = job.transfer()
+        ),
+        None => t!("queued-job", id = job.id, transfer = job.transfer()),
+    };
+    say!("{} {}", t!("queued").green(), queued);
+    if daemon_info().is_none() {
+        say!("{}", t!("daemon-not-running-hint").yellow());
+    }
+    Ok(())
+}
+
+pub fn list() {
+    let queue = load();
+    if output::is_json() {
+        output::emit(&queue.jobs);
+        return;
+    }
+    if queue.jobs.is_empty() {
+        say!("{}", t!("queue-empty"));
+        return;
+    }
+    say!(
+        "{}",
+        format!(
+            "{:>4}  {:<9}  {:<16}  {:<5}  TRANSFER",
+            "ID", "STATE", "WHEN", "TRIES"
+        )
+        .bold()
+    );
+    for job in &queue.jobs {
+        let when = match job.state {
+            State::Queued => job.at,
+            State::Running => job.started,
+            _ => job.finished,
+        }
+        .unwrap_or(job.added);
+        say!(
+            "{:>4}  {:<9}  {:<16}  {:<5}  {}",
+            job.id,
+            job.state.label(),
+            format_local(when),
+            job.attempts,
+            job.transfer()
+        );
+        if let (State::Failed, Some(error)) = (job.state, &job.error) {
+            let first = error.lines().next().unwrap_or_default();
+            say!("{:>6}{}", "", redact::redact(first).red());
+        }
+    }
+}
+
+/// The running daemon, if there is one.
+fn daemon_info() -> Option<Daemon> {
+    fs::read_to_string(daemon_path())
+        .ok()
+        .and_then(|content| serde_json::from_str::<Daemon>(&content).ok())
+        .filter(|daemon| sessions::is_running(daemon.pid))
+}
+
+#[derive(Serialize)]
+struct Status<'a> {
+    daemon: Option<&'a Daemon>,
+    queued: usize,
+    running: usize,
+    failed: usize,
+    done: usize,
+    jobs: &'a [Job],
+}
+
+pub fn status() {
+    let queue = load();
+    let daemon = daemon_info();
+    let count = |state: State| queue.jobs.iter().filter(|job| job.state == state).count();
+
+    if output::is_json() {
+        output::emit(&Status {
+            daemon: daemon.as_ref(),
+            queued: count(State::Queued),
+            running: count(State::Running),
+            failed: count(State::Failed),
+            done: count(State::Done),
+            jobs: &queue.jobs,
+        });
+        return;
+    }
+
+    match &daemon {
+        Some(daemon) => say!(
+            "{} {}",
+            t!("daemon").bold(),
+            t!(
+                "daemon-status",
+                pid = daemon.pid,
+                since = format_local(daemon.started),
+                concurrency = daemon.concurrency,
+                attempts = daemon.attempts
+            )
+        ),
+        None => say!(
+            "{} {}",
+            t!("daemon").bold(),
+            t!("daemon-status-stopped").yellow()
+        ),
+    }
+    say!(
+        "{} {}",
+        t!("log").bold(),
+        daemon
+            .as_ref()
+            .map_or(default_log(), |d| d.log.clone())
+            .display()
+    );
+    let next = queue
+        .jobs
+        .iter()
+        .filter(|job| job.state == State::Queued)
+        .filter_map(|job| job.at)
+        .min();
+    let jobs = match next {
+        Some(at) => t!(
+            "jobs-summary-next",
+            queued = count(State::Queued),
+            next = format_local(at),
+            running = count(State::Running),
+            failed = count(State::Failed),
+            done = count(State::Done)
+        ),
+        None => t!(
+            "jobs-summary",
+            queued = count(State::Queued),
+            running = count(State::Running),
+            failed = count(State::Failed),
+            done = count(State::Done)
+        ),
+    };
+    say!("{} {}", t!("jobs").bold(), jobs);
+
+    let sessions = sessions::list();
+    for job in queue.jobs.iter().filter(|job| job.state == State::Running) {
+        let progress = sessions
+            .iter()
+            .find(|session| Some(session.pid) == job.pid)
+            .and_then(|session| match (session.bytes_done, session.total_bytes) {
+                (Some(done), Some(total)) => {
+                    Some(format!("{}/{}", HumanBytes(done), HumanBytes(total)))
+                }
+                (Some(done), None) => Some(HumanBytes(done).to_string()),
+                _ => None,
+            })
+            .unwrap_or_else(|| "starting".to_string());
+        say!("  {} {} {}", job.id, job.transfer(), progress.cyan());
+    }
+}
+
+/// Cancels queued jobs, and stops running ones.
+pub fn cancel(ids: &[u64]) -> Result<(), String> {
+    let results = update(|queue| {
+        ids.iter()
+            .map(|&id| {
+                let job = match queue.jobs.iter_mut().find(|job| job.id == id) {
+                    Some(job) => job,
+                    None => return Err(t!("no-job", id = id)),
+                };
+                match job.state {
+                    State::Queued => {}
+                    State::Running => {
+                        // The transfer tool first, so it doesn't outlive xfer.
+                        if let Some(session) = sessions::list()
+                            .into_iter()
+                            .find(|session| Some(session.pid) == job.pid)
+                        {
+                            let _ = sessions::signal_all(&session.signal_pids(), "TERM");
+                        }
+                        if let Some(pid) = job.pid {
+                            let _ = sessions::signal(pid, "TERM");
+                        }
+                    }
+                    state => {
+                        return Err(t!("job-finished", id = id, state = state.label()));
+                    }
+                }
+                job.state = State::Cancelled;
+                job.finished = Some(unix_now());
+                Ok(id)
+            })
+            .collect::<Vec<_>>()
+    })?;
+
+    let mut failed = false;
+    for result in results {
+        match result {
+            Ok(id) => say!("{} {}", t!("cancelled").green(), t!("job", id = id)),
+            Err(e) => {
+                eprintln!("{}: {}", t!("error").red().bold(), e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        Err(t!("not-all-cancelled"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Runs one job; the daemon starts this as its own process for each.
+pub fn run_job(id: u64, config: &Config) -> Result<(), String> {
+    let job = load()
+        .jobs
+        .into_iter()
+        .find(|job| job.id == id)
+        .ok_or_else(|| t!("no-job", id = id))?;
+    env::set_current_dir(&job.cwd)
+        .map_err(|e| format!("Failed to enter {}: {}", job.cwd.display(), e))?;
+    sessions::mark_queued();
+    fairness::start(config.fairness.as_ref());
+
+    let opts = TransferOptions {
+        job: Some(id),
+        ..job.options.clone()
+    };
+    let sources: Vec<&str> = job.sources.iter().map(String::as_str).collect();
+    let result = TransferEngine::send_files(&sources, &job.destination, config, &opts);
+    // Only failures that won't be retried: one cut off by max_duration
+    // waits for `xfer resume`, and a permanent one would fail again.
+    if let (Err(e), Some(command)) = (&result, &config.on_failure) {
+        if deadline::expired() || !errors::retryable() {
+            hooks::notify_failure(command, &job.cwd, &format!("job {}", id), e);
+        }
+    }
+    update(|queue| {
+        if let Some(job) = queue.jobs.iter_mut().find(|job| job.id == id) {
+            match &result {
+                Ok(()) if job.state == State::Running => job.state = State::Done,
+                Ok(()) => {}
+                Err(e) => {
+                    job.error = Some(e.clone());
+                    job.permanent = !errors::retryable();
+                }
+            }
+        }
+    })?;
+    result
+}
+
+struct Log(PathBuf);
+
+impl Log {
+    fn line(&self, message: &str) {
+        if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&self.0) {
+            let _ = writeln!(file, "{} {}", format_timestamp(unix_now()), message);
+        }
+    }
+
+    fn file(&self) -> Result<File, String> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.0)
+            .map_err(|e| format!("Failed to open {}: {}", self.0.display(), e))
+    }
+}
+
+/// Settles a job whose process has exited: done, cancelled, back in the
+/// queue for another attempt, or failed for good.
+fn settle(queue: &mut Queue, id: u64, attempts: u32) -> Option<String> {
+    let job = queue.jobs.iter_mut().find(|job| job.id == id)?;
+    job.pid = None;
+    job.finished = Some(unix_now());
+    match job.state {
+        State::Done => Some(format!("job {} done: {}", id, job.transfer())),
+        State::Running => {
+            job.attempts += 1;
+            let error = job.error.clone().unwrap_or_else(|| "exited".to_string());
+            if job.attempts < attempts && !job.permanent {
+                let delay = RETRY_DELAY
+                    .saturating_mul(1 << (job.attempts - 1).min(16))
+                    .min(MAX_RETRY_DELAY);
+                job.state = State::Queued;
+                job.at = Some(unix_now() + delay);
+                job.finished = None;
+                // Like a retried transfer, pick up where this one stopped.
+                job.options.resume = true;
+                Some(format!(
+                    "job {} failed (attempt {}/{}), retrying in {}s: {}",
+                    id, job.attempts, attempts, delay, error
+                ))
+            } else {
+                job.state = State::Failed;
+                Some(format!(
+                    "job {} failed after {} attempt(s): {}",
+                    id, job.attempts, error
+                ))
+            }
+        }
+        state => Some(format!("job {} {:?}", id, state).to_lowercase()),
+    }
+}
+
+/// Starts a daemon in the background, detached from the terminal, and
+/// returns once it's running.
+pub fn detach(concurrency: usize, attempts: u32, log: Option<&str>) -> Result<(), String> {
+    if let Some(daemon) = daemon_info() {
+        return Err(t!("daemon-already-running", pid = daemon.pid));
+    }
+    let log = Log(log.map(PathBuf::from).unwrap_or_else(default_log));
+    let exe = env::current_exe().map_err(|e| format!("Failed to find xfer itself: {}", e))?;
+    let mut command = Command::new(exe);
+    command
+        .args(["--plain-output", "daemon"])
+        .args(["--concurrency", &concurrency.to_string()])
+        .args(["--attempts", &attempts.to_string()])
+        .arg("--log")
+        .arg(&log.0)
+        .stdin(Stdio::null())
+        .stdout(log.file()?)
+        .stderr(log.file()?);
+    // Its own process group, so closing the terminal doesn't hang it up.
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let child = command
+        .spawn()
+        .map_err(|e| format!("Failed to start the daemon: {}", e))?;
+    say!(
+        "{} {}",
+        t!("started").green(),
+        t!("daemon-started", pid = child.id(), log = log.0.display())
+    );
+    Ok(())
+}
+
+/// Asks the daemon to stop; jobs already running are finished first.
+pub fn stop() -> Result<(), String> {
+    let daemon = daemon_info().ok_or_else(|| t!("daemon-not-running"))?;
+    fs::remove_file(daemon_path()).map_err(|e| format!("Failed to stop the daemon: {}", e))?;
+    say!(
+        "{} {}",
+        t!("stopping").green(),
+        t!("daemon-stopping", pid = daemon.pid)
+    );
+    Ok(())
+}
+
+/// Runs queued jobs as their time comes, `concurrency` at a time, giving
+/// each up to `attempts` tries. Runs until `xfer daemon --stop`.
+pub fn daemon(concurrency: usize, attempts: u32, log: Option<&str>) -> Result<(), String> {
+    if let Some(daemon) = daemon_info() {
+        return Err(t!("daemon-already-running", pid = daemon.pid));
+    }
+    let log = Log(log.map(PathBuf::from).unwrap_or_else(default_log));
+    let info = Daemon {
+        pid: std::process::id(),
+        started: unix_now(),
+        concurrency,
+        attempts,
+        log: log.0.clone(),
+    };
+    fs::create_dir_all(data_dir())
+        .and_then(|_| {
+            fs::write(
+                daemon_path(),
+                serde_json::to_string(&info).unwrap_or_default(),
+            )
+        })
+        .map_err(|e| format!("Failed to register the daemon: {}", e))?;
+    let still_wanted = || daemon_info().is_some_and(|daemon| daemon.pid == info.pid);
+
+    // Jobs a previous daemon left running are watched until they exit, or
+    // settled now if they died with it.
+    let (mut adopted, orphans) = update(|queue| {
+        let running: Vec<(u64, Option<u32>)> = queue
+            .jobs
+            .iter()
+            .filter(|job| job.state == State::Running)
+            .map(|job| (job.id, job.pid))
+            .collect();
+        let mut adopted = Vec::new();
+        let mut messages = Vec::new();
+        for (id, pid) in running {
+            match pid {
+                Some(pid) if sessions::is_running(pid) => adopted.push((id, pid)),
+                _ => messages.extend(settle(queue, id, attempts)),
+            }
+        }
+        (adopted, messages)
+    })?;
+    log.line(&format!(
+        "daemon started (pid {}, {} at a time)",
+        info.pid, concurrency
+    ));
+    for message in orphans {
+        log.line(&message);
+    }
+    say!(
+        "{} {}",
+        t!("running").green(),
+        t!(
+            "daemon-running",
+            concurrency = concurrency,
+            log = log.0.display()
+        )
+    );
+
+    let exe = env::current_exe().map_err(|e| format!("Failed to find xfer itself: {}", e))?;
+    let mut running: Vec<(u64, Child)> = Vec::new();
+    loop {
+        let mut exited = Vec::new();
+        running.retain_mut(|(id, child)| match child.try_wait() {
+            Ok(None) => true,
+            _ => {
+                exited.push(*id);
+                false
+            }
+        });
+        adopted.retain(|&(id, pid)| {
+            let alive = sessions::is_running(pid);
+            if !alive {
+                exited.push(id);
+            }
+            alive
+        });
+        for id in exited {
+            if let Some(message) = update(|queue| settle(queue, id, attempts))? {
+                log.line(&message);
+            }
+        }
+
+        let wanted = still_wanted();
+        while wanted && running.len() + adopted.len() < concurrency {
+            let now = unix_now();
+            let job = update(|queue| {
+                let job = queue
+                    .jobs
+                    .iter_mut()
+                    .filter(|job| job.state == State::Queued)
+                    .find(|job| job.at.is_none_or(|at| at <= now))?;
+                job.state = State::Running;
+                job.started = Some(now);
+                job.error = None;
+                job.permanent = false;
+                Some(job.clone())
+            })?;
+            let job = match job {
+                Some(job) => job,
+                None => break,
+            };
+
+            let spawned = Command::new(&exe)
+                .args(["--plain-output", "queue", "run-job", &job.id.to_string()])
+                .stdin(Stdio::null())
+                .stdout(log.file()?)
+                .stderr(log.file()?)
+                .spawn();
+            match spawned {
+                Ok(child) => {
+                    let pid = child.id();
+                    update(|queue| {
+                        if let Some(job) = queue.jobs.iter_mut().find(|j| j.id == job.id) {
+                            job.pid = Some(pid);
+                        }
+                    })?;
+                    log.line(&format!(
+                        "job {} started (pid {}): {}",
+                        job.id,
+                        pid,
+                        job.transfer()
+                    ));
+                    running.push((job.id, child));
+                }
+                Err(e) => {
+                    update(|queue| {
+                        if let Some(job) = queue.jobs.iter_mut().find(|j| j.id == job.id) {
+                            job.error = Some(format!("Failed to start: {}", e));
+                        }
+                        settle(queue, job.id, attempts)
+                    })?
+                    .into_iter()
+                    .for_each(|message| log.line(&message));
+                }
+            }
+        }
+
+        if !wanted && running.is_empty() && adopted.is_empty() {
+            log.line("daemon stopped");
+            return Ok(());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
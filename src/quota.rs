@@ -0,0 +1,231 @@
+use crate::location::Location;
+use crate::platform::Platform;
+use crate::{output, shell_quote, Config, ServerConfig, TransferEngine};
+use colored::*;
+use indicatif::HumanBytes;
+use serde::Serialize;
+
+/// Printed between the `df` and `quota` output.
+const SEPARATOR: &str = "--xfer-quota--";
+
+/// A per-user disk quota, in bytes. Shared hosts enforce these long before
+/// the disk itself is full.
+#[derive(Debug, Serialize)]
+pub struct Quota {
+    pub used: u64,
+    /// Past this, writes still work for a grace period.
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+}
+
+impl Quota {
+    /// The limit writes run into first.
+    pub fn limit(&self) -> u64 {
+        self.soft.or(self.hard).unwrap_or(u64::MAX)
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.limit().saturating_sub(self.used)
+    }
+}
+
+/// Room on the filesystem holding a remote directory, in bytes.
+#[derive(Debug, Serialize)]
+pub struct Space {
+    pub filesystem: String,
+    pub mount: String,
+    pub size: u64,
+    pub used: u64,
+    pub available: u64,
+    /// The user's quota there, when `quota` reports one.
+    pub quota: Option<Quota>,
+}
+
+impl Space {
+    /// What can actually be written: the free space or the quota left,
+    /// whichever is smaller.
+    pub fn free(&self) -> u64 {
+        match &self.quota {
+            Some(quota) => self.available.min(quota.remaining()),
+            None => self.available,
+        }
+    }
+}
+
+/// The filesystem, size, used and free space and mount point in `df -Pk`
+/// output.
+fn parse_df(output: &str) -> Option<(String, u64, u64, u64, String)> {
+    let fields: Vec<&str> = output.lines().nth(1)?.split_whitespace().collect();
+    let kib = |i: usize| -> Option<u64> { Some(fields.get(i)?.parse::<u64>().ok()? * 1024) };
+    Some((
+        fields[0].to_string(),
+        kib(1)?,
+        kib(2)?,
+        kib(3)?,
+        fields.get(5..)?.join(" "),
+    ))
+}
+
+/// The user's quota on `filesystem` (or the one mounted at `mount`) from
+/// `quota` output, which lists block counts in KiB after a header line:
+///
+/// ```text
+/// Disk quotas for user alice (uid 1000):
+///      Filesystem  blocks   quota   limit   grace   files   quota   limit   grace
+///       /dev/sdb1  9500000* 9000000 10000000  6days   1200       0       0
+/// ```
+///
+/// Without `-w` a long filesystem name gets a line of its own, with the
+/// numbers on the next. A limit of 0 means none.
+fn parse_quota(output: &str, filesystem: &str, mount: &str) -> Option<Quota> {
+    let mut fields: Vec<&str> = Vec::new();
+    for line in output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("Filesystem"))
+        .skip(1)
+    {
+        fields.extend(line.split_whitespace());
+        if fields.len() < 4 {
+            continue;
+        }
+        if fields[0] == filesystem || fields[0] == mount {
+            let kib = |field: &str| -> Option<u64> {
+                field
+                    .trim_end_matches('*')
+                    .parse::<u64>()
+                    .ok()
+                    .map(|n| n * 1024)
+            };
+            let limit = |field: &str| kib(field).filter(|&n| n > 0);
+            let quota = Quota {
+                used: kib(fields[1])?,
+                soft: limit(fields[2]),
+                hard: limit(fields[3]),
+            };
+            return (quota.soft.is_some() || quota.hard.is_some()).then_some(quota);
+        }
+        fields.clear();
+    }
+    None
+}
+
+/// Looks up the room left on the server for `dir`, which may not exist
+/// yet; its nearest existing parent is checked. The quota is left out when
+/// the server has no `quota` command or no quota for the user there.
+pub fn remote(server: &ServerConfig, dir: &str) -> Result<Space, String> {
+    let command = format!(
+        "d={}; while [ ! -d \"$d\" ] && [ \"$d\" != / ]; do d=$(dirname \"$d\"); done; \
+         df -Pk \"$d\" 2>/dev/null; echo {}; \
+         if command -v quota >/dev/null 2>&1; then quota -w 2>/dev/null || quota 2>/dev/null; fi; true",
+        shell_quote(dir),
+        SEPARATOR
+    );
+    let output = TransferEngine::remote_command(server, &command, None)?;
+    let (df, quota) = output.split_once(SEPARATOR).unwrap_or((&output, ""));
+    let (filesystem, size, used, available, mount) =
+        parse_df(df).ok_or_else(|| format!("Unexpected df output for {}: {}", dir, df))?;
+    let quota = parse_quota(quota, &filesystem, &mount);
+    Ok(Space {
+        filesystem,
+        mount,
+        size,
+        used,
+        available,
+        quota,
+    })
+}
+
+#[derive(Serialize)]
+struct Report {
+    location: String,
+    #[serde(flatten)]
+    space: Space,
+    free: u64,
+}
+
+fn percent(part: u64, whole: u64) -> u64 {
+    (part as f64 * 100.0 / whole.max(1) as f64).round() as u64
+}
+
+/// `xfer df`: the free disk space and quota left for each location
+/// (`alias` for the default remote path, or `alias:/path`).
+pub fn show(locations: &[&str], config: &Config) -> Result<(), String> {
+    let mut reports = Vec::new();
+    for location in locations {
+        let location = if location.contains(':') {
+            location.to_string()
+        } else {
+            format!("{}:", location)
+        };
+        let (server, path) = match Location::parse(&location, config)? {
+            Location::Remote { server, path, .. } => (server, path.to_string()),
+            _ => {
+                return Err(format!(
+                    "'{}' is not a server (alias or alias:/path)",
+                    location
+                ))
+            }
+        };
+        if Platform::of(server)?.is_windows() {
+            return Err("df needs a POSIX shell on the server".to_string());
+        }
+        let space = remote(server, &path)?;
+        reports.push(Report {
+            location,
+            free: space.free(),
+            space,
+        });
+    }
+
+    if output::is_json() {
+        output::emit(&reports);
+        return Ok(());
+    }
+    for (i, report) in reports.iter().enumerate() {
+        let space = &report.space;
+        if i > 0 {
+            say!();
+        }
+        say!("{}", report.location.bold());
+        say!(
+            "{:>7} {} free of {} ({}% used) on {} ({})",
+            "Disk:".bold(),
+            HumanBytes(space.available),
+            HumanBytes(space.size),
+            // Like df's capacity, which leaves out space reserved for root.
+            percent(space.used, space.used + space.available),
+            space.mount,
+            space.filesystem
+        );
+        let quota = match &space.quota {
+            Some(quota) => quota,
+            None => {
+                say!("{:>7} {}", "Quota:".bold(), "none reported".dimmed());
+                continue;
+            }
+        };
+        let left = format!(
+            "{} left of {} ({}% used)",
+            HumanBytes(quota.remaining()),
+            HumanBytes(quota.limit()),
+            percent(quota.used, quota.limit())
+        );
+        let left = if quota.remaining() == 0 {
+            left.red().bold()
+        } else if quota.remaining() < space.available {
+            left.yellow()
+        } else {
+            left.normal()
+        };
+        match (quota.soft, quota.hard) {
+            (Some(_), Some(hard)) => say!(
+                "{:>7} {}; hard limit {}",
+                "Quota:".bold(),
+                left,
+                HumanBytes(hard)
+            ),
+            _ => say!("{:>7} {}", "Quota:".bold(), left),
+        }
+    }
+    Ok(())
+}
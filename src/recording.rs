@@ -0,0 +1,169 @@
+use crate::crypt::expand_tilde;
+use crate::history::format_timestamp;
+use crate::{data_dir, output, redact, unix_now};
+use serde::Serialize;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// The programs that act on other machines; local helpers like tar or
+/// gzip aren't recorded.
+const REMOTE: [&str; 4] = ["ssh", "scp", "rsync", "aws"];
+
+/// Output kept per command; the rest is summed up in `truncated`.
+const MAX_OUTPUT: usize = 64 << 10;
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+/// The transcript, opened with the first recorded command.
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Event {
+    /// Opens each transcript: who ran what, where.
+    Session {
+        timestamp: u64,
+        pid: u32,
+        user: String,
+        hostname: String,
+        cwd: String,
+        args: Vec<String>,
+    },
+    Run {
+        id: u64,
+        timestamp: u64,
+        command: String,
+    },
+    Exit {
+        id: u64,
+        timestamp: u64,
+        /// `None` when the program was killed by a signal.
+        code: Option<i32>,
+        duration_ms: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stdout: Option<String>,
+        stderr: String,
+        #[serde(skip_serializing_if = "is_zero")]
+        truncated: usize,
+    },
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// Turns recording on for this run: `--record` or `record = true`, into
+/// `record_dir` or `recordings` under the data dir.
+pub fn init(enabled: bool, dir: Option<&str>) {
+    if enabled {
+        let dir = match dir {
+            Some(dir) => expand_tilde(dir),
+            None => data_dir().join("recordings"),
+        };
+        let _ = DIR.set(dir);
+    }
+}
+
+fn write(event: &Event) {
+    let Some(dir) = DIR.get() else {
+        return;
+    };
+    let mut file = FILE.lock().unwrap_or_else(|e| e.into_inner());
+    if file.is_none() {
+        *file = open(dir);
+    }
+    if let (Some(file), Ok(line)) = (file.as_mut(), serde_json::to_string(event)) {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+/// Starts this run's transcript, named after when it began.
+fn open(dir: &PathBuf) -> Option<File> {
+    fs::create_dir_all(dir).ok()?;
+    let stamp = format_timestamp(unix_now())
+        .replace(['-', ':'], "")
+        .replace(' ', "-");
+    let path = dir.join(format!("{}-{}.jsonl", stamp, process::id()));
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .ok()?;
+    let session = Event::Session {
+        timestamp: unix_now(),
+        pid: process::id(),
+        user: env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_default(),
+        hostname: Command::new("hostname")
+            .output()
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+            .unwrap_or_default(),
+        cwd: env::current_dir()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        args: env::args().map(|arg| redact::redact(&arg)).collect(),
+    };
+    writeln!(file, "{}", serde_json::to_string(&session).ok()?).ok()?;
+    Some(file)
+}
+
+/// A recorded command that hasn't finished yet.
+pub struct Entry {
+    id: u64,
+    started: Instant,
+}
+
+/// Records that `command` is about to run, when recording is on and it is
+/// one that reaches another machine.
+pub fn start(command: &Command) -> Option<Entry> {
+    DIR.get()?;
+    let program = command.get_program().to_string_lossy();
+    if !REMOTE.contains(&program.as_ref()) {
+        return None;
+    }
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    write(&Event::Run {
+        id,
+        timestamp: unix_now(),
+        command: output::command_line(command),
+    });
+    Some(Entry {
+        id,
+        started: Instant::now(),
+    })
+}
+
+/// Records how a command started with `start` ended, with its stderr and,
+/// when it was captured, its stdout.
+pub fn finish(entry: Option<Entry>, code: Option<i32>, stdout: Option<&str>, stderr: &str) {
+    let Some(entry) = entry else {
+        return;
+    };
+    let mut truncated = 0;
+    let mut keep = |text: &str| {
+        let mut end = text.len().min(MAX_OUTPUT);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        truncated += text.len() - end;
+        redact::redact(&text[..end])
+    };
+    let stdout = stdout.map(&mut keep);
+    let stderr = keep(stderr);
+    write(&Event::Exit {
+        id: entry.id,
+        timestamp: unix_now(),
+        code,
+        duration_ms: entry.started.elapsed().as_millis() as u64,
+        stdout,
+        stderr,
+        truncated,
+    });
+}
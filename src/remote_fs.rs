@@ -0,0 +1,314 @@
+use crate::history::format_timestamp;
+use crate::listings;
+use crate::location::Location;
+use crate::platform::Platform;
+use crate::{confirm, shell_quote, Config, ServerConfig, TransferEngine};
+use colored::*;
+use indicatif::HumanBytes;
+use serde::{Deserialize, Serialize};
+
+/// Metadata of a remote file, parsed from `stat` rather than scraped from
+/// `ls` output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteStat {
+    pub path: String,
+    pub kind: String,
+    pub size: u64,
+    pub mtime: u64,
+    pub mode: String,
+    pub permissions: String,
+    pub owner: String,
+    pub group: String,
+}
+
+/// `stat` arguments printing "size|mtime|octal mode|symbolic mode|owner|
+/// group|type|name". The name goes last so a `|` in it can't shift fields.
+fn stat_format(platform: Platform) -> &'static str {
+    match platform {
+        Platform::Linux => "-c '%s|%Y|%a|%A|%U|%G|%F|%n'",
+        _ => "-f '%z|%m|%Lp|%Sp|%Su|%Sg|%HT|%N'",
+    }
+}
+
+fn parse_stat(line: &str) -> Option<RemoteStat> {
+    let fields: Vec<&str> = line.trim_end().splitn(8, '|').collect();
+    match fields.as_slice() {
+        [size, mtime, mode, permissions, owner, group, kind, path] => Some(RemoteStat {
+            path: path.to_string(),
+            kind: kind.to_lowercase(),
+            size: size.parse().ok()?,
+            mtime: mtime.parse().ok()?,
+            mode: mode.to_string(),
+            permissions: permissions.to_string(),
+            owner: owner.to_string(),
+            group: group.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Builds a command with `run`, which is given the `stat` arguments for the
+/// server's flavour of `stat`.
+fn stat_command(platform: Platform, run: impl Fn(&str) -> String) -> String {
+    match platform {
+        Platform::Linux | Platform::Macos | Platform::Bsd => run(stat_format(platform)),
+        // Unknown flavour: try GNU syntax, then BSD.
+        _ => format!(
+            "{} || {}",
+            run(stat_format(Platform::Linux)),
+            run(stat_format(Platform::Bsd))
+        ),
+    }
+}
+
+pub fn stat(server: &ServerConfig, path: &str) -> Result<RemoteStat, String> {
+    let platform = Platform::of(server)?;
+    if platform.is_windows() {
+        return Err("stat is not supported on Windows servers".to_string());
+    }
+
+    let quoted = shell_quote(path);
+    let command = stat_command(platform, |format| {
+        format!("stat {} {} 2>/dev/null", format, quoted)
+    });
+
+    let output = TransferEngine::remote_command(server, &command, None)
+        .map_err(|_| format!("No such file or directory: {}", path))?;
+    parse_stat(&output).ok_or_else(|| format!("Unexpected stat output for {}: {}", path, output))
+}
+
+/// A shell command running `find` with `args` in `dir` and stating what it
+/// prints; `parse_find` reads its output.
+pub fn find_command(platform: Platform, dir: &str, args: &str) -> String {
+    let quoted = shell_quote(dir);
+    stat_command(platform, |format| {
+        format!(
+            "cd {} && find . {} -exec stat {} {{}} + 2>/dev/null",
+            quoted, args, format
+        )
+    })
+}
+
+/// The entries `find_command` printed, with `path` relative to its `dir`.
+pub fn parse_find(output: &str) -> Vec<RemoteStat> {
+    output
+        .lines()
+        .filter_map(parse_stat)
+        .map(|mut stat| {
+            stat.path = stat.path.trim_start_matches("./").to_string();
+            stat
+        })
+        .collect()
+}
+
+/// Runs `find` with `args` in `dir` and stats what it prints, with `path`
+/// relative to `dir`.
+fn find(server: &ServerConfig, dir: &str, args: &str) -> Result<Vec<RemoteStat>, String> {
+    let platform = Platform::of(server)?;
+    if platform.is_windows() {
+        return Err("Listing metadata is not supported on Windows servers".to_string());
+    }
+
+    let output = TransferEngine::remote_command(server, &find_command(platform, dir, args), None)
+        .map_err(|_| format!("Cannot list {}", dir))?;
+    Ok(parse_find(&output))
+}
+
+/// The entries of a remote directory, with `path` holding just the name.
+pub fn list(server: &ServerConfig, dir: &str) -> Result<Vec<RemoteStat>, String> {
+    find(server, dir, "-mindepth 1 -maxdepth 1")
+}
+
+/// Every regular file under a remote directory, with `path` relative to it.
+pub fn tree(server: &ServerConfig, dir: &str) -> Result<Vec<RemoteStat>, String> {
+    find(server, dir, "-type f")
+}
+
+/// `find` arguments for `entries`: files and directories below the root.
+pub const ENTRIES: &str = "-mindepth 1 \\( -type f -o -type d \\)";
+
+/// Every file and directory under a remote directory, with `path` relative
+/// to it.
+pub fn entries(server: &ServerConfig, dir: &str) -> Result<Vec<RemoteStat>, String> {
+    find(server, dir, ENTRIES)
+}
+
+fn remote_location<'a>(
+    location: &str,
+    config: &'a Config,
+) -> Result<(&'a ServerConfig, String), String> {
+    match Location::parse(location, config)? {
+        Location::Remote { server, path, .. } => Ok((server, path.to_string())),
+        _ => Err(format!(
+            "'{}' is not a remote location (alias:/path)",
+            location
+        )),
+    }
+}
+
+pub fn show(locations: &[&str], config: &Config) -> Result<(), String> {
+    for (i, location) in locations.iter().enumerate() {
+        let (server, path) = remote_location(location, config)?;
+        let stat = stat(server, &path)?;
+
+        if i > 0 {
+            say!();
+        }
+        say!("{:>9} {}", "File:".bold(), stat.path);
+        say!("{:>9} {}", "Type:".bold(), stat.kind);
+        say!(
+            "{:>9} {} ({} bytes)",
+            "Size:".bold(),
+            HumanBytes(stat.size),
+            stat.size
+        );
+        say!(
+            "{:>9} {} UTC",
+            "Modified:".bold(),
+            format_timestamp(stat.mtime)
+        );
+        say!("{:>9} {} ({})", "Mode:".bold(), stat.permissions, stat.mode);
+        say!("{:>9} {}:{}", "Owner:".bold(), stat.owner, stat.group);
+    }
+    Ok(())
+}
+
+/// Sets the mtime of each file to now, creating missing files unless
+/// `no_create` is set (like `touch -c`).
+pub fn touch(locations: &[&str], no_create: bool, config: &Config) -> Result<(), String> {
+    for location in locations {
+        let (server, path) = remote_location(location, config)?;
+        if Platform::of(server)?.is_windows() {
+            return Err("touch is not supported on Windows servers".to_string());
+        }
+
+        let flag = if no_create { "-c " } else { "" };
+        TransferEngine::remote_command(
+            server,
+            &format!("touch {}{}", flag, shell_quote(&path)),
+            None,
+        )?;
+        listings::forget(server, &path);
+
+        match stat(server, &path) {
+            Ok(stat) => say!(
+                "{} {} (modified {} UTC)",
+                "Touched".green(),
+                location,
+                format_timestamp(stat.mtime)
+            ),
+            // With -c a missing file is silently left alone.
+            Err(_) => say!("{} {} does not exist", "Skipped".yellow(), location),
+        }
+    }
+    Ok(())
+}
+
+fn posix_location<'a>(
+    location: &str,
+    config: &'a Config,
+    command: &str,
+) -> Result<(&'a ServerConfig, String), String> {
+    let (server, path) = remote_location(location, config)?;
+    if Platform::of(server)?.is_windows() {
+        return Err(format!("{} is not supported on Windows servers", command));
+    }
+    Ok((server, path))
+}
+
+/// Creates remote directories, along with missing parents when `parents`
+/// is set (like `mkdir -p`).
+pub fn mkdir(locations: &[&str], parents: bool, config: &Config) -> Result<(), String> {
+    for location in locations {
+        let (server, path) = posix_location(location, config, "mkdir")?;
+        let flag = if parents { "-p " } else { "" };
+        TransferEngine::remote_command(
+            server,
+            &format!("mkdir {}-- {}", flag, shell_quote(&path)),
+            None,
+        )?;
+        listings::forget(server, &path);
+        say!("{} {}", "Created".green(), location);
+    }
+    Ok(())
+}
+
+/// Deletes remote files, and directories with `recursive`. Asks first
+/// unless `yes` is set.
+pub fn rm(locations: &[&str], recursive: bool, yes: bool, config: &Config) -> Result<(), String> {
+    let mut targets = Vec::new();
+    for location in locations {
+        let (server, path) = posix_location(location, config, "rm")?;
+        let trimmed = path.trim_end_matches('/');
+        if trimmed.is_empty() || trimmed == "~" || trimmed == "." {
+            return Err(format!("Refusing to delete {}", location));
+        }
+        let stat = stat(server, &path)?;
+        if stat.kind == "directory" && !recursive {
+            return Err(format!(
+                "{} is a directory; use -r to delete it and everything in it",
+                location
+            ));
+        }
+        targets.push((location, server, path, stat));
+    }
+
+    if !yes {
+        for (location, _, _, stat) in &targets {
+            say!("  {} ({})", location, stat.kind);
+        }
+        if !confirm(&format!("Delete {} item(s)?", targets.len())) {
+            return Err("Nothing deleted (pass --yes to delete without asking)".to_string());
+        }
+    }
+
+    for (location, server, path, _) in targets {
+        let flag = if recursive { "-rf" } else { "-f" };
+        TransferEngine::remote_command(
+            server,
+            &format!("rm {} -- {}", flag, shell_quote(&path)),
+            None,
+        )?;
+        listings::forget(server, &path);
+        say!("{} {}", "Deleted".green(), location);
+    }
+    Ok(())
+}
+
+/// Moves or renames a remote path within one server. Overwriting an
+/// existing file asks first unless `yes` is set.
+pub fn mv(from: &str, to: &str, yes: bool, config: &Config) -> Result<(), String> {
+    let from_location = Location::parse(from, config)?;
+    let to_location = Location::parse(to, config)?;
+    if from_location.remote().map(|(alias, _)| alias)
+        != to_location.remote().map(|(alias, _)| alias)
+    {
+        return Err("mv works within one server; use send or get to copy between them".to_string());
+    }
+    let (server, from_path) = posix_location(from, config, "mv")?;
+    let (_, to_path) = posix_location(to, config, "mv")?;
+
+    stat(server, &from_path)?;
+    if let Ok(existing) = stat(server, &to_path) {
+        if existing.kind != "directory"
+            && !yes
+            && !confirm(&format!("{} exists. Overwrite it?", to))
+        {
+            return Err("Nothing moved (pass --yes to overwrite without asking)".to_string());
+        }
+    }
+
+    TransferEngine::remote_command(
+        server,
+        &format!(
+            "mv -- {} {}",
+            shell_quote(&from_path),
+            shell_quote(&to_path)
+        ),
+        None,
+    )?;
+    listings::forget(server, &from_path);
+    listings::forget(server, &to_path);
+    say!("{} {} {} {}", "Moved".green(), from, "->".green(), to);
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use crate::{data_dir, Config, TransferEngine, TransferOptions};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// The last send/get that hasn't completed yet. It is written before a
+/// transfer starts and removed once it succeeds, so whatever is left behind
+/// is the transfer that was interrupted.
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingTransfer {
+    cwd: PathBuf,
+    sources: Vec<String>,
+    destination: String,
+    options: TransferOptions,
+}
+
+fn state_path() -> PathBuf {
+    data_dir().join("resume.json")
+}
+
+pub fn record(sources: &[&str], destination: &str, opts: &TransferOptions) {
+    let pending = PendingTransfer {
+        cwd: env::current_dir().unwrap_or_default(),
+        sources: sources.iter().map(|s| s.to_string()).collect(),
+        destination: destination.to_string(),
+        options: opts.clone(),
+    };
+
+    if fs::create_dir_all(data_dir()).is_ok() {
+        if let Ok(content) = serde_json::to_string_pretty(&pending) {
+            let _ = fs::write(state_path(), content);
+        }
+    }
+}
+
+pub fn clear() {
+    let _ = fs::remove_file(state_path());
+}
+
+pub fn resume_last(config: &Config) -> Result<(), String> {
+    let content = fs::read_to_string(state_path())
+        .map_err(|_| "No interrupted transfer to resume".to_string())?;
+    let mut pending: PendingTransfer = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to read resume state: {}", e))?;
+    pending.options.resume = true;
+
+    // Relative local paths were recorded relative to where xfer ran.
+    env::set_current_dir(&pending.cwd)
+        .map_err(|e| format!("Failed to enter {}: {}", pending.cwd.display(), e))?;
+
+    say!(
+        "{} {} {} {}",
+        "Resuming".green(),
+        pending.sources.join(", "),
+        "to".green(),
+        pending.destination
+    );
+    let sources: Vec<&str> = pending.sources.iter().map(String::as_str).collect();
+    TransferEngine::send_files(&sources, &pending.destination, config, &pending.options)
+}
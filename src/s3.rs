@@ -0,0 +1,163 @@
+use crate::backend::Backend;
+use crate::{errors, output, progress, Config, TransferEngine, TransferOptions};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Command;
+
+/// Location prefix for object storage: `s3:bucket/key`.
+pub const SCHEME: &str = "s3";
+
+/// Settings for a bucket under `[buckets.<name>]`. Buckets without an entry
+/// use the aws CLI's own defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BucketConfig {
+    /// Actual bucket name, when the table key is just a short alias.
+    pub bucket: Option<String>,
+    pub region: Option<String>,
+    /// Endpoint URL for S3-compatible services such as MinIO.
+    pub endpoint: Option<String>,
+    /// Named profile from `~/.aws/credentials`.
+    pub profile: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    /// How names are rewritten on upload; see `naming`.
+    pub on_upload: Option<String>,
+}
+
+/// Splits the part after `s3:` into bucket and key. `s3://bucket/key` is
+/// accepted as well.
+pub fn split(path: &str) -> (String, String) {
+    let path = path.trim_start_matches('/');
+    match path.split_once('/') {
+        Some((bucket, key)) => (bucket.to_string(), key.to_string()),
+        None => (path.to_string(), String::new()),
+    }
+}
+
+/// Object storage driven through the `aws s3` CLI.
+pub struct S3Backend {
+    bucket: String,
+    settings: BucketConfig,
+}
+
+impl S3Backend {
+    pub fn new(bucket: &str, config: &Config) -> S3Backend {
+        let settings = config.buckets.get(bucket).cloned().unwrap_or_default();
+        S3Backend {
+            bucket: settings
+                .bucket
+                .clone()
+                .unwrap_or_else(|| bucket.to_string()),
+            settings,
+        }
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("s3://{}/{}", self.bucket, key)
+    }
+
+    /// `aws s3 <args>`, set up for this bucket. Credentials go through the
+    /// environment so they never show up in `ps`.
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new("aws");
+        if let Some(region) = &self.settings.region {
+            command.args(["--region", region]);
+        }
+        if let Some(endpoint) = &self.settings.endpoint {
+            command.args(["--endpoint-url", endpoint]);
+        }
+        if let Some(profile) = &self.settings.profile {
+            command.args(["--profile", profile]);
+        }
+        if let (Some(id), Some(secret)) = (
+            &self.settings.access_key_id,
+            &self.settings.secret_access_key,
+        ) {
+            command
+                .env("AWS_ACCESS_KEY_ID", id)
+                .env("AWS_SECRET_ACCESS_KEY", secret);
+        }
+        command.arg("s3").args(args);
+        command
+    }
+
+    /// What `aws s3 sync --delete` would remove, from a dry run.
+    fn sync_deletions(&self, from: &str, to: &str) -> Result<Vec<String>, String> {
+        let mut command = self.command(&["sync", from, to, "--delete", "--dryrun"]);
+        output::show_command(&command);
+        let output = command
+            .output()
+            .map_err(|e| format!("Failed to execute aws: {}", e))?;
+        if !output.status.success() {
+            return Err(errors::describe(
+                "aws",
+                output.status.code(),
+                &String::from_utf8_lossy(&output.stderr),
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.strip_prefix("(dryrun) delete: "))
+            .map(String::from)
+            .collect())
+    }
+
+    fn run(&self, args: &[&str]) -> Result<(), String> {
+        let mut command = self.command(args);
+        command.stdout(output::child_stdout());
+        if progress::is_plain() && matches!(args.first(), Some(&"cp" | &"sync")) {
+            command.arg("--no-progress");
+        }
+
+        let running = TransferEngine::spawn_command("aws", command)?;
+        TransferEngine::finish("aws", running)
+    }
+}
+
+impl Backend for S3Backend {
+    fn upload(&self, local: &str, remote: &str, opts: &TransferOptions) -> Result<(), String> {
+        let url = self.url(remote);
+        if Path::new(local).is_dir() {
+            let mut args = vec!["sync", local, &url];
+            if opts.delete {
+                args.push("--delete");
+            }
+            self.run(&args)
+        } else {
+            self.run(&["cp", local, &url])
+        }
+    }
+
+    /// Keys ending in `/` (or the bucket root) are prefixes and are synced
+    /// recursively; anything else is a single object.
+    fn download(&self, remote: &str, local: &str, opts: &TransferOptions) -> Result<(), String> {
+        let url = self.url(remote);
+        if remote.is_empty() || remote.ends_with('/') {
+            let mut args = vec!["sync", &url, local];
+            if opts.delete {
+                args.push("--delete");
+            }
+            self.run(&args)
+        } else {
+            self.run(&["cp", &url, local])
+        }
+    }
+
+    fn list(&self, remote: &str) -> Result<(), String> {
+        self.run(&["ls", &self.url(remote)])
+    }
+
+    fn upload_deletions(&self, local: &str, remote: &str) -> Result<Vec<String>, String> {
+        if !Path::new(local).is_dir() {
+            return Ok(Vec::new());
+        }
+        self.sync_deletions(local, &self.url(remote))
+    }
+
+    fn download_deletions(&self, remote: &str, local: &str) -> Result<Vec<String>, String> {
+        if !(remote.is_empty() || remote.ends_with('/')) {
+            return Ok(Vec::new());
+        }
+        self.sync_deletions(&self.url(remote), local)
+    }
+}
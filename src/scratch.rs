@@ -0,0 +1,176 @@
+use crate::{quota, ServerConfig};
+use indicatif::HumanBytes;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+/// Leftovers older than this are removed even when their owner can't be
+/// checked.
+const STALE_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the scratch directory from the config's `temp_dir`, falling back to
+/// `xfer` under the system temp dir, and clears out what earlier runs left
+/// behind.
+pub fn init(temp_dir: Option<&str>) {
+    let dir = match temp_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => env::temp_dir().join("xfer"),
+    };
+    let _ = DIR.set(dir);
+    cleanup();
+}
+
+fn dir() -> PathBuf {
+    DIR.get()
+        .cloned()
+        .unwrap_or_else(|| env::temp_dir().join("xfer"))
+}
+
+/// A scratch file or directory, removed when dropped.
+pub struct Scratch {
+    pub path: PathBuf,
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = if self.path.is_dir() {
+            fs::remove_dir_all(&self.path)
+        } else {
+            fs::remove_file(&self.path)
+        };
+    }
+}
+
+/// Writes `content` to a scratch file named after this process, so
+/// `cleanup` can tell whose it is.
+pub fn file(name: &str, content: &[u8]) -> Result<Scratch, String> {
+    let dir = dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let path = dir.join(format!("{}-{}", process::id(), name));
+    fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(Scratch { path })
+}
+
+/// A path for a scratch file (or directory) the caller writes itself.
+pub fn path(name: &str) -> Result<Scratch, String> {
+    let dir = dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(Scratch {
+        path: dir.join(format!("{}-{}", process::id(), name)),
+    })
+}
+
+/// A path for a socket that another program creates and removes itself.
+pub fn socket(name: &str) -> Result<PathBuf, String> {
+    let dir = dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(dir.join(format!("{}-{}", process::id(), name)))
+}
+
+/// Whether the process that made a scratch entry is gone. Without /proc
+/// there is no cheap way to tell, so only age counts.
+fn owner_gone(name: &str) -> bool {
+    let proc = Path::new("/proc");
+    match name
+        .split('-')
+        .next()
+        .and_then(|pid| pid.parse::<u32>().ok())
+    {
+        Some(pid) if proc.is_dir() => pid != process::id() && !proc.join(pid.to_string()).exists(),
+        _ => false,
+    }
+}
+
+/// Removes scratch entries whose process has exited or that are older than
+/// a day.
+fn cleanup() {
+    let entries = match fs::read_dir(dir()) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let old = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > STALE_AFTER);
+        if old || owner_gone(&name) {
+            let path = entry.path();
+            let _ = if path.is_dir() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+        }
+    }
+}
+
+/// Available bytes from `df -Pk` output (the fourth column, in KiB).
+fn parse_df(output: &str) -> Option<u64> {
+    let line = output.lines().nth(1)?;
+    let available: u64 = line.split_whitespace().nth(3)?.parse().ok()?;
+    Some(available * 1024)
+}
+
+fn check(available: Option<u64>, needed: u64, place: &str) -> Result<(), String> {
+    match available {
+        Some(available) if available < needed => Err(t!(
+            "no-space",
+            place = place,
+            needed = HumanBytes(needed),
+            free = HumanBytes(available)
+        )),
+        // If df can't tell, let the transfer find out.
+        _ => Ok(()),
+    }
+}
+
+/// Fails early when the local filesystem holding `path` has less than
+/// `needed` bytes free.
+pub fn ensure_local_space(path: &Path, needed: u64) -> Result<(), String> {
+    let existing = path
+        .ancestors()
+        .find(|p| p.exists())
+        .unwrap_or(Path::new("."));
+    let available = Command::new("df")
+        .arg("-Pk")
+        .arg(existing)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| parse_df(&String::from_utf8_lossy(&o.stdout)));
+    check(available, needed, &existing.display().to_string())
+}
+
+/// Fails early when the server's filesystem holding `dir` has less than
+/// `needed` bytes free, or the user's quota there less than that left.
+/// `dir` may not exist yet; its nearest existing parent is checked.
+pub fn ensure_remote_space(server: &ServerConfig, dir: &str, needed: u64) -> Result<(), String> {
+    let space = match quota::remote(server, dir) {
+        Ok(space) => space,
+        Err(_) => return Ok(()),
+    };
+    if let Some(quota) = &space.quota {
+        if quota.remaining() < needed {
+            return Err(t!(
+                "no-quota",
+                dir = dir,
+                needed = HumanBytes(needed),
+                left = HumanBytes(quota.remaining()),
+                limit = HumanBytes(quota.limit())
+            ));
+        }
+    }
+    check(
+        Some(space.available),
+        needed,
+        &t!("server-place", dir = dir),
+    )
+}
@@ -0,0 +1,234 @@
+use crate::{data_dir, redact, unix_now};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+/// Set in queue job processes, whose transfers make way for foreground ones.
+static QUEUED: AtomicBool = AtomicBool::new(false);
+/// The running session, shared by every thread of the process so children
+/// started by parallel workers register with it too.
+static CURRENT: Mutex<Option<SessionHandle>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub pid: u32,
+    /// The rsync/scp/ssh children running for it; parallel chunk uploads
+    /// have several at once.
+    #[serde(default)]
+    pub child_pids: Vec<u32>,
+    pub kind: String,
+    pub source: String,
+    pub destination: String,
+    pub started: u64,
+    pub total_bytes: Option<u64>,
+    pub bytes_done: Option<u64>,
+}
+
+impl Session {
+    /// The processes that should receive pause/cancel signals: the running
+    /// children if there are any, otherwise xfer itself.
+    pub fn signal_pids(&self) -> Vec<u32> {
+        if self.child_pids.is_empty() {
+            vec![self.pid]
+        } else {
+            self.child_pids.clone()
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.signal_pids().into_iter().all(is_stopped)
+    }
+}
+
+/// Shared access to the running session, for progress reporters that run
+/// on their own thread.
+#[derive(Clone)]
+pub struct SessionHandle(Arc<Mutex<Session>>);
+
+impl SessionHandle {
+    fn update(&self, f: impl FnOnce(&mut Session)) {
+        if let Ok(mut session) = self.0.lock() {
+            f(&mut session);
+            write(&session);
+        }
+    }
+
+    pub fn set_progress(&self, bytes_done: u64) {
+        self.update(|session| session.bytes_done = Some(bytes_done));
+    }
+}
+
+/// Removes the session from the registry when the transfer finishes.
+pub struct SessionGuard(SessionHandle);
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        if let Ok(mut current) = CURRENT.lock() {
+            if current
+                .as_ref()
+                .is_some_and(|handle| Arc::ptr_eq(&handle.0, &(self.0).0))
+            {
+                *current = None;
+            }
+        }
+        if let Ok(session) = (self.0).0.lock() {
+            let _ = fs::remove_file(session_path(&session.id));
+        }
+    }
+}
+
+fn active_dir() -> PathBuf {
+    data_dir().join("active")
+}
+
+fn session_path(id: &str) -> PathBuf {
+    active_dir().join(format!("{}.json", id))
+}
+
+fn write(session: &Session) {
+    // The registry is best-effort: a transfer must never fail because
+    // `xfer top` can't see it.
+    if fs::create_dir_all(active_dir()).is_ok() {
+        if let Ok(content) = serde_json::to_string(session) {
+            let _ = fs::write(session_path(&session.id), content);
+        }
+    }
+}
+
+pub fn begin(source: &str, destination: &str, total_bytes: Option<u64>) -> SessionGuard {
+    let pid = std::process::id();
+    let session = Session {
+        id: format!("{}-{}", pid, NEXT_ID.fetch_add(1, Ordering::SeqCst)),
+        pid,
+        child_pids: Vec::new(),
+        kind: if QUEUED.load(Ordering::SeqCst) {
+            "queued"
+        } else {
+            "foreground"
+        }
+        .to_string(),
+        source: redact::redact(source),
+        destination: redact::redact(destination),
+        started: unix_now(),
+        total_bytes,
+        bytes_done: None,
+    };
+    write(&session);
+    let handle = SessionHandle(Arc::new(Mutex::new(session)));
+    if let Ok(mut current) = CURRENT.lock() {
+        *current = Some(handle.clone());
+    }
+    SessionGuard(handle)
+}
+
+pub fn current() -> Option<SessionHandle> {
+    CURRENT.lock().ok().and_then(|current| current.clone())
+}
+
+/// Registers a child the session is waiting on, from whichever thread
+/// started it.
+pub fn add_child(pid: u32) {
+    if let Some(handle) = current() {
+        handle.update(|session| {
+            if !session.child_pids.contains(&pid) {
+                session.child_pids.push(pid);
+            }
+        });
+    }
+}
+
+pub fn remove_child(pid: u32) {
+    if let Some(handle) = current() {
+        handle.update(|session| session.child_pids.retain(|&child| child != pid));
+    }
+}
+
+/// Registers every later session of this process as a queue job's.
+pub fn mark_queued() {
+    QUEUED.store(true, Ordering::SeqCst);
+}
+
+pub fn total_bytes() -> Option<u64> {
+    current().and_then(|handle| handle.0.lock().ok().and_then(|s| s.total_bytes))
+}
+
+pub fn set_progress(bytes_done: u64) {
+    if let Some(handle) = current() {
+        handle.set_progress(bytes_done);
+    }
+}
+
+/// Returns all registered sessions, pruning entries whose process is gone.
+pub fn list() -> Vec<Session> {
+    let mut sessions = Vec::new();
+    let entries = match fs::read_dir(active_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return sessions,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let session = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Session>(&content).ok());
+
+        match session {
+            Some(session) if is_running(session.pid) => sessions.push(session),
+            _ => {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+
+    sessions.sort_by_key(|s| s.started);
+    sessions
+}
+
+pub fn signal(pid: u32, signal: &str) -> Result<(), String> {
+    let status = Command::new("kill")
+        .args([&format!("-{}", signal), &pid.to_string()])
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| format!("Failed to execute kill: {}", e))?;
+
+    if !status.success() {
+        return Err(format!("Could not send SIG{} to process {}", signal, pid));
+    }
+    Ok(())
+}
+
+/// Sends `signal` to each of `pids`, failing if any of them didn't get it.
+pub fn signal_all(pids: &[u32], signal: &str) -> Result<(), String> {
+    let mut result = Ok(());
+    for &pid in pids {
+        if let Err(e) = self::signal(pid, signal) {
+            result = result.and(Err(e));
+        }
+    }
+    result
+}
+
+pub fn is_running(pid: u32) -> bool {
+    signal(pid, "0").is_ok()
+}
+
+pub fn is_stopped(pid: u32) -> bool {
+    Command::new("ps")
+        .args(["-o", "stat=", "-p", &pid.to_string()])
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .trim()
+                .starts_with('T')
+        })
+        .unwrap_or(false)
+}
+
+pub fn elapsed(session: &Session) -> u64 {
+    unix_now().saturating_sub(session.started)
+}
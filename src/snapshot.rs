@@ -0,0 +1,442 @@
+use crate::archive::{self, Compression};
+use crate::history::format_timestamp;
+use crate::location::Location;
+use crate::platform::Platform;
+use crate::preserve::Preserve;
+use crate::unpack::{self, BLOCK};
+use crate::{
+    errors, output, progress, queue, sessions, shell_quote, unix_now, Config, TransferEngine,
+    TransferOptions,
+};
+use colored::*;
+use indicatif::HumanBytes;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Where the manifest goes in the archive, after everything it describes.
+pub const MANIFEST: &str = "./.xfer-snapshot.json";
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    path: String,
+    kind: &'static str,
+    size: u64,
+    mode: String,
+    mtime: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    source: &'a str,
+    host: String,
+    created: u64,
+    files: usize,
+    bytes: u64,
+    entries: &'a [Entry],
+}
+
+#[derive(Debug, Serialize)]
+struct Saved<'a> {
+    archive: &'a str,
+    source: &'a str,
+    files: usize,
+    bytes: u64,
+    archive_bytes: u64,
+}
+
+/// Passes a tar stream through while listing and hashing its entries. The
+/// end-of-archive blocks are held back so the manifest can go before them.
+#[derive(Default)]
+struct Recorder {
+    header: Vec<u8>,
+    /// Data bytes of the current entry still to pass, then its padding.
+    data: u64,
+    padding: u64,
+    hasher: Option<Sha256>,
+    /// The data of a GNU long name ('L'/'K') or pax ('x') entry.
+    collect: Option<(u8, Vec<u8>)>,
+    long_name: Option<String>,
+    long_link: Option<String>,
+    pax_size: Option<u64>,
+    pax_mtime: Option<u64>,
+    entries: Vec<Entry>,
+    ended: bool,
+}
+
+impl Recorder {
+    fn feed(&mut self, mut data: &[u8], out: &mut impl Write) -> io::Result<()> {
+        while !data.is_empty() && !self.ended {
+            if self.data > 0 {
+                let n = (self.data as usize).min(data.len());
+                if let Some((_, collected)) = &mut self.collect {
+                    collected.extend_from_slice(&data[..n]);
+                }
+                if let Some(hasher) = &mut self.hasher {
+                    hasher.update(&data[..n]);
+                }
+                out.write_all(&data[..n])?;
+                self.data -= n as u64;
+                data = &data[n..];
+                if self.data == 0 {
+                    self.entry_done();
+                }
+                continue;
+            }
+            if self.padding > 0 {
+                let n = (self.padding as usize).min(data.len());
+                out.write_all(&data[..n])?;
+                self.padding -= n as u64;
+                data = &data[n..];
+                continue;
+            }
+            let n = (BLOCK - self.header.len()).min(data.len());
+            self.header.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.header.len() == BLOCK {
+                let header = std::mem::take(&mut self.header);
+                if header.iter().all(|&b| b == 0) {
+                    self.ended = true;
+                } else {
+                    out.write_all(&header)?;
+                    self.entry(&header);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(&mut self, header: &[u8]) {
+        let kind = header[156];
+        let mut size = unpack::number(&header[124..136]);
+        if matches!(kind, b'L' | b'K' | b'x' | b'g') {
+            if kind != b'g' {
+                self.collect = Some((kind, Vec::new()));
+            }
+            self.start_data(size);
+            return;
+        }
+        if let Some(pax_size) = self.pax_size.take() {
+            size = pax_size;
+        }
+
+        let name = self.long_name.take().unwrap_or_else(|| {
+            let name = unpack::field(&header[..100]);
+            let prefix = unpack::field(&header[345..500]);
+            if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                format!("{}/{}", prefix, name)
+            } else {
+                name
+            }
+        });
+        let link = self
+            .long_link
+            .take()
+            .unwrap_or_else(|| unpack::field(&header[157..257]));
+        let mtime = self
+            .pax_mtime
+            .take()
+            .unwrap_or_else(|| unpack::number(&header[136..148]));
+        let kind = match kind {
+            b'0' | 0 | b'7' => "file",
+            b'5' => "directory",
+            b'2' => "symlink",
+            b'1' => "hardlink",
+            _ => "other",
+        };
+
+        let path = name.trim_start_matches("./").trim_end_matches('/');
+        if !path.is_empty() && path != "." {
+            self.hasher = (kind == "file").then(Sha256::new);
+            self.entries.push(Entry {
+                path: path.to_string(),
+                kind,
+                size,
+                mode: format!("{:04o}", unpack::number(&header[100..108]) & 0o7777),
+                mtime,
+                sha256: None,
+                link: matches!(kind, "symlink" | "hardlink").then_some(link),
+            });
+        }
+        self.start_data(size);
+    }
+
+    fn start_data(&mut self, size: u64) {
+        self.data = size;
+        self.padding = unpack::padded(size) - size;
+        if size == 0 {
+            self.entry_done();
+        }
+    }
+
+    /// Takes what a long-name or pax entry says about the next one, or the
+    /// hash of the file that just passed.
+    fn entry_done(&mut self) {
+        if let Some((kind, data)) = self.collect.take() {
+            match kind {
+                b'L' => self.long_name = Some(unpack::field(&data)),
+                b'K' => self.long_link = Some(unpack::field(&data)),
+                _ => {
+                    for (key, value) in unpack::pax_records(&data) {
+                        match key.as_str() {
+                            "path" => self.long_name = Some(value),
+                            "linkpath" => self.long_link = Some(value),
+                            "size" => self.pax_size = value.parse().ok(),
+                            "mtime" => {
+                                self.pax_mtime =
+                                    value.split('.').next().and_then(|s| s.parse().ok())
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        } else if let Some(hasher) = self.hasher.take() {
+            if let Some(entry) = self.entries.last_mut() {
+                entry.sha256 = Some(format!("{:x}", hasher.finalize()));
+            }
+        }
+    }
+
+    /// Adds the manifest as the last entry and ends the archive.
+    fn finish(&self, manifest: &[u8], out: &mut impl Write) -> io::Result<()> {
+        if !self.ended {
+            return Err(unpack::stopped(
+                "The tar stream from the server ended early".to_string(),
+            ));
+        }
+        let size = manifest.len() as u64;
+        out.write_all(&header(MANIFEST, size, unix_now()))?;
+        out.write_all(manifest)?;
+        out.write_all(&vec![0; (unpack::padded(size) - size) as usize])?;
+        out.write_all(&[0; BLOCK * 2])
+    }
+}
+
+fn put_octal(block: &mut [u8], start: usize, len: usize, value: u64) {
+    let text = format!("{:0width$o}", value, width = len - 1);
+    block[start..start + len - 1].copy_from_slice(text.as_bytes());
+}
+
+/// A ustar header for a plain file owned by root with mode 0644.
+fn header(name: &str, size: u64, mtime: u64) -> [u8; BLOCK] {
+    let mut block = [0u8; BLOCK];
+    block[..name.len()].copy_from_slice(name.as_bytes());
+    put_octal(&mut block, 100, 8, 0o644);
+    put_octal(&mut block, 108, 8, 0);
+    put_octal(&mut block, 116, 8, 0);
+    put_octal(&mut block, 124, 12, size);
+    put_octal(&mut block, 136, 12, mtime);
+    block[156] = b'0';
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+    // The checksum is taken with its own field as spaces.
+    block[148..156].fill(b' ');
+    let sum: u64 = block.iter().map(|&b| u64::from(b)).sum();
+    block[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+    block
+}
+
+/// Fills in `{date}` (YYYY-MM-DD), `{time}` (HHMMSS) and `{alias}`, in
+/// local time.
+fn expand_name(pattern: &str, alias: &str, now: u64) -> String {
+    let local = format_timestamp((now as i64 + queue::local_offset()).max(0) as u64);
+    pattern
+        .replace("{date}", &local[..10])
+        .replace("{time}", &local[11..].replace(':', ""))
+        .replace("{alias}", alias)
+}
+
+fn compression(dest: &str) -> Result<Option<Compression>, String> {
+    let name = dest.to_ascii_lowercase();
+    if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+        Ok(Some(Compression::Zstd))
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Ok(Some(Compression::Gzip))
+    } else if name.ends_with(".tar") {
+        Ok(None)
+    } else {
+        Err(format!(
+            "A snapshot is saved as .tar, .tar.gz or .tar.zst, not '{}'",
+            dest
+        ))
+    }
+}
+
+/// Streams the remote directory `source` into a new local archive at
+/// `dest`, listing every entry with its SHA-256 in a manifest stored at the
+/// end of the archive. Nothing is written on the server.
+pub fn snapshot(
+    source: &str,
+    dest: &str,
+    opts: &TransferOptions,
+    config: &Config,
+) -> Result<(), String> {
+    let (alias, server, path) = match Location::parse(source, config)? {
+        Location::Remote {
+            alias,
+            server,
+            path,
+        } => (alias, server, path.to_string()),
+        _ => {
+            return Err(format!(
+                "'{}' is not a remote location (alias:/path)",
+                source
+            ))
+        }
+    };
+    if Platform::of(server)?.is_windows() {
+        return Err("snapshot needs a POSIX shell on the server".to_string());
+    }
+    let now = unix_now();
+    let dest = expand_name(dest, &alias, now);
+    let compression = compression(&dest)?;
+    if let (Some(compression), Some(level)) = (compression, opts.level) {
+        if !(1..=compression.max_level()).contains(&level) {
+            return Err(format!(
+                "{} levels go from 1 to {}",
+                compression.program(),
+                compression.max_level()
+            ));
+        }
+    }
+    let dest_path = Path::new(&dest);
+    if dest_path.exists() {
+        return Err(format!("{} already exists", dest));
+    }
+    if let Some(parent) = dest_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    say!(
+        "{} {} to {}",
+        "Snapshotting".green(),
+        source,
+        dest.as_str().cyan()
+    );
+    let _session = sessions::begin(source, &dest, None);
+    let preserved = Preserve::resolve(opts, Some(server))?;
+    let remote_command = format!(
+        "cd {} && tar {} -cf - .",
+        shell_quote(&path),
+        preserved.tar_create_args().join(" ")
+    );
+    let tmp = dest_path.with_file_name(format!(
+        "{}.xfer-part",
+        dest_path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let file =
+        File::create(&tmp).map_err(|e| format!("Failed to create {}: {}", tmp.display(), e))?;
+    let discard = |_: &String| {
+        let _ = fs::remove_file(&tmp);
+    };
+
+    let mut compressor = None;
+    let mut writer: Box<dyn Write> = match compression {
+        Some(compression) => {
+            let mut command = Command::new(compression.program());
+            command
+                .args(compression.compress_args(opts.level))
+                .stdin(Stdio::piped())
+                .stdout(file);
+            let mut running = TransferEngine::spawn_command(compression.program(), command)
+                .inspect_err(discard)?;
+            let stdin = running.child.stdin.take();
+            compressor = Some((compression.program(), running));
+            match stdin {
+                Some(stdin) => Box::new(stdin),
+                None => Box::new(io::sink()),
+            }
+        }
+        None => Box::new(BufWriter::new(file)),
+    };
+    let mut ssh = archive::ssh_command(server, remote_command)?;
+    ssh.stdin(Stdio::null()).stdout(Stdio::piped());
+    let mut running = TransferEngine::spawn_command("ssh", ssh).inspect_err(discard)?;
+    let reader = running.child.stdout.take();
+
+    let mut recorder = Recorder::default();
+    let copied = reader
+        .map(|reader| copy(reader, &mut recorder, &mut writer))
+        .unwrap_or(Ok(()))
+        .and_then(|()| {
+            let manifest = Manifest {
+                source,
+                host: format!("{}@{}", server.user, server.host),
+                created: now,
+                files: recorder.entries.iter().filter(|e| e.kind == "file").count(),
+                bytes: recorder.entries.iter().map(|e| e.size).sum(),
+                entries: &recorder.entries,
+            };
+            let json = serde_json::to_vec_pretty(&manifest)?;
+            recorder.finish(&json, &mut writer)?;
+            writer.flush()
+        });
+    drop(writer);
+
+    let mut failures = Vec::new();
+    for (program, running) in std::iter::once(("ssh", running)).chain(compressor) {
+        let (status, stderr) = TransferEngine::wait(program, running)?;
+        if !status.success() {
+            failures.push(errors::describe(program, status.code(), &stderr));
+        }
+    }
+    let result = match failures.into_iter().next() {
+        Some(failure) => Err(failure),
+        None => copied.map_err(|e| format!("Failed to write {}: {}", dest, e)),
+    };
+    result.inspect_err(discard)?;
+    fs::rename(&tmp, dest_path)
+        .map_err(|e| format!("Failed to move {} into place: {}", tmp.display(), e))?;
+
+    let saved = Saved {
+        archive: &dest,
+        source,
+        files: recorder.entries.iter().filter(|e| e.kind == "file").count(),
+        bytes: recorder.entries.iter().map(|e| e.size).sum(),
+        archive_bytes: fs::metadata(dest_path).map(|m| m.len()).unwrap_or(0),
+    };
+    if output::is_json() {
+        output::emit(&saved);
+    } else {
+        say!(
+            "{} {} files ({}) in {} ({}); the manifest is {}",
+            "Saved".green(),
+            saved.files,
+            HumanBytes(saved.bytes),
+            dest,
+            HumanBytes(saved.archive_bytes),
+            MANIFEST.trim_start_matches("./")
+        );
+    }
+    Ok(())
+}
+
+fn copy(mut reader: impl Read, recorder: &mut Recorder, writer: &mut impl Write) -> io::Result<()> {
+    let bar = progress::counter(None);
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut done = 0;
+    let result = loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break Ok(()),
+            Ok(n) => n,
+            Err(e) => break Err(e),
+        };
+        if let Err(e) = recorder.feed(&buf[..n], writer) {
+            break Err(e);
+        }
+        done += n as u64;
+        bar.set_position(done);
+        sessions::set_progress(done);
+    };
+    bar.finish_and_clear();
+    result
+}
@@ -0,0 +1,401 @@
+use crate::project::{self, Route};
+use crate::{output, parse_rate, shell_quote, Config};
+use colored::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+
+type Values = BTreeMap<&'static str, String>;
+
+/// Something a template asks for. Without a default, it has to be given.
+#[derive(Serialize)]
+struct Param {
+    key: &'static str,
+    question: &'static str,
+    default: Option<&'static str>,
+}
+
+/// A ready-made route for a common job, filled in from a few answers.
+#[derive(Serialize)]
+struct Template {
+    name: &'static str,
+    about: &'static str,
+    params: &'static [Param],
+    /// A crontab schedule that suits the job, if it is one that repeats.
+    schedule: Option<&'static str>,
+    #[serde(skip)]
+    route: fn(&Values) -> Result<Route, String>,
+}
+
+const SERVER: Param = Param {
+    key: "server",
+    question: "Server alias",
+    default: None,
+};
+
+const TEMPLATES: [Template; 4] = [
+    Template {
+        name: "static-site",
+        about: "Deploy a built static site, removing pages that are gone and verifying the upload",
+        params: &[
+            Param {
+                key: "dir",
+                question: "Built site directory",
+                default: Some("public"),
+            },
+            SERVER,
+            Param {
+                key: "path",
+                question: "Web root on the server",
+                default: Some("/var/www/html"),
+            },
+        ],
+        schedule: None,
+        route: |values| {
+            Ok(Route {
+                src: format!("{}/", values["dir"].trim_end_matches('/')),
+                dest: format!("{}:{}", values["server"], values["path"]),
+                verify: true,
+                delete: true,
+                limit: None,
+                max_duration: None,
+                before: None,
+                dry_run_hooks: false,
+                on_failure: None,
+            })
+        },
+    },
+    Template {
+        name: "db-dump",
+        about: "Dump a PostgreSQL or MySQL database and offload the dump to a server",
+        params: &[
+            Param {
+                key: "engine",
+                question: "Database engine (postgres or mysql)",
+                default: Some("postgres"),
+            },
+            Param {
+                key: "database",
+                question: "Database name",
+                default: None,
+            },
+            SERVER,
+            Param {
+                key: "path",
+                question: "Backup directory on the server",
+                default: Some("backups/"),
+            },
+        ],
+        schedule: Some("15 2 * * *"),
+        route: |values| {
+            let database = &values["database"];
+            let (file, dump) = match values["engine"].as_str() {
+                "postgres" => (
+                    format!("{}.dump", database),
+                    format!("pg_dump --format=custom {}", shell_quote(database)),
+                ),
+                "mysql" => (
+                    format!("{}.sql", database),
+                    format!("mysqldump --single-transaction {}", shell_quote(database)),
+                ),
+                other => {
+                    return Err(format!(
+                        "Unknown database engine '{}' (use postgres or mysql)",
+                        other
+                    ))
+                }
+            };
+            Ok(Route {
+                // The answers go to `sh -c`, so they are quoted as words.
+                before: Some(format!("{} > {}", dump, shell_quote(&file))),
+                dry_run_hooks: false,
+                on_failure: None,
+                src: file,
+                dest: format!(
+                    "{}:{}/",
+                    values["server"],
+                    values["path"].trim_end_matches('/')
+                ),
+                verify: true,
+                delete: false,
+                limit: None,
+                max_duration: None,
+            })
+        },
+    },
+    Template {
+        name: "logs",
+        about: "Collect a server's logs into a local directory, fetching only what is new",
+        params: &[
+            SERVER,
+            Param {
+                key: "path",
+                question: "Log directory on the server",
+                default: Some("/var/log"),
+            },
+            Param {
+                key: "dir",
+                question: "Local directory to collect them in",
+                default: Some("logs"),
+            },
+        ],
+        schedule: Some("0 * * * *"),
+        route: |values| {
+            Ok(Route {
+                src: format!(
+                    "{}:{}/",
+                    values["server"],
+                    values["path"].trim_end_matches('/')
+                ),
+                dest: format!("{}/", values["dir"].trim_end_matches('/')),
+                verify: false,
+                delete: false,
+                limit: None,
+                max_duration: None,
+                before: None,
+                dry_run_hooks: false,
+                on_failure: None,
+            })
+        },
+    },
+    Template {
+        name: "photo-backup",
+        about: "Back up a photo library overnight; photos deleted locally stay on the server",
+        params: &[
+            Param {
+                key: "dir",
+                question: "Photo library directory",
+                default: None,
+            },
+            SERVER,
+            Param {
+                key: "path",
+                question: "Backup directory on the server",
+                default: Some("photos"),
+            },
+            Param {
+                key: "limit",
+                question: "Bandwidth cap, e.g. 2M (blank for none)",
+                default: Some(""),
+            },
+        ],
+        schedule: Some("30 1 * * *"),
+        route: |values| {
+            Ok(Route {
+                src: format!("{}/", values["dir"].trim_end_matches('/')),
+                dest: format!("{}:{}", values["server"], values["path"]),
+                verify: true,
+                delete: false,
+                limit: Some(values["limit"].clone()).filter(|limit| !limit.is_empty()),
+                // Stopped before the morning; the next night picks it up.
+                max_duration: Some("6h".to_string()),
+                before: None,
+                dry_run_hooks: false,
+                on_failure: None,
+            })
+        },
+    },
+];
+
+/// `xfer template list`.
+pub fn list() {
+    if output::is_json() {
+        output::emit(&TEMPLATES);
+        return;
+    }
+    for template in &TEMPLATES {
+        say!("{} - {}", template.name.yellow(), template.about);
+        for param in template.params {
+            match param.default {
+                Some(default) if !default.is_empty() => {
+                    say!("    {:<10} {} [{}]", param.key, param.question, default)
+                }
+                _ => say!("    {:<10} {}", param.key, param.question),
+            }
+        }
+    }
+}
+
+fn ask(param: &Param) -> Result<String, String> {
+    match param.default {
+        Some(default) if !default.is_empty() => print!("{} [{}]: ", param.question, default),
+        _ => print!("{}: ", param.question),
+    }
+    let mut answer = String::new();
+    io::stdout()
+        .flush()
+        .and_then(|_| io::stdin().read_line(&mut answer))
+        .map_err(|e| format!("Failed to read the answer: {}", e))?;
+    Ok(answer.trim().to_string())
+}
+
+/// The template's answers: from `--set key=value` first, then asked for on
+/// the terminal, then the defaults.
+fn answers(template: &Template, sets: &[&str], config: &Config) -> Result<Values, String> {
+    let mut given = BTreeMap::new();
+    for set in sets {
+        let (key, value) = set
+            .split_once('=')
+            .ok_or_else(|| format!("--set takes key=value, not '{}'", set))?;
+        let param = template
+            .params
+            .iter()
+            .find(|param| param.key == key)
+            .ok_or_else(|| {
+                let keys: Vec<&str> = template.params.iter().map(|param| param.key).collect();
+                format!(
+                    "{} has no '{}' (it takes {})",
+                    template.name,
+                    key,
+                    keys.join(", ")
+                )
+            })?;
+        given.insert(param.key, value.trim().to_string());
+    }
+
+    let mut values = Values::new();
+    for param in template.params {
+        let value = match given.remove(param.key) {
+            Some(value) => value,
+            None if console::user_attended() => ask(param)?,
+            None => String::new(),
+        };
+        let value = match (value.is_empty(), param.default) {
+            (true, Some(default)) => default.to_string(),
+            (true, None) => {
+                return Err(format!(
+                    "{} needs {} (pass --set {}=...)",
+                    template.name, param.key, param.key
+                ))
+            }
+            (false, _) => value,
+        };
+        match param.key {
+            "server" if config.get_server(&value).is_none() => {
+                return Err(format!(
+                    "No server '{}' in the config (add it with `xfer server add`)",
+                    value
+                ))
+            }
+            "limit" if !value.is_empty() => {
+                parse_rate(&value).map_err(|e| format!("limit: {}", e))?;
+            }
+            _ => {}
+        }
+        values.insert(param.key, value);
+    }
+    Ok(values)
+}
+
+/// `xfer template apply`: fills in a template and adds the route it makes to
+/// the project's `.xfer.toml`, or starts one in the current directory.
+pub fn apply(
+    name: &str,
+    route_name: Option<&str>,
+    sets: &[&str],
+    config: &Config,
+) -> Result<(), String> {
+    let template = TEMPLATES
+        .iter()
+        .find(|template| template.name == name)
+        .ok_or_else(|| {
+            let names: Vec<&str> = TEMPLATES.iter().map(|template| template.name).collect();
+            format!("No template '{}' (templates: {})", name, names.join(", "))
+        })?;
+    let route_name = route_name.unwrap_or(template.name);
+    let path = match &config.project {
+        Some(project) => {
+            if project.routes.contains_key(route_name) {
+                return Err(format!(
+                    "{} already has a route '{}'; pick another name with --name",
+                    project.path.display(),
+                    route_name
+                ));
+            }
+            project.path.clone()
+        }
+        None => env::current_dir()
+            .map_err(|e| format!("Failed to read the current directory: {}", e))?
+            .join(project::FILE_NAME),
+    };
+
+    let values = answers(template, sets, config)?;
+    let route = (template.route)(&values)?;
+    let deletes = route.delete;
+    let mut routes = BTreeMap::new();
+    routes.insert("routes", BTreeMap::from([(route_name, route)]));
+    let entry = toml::to_string(&routes).map_err(|e| format!("Failed to write TOML: {}", e))?;
+
+    // Appended rather than rewritten, so the file keeps its comments.
+    let mut content = fs::read_to_string(&path).unwrap_or_default();
+    if !content.is_empty() {
+        content.push_str(if content.ends_with('\n') {
+            "\n"
+        } else {
+            "\n\n"
+        });
+    }
+    content.push_str(&format!("# From `xfer template apply {}`\n", template.name));
+    content.push_str(&entry);
+    toml::from_str::<toml::Value>(&content)
+        .map_err(|e| format!("Can't add the route to {}: {}", path.display(), e))?;
+    fs::write(&path, &content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    say!(
+        "{} route '{}' to {}:",
+        "Added".green(),
+        route_name.yellow(),
+        path.display()
+    );
+    say!("{}", entry.trim_end());
+    say!();
+    say!("Run it with `xfer run {}`.", route_name);
+    if let Some(schedule) = template.schedule {
+        let dir = path.parent().map(|dir| dir.display().to_string());
+        // cron has no terminal to confirm deletions on.
+        let force = if deletes { "--force " } else { "" };
+        say!("To run it on a schedule, add this line with `crontab -e`:");
+        say!(
+            "    {} cd {} && xfer run {}{}",
+            schedule,
+            dir.unwrap_or_default(),
+            force,
+            route_name
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db_dump(engine: &str, database: &str) -> Route {
+        let template = TEMPLATES.iter().find(|t| t.name == "db-dump").unwrap();
+        let values = Values::from([
+            ("engine", engine.to_string()),
+            ("database", database.to_string()),
+            ("server", "prod".to_string()),
+            ("path", "backups/".to_string()),
+        ]);
+        (template.route)(&values).unwrap()
+    }
+
+    #[test]
+    fn db_dump_quotes_the_answers_it_runs() {
+        assert_eq!(
+            db_dump("postgres", "app").before.unwrap(),
+            "pg_dump --format=custom 'app' > 'app.dump'"
+        );
+        assert_eq!(
+            db_dump("mysql", "x; rm -rf ~").before.unwrap(),
+            "mysqldump --single-transaction 'x; rm -rf ~' > 'x; rm -rf ~.sql'"
+        );
+        assert_eq!(
+            db_dump("postgres", "it's").before.unwrap(),
+            "pg_dump --format=custom 'it'\\''s' > 'it'\\''s.dump'"
+        );
+    }
+}
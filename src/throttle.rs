@@ -0,0 +1,173 @@
+use crate::{parse_rate, TransferOptions};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+/// Load per CPU above which the `load` rule applies, unless it sets `above`.
+const DEFAULT_LOAD: f64 = 1.0;
+
+static ANNOUNCED: AtomicBool = AtomicBool::new(false);
+
+/// How to hold back while the machine is on battery, on a metered network
+/// or busy. Each rule caps the rate and the number of parallel streams;
+/// when several apply, the tightest cap wins.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Throttle {
+    pub battery: Option<Rule>,
+    pub metered: Option<Rule>,
+    pub load: Option<Rule>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Rule {
+    /// For `load`: the 1-minute load average per CPU that triggers it.
+    pub above: Option<f64>,
+    pub limit: Option<String>,
+    pub parallel: Option<usize>,
+}
+
+/// Whether the machine runs on battery right now.
+fn on_battery() -> bool {
+    if cfg!(target_os = "macos") {
+        return Command::new("pmset")
+            .args(["-g", "batt"])
+            .output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).contains("'Battery Power'"))
+            .unwrap_or(false);
+    }
+
+    let supplies = match fs::read_dir("/sys/class/power_supply") {
+        Ok(supplies) => supplies,
+        Err(_) => return false,
+    };
+    let read = |path: &std::path::Path, name: &str| {
+        fs::read_to_string(path.join(name))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default()
+    };
+    let mut discharging = false;
+    for supply in supplies.flatten() {
+        let path = supply.path();
+        match read(&path, "type").as_str() {
+            "Mains" if read(&path, "online") == "1" => return false,
+            "Battery" if read(&path, "status") == "Discharging" => discharging = true,
+            _ => {}
+        }
+    }
+    discharging
+}
+
+/// Whether NetworkManager considers the connection metered. Elsewhere there
+/// is no reliable way to tell, so the answer is no.
+fn metered() -> bool {
+    let output = Command::new("busctl")
+        .args([
+            "get-property",
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            "org.freedesktop.NetworkManager",
+            "Metered",
+        ])
+        .output();
+    match output {
+        // NMMetered: 1 is "yes", 3 is "guessed yes".
+        Ok(o) if o.status.success() => {
+            matches!(String::from_utf8_lossy(&o.stdout).trim(), "u 1" | "u 3")
+        }
+        _ => false,
+    }
+}
+
+/// The 1-minute load average divided by the number of CPUs.
+fn load_per_cpu() -> Option<f64> {
+    let loadavg = match fs::read_to_string("/proc/loadavg") {
+        Ok(loadavg) => loadavg,
+        Err(_) => {
+            let output = Command::new("sysctl")
+                .args(["-n", "vm.loadavg"])
+                .output()
+                .ok()?;
+            String::from_utf8_lossy(&output.stdout).replace(['{', '}'], " ")
+        }
+    };
+    let load: f64 = loadavg.split_whitespace().next()?.parse().ok()?;
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    Some(load / cpus as f64)
+}
+
+/// The rules that apply right now, each with the reason why.
+fn active(throttle: &Throttle) -> Vec<(String, &Rule)> {
+    let mut rules = Vec::new();
+    if let Some(rule) = &throttle.battery {
+        if on_battery() {
+            rules.push(("on battery".to_string(), rule));
+        }
+    }
+    if let Some(rule) = &throttle.metered {
+        if metered() {
+            rules.push(("metered connection".to_string(), rule));
+        }
+    }
+    if let Some(rule) = &throttle.load {
+        if let Some(load) = load_per_cpu() {
+            if load > rule.above.unwrap_or(DEFAULT_LOAD) {
+                rules.push((format!("load {:.2} per CPU", load), rule));
+            }
+        }
+    }
+    rules
+}
+
+fn tighter<T: Ord>(current: Option<T>, cap: Option<T>) -> Option<T> {
+    match (current, cap) {
+        (Some(current), Some(cap)) => Some(current.min(cap)),
+        (current, cap) => current.or(cap),
+    }
+}
+
+/// Tightens `opts` by whatever rules of `throttle` apply right now. The
+/// first throttled transfer of a run says why.
+pub fn adjust(opts: &mut TransferOptions, throttle: Option<&Throttle>) -> Result<(), String> {
+    let throttle = match throttle {
+        Some(throttle) if !opts.no_throttle => throttle,
+        _ => return Ok(()),
+    };
+    let rules = active(throttle);
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let mut reasons = Vec::new();
+    for (reason, rule) in rules {
+        let limit = rule
+            .limit
+            .as_deref()
+            .map(parse_rate)
+            .transpose()
+            .map_err(|e| format!("throttle limit: {}", e))?;
+        opts.bwlimit = tighter(opts.bwlimit, limit);
+        if let Some(parallel) = rule.parallel {
+            let parallel = parallel.max(1);
+            opts.parallel = opts.parallel.map(|n| n.min(parallel));
+            opts.max_parallel = Some(opts.max_parallel.map_or(parallel, |n| n.min(parallel)));
+        }
+        reasons.push(reason);
+    }
+
+    if !ANNOUNCED.swap(true, Ordering::SeqCst) {
+        let rate = opts
+            .bwlimit
+            .map(|rate| format!("{}/s", indicatif::HumanBytes(rate)))
+            .unwrap_or_else(|| "unlimited".to_string());
+        say!(
+            "{} {} ({}; --no-throttle to override)",
+            "Throttled:".yellow(),
+            rate,
+            reasons.join(", ")
+        );
+    }
+    Ok(())
+}
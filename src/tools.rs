@@ -0,0 +1,79 @@
+use std::env;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The external programs xfer runs. Which of them this machine has is
+/// looked up in PATH once at startup, so a missing one is worked around or
+/// named up front instead of failing with an exit code.
+const TOOLS: [&str; 9] = [
+    "ssh",
+    "scp",
+    "rsync",
+    "tar",
+    "gzip",
+    "zstd",
+    "age",
+    "pinentry",
+    "ssh-keygen",
+];
+
+static FOUND: OnceLock<Vec<&'static str>> = OnceLock::new();
+
+fn found() -> &'static [&'static str] {
+    FOUND.get_or_init(|| TOOLS.into_iter().filter(|tool| on_path(tool)).collect())
+}
+
+/// Looks the tools up; called once at startup.
+pub fn detect() {
+    found();
+}
+
+/// Whether `tool` is available. Programs outside `TOOLS` are assumed to be.
+pub fn has(tool: &str) -> bool {
+    !TOOLS.contains(&tool) || found().contains(&tool)
+}
+
+/// Fails with a plain explanation when `tool` is missing.
+pub fn require(tool: &str) -> Result<(), String> {
+    if has(tool) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} is not installed on this machine (or not in PATH)",
+            tool
+        ))
+    }
+}
+
+fn on_path(tool: &str) -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+    // Windows finds `rsync` as rsync.exe, rsync.cmd and so on.
+    let extensions: Vec<String> = if cfg!(windows) {
+        env::var("PATHEXT")
+            .unwrap_or_else(|_| ".EXE;.CMD;.BAT;.COM".to_string())
+            .split(';')
+            .map(str::to_string)
+            .collect()
+    } else {
+        vec![String::new()]
+    };
+    env::split_paths(&path).any(|dir| {
+        extensions
+            .iter()
+            .any(|ext| executable(&dir.join(format!("{}{}", tool, ext))))
+    })
+}
+
+#[cfg(unix)]
+fn executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn executable(path: &Path) -> bool {
+    path.is_file()
+}
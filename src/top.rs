@@ -0,0 +1,239 @@
+use crate::progress;
+use crate::sessions::{self, Session};
+use colored::*;
+use console::{truncate_str, Key, Term};
+use indicatif::HumanBytes;
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const REFRESH: Duration = Duration::from_secs(1);
+
+fn format_duration(secs: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+struct Rates {
+    samples: HashMap<String, (Instant, u64)>,
+    rates: HashMap<String, f64>,
+}
+
+impl Rates {
+    fn update(&mut self, sessions: &[Session]) {
+        let now = Instant::now();
+        for session in sessions {
+            let Some(done) = session.bytes_done else {
+                continue;
+            };
+            if let Some((at, prev)) = self.samples.get(&session.id) {
+                let secs = now.duration_since(*at).as_secs_f64();
+                if secs > 0.0 {
+                    let rate = done.saturating_sub(*prev) as f64 / secs;
+                    self.rates.insert(session.id.clone(), rate);
+                }
+            }
+            self.samples.insert(session.id.clone(), (now, done));
+        }
+        self.samples
+            .retain(|id, _| sessions.iter().any(|s| &s.id == id));
+        self.rates
+            .retain(|id, _| sessions.iter().any(|s| &s.id == id));
+    }
+}
+
+fn render(
+    term: &Term,
+    sessions: &[Session],
+    selected: usize,
+    rates: &Rates,
+    status: &str,
+) -> std::io::Result<()> {
+    let mut lines = vec![
+        format!(
+            "{} — {} active transfer(s)",
+            "xfer top".green().bold(),
+            sessions.len()
+        ),
+        "[↑/↓] select  [p] pause/resume  [c] cancel  [q] quit"
+            .dimmed()
+            .to_string(),
+        String::new(),
+    ];
+    lines.extend(table(
+        sessions,
+        Some(selected),
+        rates,
+        term.size().1 as usize,
+    ));
+    lines.push(String::new());
+    lines.push(status.to_string());
+
+    term.clear_screen()?;
+    term.write_line(&lines.join("\n"))
+}
+
+/// The transfer table, one row per session. `selected` marks a row with `>`.
+fn table(
+    sessions: &[Session],
+    selected: Option<usize>,
+    rates: &Rates,
+    width: usize,
+) -> Vec<String> {
+    let mut lines = vec![format!(
+        "  {:<8} {:<10} {:<8} {:<9} {:<23} {:<12} {:<9} TRANSFER",
+        "PID", "KIND", "STATE", "ELAPSED", "TRANSFERRED", "RATE", "ETA"
+    )
+    .bold()
+    .to_string()];
+
+    if sessions.is_empty() {
+        lines.push("  No transfers running.".dimmed().to_string());
+    }
+
+    for (i, session) in sessions.iter().enumerate() {
+        let state = if session.is_paused() {
+            "paused"
+        } else {
+            "running"
+        };
+        let transferred = match (session.bytes_done, session.total_bytes) {
+            (Some(done), Some(total)) => format!("{}/{}", HumanBytes(done), HumanBytes(total)),
+            (Some(done), None) => HumanBytes(done).to_string(),
+            (None, Some(total)) => format!("?/{}", HumanBytes(total)),
+            (None, None) => "-".to_string(),
+        };
+        let rate = rates.rates.get(&session.id).copied();
+        let eta = match (rate, session.bytes_done, session.total_bytes) {
+            (Some(rate), Some(done), Some(total)) if rate > 0.0 => {
+                format_duration((total.saturating_sub(done) as f64 / rate) as u64)
+            }
+            _ => "-".to_string(),
+        };
+        let rate = rate
+            .map(|r| format!("{}/s", HumanBytes(r as u64)))
+            .unwrap_or_else(|| "-".to_string());
+
+        let row = format!(
+            "{} {:<8} {:<10} {:<8} {:<9} {:<23} {:<12} {:<9} {} -> {}",
+            if Some(i) == selected { ">" } else { " " },
+            session.pid,
+            session.kind,
+            state,
+            format_duration(sessions::elapsed(session)),
+            transferred,
+            rate,
+            eta,
+            session.source,
+            session.destination
+        );
+        let row = truncate_str(&row, width, "…").to_string();
+        lines.push(if Some(i) == selected {
+            row.reversed().to_string()
+        } else {
+            row
+        });
+    }
+    lines
+}
+
+/// A one-off listing for `--plain-output`, where a live screen that keeps
+/// redrawing is no use.
+fn snapshot() {
+    let sessions = sessions::list();
+    let rates = Rates {
+        samples: HashMap::new(),
+        rates: HashMap::new(),
+    };
+    say!("{} active transfer(s)", sessions.len());
+    for line in table(&sessions, None, &rates, usize::MAX) {
+        say!("{}", line);
+    }
+}
+
+pub fn run() -> Result<(), String> {
+    if progress::is_plain() {
+        snapshot();
+        return Ok(());
+    }
+
+    let term = Term::stdout();
+    if !term.is_term() {
+        return Err("xfer top needs an interactive terminal".to_string());
+    }
+
+    let (tx, rx) = channel();
+    let key_term = term.clone();
+    thread::spawn(move || {
+        // Stop reading once the user quits so the terminal isn't left in
+        // raw mode by a pending read when the process exits.
+        while let Ok(key) = key_term.read_key() {
+            let quit = matches!(key, Key::Char('q') | Key::Escape);
+            if tx.send(key).is_err() || quit {
+                break;
+            }
+        }
+    });
+
+    let mut selected = 0;
+    let mut status = String::new();
+    let mut rates = Rates {
+        samples: HashMap::new(),
+        rates: HashMap::new(),
+    };
+    let _ = term.hide_cursor();
+
+    loop {
+        let sessions = sessions::list();
+        rates.update(&sessions);
+        selected = selected.min(sessions.len().saturating_sub(1));
+        render(&term, &sessions, selected, &rates, &status).map_err(|e| e.to_string())?;
+
+        let key = match rx.recv_timeout(REFRESH) {
+            Ok(key) => key,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        match key {
+            Key::Char('q') | Key::Escape => break,
+            Key::ArrowUp | Key::Char('k') => selected = selected.saturating_sub(1),
+            Key::ArrowDown | Key::Char('j') => selected += 1,
+            Key::Char('p') => {
+                if let Some(session) = sessions.get(selected) {
+                    let (signal, verb) = if session.is_paused() {
+                        ("CONT", "Resumed")
+                    } else {
+                        ("STOP", "Paused")
+                    };
+                    status = match sessions::signal_all(&session.signal_pids(), signal) {
+                        Ok(()) => format!("{} transfer {}", verb, session.pid),
+                        Err(e) => e.red().to_string(),
+                    };
+                }
+            }
+            Key::Char('c') => {
+                if let Some(session) = sessions.get(selected) {
+                    let pids = session.signal_pids();
+                    // A stopped process won't act on SIGTERM until resumed.
+                    status = match sessions::signal_all(&pids, "TERM")
+                        .and_then(|_| sessions::signal_all(&pids, "CONT"))
+                    {
+                        Ok(()) => format!("Cancelled transfer {}", session.pid),
+                        Err(e) => e.red().to_string(),
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let _ = term.show_cursor();
+    let _ = term.clear_screen();
+    Ok(())
+}
@@ -0,0 +1,299 @@
+use crate::archive::{self, Compression, Sink};
+use crate::platform::Platform;
+use crate::{shell_quote, ServerConfig, TransferEngine, TransferOptions};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+pub const BLOCK: usize = 512;
+
+enum Format {
+    Tar(Option<Compression>),
+    Zip,
+}
+
+fn format(remote: &str) -> Result<Format, String> {
+    let name = remote.to_ascii_lowercase();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Ok(Format::Tar(Some(Compression::Gzip)))
+    } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+        Ok(Format::Tar(Some(Compression::Zstd)))
+    } else if name.ends_with(".tar") {
+        Ok(Format::Tar(None))
+    } else if name.ends_with(".zip") {
+        Ok(Format::Zip)
+    } else {
+        Err(format!(
+            "--unpack takes a .tar, .tar.gz, .tgz, .tar.zst or .zip file, not '{}'",
+            remote
+        ))
+    }
+}
+
+/// libarchive's tar, which can read a zip as a stream: `bsdtar`, or plain
+/// `tar` where that is it (macOS, the BSDs).
+fn bsdtar() -> Option<&'static str> {
+    ["bsdtar", "tar"].into_iter().find(|program| {
+        Command::new(program)
+            .arg("--version")
+            .output()
+            .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).contains("bsdtar"))
+    })
+}
+
+/// Where a `/`-separated path ends up relative to the destination, or
+/// `None` when it climbs out of it.
+fn normalize(path: &str) -> Option<Vec<&str>> {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts)
+}
+
+/// Reads a tar stream as it passes and stops it at the first entry that
+/// would be written outside the destination: absolute names, `..`, links
+/// pointing out, and anything placed beneath a symlink the archive made.
+#[derive(Default)]
+pub struct TarCheck {
+    header: Vec<u8>,
+    /// Data bytes of the current entry still to pass, padding included.
+    skip: u64,
+    /// The data of a GNU long name ('L'/'K') or pax ('x') entry.
+    collect: Option<(u8, Vec<u8>, u64)>,
+    long_name: Option<String>,
+    long_link: Option<String>,
+    ended: bool,
+    symlinks: HashSet<String>,
+}
+
+/// A NUL-terminated header field.
+pub fn field(block: &[u8]) -> String {
+    let end = block.iter().position(|&b| b == 0).unwrap_or(block.len());
+    String::from_utf8_lossy(&block[..end]).into_owned()
+}
+
+/// A numeric header field: octal, or base-256 when the top bit is set.
+pub fn number(block: &[u8]) -> u64 {
+    if block.first().is_some_and(|&b| b & 0x80 != 0) {
+        return block[1..]
+            .iter()
+            .fold(u64::from(block[0] & 0x7f), |n, &b| (n << 8) | u64::from(b));
+    }
+    let text = field(block);
+    u64::from_str_radix(text.trim_matches([' ', '\0']), 8).unwrap_or(0)
+}
+
+/// `size` rounded up to whole blocks.
+pub fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK as u64) * BLOCK as u64
+}
+
+/// The key/value pairs of a pax extended header, stored as records of
+/// "<length> <key>=<value>\n".
+pub fn pax_records(data: &[u8]) -> Vec<(String, String)> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|&b| b == b' ') {
+        let len: usize = match std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|n| n.parse().ok())
+        {
+            Some(len) if len > space && len <= rest.len() => len,
+            _ => break,
+        };
+        let record = String::from_utf8_lossy(&rest[space + 1..len - 1]).into_owned();
+        if let Some((key, value)) = record.split_once('=') {
+            records.push((key.to_string(), value.to_string()));
+        }
+        rest = &rest[len..];
+    }
+    records
+}
+
+impl TarCheck {
+    /// Checks the next piece of the stream.
+    pub fn feed(&mut self, mut data: &[u8]) -> Result<(), String> {
+        while !data.is_empty() && !self.ended {
+            if let Some((_, collected, remaining)) = &mut self.collect {
+                let n = (*remaining as usize).min(data.len());
+                collected.extend_from_slice(&data[..n]);
+                *remaining -= n as u64;
+                data = &data[n..];
+                if *remaining == 0 {
+                    let (kind, collected, _) = self.collect.take().unwrap();
+                    self.extended(kind, &collected);
+                }
+                continue;
+            }
+            if self.skip > 0 {
+                let n = (self.skip as usize).min(data.len());
+                self.skip -= n as u64;
+                data = &data[n..];
+                continue;
+            }
+            let n = (BLOCK - self.header.len()).min(data.len());
+            self.header.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.header.len() == BLOCK {
+                let header = std::mem::take(&mut self.header);
+                self.entry(&header)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the name or link a long-name or pax entry carries for the
+    /// entry after it.
+    fn extended(&mut self, kind: u8, data: &[u8]) {
+        match kind {
+            b'L' => self.long_name = Some(field(data)),
+            b'K' => self.long_link = Some(field(data)),
+            _ => {
+                for (key, value) in pax_records(data) {
+                    match key.as_str() {
+                        "path" => self.long_name = Some(value),
+                        "linkpath" => self.long_link = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn entry(&mut self, header: &[u8]) -> Result<(), String> {
+        if header.iter().all(|&b| b == 0) {
+            self.ended = true;
+            return Ok(());
+        }
+        let size = number(&header[124..136]);
+        let kind = header[156];
+        if matches!(kind, b'L' | b'K' | b'x') {
+            self.collect = Some((kind, Vec::new(), size));
+            self.skip = padded(size) - size;
+            return Ok(());
+        }
+        self.skip = padded(size);
+        if kind == b'g' {
+            return Ok(());
+        }
+
+        let name = self.long_name.take().unwrap_or_else(|| {
+            let name = field(&header[..100]);
+            let prefix = field(&header[345..500]);
+            if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                format!("{}/{}", prefix, name)
+            } else {
+                name
+            }
+        });
+        let link = self
+            .long_link
+            .take()
+            .unwrap_or_else(|| field(&header[157..257]));
+
+        let unsafe_entry = |reason: &str| Err(format!("Refusing to unpack '{}': {}", name, reason));
+        if name.starts_with('/') || name.split('/').any(|part| part == "..") {
+            return unsafe_entry("it would land outside the destination");
+        }
+        let parts = normalize(&name).unwrap_or_default();
+        for depth in 1..parts.len() {
+            if self.symlinks.contains(&parts[..depth].join("/")) {
+                return unsafe_entry("it would be written through a symlink");
+            }
+        }
+
+        match kind {
+            b'2' => {
+                let dir = &parts[..parts.len().saturating_sub(1)];
+                if link.starts_with('/')
+                    || normalize(&format!("{}/{}", dir.join("/"), link)).is_none()
+                {
+                    return unsafe_entry(&format!("it links outside the destination ({})", link));
+                }
+                self.symlinks.insert(parts.join("/"));
+            }
+            b'1' if link.starts_with('/') || normalize(&link).is_none() => {
+                return unsafe_entry(&format!("it links outside the destination ({})", link));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Streams a remote archive into the local directory `local`, unpacking it
+/// on the way so the archive itself is never stored. A zip is read with
+/// libarchive's tar and passes through as tar, so the same checks apply.
+pub fn unpack(
+    server: &ServerConfig,
+    remote: &str,
+    local: &str,
+    opts: &TransferOptions,
+) -> Result<(), String> {
+    if Platform::of(server)?.is_windows() {
+        return Err("--unpack needs a POSIX shell on the server".to_string());
+    }
+    let format = format(remote)?;
+    if Path::new(local).is_file() {
+        return Err(format!(
+            "--unpack needs a directory, and {} is a file",
+            local
+        ));
+    }
+
+    let mut from = vec![(
+        "ssh",
+        archive::ssh_command(server, format!("cat {}", shell_quote(remote)))?,
+    )];
+    match format {
+        Format::Tar(Some(compression)) => {
+            let mut decompress = Command::new(compression.program());
+            decompress.arg("-dc");
+            from.push((compression.program(), decompress));
+        }
+        Format::Tar(None) => {}
+        Format::Zip => {
+            let program = bsdtar().ok_or_else(|| {
+                "Unpacking a .zip as it streams needs bsdtar (libarchive)".to_string()
+            })?;
+            let mut convert = Command::new(program);
+            convert.args(["-cf", "-", "@-"]);
+            from.push(("bsdtar", convert));
+        }
+    }
+
+    fs::create_dir_all(local).map_err(|e| format!("Failed to create {}: {}", local, e))?;
+    let mut tar = Command::new("tar");
+    tar.args(opts.preserved.tar_extract_args())
+        .args(["-C", local, "-xf", "-"]);
+
+    let size =
+        TransferEngine::remote_command(server, &format!("wc -c < {}", shell_quote(remote)), None)
+            .ok()
+            .and_then(|out| out.trim().parse::<u64>().ok());
+    if let Some(size) = size {
+        crate::scratch::ensure_local_space(Path::new(local), size)?;
+    }
+
+    let mut check = TarCheck::default();
+    archive::pipe(
+        from,
+        Sink::Commands(vec![("tar", tar)]),
+        None,
+        Some(&mut check),
+    )
+}
+
+/// The error a failed check stops the stream with.
+pub fn stopped(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
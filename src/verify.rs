@@ -0,0 +1,208 @@
+use crate::platform::Platform;
+use crate::{cache_dir, helper, location, shell_quote, ServerConfig, TransferEngine};
+use colored::*;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Shell snippet that picks whichever SHA-256 tool the server has.
+pub const REMOTE_SHA256: &str =
+    "if command -v sha256sum >/dev/null 2>&1; then H=sha256sum; else H='shasum -a 256'; fi";
+
+pub fn hash_file(path: &Path) -> Result<String, String> {
+    let mut file =
+        File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hashes a file (keyed by "") or every regular file under a directory
+/// (keyed by path relative to it).
+pub fn local_hashes(path: &Path) -> Result<BTreeMap<String, String>, String> {
+    let mut hashes = BTreeMap::new();
+    if path.is_dir() {
+        walk(path, path, &mut hashes)?;
+    } else {
+        hashes.insert(String::new(), hash_file(path)?);
+    }
+    Ok(hashes)
+}
+
+fn walk(root: &Path, dir: &Path, hashes: &mut BTreeMap<String, String>) -> Result<(), String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        if file_type.is_dir() {
+            walk(root, &path, hashes)?;
+        } else if file_type.is_file() {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            hashes.insert(relative.to_string_lossy().into_owned(), hash_file(&path)?);
+        }
+    }
+    Ok(())
+}
+
+/// Hashes the remote counterpart of `local`. When `local` is a file and the
+/// remote path is a directory, the file is looked up inside it by name.
+pub fn remote_hashes(
+    server: &ServerConfig,
+    remote: &str,
+    local: &Path,
+) -> Result<BTreeMap<String, String>, String> {
+    if Platform::of(server)?.is_windows() {
+        return Err(t!("verify-windows"));
+    }
+
+    let mut hashes = BTreeMap::new();
+
+    if local.is_dir() && helper::supports(server, "hash-cached") {
+        return cached_remote_hashes(server, remote);
+    }
+
+    if local.is_dir() {
+        let command = format!(
+            "{}; cd {} 2>/dev/null && find . -type f -exec $H {{}} + || true",
+            REMOTE_SHA256,
+            shell_quote(remote)
+        );
+        let output = TransferEngine::remote_command(server, &command, None)?;
+        for line in output.lines() {
+            if let Some((hash, path)) = line.split_once("  ") {
+                let path = location::received_name(path.strip_prefix("./").unwrap_or(path))?;
+                hashes.insert(path.to_string_lossy().into_owned(), hash.to_string());
+            }
+        }
+    } else {
+        let name = local
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let command = format!(
+            "{}; p={}; [ -d \"$p\" ] && p=\"$p\"/{}; $H \"$p\" 2>/dev/null || true",
+            REMOTE_SHA256,
+            shell_quote(remote),
+            shell_quote(&name)
+        );
+        let output = TransferEngine::remote_command(server, &command, None)?;
+        if let Some(hash) = output.split_whitespace().next() {
+            hashes.insert(String::new(), hash.to_string());
+        }
+    }
+
+    Ok(hashes)
+}
+
+fn hash_cache_path(server: &ServerConfig, remote: &str) -> PathBuf {
+    let key = format!(
+        "{}@{}:{}:{}",
+        server.user,
+        server.host,
+        server.port.unwrap_or(22),
+        remote
+    );
+    let digest = format!("{:x}", Sha256::digest(key.as_bytes()));
+    cache_dir().join("hashes").join(&digest[..16])
+}
+
+/// Hashes a remote tree in one round trip through the helper. The previous
+/// result is sent along so the helper only rehashes files whose size or
+/// mtime changed since then.
+fn cached_remote_hashes(
+    server: &ServerConfig,
+    remote: &str,
+) -> Result<BTreeMap<String, String>, String> {
+    let cache_path = hash_cache_path(server, remote);
+    let previous = fs::read_to_string(&cache_path).unwrap_or_default();
+
+    let command = format!(
+        "{} hash-cached {}",
+        helper::REMOTE_PATH,
+        shell_quote(remote)
+    );
+    let output = TransferEngine::remote_command(server, &command, Some(previous.as_bytes()))?;
+
+    if let Some(dir) = cache_path.parent() {
+        if fs::create_dir_all(dir).is_ok() {
+            let _ = fs::write(&cache_path, &output);
+        }
+    }
+
+    let mut hashes = BTreeMap::new();
+    for line in output.lines() {
+        let mut fields = line.splitn(4, ' ');
+        if let (Some(_size), Some(_mtime), Some(hash), Some(path)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        {
+            let path = location::received_name(path.strip_prefix("./").unwrap_or(path))?;
+            hashes.insert(path.to_string_lossy().into_owned(), hash.to_string());
+        }
+    }
+    Ok(hashes)
+}
+
+/// Where a downloaded file ends up locally: inside `dest` if it is a
+/// directory, otherwise `dest` itself.
+pub fn local_target(dest: &str, remote_src: &str) -> PathBuf {
+    let dest = Path::new(dest);
+    if dest.is_dir() {
+        let name = Path::new(remote_src).file_name().unwrap_or_default();
+        dest.join(name)
+    } else {
+        dest.to_path_buf()
+    }
+}
+
+pub fn compare(
+    local: &BTreeMap<String, String>,
+    remote: &BTreeMap<String, String>,
+) -> Result<usize, String> {
+    let mut mismatched = 0;
+    let mut missing = 0;
+
+    for (path, hash) in local {
+        let shown = if path.is_empty() { "(file)" } else { path };
+        match remote.get(path) {
+            Some(remote_hash) if remote_hash == hash => {}
+            Some(_) => {
+                mismatched += 1;
+                eprintln!("  {} {}", "MISMATCH".red().bold(), shown);
+            }
+            None => {
+                missing += 1;
+                eprintln!("  {} {}", "MISSING".red().bold(), shown);
+            }
+        }
+    }
+
+    if mismatched + missing > 0 {
+        return Err(t!(
+            "verify-failed",
+            mismatched = mismatched,
+            missing = missing
+        ));
+    }
+    Ok(local.len())
+}
+
+pub fn verify(local: &Path, server: &ServerConfig, remote: &str) -> Result<(), String> {
+    if !local.exists() {
+        return Err(t!("local-missing", path = local.display()));
+    }
+
+    let local_hashes = local_hashes(local)?;
+    let remote_hashes = remote_hashes(server, remote, local)?;
+    let count = compare(&local_hashes, &remote_hashes)?;
+
+    say!("{} {} file(s) match (SHA-256)", "Verified".green(), count);
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use crate::location::Location;
+use crate::{Config, TransferEngine, TransferOptions};
+use colored::*;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+pub fn watch(
+    src: &str,
+    dest: &str,
+    config: &Config,
+    opts: &TransferOptions,
+    debounce: Duration,
+) -> Result<(), String> {
+    if !Path::new(src).is_dir() {
+        return Err(format!("'{}' is not a directory", src));
+    }
+
+    if Location::parse(dest, config)?.is_local() {
+        return Err("Watch destination must be a remote location (alias:/path)".to_string());
+    }
+
+    let (tx, rx) = channel();
+    let mut watcher =
+        notify::recommended_watcher(tx).map_err(|e| format!("Failed to start watcher: {}", e))?;
+    watcher
+        .watch(Path::new(src), RecursiveMode::Recursive)
+        .map_err(|e| format!("Failed to watch '{}': {}", src, e))?;
+
+    say!("{} {} {} {}", "Watching".green(), src, "->".green(), dest);
+    TransferEngine::send_recorded(src, dest, config, opts)?;
+
+    loop {
+        let mut changed = 0;
+
+        // Block until something happens, then keep draining until the
+        // directory has been quiet for the whole debounce window.
+        let mut timeout = None;
+        loop {
+            let event = match timeout {
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                Some(t) => rx.recv_timeout(t),
+            };
+
+            match event {
+                Ok(Ok(event)) => {
+                    if !matches!(event.kind, EventKind::Access(_)) {
+                        changed += event.paths.len();
+                        timeout = Some(debounce);
+                    }
+                }
+                Ok(Err(e)) => eprintln!("{}: {}", "Watch error".yellow(), e),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err("Filesystem watcher stopped unexpectedly".to_string())
+                }
+            }
+        }
+
+        say!(
+            "{} {} change(s), syncing to {}",
+            "Detected".green(),
+            changed,
+            dest
+        );
+        if let Err(e) = TransferEngine::send_recorded(src, dest, config, opts) {
+            eprintln!("{}: {}", t!("error").red().bold(), e);
+        }
+    }
+}
@@ -0,0 +1,801 @@
+use crate::preserve::Preserve;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP64_END: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const END: u32 = 0x0605_4b50;
+
+/// Sizes are known only after the data (flag bit 3), and names are UTF-8
+/// (bit 11).
+const FLAG_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// Version 4.5 brings Zip64; 2.0 covers deflate and directories.
+const VERSION_ZIP64: u16 = 45;
+const VERSION_DEFAULT: u16 = 20;
+/// "Made by" Unix, so readers take the mode from the external attributes.
+const MADE_BY_UNIX: u16 = 3 << 8;
+const ZIP64_EXTRA: u16 = 0x0001;
+const TIMESTAMP_EXTRA: u16 = 0x5455;
+/// Above this a file might outgrow 32-bit sizes by the time it is read
+/// (or deflated), so it gets Zip64 fields from the start.
+const ZIP64_THRESHOLD: u64 = 0xffff_0000;
+const MAX32: u64 = 0xffff_ffff;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Store,
+    Deflate(Option<u32>),
+}
+
+impl Method {
+    /// The method for `--compress` (deflate unless "store") and `--level`.
+    pub fn parse(compress: Option<&str>, level: Option<u32>) -> Result<Method, String> {
+        match compress {
+            None | Some("deflate") => match level {
+                Some(level) if !(1..=9).contains(&level) => {
+                    Err("deflate levels go from 1 to 9".to_string())
+                }
+                level => Ok(Method::Deflate(level)),
+            },
+            Some("store") if level.is_some() => {
+                Err("--level doesn't apply to a stored zip".to_string())
+            }
+            Some("store") => Ok(Method::Store),
+            Some(other) => Err(format!(
+                "A zip archive is compressed with deflate or store, not {}",
+                other
+            )),
+        }
+    }
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC_TABLE: [u32; 256] = crc_table();
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, &b| {
+        CRC_TABLE[((crc ^ u32::from(b)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Copies `reader` into `out` as it is, computing the CRC-32 on the way.
+/// `on_chunk` gets the bytes copied so far. Returns the CRC and the size.
+fn store<W: Write>(
+    mut reader: File,
+    out: &mut Counted<W>,
+    on_chunk: &mut dyn FnMut(u64),
+) -> io::Result<(u32, u64)> {
+    let mut buf = vec![0u8; 64 * 1024];
+    let (mut crc, mut size) = (0, 0);
+    loop {
+        match reader.read(&mut buf)? {
+            0 => return Ok((crc, size)),
+            n => {
+                out.write_all(&buf[..n])?;
+                crc = crc32(crc, &buf[..n]);
+                size += n as u64;
+                on_chunk(size);
+            }
+        }
+    }
+}
+
+/// MS-DOS time and date fields, in UTC; the extended timestamp carries the
+/// exact time for readers that look.
+fn dos_time(mtime: u64) -> (u16, u16) {
+    // 1980-01-01, the earliest date the format has.
+    let mtime = mtime.max(315_532_800);
+    let stamp = crate::history::format_timestamp(mtime);
+    let field = |range: std::ops::Range<usize>| stamp[range].parse::<u16>().unwrap_or(0);
+    let time = (field(11..13) << 11) | (field(14..16) << 5) | (field(17..19) / 2);
+    let date = ((field(0..4) - 1980) << 9) | (field(5..7) << 5) | field(8..10);
+    (time, date)
+}
+
+struct Counted<W: Write> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+enum Kind {
+    Directory,
+    File,
+    Symlink(PathBuf),
+}
+
+struct Entry {
+    /// `/`-separated, relative to the archived directory.
+    name: String,
+    path: PathBuf,
+    kind: Kind,
+    mode: u32,
+    mtime: u64,
+    size: u64,
+}
+
+/// What the central directory needs to know about a written entry.
+struct Written {
+    name: String,
+    method: u16,
+    flags: u16,
+    time: (u16, u16),
+    mtime: u64,
+    crc: u32,
+    compressed: u64,
+    size: u64,
+    offset: u64,
+    external: u32,
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.mode()
+}
+
+#[cfg(not(unix))]
+fn mode_of(metadata: &fs::Metadata) -> u32 {
+    if metadata.is_dir() {
+        0o040755
+    } else {
+        0o100644
+    }
+}
+
+/// Every entry under `dir`, parents before their contents. Symlinks are
+/// stored as links when `preserve.links` is on and followed otherwise;
+/// sockets, devices and the like are left out. `parents` holds the
+/// resolved directories above `dir`, so a link back to one of them is
+/// refused instead of followed forever.
+fn walk(
+    dir: &Path,
+    prefix: &str,
+    preserve: Preserve,
+    parents: &mut Vec<PathBuf>,
+    entries: &mut Vec<Entry>,
+) -> io::Result<()> {
+    let mut children: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    children.sort_by_key(|child| child.file_name());
+    for child in children {
+        let path = child.path();
+        let name = format!("{}{}", prefix, child.file_name().to_string_lossy());
+        let link = fs::symlink_metadata(&path)?;
+        let metadata = if link.file_type().is_symlink() && !preserve.links {
+            match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                // A dangling link has nothing to follow.
+                Err(_) => continue,
+            }
+        } else {
+            link
+        };
+        let kind = if metadata.file_type().is_symlink() {
+            Kind::Symlink(fs::read_link(&path)?)
+        } else if metadata.is_dir() {
+            Kind::Directory
+        } else if metadata.is_file() {
+            Kind::File
+        } else {
+            continue;
+        };
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        let is_dir = matches!(kind, Kind::Directory);
+        let prefix = format!("{}/", name);
+        entries.push(Entry {
+            name: if is_dir { prefix.clone() } else { name },
+            path: path.clone(),
+            kind,
+            mode: mode_of(&metadata),
+            mtime,
+            size: metadata.len(),
+        });
+        if is_dir {
+            let resolved = fs::canonicalize(&path)?;
+            if parents.contains(&resolved) {
+                return Err(io::Error::other(format!(
+                    "{} links back to {}, which would never end; keep links as links with --preserve links",
+                    path.display(),
+                    resolved.display()
+                )));
+            }
+            parents.push(resolved);
+            walk(&path, &prefix, preserve, parents, entries)?;
+            parents.pop();
+        }
+    }
+    Ok(())
+}
+
+impl<W: Write> Counted<W> {
+    fn u16(&mut self, n: u16) -> io::Result<()> {
+        self.write_all(&n.to_le_bytes())
+    }
+
+    fn u32(&mut self, n: u32) -> io::Result<()> {
+        self.write_all(&n.to_le_bytes())
+    }
+
+    fn u64(&mut self, n: u64) -> io::Result<()> {
+        self.write_all(&n.to_le_bytes())
+    }
+
+    /// A size or offset field: the value, or the Zip64 marker when it
+    /// lives in the Zip64 extra field instead.
+    fn u32_or_marker(&mut self, n: u64, zip64: bool) -> io::Result<()> {
+        self.u32(if zip64 { MAX32 as u32 } else { n as u32 })
+    }
+
+    fn local_header(&mut self, entry: &Written, version: u16, zip64: bool) -> io::Result<()> {
+        self.u32(LOCAL_HEADER)?;
+        self.u16(version)?;
+        self.u16(entry.flags)?;
+        self.u16(entry.method)?;
+        self.u16(entry.time.0)?;
+        self.u16(entry.time.1)?;
+        self.u32(entry.crc)?;
+        self.u32_or_marker(entry.compressed, zip64)?;
+        self.u32_or_marker(entry.size, zip64)?;
+        self.u16(entry.name.len() as u16)?;
+        let extra_len = if zip64 { 20 } else { 0 } + 9;
+        self.u16(extra_len)?;
+        self.write_all(entry.name.as_bytes())?;
+        if zip64 {
+            self.u16(ZIP64_EXTRA)?;
+            self.u16(16)?;
+            self.u64(entry.size)?;
+            self.u64(entry.compressed)?;
+        }
+        self.timestamp(entry.mtime)
+    }
+
+    fn timestamp(&mut self, mtime: u64) -> io::Result<()> {
+        self.u16(TIMESTAMP_EXTRA)?;
+        self.u16(5)?;
+        self.write_all(&[1])?;
+        self.u32(mtime.min(MAX32) as u32)
+    }
+}
+
+/// Streams `reader` through `gzip` and writes the raw deflate data in
+/// between gzip's header and trailer. `read` counts the input taken so far,
+/// and `on_chunk` runs after each piece of output. Returns the CRC-32 from
+/// the trailer and the compressed size.
+fn deflate<W: Write>(
+    reader: File,
+    level: Option<u32>,
+    out: &mut Counted<W>,
+    read: Arc<AtomicU64>,
+    on_chunk: &mut dyn FnMut(),
+) -> io::Result<(u32, u64)> {
+    let mut gzip = Command::new("gzip");
+    // -n leaves the name and time out, so the header is exactly 10 bytes.
+    gzip.args(["-n", "-c"]);
+    if let Some(level) = level {
+        gzip.arg(format!("-{}", level));
+    }
+    let mut child = gzip
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to run gzip for deflate: {}", e)))?;
+
+    let mut stdin = child.stdin.take();
+    let feeder = thread::spawn(move || -> io::Result<()> {
+        let mut reader = reader;
+        let mut buf = vec![0u8; 64 * 1024];
+        let stdin = stdin.as_mut().ok_or(io::ErrorKind::BrokenPipe)?;
+        loop {
+            match reader.read(&mut buf)? {
+                0 => return Ok(()),
+                n => {
+                    stdin.write_all(&buf[..n])?;
+                    read.fetch_add(n as u64, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+
+    let mut stdout = child.stdout.take().ok_or(io::ErrorKind::BrokenPipe)?;
+    let mut header = [0u8; 10];
+    stdout.read_exact(&mut header)?;
+    // Hold back the last 8 bytes seen: at the end they are the trailer.
+    let mut tail: Vec<u8> = Vec::with_capacity(8);
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut compressed = 0;
+    loop {
+        let n = stdout.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        tail.extend_from_slice(&buf[..n]);
+        if tail.len() > 8 {
+            let ready = tail.len() - 8;
+            out.write_all(&tail[..ready])?;
+            compressed += ready as u64;
+            tail.drain(..ready);
+        }
+        on_chunk();
+    }
+    feeder
+        .join()
+        .map_err(|_| io::Error::other("The deflate feeder stopped"))??;
+    let status = child.wait()?;
+    if !status.success() || tail.len() != 8 {
+        return Err(io::Error::other("gzip failed to deflate a file"));
+    }
+    let crc = u32::from_le_bytes([tail[0], tail[1], tail[2], tail[3]]);
+    Ok((crc, compressed))
+}
+
+/// Writes the contents of `dir` to `out` as a zip archive, front to back
+/// so it can go straight into a pipe. `progress` gets the bytes of input
+/// read so far. Returns the archive's size.
+pub fn write(
+    dir: &Path,
+    out: impl Write,
+    method: Method,
+    preserve: Preserve,
+    mut progress: impl FnMut(u64),
+) -> io::Result<u64> {
+    let mut entries = Vec::new();
+    walk(
+        dir,
+        "",
+        preserve,
+        &mut vec![fs::canonicalize(dir)?],
+        &mut entries,
+    )?;
+    let mut out = Counted {
+        inner: out,
+        written: 0,
+    };
+    let mut written = Vec::new();
+    let mut done = 0;
+
+    for entry in entries {
+        let file_type = entry.mode & 0o170000;
+        let permissions = if preserve.perms {
+            entry.mode & 0o7777
+        } else if matches!(entry.kind, Kind::File) {
+            0o644
+        } else {
+            0o755
+        };
+        let mut external = (file_type | permissions) << 16;
+        if matches!(entry.kind, Kind::Directory) {
+            // The MS-DOS directory attribute, for readers that ignore modes.
+            external |= 0x10;
+        }
+
+        let mut record = Written {
+            name: entry.name.clone(),
+            method: STORED,
+            flags: FLAG_UTF8,
+            time: dos_time(entry.mtime),
+            mtime: entry.mtime,
+            crc: 0,
+            compressed: 0,
+            size: 0,
+            offset: out.written,
+            external,
+        };
+
+        match (&entry.kind, method) {
+            (Kind::Directory, _) => out.local_header(&record, VERSION_DEFAULT, false)?,
+            (Kind::Symlink(target), _) => {
+                // Info-ZIP's convention: the link's target is its content.
+                let target = target.to_string_lossy().into_owned();
+                record.crc = crc32(0, target.as_bytes());
+                record.size = target.len() as u64;
+                record.compressed = record.size;
+                out.local_header(&record, VERSION_DEFAULT, false)?;
+                out.write_all(target.as_bytes())?;
+            }
+            (Kind::File, method) => {
+                // Both methods stream the file and put the CRC and sizes in
+                // a data descriptor after it, so they describe what was
+                // actually read even if the file changed since the walk.
+                record.flags |= FLAG_DESCRIPTOR;
+                let zip64 = entry.size >= ZIP64_THRESHOLD;
+                let version = if zip64 {
+                    VERSION_ZIP64
+                } else {
+                    VERSION_DEFAULT
+                };
+                if let Method::Deflate(_) = method {
+                    record.method = DEFLATED;
+                }
+                out.local_header(&record, version, zip64)?;
+
+                let file = File::open(&entry.path)?;
+                let (crc, size, compressed) = match method {
+                    Method::Store => {
+                        let (crc, size) = store(file, &mut out, &mut |read| progress(done + read))?;
+                        (crc, size, size)
+                    }
+                    Method::Deflate(level) => {
+                        let read = Arc::new(AtomicU64::new(0));
+                        let (crc, compressed) =
+                            deflate(file, level, &mut out, Arc::clone(&read), &mut || {
+                                progress(done + read.load(Ordering::Relaxed))
+                            })?;
+                        (crc, read.load(Ordering::Relaxed), compressed)
+                    }
+                };
+                record.crc = crc;
+                record.size = size;
+                record.compressed = compressed;
+                done += record.size;
+                progress(done);
+
+                out.u32(DATA_DESCRIPTOR)?;
+                out.u32(record.crc)?;
+                if zip64 {
+                    out.u64(record.compressed)?;
+                    out.u64(record.size)?;
+                } else if record.size > MAX32 || record.compressed > MAX32 {
+                    return Err(io::Error::other(format!(
+                        "{} grew past 4 GiB while it was archived",
+                        entry.path.display()
+                    )));
+                } else {
+                    out.u32(record.compressed as u32)?;
+                    out.u32(record.size as u32)?;
+                }
+            }
+        }
+        written.push(record);
+    }
+
+    let directory_start = out.written;
+    for record in &written {
+        let sizes64 = record.size >= MAX32 || record.compressed >= MAX32;
+        let offset64 = record.offset >= MAX32;
+        let mut zip64 = Vec::new();
+        if sizes64 {
+            zip64.extend_from_slice(&record.size.to_le_bytes());
+            zip64.extend_from_slice(&record.compressed.to_le_bytes());
+        }
+        if offset64 {
+            zip64.extend_from_slice(&record.offset.to_le_bytes());
+        }
+        let version = if zip64.is_empty() {
+            VERSION_DEFAULT
+        } else {
+            VERSION_ZIP64
+        };
+
+        out.u32(CENTRAL_HEADER)?;
+        out.u16(MADE_BY_UNIX | version)?;
+        out.u16(version)?;
+        out.u16(record.flags)?;
+        out.u16(record.method)?;
+        out.u16(record.time.0)?;
+        out.u16(record.time.1)?;
+        out.u32(record.crc)?;
+        out.u32_or_marker(record.compressed, sizes64)?;
+        out.u32_or_marker(record.size, sizes64)?;
+        out.u16(record.name.len() as u16)?;
+        let extra = if zip64.is_empty() { 0 } else { 4 + zip64.len() } + 9;
+        out.u16(extra as u16)?;
+        out.u16(0)?; // comment
+        out.u16(0)?; // disk
+        out.u16(0)?; // internal attributes
+        out.u32(record.external)?;
+        out.u32_or_marker(record.offset, offset64)?;
+        out.write_all(record.name.as_bytes())?;
+        if !zip64.is_empty() {
+            out.u16(ZIP64_EXTRA)?;
+            out.u16(zip64.len() as u16)?;
+            out.write_all(&zip64)?;
+        }
+        out.timestamp(record.mtime)?;
+    }
+    let directory_size = out.written - directory_start;
+
+    let count = written.len() as u64;
+    if count >= 0xffff || directory_start >= MAX32 || directory_size >= MAX32 {
+        let zip64_end = out.written;
+        out.u32(ZIP64_END)?;
+        out.u64(44)?;
+        out.u16(MADE_BY_UNIX | VERSION_ZIP64)?;
+        out.u16(VERSION_ZIP64)?;
+        out.u32(0)?;
+        out.u32(0)?;
+        out.u64(count)?;
+        out.u64(count)?;
+        out.u64(directory_size)?;
+        out.u64(directory_start)?;
+        out.u32(ZIP64_LOCATOR)?;
+        out.u32(0)?;
+        out.u64(zip64_end)?;
+        out.u32(1)?;
+    }
+    out.u32(END)?;
+    out.u16(0)?;
+    out.u16(0)?;
+    out.u16(count.min(0xffff) as u16)?;
+    out.u16(count.min(0xffff) as u16)?;
+    out.u32(directory_size.min(MAX32) as u32)?;
+    out.u32(directory_start.min(MAX32) as u32)?;
+    out.u16(0)?;
+    out.flush()?;
+    Ok(out.written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("xfer-zip-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A tree with nested directories, an empty file and a file spanning
+    /// several read buffers.
+    fn tree(name: &str) -> (PathBuf, Vec<(String, Vec<u8>)>) {
+        let dir = scratch(name);
+        let big: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let files = vec![
+            ("a.txt".to_string(), b"hello\n".to_vec()),
+            ("docs/empty".to_string(), Vec::new()),
+            ("docs/deep/big.bin".to_string(), big),
+        ];
+        for (name, data) in &files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+        (dir, files)
+    }
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    /// Raw deflate data back to bytes, by giving gzip the header and
+    /// trailer it left out.
+    fn inflate(raw: &[u8], crc: u32, size: u32) -> Vec<u8> {
+        let mut gzip = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3];
+        gzip.extend_from_slice(raw);
+        gzip.extend_from_slice(&crc.to_le_bytes());
+        gzip.extend_from_slice(&size.to_le_bytes());
+        let mut child = Command::new("gzip")
+            .arg("-dc")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let feeder = thread::spawn(move || stdin.write_all(&gzip));
+        let output = child.wait_with_output().unwrap();
+        feeder.join().unwrap().unwrap();
+        assert!(output.status.success());
+        output.stdout
+    }
+
+    struct Unpacked {
+        name: String,
+        method: u16,
+        external: u32,
+        data: Vec<u8>,
+    }
+
+    /// Reads an archive back through its central directory, checking each
+    /// entry's local header, data descriptor and CRC on the way.
+    fn unpack(archive: &[u8]) -> Vec<Unpacked> {
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(archive, end), END);
+        let count = u16_at(archive, end + 10);
+        let mut at = u32_at(archive, end + 16) as usize;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(archive, at), CENTRAL_HEADER);
+            let flags = u16_at(archive, at + 8);
+            let method = u16_at(archive, at + 10);
+            let crc = u32_at(archive, at + 16);
+            let compressed = u32_at(archive, at + 20) as usize;
+            let size = u32_at(archive, at + 24);
+            let name_len = u16_at(archive, at + 28) as usize;
+            let extra_len = u16_at(archive, at + 30) as usize;
+            let external = u32_at(archive, at + 38);
+            let offset = u32_at(archive, at + 42) as usize;
+            let name = String::from_utf8(archive[at + 46..at + 46 + name_len].to_vec()).unwrap();
+            at += 46 + name_len + extra_len;
+
+            assert_eq!(u32_at(archive, offset), LOCAL_HEADER);
+            assert_eq!(u16_at(archive, offset + 8), method);
+            let start = offset
+                + 30
+                + u16_at(archive, offset + 26) as usize
+                + u16_at(archive, offset + 28) as usize;
+            let raw = &archive[start..start + compressed];
+            if flags & FLAG_DESCRIPTOR != 0 {
+                let descriptor = start + compressed;
+                assert_eq!(u32_at(archive, descriptor), DATA_DESCRIPTOR);
+                assert_eq!(u32_at(archive, descriptor + 4), crc);
+                assert_eq!(u32_at(archive, descriptor + 8) as usize, compressed);
+                assert_eq!(u32_at(archive, descriptor + 12), size);
+            }
+            let data = match method {
+                STORED => raw.to_vec(),
+                DEFLATED => inflate(raw, crc, size),
+                other => panic!("unknown method {}", other),
+            };
+            assert_eq!(data.len() as u32, size, "{}", name);
+            assert_eq!(crc32(0, &data), crc, "{}", name);
+            entries.push(Unpacked {
+                name,
+                method,
+                external,
+                data,
+            });
+        }
+        entries
+    }
+
+    fn round_trip(name: &str, method: Method, expected: u16) {
+        let (dir, files) = tree(name);
+        let mut archive = Vec::new();
+        let size = write(&dir, &mut archive, method, Preserve::default(), |_| {}).unwrap();
+        assert_eq!(size, archive.len() as u64);
+
+        let entries = unpack(&archive);
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "a.txt",
+                "docs/",
+                "docs/deep/",
+                "docs/deep/big.bin",
+                "docs/empty"
+            ]
+        );
+        for (name, data) in files {
+            let entry = entries.iter().find(|entry| entry.name == name).unwrap();
+            assert_eq!(entry.method, expected, "{}", name);
+            assert_eq!(entry.data, data, "{}", name);
+        }
+        let docs = entries.iter().find(|entry| entry.name == "docs/").unwrap();
+        assert_eq!(docs.external & 0x10, 0x10);
+        assert_eq!(docs.external >> 16 & 0o170000, 0o040000);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stored_archives_round_trip() {
+        round_trip("store", Method::Store, STORED);
+    }
+
+    #[test]
+    fn deflated_archives_round_trip() {
+        round_trip("deflate", Method::Deflate(None), DEFLATED);
+        round_trip("deflate-9", Method::Deflate(Some(9)), DEFLATED);
+    }
+
+    #[test]
+    fn an_empty_directory_is_an_empty_archive() {
+        let dir = scratch("empty");
+        let mut archive = Vec::new();
+        write(
+            &dir,
+            &mut archive,
+            Method::Store,
+            Preserve::default(),
+            |_| {},
+        )
+        .unwrap();
+        assert!(unpack(&archive).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_progress_up_to_the_input_size() {
+        let (dir, files) = tree("progress");
+        let total: usize = files.iter().map(|(_, data)| data.len()).sum();
+        let mut last = 0;
+        write(
+            &dir,
+            io::sink(),
+            Method::Store,
+            Preserve::default(),
+            |done| {
+                assert!(done >= last);
+                last = done;
+            },
+        )
+        .unwrap();
+        assert_eq!(last, total as u64);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_to_follow_a_link_back_up_the_tree() {
+        let dir = scratch("loop");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::write(dir.join("a/file"), b"x").unwrap();
+        std::os::unix::fs::symlink("..", dir.join("a/up")).unwrap();
+
+        let followed = Preserve {
+            links: false,
+            ..Preserve::default()
+        };
+        let error = write(&dir, io::sink(), Method::Store, followed, |_| {}).unwrap_err();
+        assert!(error.to_string().contains("links back"), "{}", error);
+
+        // Kept as a link, it is just an entry holding its target.
+        let mut archive = Vec::new();
+        write(
+            &dir,
+            &mut archive,
+            Method::Store,
+            Preserve::default(),
+            |_| {},
+        )
+        .unwrap();
+        let entries = unpack(&archive);
+        let up = entries.iter().find(|entry| entry.name == "a/up").unwrap();
+        assert_eq!(up.data, b"..");
+        assert_eq!(up.external >> 16 & 0o170000, 0o120000);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}